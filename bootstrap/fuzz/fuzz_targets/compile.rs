@@ -0,0 +1,14 @@
+//! Feed arbitrary bytes through the whole compiler.
+//!
+//! `compile_str` must return `Ok` or `Err` for every input; libFuzzer
+//! reports a panic (or a stack overflow) as a crash.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Invalid UTF-8 becomes U+FFFD, so the lexer still sees the rest
+    let source = String::from_utf8_lossy(data);
+    let _ = nova::compile_str(&source);
+});
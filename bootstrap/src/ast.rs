@@ -7,7 +7,8 @@
 
 #![allow(dead_code)]
 
-use crate::token::Span;
+use crate::intern::Symbol;
+use crate::token::{Span, Token};
 
 /// A complete Nova program
 #[derive(Debug, Clone)]
@@ -25,6 +26,144 @@ pub enum Item {
     Trait(TraitDef),
     Use(UseStmt),
     TypeAlias(TypeAlias),
+    Static(StaticDef),
+    Mod(ModDecl),
+}
+
+impl Item {
+    /// The attributes written before the item.
+    pub fn attrs(&self) -> &[Attribute] {
+        match self {
+            Item::Function(f) => &f.attrs,
+            Item::Struct(s) => &s.attrs,
+            Item::Enum(e) => &e.attrs,
+            Item::Impl(i) => &i.attrs,
+            Item::Trait(t) => &t.attrs,
+            Item::Use(u) => &u.attrs,
+            Item::TypeAlias(a) => &a.attrs,
+            Item::Static(s) => &s.attrs,
+            Item::Mod(m) => &m.attrs,
+        }
+    }
+
+    /// Mutable access to the item's visibility, for the items that have one.
+    pub fn vis_mut(&mut self) -> Option<&mut Visibility> {
+        match self {
+            Item::Function(f) => Some(&mut f.vis),
+            Item::Struct(s) => Some(&mut s.vis),
+            Item::Enum(e) => Some(&mut e.vis),
+            Item::Trait(t) => Some(&mut t.vis),
+            _ => None,
+        }
+    }
+
+    /// The item's span, which starts after its attributes.
+    pub fn span(&self) -> Span {
+        match self {
+            Item::Function(f) => f.span,
+            Item::Struct(s) => s.span,
+            Item::Enum(e) => e.span,
+            Item::Impl(i) => i.span,
+            Item::Trait(t) => t.span,
+            Item::Use(u) => u.span,
+            Item::TypeAlias(a) => a.span,
+            Item::Static(s) => s.span,
+            Item::Mod(m) => m.span,
+        }
+    }
+
+    /// The name [`diff`] matches the item by: `f`, `impl Show for P`,
+    /// `use a::b`.
+    pub fn key(&self) -> String {
+        let type_text = |ty: &Type| match &ty.kind {
+            TypeKind::Path(path) => path_text(path),
+            _ => "?".to_string(),
+        };
+        match self {
+            Item::Function(f) => f.name.name.to_string(),
+            Item::Struct(s) => s.name.name.to_string(),
+            Item::Enum(e) => e.name.name.to_string(),
+            Item::Impl(i) => match &i.trait_ {
+                Some(t) => format!("impl {} for {}", type_text(t), type_text(&i.self_type)),
+                None => format!("impl {}", type_text(&i.self_type)),
+            },
+            Item::Trait(t) => t.name.name.to_string(),
+            Item::Use(u) => format!("use {}", path_text(&u.tree.path)),
+            Item::TypeAlias(a) => a.name.name.to_string(),
+            Item::Static(s) => s.name.name.to_string(),
+            Item::Mod(m) => format!("mod {}", m.name.name),
+        }
+    }
+
+    /// Mutable access to the item's attributes.
+    pub fn attrs_mut(&mut self) -> &mut Vec<Attribute> {
+        match self {
+            Item::Function(f) => &mut f.attrs,
+            Item::Struct(s) => &mut s.attrs,
+            Item::Enum(e) => &mut e.attrs,
+            Item::Impl(i) => &mut i.attrs,
+            Item::Trait(t) => &mut t.attrs,
+            Item::Use(u) => &mut u.attrs,
+            Item::TypeAlias(a) => &mut a.attrs,
+            Item::Static(s) => &mut s.attrs,
+            Item::Mod(m) => &mut m.attrs,
+        }
+    }
+}
+
+/// Where an item can be used from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Visibility {
+    #[default]
+    Private, // the module it's in
+    Crate,  // `pub(crate)`
+    Public, // `pub`
+}
+
+/// An attribute: `#[name]`, `#[path::name(...)]`
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub kind: AttributeKind,
+    pub span: Span,
+}
+
+impl Attribute {
+    /// The doc comment among `attrs`, its lines joined, if there is one
+    pub fn doc(attrs: &[Attribute]) -> Option<String> {
+        let lines: Vec<_> = attrs
+            .iter()
+            .filter_map(|attr| match &attr.kind {
+                AttributeKind::Doc(line) => Some(line.as_str()),
+                _ => None,
+            })
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
+/// Attribute kinds
+#[derive(Debug, Clone)]
+pub enum AttributeKind {
+    /// `#[cfg(predicate)]`
+    Cfg(CfgPredicate),
+    /// `#[repr(C)]`, `#[repr(C, packed)]`: the layout hints, checked when
+    /// the item is
+    Repr(Vec<Ident>),
+    /// Any other attribute, with the tokens inside its parentheses if it
+    /// has any: `#[derive(Debug, Clone)]`
+    Other(Path, Option<Vec<Token>>),
+    /// A line of a `///` doc comment, without the slashes
+    Doc(String),
+}
+
+/// A `cfg` predicate
+#[derive(Debug, Clone)]
+pub enum CfgPredicate {
+    Flag(Ident),             // test
+    KeyValue(Ident, String), // target = "wasm"
+    All(Vec<CfgPredicate>),  // all(a, b)
+    Any(Vec<CfgPredicate>),  // any(a, b)
+    Not(Box<CfgPredicate>),  // not(a)
 }
 
 /// A function definition
@@ -36,6 +175,9 @@ pub struct Function {
     pub return_type: Option<Type>,
     pub where_clause: Option<WhereClause>,
     pub body: Block,
+    pub vis: Visibility,
+    pub is_unsafe: bool,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -44,6 +186,7 @@ pub struct Function {
 pub struct Param {
     pub pattern: Pattern,
     pub ty: Type,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -53,6 +196,9 @@ pub struct StructDef {
     pub name: Ident,
     pub generics: Vec<GenericParam>,
     pub fields: Vec<Field>,
+    pub is_tuple: bool, // `struct Point(i64, i64);` or `struct Marker;`
+    pub vis: Visibility,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -61,6 +207,7 @@ pub struct StructDef {
 pub struct Field {
     pub name: Ident,
     pub ty: Type,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -70,6 +217,8 @@ pub struct EnumDef {
     pub name: Ident,
     pub generics: Vec<GenericParam>,
     pub variants: Vec<Variant>,
+    pub vis: Visibility,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -78,6 +227,8 @@ pub struct EnumDef {
 pub struct Variant {
     pub name: Ident,
     pub fields: VariantFields,
+    /// An explicit discriminant (`A = 1`), only on unit variants
+    pub discriminant: Option<Expr>,
     pub span: Span,
 }
 
@@ -96,6 +247,7 @@ pub struct ImplBlock {
     pub trait_: Option<Type>,
     pub self_type: Type,
     pub items: Vec<ImplItem>,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -103,6 +255,38 @@ pub struct ImplBlock {
 #[derive(Debug, Clone)]
 pub enum ImplItem {
     Function(Function),
+    Const(AssocConst),
+    Type(AssocType),
+}
+
+impl ImplItem {
+    /// The attributes written before the item.
+    pub fn attrs(&self) -> &[Attribute] {
+        match self {
+            ImplItem::Function(f) => &f.attrs,
+            ImplItem::Const(c) => &c.attrs,
+            ImplItem::Type(t) => &t.attrs,
+        }
+    }
+}
+
+/// An associated constant: `const N: i32 = 5;`
+#[derive(Debug, Clone)]
+pub struct AssocConst {
+    pub name: Ident,
+    pub ty: Type,
+    pub value: Expr,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+/// An associated type: `type Item = i32;`
+#[derive(Debug, Clone)]
+pub struct AssocType {
+    pub name: Ident,
+    pub ty: Type,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
 }
 
 /// A trait definition
@@ -112,6 +296,8 @@ pub struct TraitDef {
     pub generics: Vec<GenericParam>,
     pub bounds: Vec<Type>,
     pub items: Vec<TraitItem>,
+    pub vis: Visibility,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -119,6 +305,8 @@ pub struct TraitDef {
 #[derive(Debug, Clone)]
 pub enum TraitItem {
     Function(TraitFunction),
+    Const(TraitConst),
+    Type(TraitType),
 }
 
 /// A function signature in a trait
@@ -132,10 +320,56 @@ pub struct TraitFunction {
     pub span: Span,
 }
 
-/// A use statement
+/// An associated constant declared in a trait: `const N: i32;`
+#[derive(Debug, Clone)]
+pub struct TraitConst {
+    pub name: Ident,
+    pub ty: Type,
+    pub default: Option<Expr>,
+    pub span: Span,
+}
+
+/// An associated type declared in a trait: `type Item: Bound;`
+#[derive(Debug, Clone)]
+pub struct TraitType {
+    pub name: Ident,
+    pub bounds: Vec<Type>,
+    pub default: Option<Type>,
+    pub span: Span,
+}
+
+/// A use statement: `use a::b;`, `use a::b as c;` or `use a::{b, c};`
 #[derive(Debug, Clone)]
 pub struct UseStmt {
+    pub tree: UseTree,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+/// What a `use` imports, relative to the path of its enclosing group
+#[derive(Debug, Clone)]
+pub struct UseTree {
     pub path: Path,
+    pub kind: UseTreeKind,
+    pub span: Span,
+}
+
+/// Use tree kinds
+#[derive(Debug, Clone)]
+pub enum UseTreeKind {
+    /// The path itself, optionally renamed: `a::b as c`
+    Simple(Option<Ident>),
+    /// Each tree under the path: `a::{b, c::d}`
+    Group(Vec<UseTree>),
+}
+
+/// A module: `mod name { items }`, or `mod name;` loaded from `name.nova`
+#[derive(Debug, Clone)]
+pub struct ModDecl {
+    pub name: Ident,
+    /// The inline items, or `None` for `mod name;`
+    pub items: Option<Vec<Item>>,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -145,6 +379,18 @@ pub struct TypeAlias {
     pub name: Ident,
     pub generics: Vec<GenericParam>,
     pub ty: Type,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+/// A static item: `static mut COUNTER: i32 = 0;`
+#[derive(Debug, Clone)]
+pub struct StaticDef {
+    pub name: Ident,
+    pub mutable: bool,
+    pub ty: Type,
+    pub value: Expr,
+    pub attrs: Vec<Attribute>,
     pub span: Span,
 }
 
@@ -215,6 +461,7 @@ pub struct Expr {
 pub enum ExprKind {
     // Literals
     Literal(Literal),
+    FormatString(Vec<FormatPart>), // f"x = {x}"
 
     // Variables
     Path(Path),
@@ -250,6 +497,7 @@ pub enum ExprKind {
 
     // Block
     Block(Block),
+    Unsafe(Block), // unsafe { ... }
 
     // Closures
     Closure(Vec<Param>, Option<Type>, Box<Expr>),
@@ -262,6 +510,9 @@ pub enum ExprKind {
     // Range
     Range(Option<Box<Expr>>, Option<Box<Expr>>, bool), // inclusive?
 
+    // Cast: `expr as Type`
+    Cast(Box<Expr>, Type),
+
     // Reference
     Ref(bool, Box<Expr>), // mutable?
     Deref(Box<Expr>),
@@ -271,16 +522,42 @@ pub enum ExprKind {
 
     // Try (?)
     Try(Box<Expr>),
+
+    // Macro invocation: `name!(...)`, `name![...]` or `name!{...}`
+    MacroCall {
+        path: Path,
+        delimiter: Delimiter,
+        tokens: Vec<Token>, // between the delimiters, unexpanded
+    },
+}
+
+/// The brackets around a macro's arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    Paren,   // ( )
+    Bracket, // [ ]
+    Brace,   // { }
 }
 
 /// A literal value
 #[derive(Debug, Clone)]
 pub enum Literal {
-    Int(i64),
+    /// Wide enough for every `i64` and `u64`, so `u64::MAX` isn't lost
+    Int(i128),
     Float(f64),
     String(String),
     Bool(bool),
     Char(char),
+    Bytes(Vec<u8>),
+}
+
+/// A piece of a format string literal
+#[derive(Debug, Clone)]
+pub enum FormatPart {
+    /// Text with escapes and `{{`/`}}` already resolved
+    Literal(String),
+    /// An interpolated `{expr}`
+    Expr(Expr),
 }
 
 /// Binary operators
@@ -345,12 +622,15 @@ pub enum PatternKind {
     Wildcard,
     Ident(Ident, bool), // mutable?
     Literal(Literal),
+    Path(Path), // unit struct or variant, e.g. `Color::Red`
     Tuple(Vec<Pattern>),
     Struct(Path, Vec<FieldPattern>),
     TupleStruct(Path, Vec<Pattern>),
     Or(Vec<Pattern>),
     Ref(bool, Box<Pattern>), // mutable?
     Range(Option<Box<Pattern>>, Option<Box<Pattern>>, bool),
+    Slice(Vec<Pattern>), // `[a, b]`, with at most one `Rest` element
+    Rest(Option<Ident>), // `..` or `name @ ..` in a slice pattern
 }
 
 /// A field in a struct pattern
@@ -377,8 +657,11 @@ pub enum TypeKind {
     Slice(Box<Type>),
     Reference(bool, Box<Type>), // mutable?
     Fn(Vec<Type>, Option<Box<Type>>),
+    ImplTrait(Vec<Type>), // impl Bound + Bound
+    DynTrait(Vec<Type>),  // dyn Bound + Bound
     Never,
     Infer,
+    SelfType, // `Self` in an impl or trait
 }
 
 /// A path (like `std::collections::HashMap`)
@@ -397,14 +680,471 @@ pub struct PathSegment {
 }
 
 /// An identifier
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct Ident {
-    pub name: String,
+    pub name: Symbol,
     pub span: Span,
 }
 
 impl Ident {
-    pub fn new(name: String, span: Span) -> Self {
-        Self { name, span }
+    pub fn new(name: &str, span: Span) -> Self {
+        Self {
+            name: Symbol::intern(name),
+            span,
+        }
+    }
+}
+
+/// How a top-level item changed between two parses, keyed by [`Item::key`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemChange {
+    Added(String),
+    Removed(String),
+    Modified(String),
+}
+
+/// The top-level items added, removed or modified going from `old` to `new`.
+///
+/// Items are compared with their spans made relative to their start, so an
+/// item that only moved is unchanged. Items sharing a key (two `impl P`
+/// blocks) are matched in order. The changes follow `new`, with removed
+/// items last.
+pub fn diff(old: &Program, new: &Program) -> Vec<ItemChange> {
+    let old_items = keyed(old);
+    let new_items = keyed(new);
+
+    let mut changes = Vec::new();
+    for (key, item) in &new_items {
+        match old_items.iter().find(|(k, _)| k == key) {
+            Some((_, before)) if shape(before) != shape(item) => {
+                changes.push(ItemChange::Modified(key.0.clone()));
+            }
+            Some(_) => {}
+            None => changes.push(ItemChange::Added(key.0.clone())),
+        }
+    }
+    for (key, _) in &old_items {
+        if !new_items.iter().any(|(k, _)| k == key) {
+            changes.push(ItemChange::Removed(key.0.clone()));
+        }
+    }
+    changes
+}
+
+/// Each item with its key and how many earlier items share the key
+fn keyed(program: &Program) -> Vec<((String, usize), &Item)> {
+    let mut items: Vec<((String, usize), &Item)> = Vec::new();
+    for item in &program.items {
+        let key = item.key();
+        let nth = items.iter().filter(|((k, _), _)| *k == key).count();
+        items.push(((key, nth), item));
+    }
+    items
+}
+
+/// An item's `Debug` output with its spans made relative to its start
+fn shape(item: &Item) -> String {
+    let base = start_with_attrs(item.attrs(), item.span());
+    format!("{:?}", rebased(item, base))
+}
+
+/// Where an item begins counting its attributes (doc comments included),
+/// which come before its own span
+pub(crate) fn start_with_attrs(attrs: &[Attribute], span: Span) -> u32 {
+    attrs
+        .first()
+        .map_or(span.start(), |attr| attr.span.start().min(span.start()))
+}
+
+fn path_text(path: &Path) -> String {
+    path.segments
+        .iter()
+        .map(|s| s.ident.name.as_str())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// A copy of `node` with its spans made relative to `base`, so copies of
+/// a node written in different places are the same
+pub(crate) fn rebased<T: Clone + VisitSpans>(node: &T, base: u32) -> T {
+    let mut node = node.clone();
+    node.visit_spans(&mut |span| {
+        let start = span.start().saturating_sub(base);
+        *span = Span::new(start, span.end().saturating_sub(base).max(start));
+    });
+    node
+}
+
+/// Syntax tree nodes whose spans can be rewritten in place
+pub(crate) trait VisitSpans {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span));
+}
+
+impl VisitSpans for Span {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        f(self)
+    }
+}
+
+impl VisitSpans for Token {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        let mut span = self.span();
+        f(&mut span);
+        *self = Token::new(self.kind(), span);
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for Vec<T> {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        for node in self {
+            node.visit_spans(f);
+        }
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for Option<T> {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        if let Some(node) = self {
+            node.visit_spans(f);
+        }
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for Box<T> {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        (**self).visit_spans(f)
+    }
+}
+
+/// Implement `VisitSpans` for structs by visiting the listed fields, which
+/// must be every field that holds a span
+macro_rules! visit_fields {
+    ($($node:ty { $($field:ident),* })*) => {
+        $(impl VisitSpans for $node {
+            fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+                $(self.$field.visit_spans(f);)*
+            }
+        })*
+    };
+}
+
+visit_fields! {
+    Program { items }
+    Attribute { kind, span }
+    Function { name, generics, params, return_type, where_clause, body, attrs, span }
+    Param { pattern, ty, attrs, span }
+    StructDef { name, generics, fields, attrs, span }
+    Field { name, ty, attrs, span }
+    EnumDef { name, generics, variants, attrs, span }
+    Variant { name, fields, discriminant, span }
+    ImplBlock { generics, trait_, self_type, items, attrs, span }
+    AssocConst { name, ty, value, attrs, span }
+    AssocType { name, ty, attrs, span }
+    TraitDef { name, generics, bounds, items, attrs, span }
+    TraitFunction { name, generics, params, return_type, default_body, span }
+    TraitConst { name, ty, default, span }
+    TraitType { name, bounds, default, span }
+    UseStmt { tree, attrs, span }
+    UseTree { path, kind, span }
+    ModDecl { name, items, attrs, span }
+    TypeAlias { name, generics, ty, attrs, span }
+    StaticDef { name, ty, value, attrs, span }
+    GenericParam { name, bounds, span }
+    WhereClause { predicates, span }
+    WherePredicate { ty, bounds, span }
+    Block { stmts, span }
+    LetStmt { pattern, ty, value, span }
+    ExprStmt { expr, span }
+    Expr { kind, span }
+    FieldInit { name, value, span }
+    MatchArm { pattern, guard, body, span }
+    Pattern { kind, span }
+    FieldPattern { name, pattern, span }
+    Type { kind, span }
+    Path { segments, span }
+    PathSegment { ident, generics, span }
+    Ident { span }
+}
+
+impl VisitSpans for Item {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Item::Function(func) => func.visit_spans(f),
+            Item::Struct(s) => s.visit_spans(f),
+            Item::Enum(e) => e.visit_spans(f),
+            Item::Impl(i) => i.visit_spans(f),
+            Item::Trait(t) => t.visit_spans(f),
+            Item::Use(u) => u.visit_spans(f),
+            Item::TypeAlias(a) => a.visit_spans(f),
+            Item::Static(s) => s.visit_spans(f),
+            Item::Mod(m) => m.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for AttributeKind {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            AttributeKind::Cfg(predicate) => predicate.visit_spans(f),
+            AttributeKind::Repr(hints) => hints.visit_spans(f),
+            AttributeKind::Other(path, tokens) => {
+                path.visit_spans(f);
+                tokens.visit_spans(f);
+            }
+            AttributeKind::Doc(_) => {}
+        }
+    }
+}
+
+impl VisitSpans for CfgPredicate {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            CfgPredicate::Flag(name) | CfgPredicate::KeyValue(name, _) => name.visit_spans(f),
+            CfgPredicate::All(predicates) | CfgPredicate::Any(predicates) => {
+                predicates.visit_spans(f)
+            }
+            CfgPredicate::Not(predicate) => predicate.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for VariantFields {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            VariantFields::Unit => {}
+            VariantFields::Tuple(types) => types.visit_spans(f),
+            VariantFields::Struct(fields) => fields.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for ImplItem {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            ImplItem::Function(func) => func.visit_spans(f),
+            ImplItem::Const(c) => c.visit_spans(f),
+            ImplItem::Type(t) => t.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for TraitItem {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            TraitItem::Function(func) => func.visit_spans(f),
+            TraitItem::Const(c) => c.visit_spans(f),
+            TraitItem::Type(t) => t.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for UseTreeKind {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            UseTreeKind::Simple(rename) => rename.visit_spans(f),
+            UseTreeKind::Group(trees) => trees.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for Stmt {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Stmt::Let(stmt) => stmt.visit_spans(f),
+            Stmt::Expr(stmt) => stmt.visit_spans(f),
+            Stmt::Item(item) => item.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for ExprKind {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            ExprKind::Literal(_) | ExprKind::Continue => {}
+            ExprKind::FormatString(parts) => {
+                for part in parts {
+                    if let FormatPart::Expr(e) = part {
+                        e.visit_spans(f);
+                    }
+                }
+            }
+            ExprKind::Path(path) => path.visit_spans(f),
+            ExprKind::Binary(left, _, right) | ExprKind::Index(left, right) => {
+                left.visit_spans(f);
+                right.visit_spans(f);
+            }
+            ExprKind::Unary(_, e)
+            | ExprKind::Ref(_, e)
+            | ExprKind::Deref(e)
+            | ExprKind::Await(e)
+            | ExprKind::Try(e) => e.visit_spans(f),
+            ExprKind::Call(callee, args) => {
+                callee.visit_spans(f);
+                args.visit_spans(f);
+            }
+            ExprKind::Field(base, name) => {
+                base.visit_spans(f);
+                name.visit_spans(f);
+            }
+            ExprKind::StructLit(path, fields) => {
+                path.visit_spans(f);
+                fields.visit_spans(f);
+            }
+            ExprKind::Array(elems) | ExprKind::Tuple(elems) => elems.visit_spans(f),
+            ExprKind::If(cond, then_block, else_expr) => {
+                cond.visit_spans(f);
+                then_block.visit_spans(f);
+                else_expr.visit_spans(f);
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                scrutinee.visit_spans(f);
+                arms.visit_spans(f);
+            }
+            ExprKind::While(cond, body) => {
+                cond.visit_spans(f);
+                body.visit_spans(f);
+            }
+            ExprKind::For(pattern, iter, body) => {
+                pattern.visit_spans(f);
+                iter.visit_spans(f);
+                body.visit_spans(f);
+            }
+            ExprKind::Loop(body) | ExprKind::Block(body) | ExprKind::Unsafe(body) => {
+                body.visit_spans(f)
+            }
+            ExprKind::Closure(params, ret, body) => {
+                params.visit_spans(f);
+                ret.visit_spans(f);
+                body.visit_spans(f);
+            }
+            ExprKind::Return(value) | ExprKind::Break(value) => value.visit_spans(f),
+            ExprKind::Range(start, end, _) => {
+                start.visit_spans(f);
+                end.visit_spans(f);
+            }
+            ExprKind::Cast(e, ty) => {
+                e.visit_spans(f);
+                ty.visit_spans(f);
+            }
+            ExprKind::MacroCall { path, tokens, .. } => {
+                path.visit_spans(f);
+                tokens.visit_spans(f);
+            }
+        }
+    }
+}
+
+impl VisitSpans for PatternKind {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            PatternKind::Wildcard | PatternKind::Literal(_) => {}
+            PatternKind::Ident(name, _) => name.visit_spans(f),
+            PatternKind::Path(path) => path.visit_spans(f),
+            PatternKind::Tuple(elems) | PatternKind::Or(elems) | PatternKind::Slice(elems) => {
+                elems.visit_spans(f)
+            }
+            PatternKind::Struct(path, fields) => {
+                path.visit_spans(f);
+                fields.visit_spans(f);
+            }
+            PatternKind::TupleStruct(path, elems) => {
+                path.visit_spans(f);
+                elems.visit_spans(f);
+            }
+            PatternKind::Ref(_, inner) => inner.visit_spans(f),
+            PatternKind::Range(lo, hi, _) => {
+                lo.visit_spans(f);
+                hi.visit_spans(f);
+            }
+            PatternKind::Rest(name) => name.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for TypeKind {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            TypeKind::Path(path) => path.visit_spans(f),
+            TypeKind::Tuple(types) | TypeKind::ImplTrait(types) | TypeKind::DynTrait(types) => {
+                types.visit_spans(f)
+            }
+            TypeKind::Array(elem, len) => {
+                elem.visit_spans(f);
+                len.visit_spans(f);
+            }
+            TypeKind::Slice(inner) | TypeKind::Reference(_, inner) => inner.visit_spans(f),
+            TypeKind::Fn(params, ret) => {
+                params.visit_spans(f);
+                ret.visit_spans(f);
+            }
+            TypeKind::Never | TypeKind::Infer | TypeKind::SelfType => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn changes(old: &str, new: &str) -> Vec<ItemChange> {
+        let old = parse(old, lex(old).unwrap()).unwrap();
+        let new = parse(new, lex(new).unwrap()).unwrap();
+        diff(&old, &new)
+    }
+
+    #[test]
+    fn test_rebased_ignores_where_an_item_is() {
+        let shapes: Vec<_> = ["", "fn e() {}\n"]
+            .map(|before| format!(r#"{before}fn f() {{ g("3..4", '"', 7) }}"#))
+            .iter()
+            .map(|source| {
+                let program = parse(source, lex(source).unwrap()).unwrap();
+                shape(program.items.last().unwrap())
+            })
+            .collect();
+        assert_eq!(shapes[0], shapes[1]);
+        // Literals keep their text; only the spans around them change
+        assert!(shapes[0].contains(r#"String("3..4")"#), "{}", shapes[0]);
+        let mut starts = Vec::new();
+        let source = "fn e() {}\nfn f() { g(1) }";
+        let program = parse(source, lex(source).unwrap()).unwrap();
+        rebased(&program.items[1], 10).visit_spans(&mut |span| starts.push(span.start()));
+        assert_eq!(starts.iter().min(), Some(&0));
+        assert!(starts.iter().all(|&start| start < 15));
+    }
+
+    #[test]
+    fn test_diff_added_function() {
+        let changes = changes("fn f() {}", "fn f() {} fn g() {}");
+        assert_eq!(changes, [ItemChange::Added("g".to_string())]);
+    }
+
+    #[test]
+    fn test_diff_removed_struct() {
+        let changes = changes("struct P { x: i64 } fn f() {}", "fn f() {}");
+        assert_eq!(changes, [ItemChange::Removed("P".to_string())]);
+    }
+
+    #[test]
+    fn test_diff_modified_body() {
+        let old = "fn f() { 1; } fn g() { 2; } struct P; impl P { fn m(self) {} }";
+        // `g` and the impl move but are otherwise the same
+        let new = "fn f() { 10 + 1; } fn g() { 2; } struct P; impl P { fn m(self) {} }";
+        assert_eq!(changes(old, new), [ItemChange::Modified("f".to_string())]);
+        assert!(changes(old, old).is_empty());
+        let new = "fn f() { 1; } fn g() { 2; } struct P; impl P { fn m(self) { self; } }";
+        assert_eq!(
+            changes(old, new),
+            [ItemChange::Modified("impl P".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_diff_ignores_moved_attributes() {
+        let old = "fn a() {} #[inline] fn f() {} /// Docs\nstruct P;";
+        let new = "fn a() { 1; } #[inline] fn f() {} /// Docs\nstruct P;";
+        assert_eq!(changes(old, new), [ItemChange::Modified("a".to_string())]);
     }
 }
@@ -0,0 +1,143 @@
+//! Conditional compilation
+//!
+//! Items marked `#[cfg(predicate)]` are kept only when the predicate holds
+//! for the active configuration, which is set on the command line with
+//! `--cfg name` or `--cfg key="value"`:
+//!
+//! ```text
+//! #[cfg(test)]                        --cfg test
+//! #[cfg(target = "wasm")]             --cfg target="wasm"
+//! #[cfg(all(test, not(fast)))]
+//! ```
+//!
+//! Items are stripped right after each file is parsed, so a disabled `mod`
+//! is never loaded and disabled items are never type checked.
+
+use std::collections::HashSet;
+
+use crate::ast::{Attribute, AttributeKind, CfgPredicate, Item};
+
+/// The active configuration
+#[derive(Debug, Clone, Default)]
+pub struct CfgSet {
+    flags: HashSet<String>,
+    values: HashSet<(String, String)>,
+}
+
+impl CfgSet {
+    /// Creates an empty configuration, with no flags set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables a `--cfg` argument: `name` or `key="value"`.
+    pub fn enable(&mut self, spec: &str) {
+        match spec.split_once('=') {
+            Some((key, value)) => {
+                let value = value.trim_matches('"');
+                self.values.insert((key.to_string(), value.to_string()));
+            }
+            None => {
+                self.flags.insert(spec.to_string());
+            }
+        }
+    }
+
+    /// Evaluates a predicate against this configuration.
+    pub fn eval(&self, predicate: &CfgPredicate) -> bool {
+        match predicate {
+            CfgPredicate::Flag(name) => self.flags.contains(name.name.as_str()),
+            CfgPredicate::KeyValue(key, value) => {
+                self.values.contains(&(key.name.to_string(), value.clone()))
+            }
+            CfgPredicate::All(args) => args.iter().all(|p| self.eval(p)),
+            CfgPredicate::Any(args) => args.iter().any(|p| self.eval(p)),
+            CfgPredicate::Not(arg) => !self.eval(arg),
+        }
+    }
+
+    /// Returns true if every `cfg` attribute in `attrs` holds.
+    pub fn is_enabled(&self, attrs: &[Attribute]) -> bool {
+        attrs.iter().all(|attr| match &attr.kind {
+            AttributeKind::Cfg(predicate) => self.eval(predicate),
+            AttributeKind::Repr(_) | AttributeKind::Other(..) | AttributeKind::Doc(_) => true,
+        })
+    }
+
+    /// Drops disabled items, and disabled methods of the remaining impls.
+    pub fn strip(&self, items: Vec<Item>) -> Vec<Item> {
+        items
+            .into_iter()
+            .filter(|item| self.is_enabled(item.attrs()))
+            .map(|item| match item {
+                Item::Impl(mut imp) => {
+                    imp.items.retain(|item| self.is_enabled(item.attrs()));
+                    Item::Impl(imp)
+                }
+                item => item,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn item_count(source: &str, cfg: &CfgSet) -> usize {
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        cfg.strip(program.items).len()
+    }
+
+    fn cfg(specs: &[&str]) -> CfgSet {
+        let mut cfg = CfgSet::new();
+        for spec in specs {
+            cfg.enable(spec);
+        }
+        cfg
+    }
+
+    #[test]
+    fn test_cfg_test_excluded_without_flag() {
+        let source = "#[cfg(test)] fn t() {} fn main() {}";
+        assert_eq!(item_count(source, &cfg(&[])), 1);
+    }
+
+    #[test]
+    fn test_cfg_test_included_with_flag() {
+        let source = "#[cfg(test)] fn t() {} fn main() {}";
+        assert_eq!(item_count(source, &cfg(&["test"])), 2);
+    }
+
+    #[test]
+    fn test_cfg_combinators() {
+        let source = r#"
+            #[cfg(all(test, not(fast)))] fn a() {}
+            #[cfg(any(fast, target = "wasm"))] fn b() {}
+        "#;
+        assert_eq!(item_count(source, &cfg(&["test"])), 1);
+        assert_eq!(item_count(source, &cfg(&["test", "fast"])), 1);
+        assert_eq!(item_count(source, &cfg(&["test", "target=\"wasm\""])), 2);
+        assert_eq!(item_count(source, &cfg(&[])), 0);
+    }
+
+    #[test]
+    fn test_cfg_strips_impl_methods() {
+        let source = "struct S {} impl S { #[cfg(test)] fn t() {} fn f() {} }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match &cfg(&[]).strip(program.items)[1] {
+            Item::Impl(imp) => assert_eq!(imp.items.len(), 1),
+            other => panic!("Expected impl, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_other_attributes_are_kept() {
+        let source = "#[inline(always)] fn f() {}";
+        assert_eq!(item_count(source, &cfg(&[])), 1);
+    }
+}
@@ -11,26 +11,120 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-use crate::ir::{BasicBlock, Function, Instruction, InstructionKind, IrType, Module, Terminator};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use crate::error::NovaError;
+use crate::ir::{
+    tuple_offsets, BlockId, Function, Instruction, InstructionKind, IrType, Module, Terminator,
+    ValueId,
+};
+use crate::locals::{self, LocalSlots};
+use crate::span::Span;
+
+/// Module size limits enforced before a binary is produced.
+///
+/// The defaults are the implementation limits shared by the major WASM
+/// engines (WebAssembly JS API, "Limits"). A module that exceeds them is
+/// rejected by every engine, so we report an error instead of emitting it.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Maximum number of functions in a module
+    pub max_functions: usize,
+    /// Maximum number of locals (including parameters) in a function
+    pub max_locals: usize,
+    /// Maximum size of a function body in bytes
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_functions: 1_000_000,
+            max_locals: 50_000,
+            max_body_size: 7_654_321,
+        }
+    }
+}
 
 /// Generate WebAssembly binary from IR
-pub fn generate(module: &Module) -> Vec<u8> {
+pub fn generate(module: &Module) -> Result<Vec<u8>, NovaError> {
+    generate_with_limits(module, &Limits::default())
+}
+
+/// Generate WebAssembly binary from IR, checking against custom limits
+pub fn generate_with_limits(module: &Module, limits: &Limits) -> Result<Vec<u8>, NovaError> {
     let mut generator = WasmGenerator::new();
-    generator.generate(module)
+    generator.generate(module, limits)
+}
+
+/// Check a count against its limit
+fn check_limit(what: &'static str, count: usize, max: usize) -> Result<(), NovaError> {
+    if count > max {
+        return Err(NovaError::ModuleLimitExceeded { what, count, max });
+    }
+    Ok(())
 }
 
 /// WebAssembly generator
 struct WasmGenerator {
     /// Output buffer
     output: Vec<u8>,
+    /// Index of each function by name, the target of a `call`
+    functions: Rc<HashMap<String, u32>>,
+    /// Address of each string literal in the data section
+    strings: Rc<HashMap<String, u32>>,
+    /// The global holding the next free address after the data section,
+    /// with where it starts, if anything is allocated
+    stack_pointer: Option<(u32, u32)>,
 }
 
 impl WasmGenerator {
     fn new() -> Self {
-        Self { output: Vec::new() }
+        Self {
+            output: Vec::new(),
+            functions: Rc::default(),
+            strings: Rc::default(),
+            stack_pointer: None,
+        }
+    }
+
+    /// A generator writing to `output` that resolves calls like this one
+    fn nested(&self, output: Vec<u8>) -> Self {
+        Self {
+            output,
+            functions: Rc::clone(&self.functions),
+            strings: Rc::clone(&self.strings),
+            stack_pointer: self.stack_pointer,
+        }
     }
 
-    fn generate(&mut self, module: &Module) -> Vec<u8> {
+    fn generate(&mut self, module: &Module, limits: &Limits) -> Result<Vec<u8>, NovaError> {
+        check_limit("functions", module.functions.len(), limits.max_functions)?;
+
+        // Functions are numbered in the order they're declared
+        self.functions = Rc::new(
+            module
+                .functions
+                .iter()
+                .enumerate()
+                .map(|(idx, func)| (func.name.clone(), idx as u32))
+                .collect(),
+        );
+        let (strings, data) = layout_strings(module);
+        self.strings = Rc::new(strings);
+        // Allocations start past the strings, after the statics' globals
+        let allocates = module
+            .functions
+            .iter()
+            .flat_map(|f| &f.blocks)
+            .flat_map(|b| &b.instructions)
+            .any(|instr| matches!(instr.kind, InstructionKind::Alloca(_)));
+        self.stack_pointer = allocates.then(|| {
+            let index = module.globals.len() as u32;
+            (index, (data.len() as u32).next_multiple_of(STACK_ALIGN))
+        });
+
         // WASM magic number and version
         self.emit_bytes(&[0x00, 0x61, 0x73, 0x6D]); // \0asm
         self.emit_bytes(&[0x01, 0x00, 0x00, 0x00]); // version 1
@@ -41,13 +135,26 @@ impl WasmGenerator {
         // Function section (3)
         self.emit_function_section(module);
 
+        // Memory section (5)
+        self.emit_memory_section();
+
+        // Global section (6)
+        if !module.globals.is_empty() || self.stack_pointer.is_some() {
+            self.emit_global_section(module);
+        }
+
         // Export section (7)
         self.emit_export_section(module);
 
         // Code section (10)
-        self.emit_code_section(module);
+        self.emit_code_section(module, limits)?;
+
+        // Data section (11)
+        if !data.is_empty() {
+            self.emit_data_section(&data);
+        }
 
-        std::mem::take(&mut self.output)
+        Ok(std::mem::take(&mut self.output))
     }
 
     /// Emit raw bytes
@@ -76,6 +183,10 @@ impl WasmGenerator {
     }
 
     /// Emit a signed LEB128 integer
+    ///
+    /// `>>=` is an arithmetic shift, which is what signed LEB128 needs: the
+    /// value converges to 0 or -1 and the sign bit (0x40) of the last byte
+    /// says which. This terminates for every value, including `i32::MIN`.
     fn emit_i32(&mut self, mut value: i32) {
         loop {
             let byte = (value & 0x7F) as u8;
@@ -90,7 +201,7 @@ impl WasmGenerator {
         }
     }
 
-    /// Emit a signed LEB128 i64
+    /// Emit a signed LEB128 i64 (see `emit_i32`)
     fn emit_i64(&mut self, mut value: i64) {
         loop {
             let byte = (value & 0x7F) as u8;
@@ -121,7 +232,7 @@ impl WasmGenerator {
     /// Emit the type section
     fn emit_type_section(&mut self, module: &Module) {
         let mut contents = Vec::new();
-        let mut gen = WasmGenerator { output: contents };
+        let mut gen = self.nested(contents);
 
         // Number of types
         gen.emit_u32(module.functions.len() as u32);
@@ -130,18 +241,22 @@ impl WasmGenerator {
             // Function type marker
             gen.emit_byte(0x60);
 
-            // Parameters
-            gen.emit_u32(func.params.len() as u32);
-            for (_, ty) in &func.params {
+            // Parameters, with a tuple passed as its elements
+            let params: Vec<_> = func
+                .params
+                .iter()
+                .flat_map(|(_, ty)| ty.scalars())
+                .collect();
+            gen.emit_u32(params.len() as u32);
+            for ty in &params {
                 gen.emit_byte(Self::ir_type_to_wasm(ty));
             }
 
-            // Results
-            if matches!(func.return_type, IrType::Void) {
-                gen.emit_u32(0);
-            } else {
-                gen.emit_u32(1);
-                gen.emit_byte(Self::ir_type_to_wasm(&func.return_type));
+            // Results: none, one, or a tuple's elements (multi-value)
+            let results = func.return_type.scalars();
+            gen.emit_u32(results.len() as u32);
+            for ty in &results {
+                gen.emit_byte(Self::ir_type_to_wasm(ty));
             }
         }
 
@@ -152,7 +267,7 @@ impl WasmGenerator {
     /// Emit the function section
     fn emit_function_section(&mut self, module: &Module) {
         let mut contents = Vec::new();
-        let mut gen = WasmGenerator { output: contents };
+        let mut gen = self.nested(contents);
 
         gen.emit_u32(module.functions.len() as u32);
         for (i, _) in module.functions.iter().enumerate() {
@@ -163,10 +278,48 @@ impl WasmGenerator {
         self.emit_section(3, contents);
     }
 
+    /// Emit the memory section: one memory, starting at one page, that
+    /// `memory.grow` may enlarge
+    fn emit_memory_section(&mut self) {
+        // One memory, limits with a minimum only, of one page
+        self.emit_section(5, vec![0x01, 0x00, 0x01]);
+    }
+
+    /// Emit the global section, one global per `static` and then the stack
+    /// pointer
+    fn emit_global_section(&mut self, module: &Module) {
+        let mut contents = Vec::new();
+        let mut gen = self.nested(contents);
+
+        let count = module.globals.len() + self.stack_pointer.is_some() as usize;
+        gen.emit_u32(count as u32);
+        for global in &module.globals {
+            gen.emit_byte(Self::ir_type_to_wasm(&global.ty));
+            gen.emit_byte(global.mutable as u8); // 0x00 const, 0x01 var
+
+            // The initializer is a constant expression
+            let init = Instruction {
+                result: ValueId(0),
+                kind: global.init.clone(),
+            };
+            gen.emit_instruction(&init, &global.ty);
+            gen.emit_byte(0x0B); // end
+        }
+        if let Some((_, start)) = self.stack_pointer {
+            gen.emit_bytes(&[0x7F, 0x01]); // mutable i32
+            gen.emit_byte(0x41); // i32.const
+            gen.emit_i32(start as i32);
+            gen.emit_byte(0x0B); // end
+        }
+
+        contents = gen.output;
+        self.emit_section(6, contents);
+    }
+
     /// Emit the export section
     fn emit_export_section(&mut self, module: &Module) {
         let mut contents = Vec::new();
-        let mut gen = WasmGenerator { output: contents };
+        let mut gen = self.nested(contents);
 
         // Export all functions
         gen.emit_u32(module.functions.len() as u32);
@@ -180,34 +333,91 @@ impl WasmGenerator {
         self.emit_section(7, contents);
     }
 
+    /// Emit the data section: one active segment, at address 0, holding
+    /// every string literal
+    fn emit_data_section(&mut self, data: &[u8]) {
+        let mut gen = self.nested(Vec::new());
+        gen.emit_u32(1);
+        gen.emit_u32(0); // active, memory 0
+        gen.emit_byte(0x41); // i32.const
+        gen.emit_i32(0);
+        gen.emit_byte(0x0B); // end
+        gen.emit_u32(data.len() as u32);
+        gen.emit_bytes(data);
+        let contents = gen.output;
+        self.emit_section(11, contents);
+    }
+
     /// Emit the code section
-    fn emit_code_section(&mut self, module: &Module) {
+    fn emit_code_section(&mut self, module: &Module, limits: &Limits) -> Result<(), NovaError> {
         let mut contents = Vec::new();
-        let mut gen = WasmGenerator { output: contents };
+        let mut gen = self.nested(contents);
 
         gen.emit_u32(module.functions.len() as u32);
         for func in &module.functions {
-            let func_body = gen.emit_function(func);
+            // Parameters count towards the local limit
+            let slots = locals::allocate(func, module);
+            let params: usize = func.params.iter().map(|(_, ty)| ty.scalars().len()).sum();
+            let local_count = params + slots.locals().len();
+            check_limit("locals", local_count, limits.max_locals)?;
+            self.check_callees(func)?;
+
+            let func_body = gen.emit_function(func, &slots);
+            check_limit(
+                "bytes in a function body",
+                func_body.len(),
+                limits.max_body_size,
+            )?;
             gen.emit_u32(func_body.len() as u32);
             gen.emit_bytes(&func_body);
         }
 
         contents = gen.output;
         self.emit_section(10, contents);
+        Ok(())
+    }
+
+    /// Check that every function `func` calls is in the module
+    fn check_callees(&self, func: &Function) -> Result<(), NovaError> {
+        for block in &func.blocks {
+            for instr in &block.instructions {
+                if let InstructionKind::Call(name, _) = &instr.kind {
+                    if !self.functions.contains_key(name) {
+                        return Err(NovaError::UndefinedFunction {
+                            name: name.clone(),
+                            span: Span::new(0, 0),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Emit a function body
-    fn emit_function(&mut self, func: &Function) -> Vec<u8> {
+    fn emit_function(&mut self, func: &Function, slots: &LocalSlots) -> Vec<u8> {
         let body = Vec::new();
-        let mut gen = WasmGenerator { output: body };
+        let mut gen = self.nested(body);
 
-        // Local declarations (simplified: just count locals)
-        // TODO: Proper local handling
-        gen.emit_u32(0); // No additional locals for now
+        // Local declarations, one entry per run of locals of the same type
+        let mut runs: Vec<(u32, u8)> = Vec::new();
+        for ty in slots.locals() {
+            let wasm = Self::ir_type_to_wasm(ty);
+            match runs.last_mut() {
+                Some((count, last)) if *last == wasm => *count += 1,
+                _ => runs.push((1, wasm)),
+            }
+        }
+        gen.emit_u32(runs.len() as u32);
+        for (count, wasm) in runs {
+            gen.emit_u32(count);
+            gen.emit_byte(wasm);
+        }
 
-        // Emit instructions for each block
-        for block in &func.blocks {
-            gen.emit_block(block);
+        // Emit the blocks as structured control flow, from the entry
+        if let Some(entry) = func.blocks.first() {
+            let mut nesting = Nesting::default();
+            gen.emit_region(func, slots, entry.id, None, &mut nesting);
         }
 
         // End of function
@@ -216,36 +426,293 @@ impl WasmGenerator {
         gen.output
     }
 
-    /// Emit a basic block
-    fn emit_block(&mut self, block: &BasicBlock) {
-        for instr in &block.instructions {
-            self.emit_instruction(instr);
+    /// Emit the blocks from `start` up to (not including) `stop`
+    ///
+    /// Values live in their locals between instructions, so control flow
+    /// carries nothing on the stack. A `Phi` is written by each predecessor
+    /// on its way to the phi's block. A `CondBranch` becomes `if`/`else`/`end`:
+    /// each arm is the region from its target to the join, the first block
+    /// both arms reach. An arm that never reaches a join (it returns, is
+    /// unreachable or leaves a loop) ends at `stop` instead.
+    ///
+    /// A loop header, a block with an edge back to it from a block it
+    /// dominates, becomes `block`/`loop`/`end`/`end`: an edge to the header
+    /// is a `br` to the `loop` and an edge to the loop's exit, the block
+    /// after it, a `br` to the `block`. So the body is emitted without a
+    /// `stop`, and ends where every path has left the iteration.
+    fn emit_region(
+        &mut self,
+        func: &Function,
+        slots: &LocalSlots,
+        start: BlockId,
+        stop: Option<BlockId>,
+        nesting: &mut Nesting,
+    ) {
+        let mut current = start;
+        while Some(current) != stop && !nesting.emitted.contains(&current) {
+            if !nesting.labels.contains(&Some(current)) && is_loop_header(func, current) {
+                let exit = loop_exit(func, current);
+                self.emit_bytes(&[0x02, 0x40]); // block (no result)
+                self.emit_bytes(&[0x03, 0x40]); // loop (no result)
+                nesting.labels.extend([exit, Some(current)]);
+                self.emit_region(func, slots, current, None, nesting);
+                nesting.labels.truncate(nesting.labels.len() - 2);
+                self.emit_bytes(&[0x0B, 0x0B]); // end (loop), end (block)
+                match exit {
+                    Some(exit) => current = exit,
+                    None => {
+                        // The loop never finishes
+                        self.emit_byte(0x00); // unreachable
+                        return;
+                    }
+                }
+                continue;
+            }
+            nesting.emitted.insert(current);
+            let Some(block) = func.blocks.iter().find(|b| b.id == current) else {
+                return;
+            };
+            for instr in &block.instructions {
+                self.emit_in_locals(instr, slots);
+            }
+
+            match block.terminator {
+                Terminator::Return(value) => {
+                    if let (Some(value), false) = (value, func.return_type == IrType::Void) {
+                        self.emit_get(slots, value);
+                    }
+                    // The end of the function returns what's on the stack
+                    if stop.is_some() || !nesting.labels.is_empty() {
+                        self.emit_byte(0x0F); // return
+                    }
+                    return;
+                }
+                Terminator::Branch(target) => {
+                    self.emit_phi_copies(func, slots, current, target);
+                    if self.emit_br(nesting, target) {
+                        return;
+                    }
+                    current = target;
+                }
+                Terminator::CondBranch(cond, then_block, else_block) => {
+                    // Leaving a loop (or going round again) on one side
+                    // without phis to write is a `br_if`
+                    let mut copies = self.nested(Vec::new());
+                    copies.emit_phi_copies(func, slots, current, else_block);
+                    if copies.output.is_empty() && label_depth(nesting, else_block).is_some() {
+                        self.emit_get(slots, cond);
+                        self.emit_byte(0x45); // i32.eqz
+                        self.emit_br_if(nesting, else_block);
+                        self.emit_phi_copies(func, slots, current, then_block);
+                        if self.emit_br(nesting, then_block) {
+                            return;
+                        }
+                        current = then_block;
+                        continue;
+                    }
+                    let mut copies = self.nested(Vec::new());
+                    copies.emit_phi_copies(func, slots, current, then_block);
+                    if copies.output.is_empty() && label_depth(nesting, then_block).is_some() {
+                        self.emit_get(slots, cond);
+                        self.emit_br_if(nesting, then_block);
+                        self.emit_phi_copies(func, slots, current, else_block);
+                        if self.emit_br(nesting, else_block) {
+                            return;
+                        }
+                        current = else_block;
+                        continue;
+                    }
+
+                    // A join outside the loop being emitted isn't one
+                    let join = join_block(func, then_block, else_block)
+                        .filter(|&join| label_depth(nesting, join).is_none())
+                        .or(stop);
+                    self.emit_get(slots, cond);
+                    self.emit_bytes(&[0x04, 0x40]); // if (no result)
+                    nesting.labels.push(None);
+                    self.emit_phi_copies(func, slots, current, then_block);
+                    if !self.emit_br(nesting, then_block) {
+                        self.emit_region(func, slots, then_block, join, nesting);
+                    }
+                    let mut else_arm = self.nested(Vec::new());
+                    else_arm.emit_phi_copies(func, slots, current, else_block);
+                    if !else_arm.emit_br(nesting, else_block) {
+                        else_arm.emit_region(func, slots, else_block, join, nesting);
+                    }
+                    nesting.labels.pop();
+                    if !else_arm.output.is_empty() {
+                        self.emit_byte(0x05); // else
+                        self.emit_bytes(&else_arm.output);
+                    }
+                    self.emit_byte(0x0B); // end
+                    match join {
+                        Some(join) => current = join,
+                        None => {
+                            // Both arms left the function (or the loop)
+                            self.emit_byte(0x00); // unreachable
+                            return;
+                        }
+                    }
+                }
+                Terminator::Unreachable => {
+                    self.emit_byte(0x00); // unreachable
+                    return;
+                }
+            }
         }
+    }
+
+    /// Emit a `br` for an edge to `target` if that leaves (or goes round)
+    /// an enclosing loop, returning whether it did
+    fn emit_br(&mut self, nesting: &Nesting, target: BlockId) -> bool {
+        let Some(depth) = label_depth(nesting, target) else {
+            return false;
+        };
+        self.emit_byte(0x0C); // br
+        self.emit_u32(depth);
+        true
+    }
 
-        match &block.terminator {
-            Terminator::Return(_value) => {
-                // Value should already be on stack from last instruction
-                // Return is implicit at end of function
+    /// Emit a `br_if` to the label for `target`, which must have one
+    fn emit_br_if(&mut self, nesting: &Nesting, target: BlockId) {
+        let depth = label_depth(nesting, target).expect("br_if to a block without a label");
+        self.emit_byte(0x0D); // br_if
+        self.emit_u32(depth);
+    }
+
+    /// Emit an instruction with its operands read from their locals, and
+    /// its result (if it has one) stored in its own
+    fn emit_in_locals(&mut self, instr: &Instruction, slots: &LocalSlots) {
+        // A parameter is read from its own local wherever it's used, and a
+        // phi's local is written by its predecessors
+        if matches!(
+            instr.kind,
+            InstructionKind::GetParam(_) | InstructionKind::Phi(_)
+        ) {
+            return;
+        }
+        // An op takes its width from its operands, an index's for an
+        // `ElementPtr`
+        let operand = match &instr.kind {
+            InstructionKind::ElementPtr(_, index, _) => Some(*index),
+            kind => kind.operands().first().copied(),
+        };
+        let operand_type = operand
+            .and_then(|v| slots.type_of(v))
+            .cloned()
+            .unwrap_or(IrType::I32);
+        match &instr.kind {
+            // A tuple in memory is moved an element at a time
+            InstructionKind::Load(addr, IrType::Tuple(elems)) => {
+                for (elem, offset) in elems.iter().zip(tuple_offsets(elems)) {
+                    self.emit_get(slots, *addr);
+                    self.emit_byte(Self::load_op(elem));
+                    self.emit_memarg(elem, offset);
+                }
+            }
+            InstructionKind::Store(addr, value, IrType::Tuple(elems)) => {
+                let locals = slots.locals_of(*value);
+                for ((elem, offset), local) in elems.iter().zip(tuple_offsets(elems)).zip(locals) {
+                    self.emit_get(slots, *addr);
+                    self.emit_byte(0x20); // local.get
+                    self.emit_u32(local);
+                    self.emit_byte(Self::store_op(elem));
+                    self.emit_memarg(elem, offset);
+                }
+            }
+            InstructionKind::TupleGet(tuple, index) => {
+                let local = slots.locals_of(*tuple).nth(*index as usize);
+                if let Some(local) = local {
+                    self.emit_byte(0x20); // local.get
+                    self.emit_u32(local);
+                }
+            }
+            // Floats have their own `neg`
+            InstructionKind::Neg(value) if matches!(operand_type, IrType::F32 | IrType::F64) => {
+                self.emit_get(slots, *value);
+                self.emit_instruction(instr, &operand_type);
             }
-            Terminator::Branch(_) => {
-                // TODO: br instruction
+            InstructionKind::Neg(value) => {
+                let zero = Instruction {
+                    result: instr.result,
+                    kind: InstructionKind::ConstInt(0, operand_type.clone()),
+                };
+                self.emit_instruction(&zero, &operand_type);
+                self.emit_get(slots, *value);
+                self.emit_instruction(instr, &operand_type);
             }
-            Terminator::CondBranch(_, _, _) => {
-                // TODO: br_if instruction
+            _ => {
+                for operand in instr.kind.operands() {
+                    self.emit_get(slots, operand);
+                }
+                self.emit_instruction(instr, &operand_type);
             }
-            Terminator::Unreachable => {
-                self.emit_byte(0x00); // unreachable
+        }
+        self.emit_set(slots, instr.result);
+    }
+
+    /// Push `value` from its locals. Values without any (results of calls
+    /// to functions returning nothing) push nothing.
+    fn emit_get(&mut self, slots: &LocalSlots, value: ValueId) {
+        for local in slots.locals_of(value) {
+            self.emit_byte(0x20); // local.get
+            self.emit_u32(local);
+        }
+    }
+
+    /// Pop `value` into its locals, the last element of a tuple first
+    fn emit_set(&mut self, slots: &LocalSlots, value: ValueId) {
+        for local in slots.locals_of(value).rev() {
+            self.emit_byte(0x21); // local.set
+            self.emit_u32(local);
+        }
+    }
+
+    /// Write the values `to`'s phis take on the edge from `from`
+    ///
+    /// Every incoming value is read before any phi is written, so phis that
+    /// swap values (as a loop's can) see the values from before the edge.
+    fn emit_phi_copies(&mut self, func: &Function, slots: &LocalSlots, from: BlockId, to: BlockId) {
+        let Some(target) = func.blocks.iter().find(|b| b.id == to) else {
+            return;
+        };
+        let mut copies = Vec::new();
+        for instr in &target.instructions {
+            let InstructionKind::Phi(incoming) = &instr.kind else {
+                continue;
+            };
+            let Some(&(_, value)) = incoming.iter().find(|(pred, _)| *pred == from) else {
+                continue;
+            };
+            if slots.locals_of(value) != slots.locals_of(instr.result) {
+                copies.push((value, instr.result));
             }
         }
+        for &(value, _) in &copies {
+            self.emit_get(slots, value);
+        }
+        for &(_, phi) in copies.iter().rev() {
+            self.emit_set(slots, phi);
+        }
     }
 
-    /// Emit an instruction
-    fn emit_instruction(&mut self, instr: &Instruction) {
+    /// Emit an instruction whose operands have type `operand_type`, which
+    /// picks the `I32` or `I64` form of an integer op
+    fn emit_instruction(&mut self, instr: &Instruction, operand_type: &IrType) {
+        let wide = *operand_type == IrType::I64;
+        // Pick the i32 or i64 form of an integer opcode
+        let op = |narrow: u8, wide_op: u8| if wide { wide_op } else { narrow };
+        // Every `_s` opcode is directly followed by its `_u` form
+        let op_s = |narrow: u8, wide_op: u8, signed: bool| op(narrow, wide_op) + u8::from(!signed);
         match &instr.kind {
-            InstructionKind::ConstInt(n) => {
+            InstructionKind::ConstInt(n, IrType::I64) => {
                 self.emit_byte(0x42); // i64.const
                 self.emit_i64(*n);
             }
+            InstructionKind::ConstInt(n, _) => {
+                self.emit_byte(0x41); // i32.const (wraps to 32 bits)
+                self.emit_i32(*n as i32);
+            }
             InstructionKind::ConstFloat(n) => {
                 self.emit_byte(0x44); // f64.const
                 self.emit_bytes(&n.to_le_bytes());
@@ -254,94 +721,163 @@ impl WasmGenerator {
                 self.emit_byte(0x41); // i32.const
                 self.emit_i32(if *b { 1 } else { 0 });
             }
-            InstructionKind::ConstString(_) => {
-                // TODO: String handling
-                self.emit_byte(0x41); // i32.const (pointer placeholder)
-                self.emit_i32(0);
+            InstructionKind::ConstString(s) => {
+                let addr = self.strings[s];
+                self.emit_byte(0x41); // i32.const
+                self.emit_i32(addr as i32);
+                self.emit_byte(0x41); // i32.const
+                self.emit_i32(s.len() as i32);
             }
             InstructionKind::Add(_, _) => {
-                self.emit_byte(0x7C); // i64.add
+                self.emit_byte(op(0x6A, 0x7C)); // i32.add / i64.add
             }
             InstructionKind::Sub(_, _) => {
-                self.emit_byte(0x7D); // i64.sub
+                self.emit_byte(op(0x6B, 0x7D)); // i32.sub / i64.sub
             }
             InstructionKind::Mul(_, _) => {
-                self.emit_byte(0x7E); // i64.mul
+                self.emit_byte(op(0x6C, 0x7E)); // i32.mul / i64.mul
             }
-            InstructionKind::Div(_, _) => {
-                self.emit_byte(0x7F); // i64.div_s
+            InstructionKind::Div(_, _, signed) => {
+                self.emit_byte(op_s(0x6D, 0x7F, *signed)); // i32.div_s / i64.div_s, or `_u`
             }
-            InstructionKind::Rem(_, _) => {
-                self.emit_byte(0x81); // i64.rem_s
+            InstructionKind::Rem(_, _, signed) => {
+                self.emit_byte(op_s(0x6F, 0x81, *signed)); // i32.rem_s / i64.rem_s, or `_u`
             }
             InstructionKind::Eq(_, _) => {
-                self.emit_byte(0x51); // i64.eq
+                self.emit_byte(op(0x46, 0x51)); // i32.eq / i64.eq
             }
             InstructionKind::Ne(_, _) => {
-                self.emit_byte(0x52); // i64.ne
+                self.emit_byte(op(0x47, 0x52)); // i32.ne / i64.ne
             }
-            InstructionKind::Lt(_, _) => {
-                self.emit_byte(0x53); // i64.lt_s
+            InstructionKind::Lt(_, _, signed) => {
+                self.emit_byte(op_s(0x48, 0x53, *signed)); // i32.lt_s / i64.lt_s, or `_u`
             }
-            InstructionKind::Le(_, _) => {
-                self.emit_byte(0x57); // i64.le_s
+            InstructionKind::Le(_, _, signed) => {
+                self.emit_byte(op_s(0x4C, 0x57, *signed)); // i32.le_s / i64.le_s, or `_u`
             }
-            InstructionKind::Gt(_, _) => {
-                self.emit_byte(0x55); // i64.gt_s
+            InstructionKind::Gt(_, _, signed) => {
+                self.emit_byte(op_s(0x4A, 0x55, *signed)); // i32.gt_s / i64.gt_s, or `_u`
             }
-            InstructionKind::Ge(_, _) => {
-                self.emit_byte(0x59); // i64.ge_s
+            InstructionKind::Ge(_, _, signed) => {
+                self.emit_byte(op_s(0x4E, 0x59, *signed)); // i32.ge_s / i64.ge_s, or `_u`
             }
             InstructionKind::And(_, _) => {
-                self.emit_byte(0x83); // i64.and
+                self.emit_byte(op(0x71, 0x83)); // i32.and / i64.and
             }
             InstructionKind::Or(_, _) => {
-                self.emit_byte(0x84); // i64.or
+                self.emit_byte(op(0x72, 0x84)); // i32.or / i64.or
             }
             InstructionKind::BitAnd(_, _) => {
-                self.emit_byte(0x83); // i64.and
+                self.emit_byte(op(0x71, 0x83)); // i32.and / i64.and
             }
             InstructionKind::BitOr(_, _) => {
-                self.emit_byte(0x84); // i64.or
+                self.emit_byte(op(0x72, 0x84)); // i32.or / i64.or
             }
             InstructionKind::BitXor(_, _) => {
-                self.emit_byte(0x85); // i64.xor
+                self.emit_byte(op(0x73, 0x85)); // i32.xor / i64.xor
             }
             InstructionKind::Shl(_, _) => {
-                self.emit_byte(0x86); // i64.shl
+                self.emit_byte(op(0x74, 0x86)); // i32.shl / i64.shl
             }
-            InstructionKind::Shr(_, _) => {
-                self.emit_byte(0x87); // i64.shr_s
+            InstructionKind::Shr(_, _, signed) => {
+                self.emit_byte(op_s(0x75, 0x87, *signed)); // i32.shr_s / i64.shr_s, or `_u`
             }
             InstructionKind::Not(_) => {
-                self.emit_byte(0x45); // i64.eqz (not really not, but close)
-            }
-            InstructionKind::Neg(_) => {
-                // No direct neg, use 0 - x
-                self.emit_byte(0x42); // i64.const 0
-                self.emit_i64(0);
-                // TODO: Need to handle operand ordering
-                self.emit_byte(0x7D); // i64.sub
-            }
-            InstructionKind::Alloca(_) => {
-                // TODO: Stack allocation
-            }
-            InstructionKind::Load(_) => {
-                // TODO: Memory load
-                self.emit_byte(0x29); // i64.load
-                self.emit_u32(0); // align
-                self.emit_u32(0); // offset
-            }
-            InstructionKind::Store(_, _) => {
-                // TODO: Memory store
-                self.emit_byte(0x37); // i64.store
-                self.emit_u32(0); // align
-                self.emit_u32(0); // offset
-            }
-            InstructionKind::Call(_name, _args) => {
-                // TODO: Proper call handling
+                self.emit_byte(0x45); // i32.eqz, of a bool
+            }
+            InstructionKind::Neg(_) => match operand_type {
+                IrType::F64 => self.emit_byte(0x9A), // f64.neg
+                IrType::F32 => self.emit_byte(0x8C), // f32.neg
+                // No integer neg, so `0 - x`, with the zero pushed before
+                // the operand (see `emit_in_locals`)
+                _ => self.emit_byte(op(0x6B, 0x7D)), // i32.sub / i64.sub
+            },
+            InstructionKind::Convert(_, to, signed) => {
+                // Pick the signed or unsigned form
+                let sign = |signed_op: u8| if *signed { signed_op } else { signed_op + 1 };
+                match (operand_type, to) {
+                    (IrType::I64, IrType::I32) => self.emit_byte(0xA7), // i32.wrap_i64
+                    (IrType::I32 | IrType::Bool, IrType::I64) => {
+                        self.emit_byte(sign(0xAC)); // i64.extend_i32_s / _u
+                    }
+                    (IrType::I32 | IrType::Bool, IrType::F64) => {
+                        self.emit_byte(sign(0xB7)); // f64.convert_i32_s / _u
+                    }
+                    (IrType::I64, IrType::F64) => {
+                        self.emit_byte(sign(0xB9)); // f64.convert_i64_s / _u
+                    }
+                    (IrType::F64, IrType::I32) => {
+                        // i32.trunc_sat_f64_s / _u
+                        self.emit_bytes(&[0xFC, sign(0x02)]);
+                    }
+                    (IrType::F64, IrType::I64) => {
+                        // i64.trunc_sat_f64_s / _u
+                        self.emit_bytes(&[0xFC, sign(0x06)]);
+                    }
+                    // The same WASM type, such as `bool` to `i32`
+                    _ => {}
+                }
+            }
+            InstructionKind::Alloca(ty) => {
+                // Bump the stack pointer, leaving its old value as the address.
+                // TODO: Free a function's allocations when it returns, once
+                // values returned through memory are copied out first
+                let (global, _) = self
+                    .stack_pointer
+                    .expect("a module with allocas has a stack pointer");
+                let size = ty.size().next_multiple_of(STACK_ALIGN);
+                for _ in 0..2 {
+                    self.emit_byte(0x23); // global.get
+                    self.emit_u32(global);
+                }
+                self.emit_byte(0x41); // i32.const
+                self.emit_i32(size as i32);
+                self.emit_byte(0x6A); // i32.add
+                self.emit_byte(0x24); // global.set
+                self.emit_u32(global);
+            }
+            InstructionKind::Load(_, ty) => {
+                self.emit_byte(Self::load_op(ty));
+                self.emit_memarg(ty, 0);
+            }
+            InstructionKind::Store(_, _, ty) => {
+                self.emit_byte(Self::store_op(ty));
+                self.emit_memarg(ty, 0);
+            }
+            InstructionKind::FieldPtr(_, offset) => {
+                self.emit_byte(0x41); // i32.const
+                self.emit_i32(*offset as i32);
+                self.emit_byte(0x6A); // i32.add
+            }
+            InstructionKind::ElementPtr(_, _, size) => {
+                if wide {
+                    self.emit_byte(0xA7); // i32.wrap_i64 (addresses are 32-bit)
+                }
+                self.emit_byte(0x41); // i32.const
+                self.emit_i32(*size as i32);
+                self.emit_byte(0x6C); // i32.mul
+                self.emit_byte(0x6A); // i32.add
+            }
+            InstructionKind::MemorySize => {
+                self.emit_bytes(&[0x3F, 0x00]); // memory.size (memory 0)
+            }
+            InstructionKind::MemoryGrow(_) => {
+                self.emit_bytes(&[0x40, 0x00]); // memory.grow (memory 0)
+            }
+            InstructionKind::Call(name, _args) => {
+                // Callees are checked before any body is emitted
+                let idx = *self
+                    .functions
+                    .get(name)
+                    .unwrap_or_else(|| panic!("call to unknown function `{}`", name));
                 self.emit_byte(0x10); // call
-                self.emit_u32(0); // function index (placeholder)
+                self.emit_u32(idx);
+            }
+            InstructionKind::Tuple(_) => {
+                // The elements on the stack are the tuple
+            }
+            InstructionKind::TupleGet(..) => {
+                // Read straight from the tuple's locals, see `emit_in_locals`
             }
             InstructionKind::Phi(_) => {
                 // Phi nodes are resolved during SSA construction
@@ -350,6 +886,40 @@ impl WasmGenerator {
                 self.emit_byte(0x20); // local.get
                 self.emit_u32(*idx as u32);
             }
+            InstructionKind::GlobalGet(idx) => {
+                self.emit_byte(0x23); // global.get
+                self.emit_u32(*idx as u32);
+            }
+            InstructionKind::GlobalSet(idx, _) => {
+                self.emit_byte(0x24); // global.set
+                self.emit_u32(*idx as u32);
+            }
+        }
+    }
+
+    /// Emit the alignment (as a power of two) and offset of a load or store
+    fn emit_memarg(&mut self, ty: &IrType, offset: u32) {
+        self.emit_u32(ty.align().trailing_zeros());
+        self.emit_u32(offset);
+    }
+
+    /// The opcode loading a scalar of type `ty`
+    fn load_op(ty: &IrType) -> u8 {
+        match ty {
+            IrType::I64 => 0x29, // i64.load
+            IrType::F32 => 0x2A, // f32.load
+            IrType::F64 => 0x2B, // f64.load
+            _ => 0x28,           // i32.load
+        }
+    }
+
+    /// The opcode storing a scalar of type `ty`
+    fn store_op(ty: &IrType) -> u8 {
+        match ty {
+            IrType::I64 => 0x37, // i64.store
+            IrType::F32 => 0x38, // f32.store
+            IrType::F64 => 0x39, // f64.store
+            _ => 0x36,           // i32.store
         }
     }
 
@@ -360,33 +930,638 @@ impl WasmGenerator {
             IrType::I64 => 0x7E,
             IrType::F32 => 0x7D,
             IrType::F64 => 0x7C,
-            IrType::Bool => 0x7F,   // i32
-            IrType::Ptr(_) => 0x7F, // i32 (32-bit address space)
-            IrType::Void => 0x40,   // empty (for block types)
+            IrType::Bool => 0x7F,             // i32
+            IrType::Ptr(_) => 0x7F,           // i32 (32-bit address space)
+            IrType::Aggregate { .. } => 0x7F, // i32 (passed by address)
+            IrType::Void => 0x40,             // empty (for block types)
+            IrType::Tuple(_) => unreachable!("a tuple is several WASM values, see `scalars`"),
+        }
+    }
+}
+
+/// How far a function's emission has got: the blocks already emitted, and
+/// the labels around the code being emitted
+#[derive(Debug, Default)]
+struct Nesting {
+    emitted: HashSet<BlockId>,
+    /// The block a `br` to each label goes to, innermost last. An `if`'s
+    /// label isn't the target of any branch.
+    labels: Vec<Option<BlockId>>,
+}
+
+/// The `br` depth of the innermost label going to `target`, if any
+fn label_depth(nesting: &Nesting, target: BlockId) -> Option<u32> {
+    let depth = nesting
+        .labels
+        .iter()
+        .rev()
+        .position(|&l| l == Some(target))?;
+    Some(depth as u32)
+}
+
+/// Whether `id` is a loop header: some block it dominates branches to it
+fn is_loop_header(func: &Function, id: BlockId) -> bool {
+    !back_edge_sources(func, id).is_empty()
+}
+
+/// The blocks with an edge back to `header`: ones it reaches and dominates
+///
+/// Unreachable code (after a `break`, say) is dominated by every block, but
+/// doesn't make a loop.
+fn back_edge_sources(func: &Function, header: BlockId) -> Vec<BlockId> {
+    let from_header = reachable(func, header);
+    func.blocks
+        .iter()
+        .filter(|b| b.terminator.successors().contains(&header))
+        .map(|b| b.id)
+        .filter(|pred| from_header.contains(pred))
+        .filter(|&pred| dominates(func, header, pred))
+        .collect()
+}
+
+/// Whether every path from the entry to `b` goes through `a`
+fn dominates(func: &Function, a: BlockId, b: BlockId) -> bool {
+    let Some(entry) = func.blocks.first() else {
+        return false;
+    };
+    if a == entry.id {
+        return true;
+    }
+    let mut seen = HashSet::from([a]);
+    let mut stack = vec![entry.id];
+    while let Some(id) = stack.pop() {
+        if id == b {
+            return false;
+        }
+        if seen.insert(id) {
+            stack.extend(successors(func, id));
+        }
+    }
+    true
+}
+
+/// The block the loop at `header` exits to: the first successor of a block
+/// in the loop that isn't in it
+///
+/// The blocks in the loop are the header and those that reach an edge back
+/// to it without going through it.
+fn loop_exit(func: &Function, header: BlockId) -> Option<BlockId> {
+    let mut body = HashSet::from([header]);
+    let mut stack = back_edge_sources(func, header);
+    while let Some(id) = stack.pop() {
+        if body.insert(id) {
+            let preds = func
+                .blocks
+                .iter()
+                .filter(|b| b.terminator.successors().contains(&id));
+            stack.extend(preds.map(|b| b.id));
+        }
+    }
+    // In block order, so the exit the lowering made for the loop comes first
+    func.blocks
+        .iter()
+        .filter(|b| body.contains(&b.id))
+        .flat_map(|b| b.terminator.successors())
+        .find(|succ| !body.contains(succ))
+}
+
+/// The alignment of every allocation, enough for any value
+const STACK_ALIGN: u32 = 8;
+
+/// Give each distinct string literal in `module` an address, packing them
+/// one after another from 0
+///
+/// Returns the addresses and the bytes to put in memory there.
+fn layout_strings(module: &Module) -> (HashMap<String, u32>, Vec<u8>) {
+    let mut addresses = HashMap::new();
+    let mut data = Vec::new();
+    let instructions = module
+        .functions
+        .iter()
+        .flat_map(|f| &f.blocks)
+        .flat_map(|b| &b.instructions);
+    for instr in instructions {
+        if let InstructionKind::ConstString(s) = &instr.kind {
+            addresses.entry(s.clone()).or_insert_with(|| {
+                let addr = data.len() as u32;
+                data.extend_from_slice(s.as_bytes());
+                addr
+            });
+        }
+    }
+    (addresses, data)
+}
+
+/// The first block reachable from both `a` and `b`, nearest to `b`
+fn join_block(func: &Function, a: BlockId, b: BlockId) -> Option<BlockId> {
+    let from_a = reachable(func, a);
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([b]);
+    while let Some(id) = queue.pop_front() {
+        if from_a.contains(&id) {
+            return Some(id);
+        }
+        if seen.insert(id) {
+            queue.extend(successors(func, id));
+        }
+    }
+    None
+}
+
+/// Every block reachable from `start`, including itself
+fn reachable(func: &Function, start: BlockId) -> HashSet<BlockId> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![start];
+    while let Some(id) = stack.pop() {
+        if seen.insert(id) {
+            stack.extend(successors(func, id));
         }
     }
+    seen
+}
+
+fn successors(func: &Function, id: BlockId) -> Vec<BlockId> {
+    func.blocks
+        .iter()
+        .find(|b| b.id == id)
+        .map(|b| b.terminator.successors())
+        .unwrap_or_default()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ir::lower;
+    use crate::leb128;
     use crate::lexer::lex;
     use crate::parser::parse;
-    use crate::types::check;
+    use crate::types::{check, check_with_options, CheckOptions, DefaultInt};
 
     #[test]
     fn test_generate_simple() {
-        let source = "fn main() { return 42; }";
+        let source = "fn main() -> i64 { return 42; }";
         let tokens = lex(source).unwrap();
         let ast = parse(source, tokens).unwrap();
         let typed = check(&ast).unwrap();
         let ir = lower(&typed);
-        let wasm = generate(&ir);
+        let wasm = generate(&ir).unwrap();
 
         // Check WASM magic number
         assert_eq!(&wasm[0..4], &[0x00, 0x61, 0x73, 0x6D]);
         // Check version
         assert_eq!(&wasm[4..8], &[0x01, 0x00, 0x00, 0x00]);
     }
+
+    const I64_BOUNDARIES: &[i64] = &[
+        i64::MIN,
+        i64::MIN + 1,
+        i64::MAX,
+        i64::MAX - 1,
+        i32::MIN as i64,
+        i32::MAX as i64,
+        -1,
+        0,
+        1,
+        -2,
+        -63,
+        -64,
+        -65,
+        63,
+        64,
+        -128,
+        127,
+        128,
+        -8192,
+        8191,
+        8192,
+    ];
+
+    #[test]
+    fn test_i64_leb128_round_trips() {
+        for &value in I64_BOUNDARIES {
+            let mut generator = WasmGenerator::new();
+            generator.emit_i64(value);
+            let (decoded, len) = leb128::decode_i64(&generator.output).unwrap();
+            assert_eq!(decoded, value, "{:#x}", value);
+            assert_eq!(len, generator.output.len(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_i32_leb128_round_trips() {
+        let values = I64_BOUNDARIES
+            .iter()
+            .filter_map(|&v| i32::try_from(v).ok())
+            .chain([i32::MIN + 1, i32::MAX - 1]);
+        for value in values {
+            let mut generator = WasmGenerator::new();
+            generator.emit_i32(value);
+            let (decoded, len) = leb128::decode_i32(&generator.output).unwrap();
+            assert_eq!(decoded, value, "{:#x}", value);
+            assert_eq!(len, generator.output.len(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_u32_leb128_round_trips() {
+        for value in [0, 1, 127, 128, 16_383, 16_384, u32::MAX - 1, u32::MAX] {
+            let mut generator = WasmGenerator::new();
+            generator.emit_u32(value);
+            let (decoded, len) = leb128::decode_u32(&generator.output).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(len, generator.output.len(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_leb128_most_negative_encodings() {
+        let mut generator = WasmGenerator::new();
+        generator.emit_i64(i64::MIN);
+        let mut expected = vec![0x80; 9];
+        expected.push(0x7F);
+        assert_eq!(generator.output, expected);
+
+        let mut generator = WasmGenerator::new();
+        generator.emit_i32(i32::MIN);
+        assert_eq!(generator.output, vec![0x80, 0x80, 0x80, 0x80, 0x78]);
+    }
+
+    fn lower_source(source: &str) -> Module {
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check(&ast).unwrap();
+        lower(&typed)
+    }
+
+    #[test]
+    fn test_function_limit_exceeded() {
+        let ir = lower_source("fn a() { } fn b() { }");
+        let limits = Limits {
+            max_functions: 1,
+            ..Limits::default()
+        };
+        match generate_with_limits(&ir, &limits) {
+            Err(NovaError::ModuleLimitExceeded { count, max, .. }) => {
+                assert_eq!(count, 2);
+                assert_eq!(max, 1);
+            }
+            other => panic!("Expected ModuleLimitExceeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_local_limit_exceeded() {
+        let ir = lower_source("fn f(a: i64, b: i64, c: i64) { }");
+        let limits = Limits {
+            max_locals: 2,
+            ..Limits::default()
+        };
+        let err = generate_with_limits(&ir, &limits).unwrap_err();
+        assert_eq!(err.code(), "E0300");
+    }
+
+    #[test]
+    fn test_body_size_limit_exceeded() {
+        let ir = lower_source("fn main() -> i64 { return 42; }");
+        let limits = Limits {
+            max_body_size: 1,
+            ..Limits::default()
+        };
+        assert!(generate_with_limits(&ir, &limits).is_err());
+    }
+
+    #[test]
+    fn test_default_limits_accept_small_module() {
+        let ir = lower_source("fn a() { } fn b(x: i64) -> i64 { x }");
+        assert!(generate(&ir).is_ok());
+    }
+
+    /// Compile `source` with the given default integer type
+    fn compile_with_default_int(source: &str, default_int: DefaultInt) -> Vec<u8> {
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check_with_options(&ast, &CheckOptions { default_int }).unwrap();
+        generate(&lower(&typed)).unwrap()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn test_default_int_selects_opcode_width() {
+        // Nothing gives the literals a type, so they take the default one
+        let source = "fn f() { let x = 40 + 2; }";
+
+        let wide = compile_with_default_int(source, DefaultInt::I64);
+        // i64.const 40, i64.const 2, each set to a local, read back for i64.add
+        let add = [0x42, 40, 0x21, 0, 0x42, 2, 0x21, 1, 0x20, 0, 0x20, 1, 0x7C];
+        assert!(contains(&wide, &add));
+
+        let narrow = compile_with_default_int(source, DefaultInt::I32);
+        let add = [0x41, 40, 0x21, 0, 0x41, 2, 0x21, 1, 0x20, 0, 0x20, 1, 0x6A];
+        assert!(contains(&narrow, &add)); // i32.const, i32.add
+    }
+
+    #[test]
+    fn test_declared_width_overrides_default_int() {
+        let source = "fn f() -> i64 { let x: i64 = 40 + 2; x }";
+        let wasm = compile_with_default_int(source, DefaultInt::I32);
+        let add = [0x42, 40, 0x21, 0, 0x42, 2, 0x21, 1, 0x20, 0, 0x20, 1, 0x7C];
+        assert!(contains(&wasm, &add)); // i64.const, i64.add
+        assert!(contains(&wasm, &[0x60, 0x00, 0x01, 0x7E])); // () -> i64
+
+        let source = "fn f(n: i32) -> i32 { n * 2 }";
+        let wasm = compile_with_default_int(source, DefaultInt::I64);
+        // local.get 0, i32.const 2 through local 1, i32.mul
+        let mul = [0x41, 2, 0x21, 1, 0x20, 0, 0x20, 1, 0x6C];
+        assert!(contains(&wasm, &mul));
+        assert!(contains(&wasm, &[0x60, 0x01, 0x7F, 0x01, 0x7F])); // (i32) -> i32
+    }
+
+    #[test]
+    fn test_global_initializer_has_the_global_type() {
+        let wasm = compile_with_default_int("static X: i32 = 5;", DefaultInt::I64);
+        // Global section: one i32 const global, i32.const 5, end
+        assert!(contains(
+            &wasm,
+            &[0x06, 0x06, 0x01, 0x7F, 0x00, 0x41, 0x05, 0x0B]
+        ));
+    }
+
+    #[test]
+    fn test_if_else_is_a_wasm_if() {
+        let source = "fn f(c: bool) -> i64 { if c { 1 } else { 2 } }";
+        let wasm = compile_with_default_int(source, DefaultInt::I64);
+        // Both arms and the phi joining them share local 1
+        assert!(contains(
+            &wasm,
+            &[
+                0x20, 0x00, // local.get 0
+                0x04, 0x40, // if
+                0x42, 0x01, 0x21, 0x01, // i64.const 1, local.set 1
+                0x05, // else
+                0x42, 0x02, 0x21, 0x01, // i64.const 2, local.set 1
+                0x0B, // end
+                0x20, 0x01, // local.get 1
+                0x0B, // end of function
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_if_without_else_has_no_result() {
+        let source = "static mut N: i64 = 0; fn f(c: bool) { if c { N = 1; } }";
+        let wasm = compile_with_default_int(source, DefaultInt::I64);
+        // local.get 0, if, i64.const 1 through local 1, global.set 0, end
+        let body = [
+            0x20, 0, 0x04, 0x40, 0x42, 1, 0x21, 1, 0x20, 1, 0x24, 0, 0x0B,
+        ];
+        assert!(contains(&wasm, &body));
+    }
+
+    #[test]
+    fn test_enum_tag_width_follows_default_int() {
+        let source = "enum O { S(f64), N } fn f(o: O) -> i64 { match o { S(_) => 1, N => 0, } }";
+        // The tag is loaded with the integer width, 8-byte aligned for i64
+        let wide = compile_with_default_int(source, DefaultInt::I64);
+        assert!(contains(&wide, &[0x20, 0, 0x29, 0x03, 0x00])); // local.get 0, i64.load
+        let narrow = compile_with_default_int(source, DefaultInt::I32);
+        assert!(contains(&narrow, &[0x20, 0, 0x28, 0x02, 0x00])); // local.get 0, i32.load
+    }
+
+    #[test]
+    fn test_casts_convert_between_wasm_types() {
+        let cases: [(&str, &[u8]); 6] = [
+            ("fn f(x: i64) -> f64 { x as f64 }", &[0xB9]), // f64.convert_i64_s
+            ("fn f(x: u32) -> f64 { x as f64 }", &[0xB8]), // f64.convert_i32_u
+            ("fn f(x: i64) -> i32 { x as i32 }", &[0xA7]), // i32.wrap_i64
+            ("fn f(x: i32) -> i64 { x as i64 }", &[0xAC]), // i64.extend_i32_s
+            ("fn f(x: f64) -> i64 { x as i64 }", &[0xFC, 0x06]), // i64.trunc_sat_f64_s
+            ("fn f(x: f64) -> u32 { x as u32 }", &[0xFC, 0x03]), // i32.trunc_sat_f64_u
+        ];
+        for (source, op) in cases {
+            let wasm = compile_with_default_int(source, DefaultInt::I64);
+            assert!(contains(&wasm, &[&[0x20, 0x00], op].concat()), "{source}");
+        }
+        // The same WASM type needs no conversion
+        let wasm = compile_with_default_int("fn f(x: i64) -> u64 { x as u64 }", DefaultInt::I64);
+        assert!(!contains(&wasm, &[0xA7]) && !contains(&wasm, &[0xAC]));
+    }
+
+    #[test]
+    fn test_static_mut_is_a_mutable_global() {
+        let source = "static mut COUNTER: i32 = 0;
+            fn get() -> i32 { COUNTER }
+            fn bump() { COUNTER = COUNTER + 1; }";
+        let wasm = compile_with_default_int(source, DefaultInt::I32);
+        // One mutable i32 global initialized by `i32.const 0; end`
+        assert!(contains(
+            &wasm,
+            &[0x06, 0x06, 0x01, 0x7F, 0x01, 0x41, 0x00, 0x0B]
+        ));
+        // global.get 0 through a local, end
+        assert!(contains(&wasm, &[0x23, 0x00, 0x21, 0x00, 0x20, 0x00, 0x0B]));
+        // `COUNTER + 1`, then global.set 0
+        let add = [0x23, 0, 0x21, 0, 0x41, 1, 0x21, 1, 0x20, 0, 0x20, 1, 0x6A];
+        assert!(contains(&wasm, &add));
+        assert!(contains(&wasm, &[0x21, 0x00, 0x20, 0x00, 0x24, 0x00, 0x0B]));
+    }
+
+    #[test]
+    fn test_memory_intrinsics() {
+        let source = "fn grow() -> i32 { memory_grow(1) } fn size() -> i32 { memory_size() }";
+        let wasm = compile_with_default_int(source, DefaultInt::I32);
+        // One memory of 1 page
+        assert!(contains(&wasm, &[0x05, 0x03, 0x01, 0x00, 0x01]));
+        // i32.const 1 through a local, memory.grow
+        assert!(contains(
+            &wasm,
+            &[0x41, 0x01, 0x21, 0x00, 0x20, 0x00, 0x40, 0x00]
+        ));
+        assert!(contains(&wasm, &[0x3F, 0x00, 0x21, 0x00])); // memory.size
+
+        // Pages are i32 whatever the default integer is, so nothing converts
+        let wide = compile_with_default_int(source, DefaultInt::I64);
+        assert_eq!(wide, wasm);
+    }
+
+    #[test]
+    fn test_let_bindings_live_in_locals() {
+        let source = "fn f() -> i64 { let x = 5; let y = x; return y; }";
+        let wasm = compile_with_default_int(source, DefaultInt::I64);
+        assert!(contains(
+            &wasm,
+            &[
+                0x01, 0x01, 0x7E, // one i64 local
+                0x42, 0x05, 0x21, 0x00, // i64.const 5, local.set 0
+                0x20, 0x00, // local.get 0
+                0x0B,
+            ]
+        ));
+
+        // Parameters come first, so the local after them is 2
+        let source = "fn f(a: i64, b: i64) -> i64 { let x = a + b; x * a }";
+        let wasm = compile_with_default_int(source, DefaultInt::I64);
+        let body = [
+            0x20, 0, 0x20, 1, 0x7C, 0x21, 2, 0x20, 2, 0x20, 0, 0x7E, 0x21, 2,
+        ];
+        assert!(contains(&wasm, &body));
+    }
+
+    #[test]
+    fn test_tuple_return_is_multi_value() {
+        let source = "fn swap(a: i32, b: i32) -> (i32, i32) { (b, a) }";
+        let wasm = compile_with_default_int(source, DefaultInt::I64);
+        assert!(contains(&wasm, &[0x60, 0x02, 0x7F, 0x7F, 0x02, 0x7F, 0x7F])); // (i32, i32) -> (i32, i32)
+        assert!(contains(
+            &wasm,
+            &[
+                0x02, 0x7F, // two i32 locals for the tuple
+                0x20, 0x01, 0x20, 0x00, // local.get 1, local.get 0
+                0x21, 0x03, 0x21, 0x02, // local.set 3, local.set 2
+                0x20, 0x02, 0x20, 0x03, // local.get 2, local.get 3
+                0x0B,
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_neg_subtracts_from_zero() {
+        let wasm = compile_with_default_int("fn f() -> i64 { -5 }", DefaultInt::I64);
+        assert!(contains(
+            &wasm,
+            &[
+                0x42, 0x05, 0x21, 0x00, // i64.const 5, local.set 0
+                0x42, 0x00, 0x20, 0x00, 0x7D, // i64.const 0, local.get 0, i64.sub
+            ]
+        ));
+        let wasm = compile_with_default_int("fn f(x: i32) -> i32 { -x }", DefaultInt::I32);
+        assert!(contains(&wasm, &[0x41, 0x00, 0x20, 0x00, 0x6B])); // 0 - x
+                                                                   // A float is negated with `f64.neg`, with no zero
+        let wasm = compile_with_default_int("fn f(x: f64) -> f64 { -x }", DefaultInt::I64);
+        assert!(contains(&wasm, &[0x20, 0x00, 0x9A]));
+        assert!(!contains(&wasm, &[0x44]));
+    }
+
+    #[test]
+    fn test_calls_resolve_to_function_indices() {
+        let source = "fn a() -> i64 { 1 } fn b() -> i64 { 2 } fn c() -> i64 { b() + a() }";
+        let wasm = generate(&lower_source(source)).unwrap();
+        // `b` is function 1, `a` is function 0
+        assert!(
+            contains(&wasm, &[0x10, 0x01]),
+            "expected call 1 in {:02X?}",
+            wasm
+        );
+        assert!(
+            contains(&wasm, &[0x10, 0x00]),
+            "expected call 0 in {:02X?}",
+            wasm
+        );
+    }
+
+    #[test]
+    fn test_call_to_unknown_function_is_an_error() {
+        let source = "fn f() -> i64 { let g = |x: i64| x; g(1) }";
+        let err = generate(&lower_source(source)).unwrap_err();
+        assert!(
+            matches!(&err, NovaError::UndefinedFunction { name, .. } if name == "g"),
+            "unexpected error: {:?}",
+            err
+        );
+    }
+
+    /// The contents of the section with `id`, walking the section headers
+    fn section(wasm: &[u8], id: u8) -> Option<&[u8]> {
+        let mut pos = 8;
+        while pos < wasm.len() {
+            let section_id = wasm[pos];
+            let (mut size, mut shift) = (0usize, 0);
+            pos += 1;
+            loop {
+                let byte = wasm[pos];
+                pos += 1;
+                size |= ((byte & 0x7F) as usize) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            if section_id == id {
+                return Some(&wasm[pos..pos + size]);
+            }
+            pos += size;
+        }
+        None
+    }
+
+    #[test]
+    fn test_strings_are_laid_out_in_the_data_section() {
+        let source =
+            r#"fn a() -> str { "hello" } fn b() -> str { "world!" } fn c() -> str { "hello" }"#;
+        let wasm = generate(&lower_source(source)).unwrap();
+        let data = section(&wasm, 11).expect("no data section");
+        // One active segment at address 0, holding each distinct literal once
+        assert_eq!(&data[..5], &[0x01, 0x00, 0x41, 0x00, 0x0B]);
+        assert_eq!(&data[5..], b"\x0Bhelloworld!");
+        // Each literal pushes its address and length
+        assert!(contains(&wasm, &[0x41, 0x00, 0x41, 0x05]), "hello at 0");
+        assert!(contains(&wasm, &[0x41, 0x05, 0x41, 0x06]), "world! at 5");
+    }
+
+    #[test]
+    fn test_allocas_bump_a_stack_pointer_past_the_strings() {
+        let source = r#"fn f() -> str { let xs = [1, 2, 3]; "hello" }"#;
+        let wasm = generate(&lower_source(source)).unwrap();
+        // A mutable i32 global starting at the first aligned address
+        // after the five bytes of "hello"
+        let globals = section(&wasm, 6).expect("no global section");
+        assert_eq!(globals, &[0x01, 0x7F, 0x01, 0x41, 0x08, 0x0B]);
+        // Each alloca takes the pointer then moves it past its size
+        let bump = [0x23, 0x00, 0x23, 0x00, 0x41, 24, 0x6A, 0x24, 0x00];
+        assert!(contains(&wasm, &bump), "global.get, add 24, global.set");
+    }
+
+    #[test]
+    fn test_no_strings_no_data_section() {
+        let wasm = generate(&lower_source("fn f() -> i64 { 1 }")).unwrap();
+        assert!(section(&wasm, 11).is_none());
+    }
+
+    #[test]
+    fn test_while_is_a_block_and_loop() {
+        let source = "fn f() -> i64 { let mut i = 0; while i < 10 { i = i + 1; } i }";
+        let wasm = generate(&lower_source(source)).unwrap();
+        assert!(contains(&wasm, &[0x02, 0x40, 0x03, 0x40]), "block, loop");
+        // Leaving when the condition is false, going round at the end
+        assert!(contains(&wasm, &[0x45, 0x0D, 0x01]), "i32.eqz, br_if 1");
+        assert!(contains(&wasm, &[0x0C, 0x00, 0x0B, 0x0B]), "br 0, end, end");
+    }
+
+    #[test]
+    fn test_break_and_continue_are_brs() {
+        let source = "fn f() -> i64 {
+            let mut i = 0;
+            while true { i = i + 1; if i < 3 { continue; } if i == 5 { break; } }
+            i
+        }";
+        let wasm = generate(&lower_source(source)).unwrap();
+        // Inside an `if`, the loop is one label out and the block two
+        assert!(contains(&wasm, &[0x04, 0x40, 0x0C, 0x01]), "continue");
+        assert!(
+            contains(&wasm, &[0x0C, 0x02]) || contains(&wasm, &[0x0D, 0x02]),
+            "break in {:02X?}",
+            wasm
+        );
+    }
+
+    #[test]
+    fn test_default_int_is_i64() {
+        let source = "fn f() -> i64 { 40 + 2 }";
+        assert_eq!(
+            compile_with_default_int(source, DefaultInt::default()),
+            generate(&lower_source(source)).unwrap()
+        );
+    }
 }
@@ -4,8 +4,38 @@
 
 #![allow(dead_code)]
 
+use crate::source_map::SourceMap;
 use crate::token::{Span, TokenKind};
-use ariadne::{Color, Label, Report, ReportKind, Source};
+use ariadne::{Color, Config, Label, Report, ReportKind, Source};
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether diagnostics are printed with ANSI colors (see `set_color`)
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables colored diagnostics for the rest of the process.
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+/// Decides whether to color diagnostics.
+///
+/// Color is off when `--no-color` was given, when `NO_COLOR` is set to a
+/// non-empty value (see <https://no-color.org>), or when stderr is not a
+/// terminal, e.g. when piped to a file or a CI log.
+pub fn should_color(no_color_flag: bool) -> bool {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color_flag && !no_color_env && io::stderr().is_terminal()
+}
+
+/// How serious a diagnostic is, most serious first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Compilation fails
+    Error,
+    /// Compilation continues, but the code is suspicious
+    Warning,
+}
 
 /// All possible Nova errors
 #[derive(Debug, Clone)]
@@ -29,6 +59,9 @@ pub enum NovaError {
         kind: &'static str,
         span: Span,
     },
+    InvalidDigitSeparator {
+        span: Span,
+    },
 
     // Security errors (resource limits)
     SourceTooLarge {
@@ -70,6 +103,123 @@ pub enum NovaError {
         name: String,
         span: Span,
     },
+    WrongPatternArity {
+        constructor: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    TryInNonResultFn {
+        return_type: String,
+        span: Span,
+    },
+    InfinitelyRecursiveType {
+        name: String,
+        span: Span,
+    },
+    SelfOutsideImpl {
+        span: Span,
+    },
+    NotCallable {
+        ty: String,
+        span: Span,
+    },
+    BreakValueInWhile {
+        span: Span,
+    },
+    MutabilityMismatch {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    InferInSignature {
+        span: Span,
+    },
+    NonConstDiscriminant {
+        span: Span,
+    },
+    NegativeDiscriminant {
+        value: i64,
+        span: Span,
+    },
+    DuplicateDiscriminant {
+        value: i64,
+        first: String,
+        span: Span,
+    },
+    NonConstStatic {
+        span: Span,
+    },
+    AssignToImmutable {
+        name: String,
+        span: Span,
+    },
+    MissingTraitMethod {
+        trait_name: String,
+        method: String,
+        span: Span,
+    },
+    TraitMethodSignatureMismatch {
+        method: String,
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    InvalidCast {
+        from: String,
+        to: String,
+        span: Span,
+    },
+    OverlappingPatterns {
+        first: Span,
+        second: Span,
+    },
+    InfiniteType {
+        ty: String,
+        span: Span,
+    },
+    WrongArgCount {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    UnknownRepr {
+        name: String,
+        span: Span,
+    },
+    ForPatternNotName {
+        span: Span,
+    },
+    IntLiteralOutOfRange {
+        value: i128,
+        ty: String,
+        span: Span,
+    },
+    FormatInterpolation {
+        span: Span,
+    },
+    UnsupportedExpression {
+        what: &'static str,
+        span: Span,
+    },
+    BorrowImmutableAsMut {
+        name: String,
+        span: Span,
+    },
+
+    // Module errors
+    ModuleNotFound {
+        name: String,
+        path: String,
+        span: Span,
+    },
+
+    // Codegen errors
+    ModuleLimitExceeded {
+        what: &'static str,
+        count: usize,
+        max: usize,
+    },
 
     // General
     Custom {
@@ -87,6 +237,7 @@ impl NovaError {
             NovaError::InvalidEscape { span, .. } => *span,
             NovaError::InvalidNumber { span } => *span,
             NovaError::InvalidLiteral { span, .. } => *span,
+            NovaError::InvalidDigitSeparator { span } => *span,
             NovaError::SourceTooLarge { .. } => Span::new(0, 0),
             NovaError::NestingTooDeep { span, .. } => *span,
             NovaError::UnexpectedToken { span, .. } => *span,
@@ -95,6 +246,33 @@ impl NovaError {
             NovaError::UndefinedVariable { span, .. } => *span,
             NovaError::UndefinedType { span, .. } => *span,
             NovaError::UndefinedFunction { span, .. } => *span,
+            NovaError::WrongPatternArity { span, .. } => *span,
+            NovaError::TryInNonResultFn { span, .. } => *span,
+            NovaError::InfinitelyRecursiveType { span, .. } => *span,
+            NovaError::SelfOutsideImpl { span } => *span,
+            NovaError::NotCallable { span, .. } => *span,
+            NovaError::BreakValueInWhile { span } => *span,
+            NovaError::MutabilityMismatch { span, .. } => *span,
+            NovaError::InferInSignature { span } => *span,
+            NovaError::NonConstDiscriminant { span } => *span,
+            NovaError::NegativeDiscriminant { span, .. } => *span,
+            NovaError::DuplicateDiscriminant { span, .. } => *span,
+            NovaError::NonConstStatic { span } => *span,
+            NovaError::AssignToImmutable { span, .. } => *span,
+            NovaError::MissingTraitMethod { span, .. } => *span,
+            NovaError::TraitMethodSignatureMismatch { span, .. } => *span,
+            NovaError::InvalidCast { span, .. } => *span,
+            NovaError::OverlappingPatterns { second, .. } => *second,
+            NovaError::InfiniteType { span, .. } => *span,
+            NovaError::WrongArgCount { span, .. } => *span,
+            NovaError::UnknownRepr { span, .. } => *span,
+            NovaError::ForPatternNotName { span } => *span,
+            NovaError::IntLiteralOutOfRange { span, .. } => *span,
+            NovaError::FormatInterpolation { span } => *span,
+            NovaError::UnsupportedExpression { span, .. } => *span,
+            NovaError::BorrowImmutableAsMut { span, .. } => *span,
+            NovaError::ModuleNotFound { span, .. } => *span,
+            NovaError::ModuleLimitExceeded { .. } => Span::new(0, 0),
             NovaError::Custom { span, .. } => *span,
         }
     }
@@ -113,6 +291,9 @@ impl NovaError {
             NovaError::InvalidLiteral { kind, .. } => {
                 format!("Invalid {} literal", kind)
             }
+            NovaError::InvalidDigitSeparator { .. } => {
+                "Digit separator `_` must be placed between two digits".to_string()
+            }
             NovaError::SourceTooLarge { size, max } => {
                 format!("Source file too large: {} bytes (max: {} bytes)", size, max)
             }
@@ -141,10 +322,141 @@ impl NovaError {
             NovaError::UndefinedFunction { name, .. } => {
                 format!("Undefined function: {}", name)
             }
+            NovaError::WrongPatternArity {
+                constructor,
+                expected,
+                found,
+                ..
+            } => {
+                format!(
+                    "Pattern `{}` has {} fields, but the constructor has {}",
+                    constructor, found, expected
+                )
+            }
+            NovaError::TryInNonResultFn { return_type, .. } => {
+                format!(
+                    "The `?` operator can only be used in a function returning `Result` or `Option`, not {}",
+                    return_type
+                )
+            }
+            NovaError::InfinitelyRecursiveType { name, .. } => {
+                format!(
+                    "Recursive type `{}` has infinite size; insert indirection such as `&{}`",
+                    name, name
+                )
+            }
+            NovaError::SelfOutsideImpl { .. } => {
+                "`Self` is only available inside an `impl` or `trait`".to_string()
+            }
+            NovaError::NotCallable { ty, .. } => {
+                format!("Cannot call a value of type {}; expected a function", ty)
+            }
+            NovaError::BreakValueInWhile { .. } => {
+                "`break` with a value is only allowed in `loop`, not in `while` or `for`"
+                    .to_string()
+            }
+            NovaError::MutabilityMismatch {
+                expected, found, ..
+            } => {
+                format!(
+                    "Expected a mutable reference {}, found a shared reference {}",
+                    expected, found
+                )
+            }
+            NovaError::InferInSignature { .. } => {
+                "The placeholder `_` is not allowed in function signatures; write the type out"
+                    .to_string()
+            }
+            NovaError::NonConstDiscriminant { .. } => {
+                "Enum discriminant must be a constant integer expression".to_string()
+            }
+            NovaError::NegativeDiscriminant { value, .. } => {
+                format!("Enum discriminant {} is negative", value)
+            }
+            NovaError::DuplicateDiscriminant { value, first, .. } => {
+                format!(
+                    "Enum discriminant {} is already used by variant `{}`",
+                    value, first
+                )
+            }
+            NovaError::NonConstStatic { .. } => {
+                "Static initializer must be a constant expression".to_string()
+            }
+            NovaError::AssignToImmutable { name, .. } => {
+                format!("Cannot assign to `{}`, which is not mutable", name)
+            }
+            NovaError::MissingTraitMethod {
+                trait_name, method, ..
+            } => {
+                format!(
+                    "Missing method `{}` required by trait `{}`",
+                    method, trait_name
+                )
+            }
+            NovaError::TraitMethodSignatureMismatch {
+                method,
+                expected,
+                found,
+                ..
+            } => {
+                format!(
+                    "Method `{}` doesn't match its trait: expected {}, found {}",
+                    method, expected, found
+                )
+            }
+            NovaError::InvalidCast { from, to, .. } => {
+                format!("Cannot cast {} as {}", from, to)
+            }
+            NovaError::OverlappingPatterns { .. } => {
+                "Range pattern overlaps the range of an earlier arm".to_string()
+            }
+            NovaError::InfiniteType { ty, .. } => {
+                format!("Type would have to contain itself: {}", ty)
+            }
+            NovaError::WrongArgCount {
+                expected, found, ..
+            } => {
+                let plural = if *expected == 1 { "" } else { "s" };
+                let were = if *found == 1 { "was" } else { "were" };
+                format!(
+                    "Function takes {} argument{}, but {} {} given",
+                    expected, plural, found, were
+                )
+            }
+            NovaError::UnknownRepr { name, .. } => {
+                format!("Unknown repr `{}`: expected `C` or `packed`", name)
+            }
+            NovaError::ForPatternNotName { .. } => {
+                "`for` can only bind a single name for now, not a pattern".to_string()
+            }
+            NovaError::IntLiteralOutOfRange { value, ty, .. } => {
+                format!("Integer literal {} doesn't fit in {}", value, ty)
+            }
+            NovaError::FormatInterpolation { .. } => {
+                "Format strings can't interpolate values yet".to_string()
+            }
+            NovaError::UnsupportedExpression { what, .. } => {
+                format!("{} aren't supported yet", what)
+            }
+            NovaError::BorrowImmutableAsMut { name, .. } => {
+                format!("Cannot borrow `{}` as mutable, as it is not mutable", name)
+            }
+            NovaError::ModuleNotFound { name, path, .. } => {
+                format!("Module `{}` not found: no file at {}", name, path)
+            }
+            NovaError::ModuleLimitExceeded { what, count, max } => {
+                format!("WASM limit exceeded: {} {} (max: {})", count, what, max)
+            }
             NovaError::Custom { message, .. } => message.clone(),
         }
     }
 
+    /// How serious the diagnostic is. Every one is an error for now; the
+    /// severity is there for warnings to slot in beside them.
+    pub fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
     /// Get the error code
     pub fn code(&self) -> &'static str {
         match self {
@@ -153,6 +465,7 @@ impl NovaError {
             NovaError::InvalidEscape { .. } => "E0003",
             NovaError::InvalidNumber { .. } => "E0004",
             NovaError::InvalidLiteral { .. } => "E0005",
+            NovaError::InvalidDigitSeparator { .. } => "E0006",
             NovaError::SourceTooLarge { .. } => "E0010",
             NovaError::NestingTooDeep { .. } => "E0011",
             NovaError::UnexpectedToken { .. } => "E0100",
@@ -161,11 +474,624 @@ impl NovaError {
             NovaError::UndefinedVariable { .. } => "E0201",
             NovaError::UndefinedType { .. } => "E0202",
             NovaError::UndefinedFunction { .. } => "E0203",
+            NovaError::WrongPatternArity { .. } => "E0204",
+            NovaError::TryInNonResultFn { .. } => "E0205",
+            NovaError::InfinitelyRecursiveType { .. } => "E0206",
+            NovaError::SelfOutsideImpl { .. } => "E0207",
+            NovaError::NotCallable { .. } => "E0208",
+            NovaError::BreakValueInWhile { .. } => "E0209",
+            NovaError::MutabilityMismatch { .. } => "E0210",
+            NovaError::InferInSignature { .. } => "E0211",
+            NovaError::NonConstDiscriminant { .. } => "E0212",
+            NovaError::NegativeDiscriminant { .. } => "E0213",
+            NovaError::DuplicateDiscriminant { .. } => "E0214",
+            NovaError::MissingTraitMethod { .. } => "E0215",
+            NovaError::TraitMethodSignatureMismatch { .. } => "E0216",
+            NovaError::NonConstStatic { .. } => "E0217",
+            NovaError::AssignToImmutable { .. } => "E0218",
+            NovaError::InvalidCast { .. } => "E0219",
+            NovaError::OverlappingPatterns { .. } => "E0220",
+            NovaError::InfiniteType { .. } => "E0221",
+            NovaError::WrongArgCount { .. } => "E0222",
+            NovaError::UnknownRepr { .. } => "E0223",
+            NovaError::ForPatternNotName { .. } => "E0224",
+            NovaError::IntLiteralOutOfRange { .. } => "E0225",
+            NovaError::FormatInterpolation { .. } => "E0226",
+            NovaError::BorrowImmutableAsMut { .. } => "E0227",
+            NovaError::UnsupportedExpression { .. } => "E0228",
+            NovaError::ModuleNotFound { .. } => "E0400",
+            NovaError::ModuleLimitExceeded { .. } => "E0300",
             NovaError::Custom { .. } => "E9999",
         }
     }
 }
 
+/// The long-form explanation of an error code, for `nova explain`
+///
+/// Each says when the error happens, with an example, and how to fix it.
+pub fn explain(code: &str) -> Option<&'static str> {
+    let text = match code {
+        "E0001" => {
+            "A character that can't start any token appeared outside a string or comment.
+
+Erroneous example:
+
+    let name = `nova`;
+
+Nova has no backtick quotes. Remove the character, or use a string:
+
+    let name = \"nova\";"
+        }
+        "E0002" => {
+            "A string or character literal wasn't closed before the end of the file.
+
+Erroneous example:
+
+    let greeting = \"hello;
+
+Add the closing quote:
+
+    let greeting = \"hello\";
+
+A raw string `r#\"...\"#` ends only at a quote followed by as many `#` as
+opened it, so check that the counts match."
+        }
+        "E0003" => {
+            "A backslash in a string or character literal isn't followed by a known
+escape. Escapes aren't validated yet, so this isn't reported today.
+
+Example:
+
+    let path = \"C:\\data\";
+
+Escape the backslash itself, or use a raw string, where nothing is escaped:
+
+    let path = \"C:\\\\data\";
+    let path = r\"C:\\data\";"
+        }
+        "E0004" => {
+            "A number literal couldn't be read as a number. Malformed numbers are
+reported as E0005, an invalid literal, today.
+
+Example:
+
+    let mask = 0x;
+
+Give a number prefixed by `0x`, `0o` or `0b` at least one digit:
+
+    let mask = 0xFF;"
+        }
+        "E0005" => {
+            "A literal is malformed.
+
+Erroneous example:
+
+    let mask = 0x;
+    let text = f\"total }\";
+
+Give a prefixed number its digits, and write a literal brace in a format
+string twice:
+
+    let mask = 0xFF;
+    let text = f\"total }}\";"
+        }
+        "E0006" => {
+            "A digit separator `_` in a number literal isn't between two digits.
+
+Erroneous example:
+
+    let million = 1_000_000_;
+    let mask = 0x_FF;
+
+Put each `_` between digits, or remove it:
+
+    let million = 1_000_000;
+    let mask = 0xFF;"
+        }
+        "E0010" => {
+            "The source file is larger than a span can address (4 GiB).
+
+Split the program into modules, each in a file of its own:
+
+    mod parser;
+    mod codegen;"
+        }
+        "E0011" => {
+            "Block comments or expressions are nested deeper than the compiler allows,
+for example hundreds of `(` or `/*` inside each other.
+
+Flatten the nesting, for example by naming intermediate values with
+`let`. Deeply nested expressions can also be compiled with a larger
+`--stack-size`, which raises the limit."
+        }
+        "E0100" => {
+            "The parser found a token where it expected something else.
+
+Erroneous example:
+
+    fn add(a: i64, b: i64) -> i64 {
+        let sum = a + b
+        sum
+    }
+
+The `let` needs a `;` before the next statement:
+
+    fn add(a: i64, b: i64) -> i64 {
+        let sum = a + b;
+        sum
+    }
+
+The message says what was expected. Check the code just before the reported
+token, which is often where the mistake is."
+        }
+        "E0101" => {
+            "The file ended in the middle of an item or expression. The parser
+reports this as E0100 today, with `<eof>` as the token it found.
+
+Example:
+
+    fn main() {
+        let x = 1;
+
+Close every open `{`, `(` and `[`:
+
+    fn main() {
+        let x = 1;
+    }"
+        }
+        "E0200" => {
+            "A value's type isn't the type its use requires.
+
+Erroneous example:
+
+    fn main() {
+        let n: i64 = true;
+    }
+
+Use a value of the required type, or convert it with `as` where that is
+allowed:
+
+    fn main() {
+        let n: i64 = true as i64;
+    }"
+        }
+        "E0201" => {
+            "A name was used that isn't a variable, function or constant in scope.
+
+Erroneous example:
+
+    fn main() -> i64 {
+        count + 1
+    }
+
+Declare it first, and check the spelling. A `let` is only visible after it
+and inside the block it's in:
+
+    fn main() -> i64 {
+        let count = 1;
+        count + 1
+    }"
+        }
+        "E0202" => {
+            "A type was named that isn't declared.
+
+Erroneous example:
+
+    fn area(p: Point) -> i64 { 0 }
+
+Declare the type, or correct its name:
+
+    struct Point { x: i64, y: i64 }
+    fn area(p: Point) -> i64 { 0 }"
+        }
+        "E0203" => {
+            "A call names a function that doesn't exist.
+
+Erroneous example:
+
+    fn main() -> i64 {
+        double(2)
+    }
+
+Define the function, or correct its name:
+
+    fn double(x: i64) -> i64 { x * 2 }
+    fn main() -> i64 {
+        double(2)
+    }"
+        }
+        "E0204" => {
+            "A pattern has a different number of fields than the variant or struct it
+matches.
+
+Erroneous example:
+
+    enum Shape { Rect(i64, i64) }
+    fn area(s: Shape) -> i64 {
+        match s { Shape::Rect(w) => w }
+    }
+
+Give one sub-pattern per field, using `_` for fields you don't need:
+
+    enum Shape { Rect(i64, i64) }
+    fn area(s: Shape) -> i64 {
+        match s { Shape::Rect(w, _) => w }
+    }"
+        }
+        "E0205" => {
+            "The `?` operator was used in a function that doesn't return `Result` or
+`Option`, so there is nothing to return the error in.
+
+Erroneous example:
+
+    fn total(x: Option<i64>) -> i64 {
+        x? + 1
+    }
+
+Return an `Option` (or `Result`) too, or handle each case with `match`:
+
+    fn total(x: Option<i64>) -> i64 {
+        match x { Some(v) => v + 1, None => 0 }
+    }"
+        }
+        "E0206" => {
+            "A type contains itself directly, so it would be infinitely large.
+
+Erroneous example:
+
+    struct Node { value: i64, next: Node }
+
+Put the recursive field behind a reference, which has a fixed size:
+
+    struct Node { value: i64, next: &Node }"
+        }
+        "E0207" => {
+            "`Self` was used outside an `impl` or `trait`, where it has no meaning.
+
+Erroneous example:
+
+    fn origin() -> Self { 0 }
+
+Name the type, or move the function into its `impl`:
+
+    struct Point { x: i64 }
+    impl Point {
+        fn origin() -> Self { Point { x: 0 } }
+    }"
+        }
+        "E0208" => {
+            "A value that isn't a function or closure was called.
+
+Erroneous example:
+
+    fn main() -> i64 {
+        let x = 5;
+        x(1)
+    }
+
+Only call functions and closures. To multiply, write the operator:
+
+    fn main() -> i64 {
+        let x = 5;
+        x * 1
+    }"
+        }
+        "E0209" => {
+            "`break` was given a value inside a `while` or `for`. Those loops end when
+their condition does, so they have no value to give.
+
+Erroneous example:
+
+    while i < 10 {
+        break i;
+    }
+
+Use `loop`, whose value is what `break` carries, or assign to a variable:
+
+    let found = loop {
+        if i >= 10 { break i; }
+        i = i + 1;
+    };"
+        }
+        "E0210" => {
+            "A shared reference `&T` was used where a mutable reference `&mut T` is
+required. `&mut T` coerces to `&T`, but not the other way around.
+
+Erroneous example:
+
+    fn bump(x: &mut i64) { }
+    fn main() {
+        let mut n = 0;
+        bump(&n);
+    }
+
+Borrow mutably:
+
+    fn bump(x: &mut i64) { }
+    fn main() {
+        let mut n = 0;
+        bump(&mut n);
+    }"
+        }
+        "E0211" => {
+            "The placeholder type `_` appeared in a function signature. Signatures are
+written out in full, so calls can be checked without reading the body.
+
+Erroneous example:
+
+    fn double(x: i64) -> _ { x * 2 }
+
+Write the type:
+
+    fn double(x: i64) -> i64 { x * 2 }"
+        }
+        "E0212" => {
+            "An enum discriminant isn't a constant integer.
+
+Erroneous example:
+
+    enum Level { Low = 1.5, High = 2 }
+
+Use an integer literal or constant expression:
+
+    enum Level { Low = 1, High = 2 }"
+        }
+        "E0213" => {
+            "An enum discriminant is negative. Discriminants are stored as unsigned
+tags.
+
+Erroneous example:
+
+    enum Sign { Minus = -1, Plus = 1 }
+
+Use values from zero up:
+
+    enum Sign { Minus = 0, Plus = 1 }"
+        }
+        "E0214" => {
+            "Two variants of an enum have the same discriminant, so they can't be
+told apart.
+
+Erroneous example:
+
+    enum Op { Add = 1, Sub = 1 }
+
+Give each variant its own value:
+
+    enum Op { Add = 1, Sub = 2 }
+
+A variant without `= value` takes the value after the previous variant's."
+        }
+        "E0215" => {
+            "An `impl` of a trait doesn't define every method the trait requires.
+
+Erroneous example:
+
+    trait Shape { fn area(&self) -> i64; }
+    struct Square { side: i64 }
+    impl Shape for Square { }
+
+Define the missing method:
+
+    impl Shape for Square {
+        fn area(&self) -> i64 { self.side * self.side }
+    }"
+        }
+        "E0216" => {
+            "A method in a trait `impl` has a different signature from the trait's.
+
+Erroneous example:
+
+    trait Shape { fn area(&self) -> i64; }
+    impl Shape for Square {
+        fn area(&self) -> f64 { 1.0 }
+    }
+
+Match the trait's parameter and return types:
+
+    impl Shape for Square {
+        fn area(&self) -> i64 { 1 }
+    }"
+        }
+        "E0217" => {
+            "A `static` is initialized with something other than a constant. Statics
+are laid out before the program runs, so their value must be known then.
+
+Erroneous example:
+
+    fn start() -> i64 { 1 }
+    static COUNTER: i64 = start();
+
+Use a literal or constant expression, and set it at run time if needed:
+
+    static mut COUNTER: i64 = 1;"
+        }
+        "E0218" => {
+            "A variable that isn't declared `mut` was assigned to.
+
+Erroneous example:
+
+    fn main() {
+        let total = 0;
+        total = 5;
+    }
+
+Declare it mutable:
+
+    fn main() {
+        let mut total = 0;
+        total = 5;
+    }"
+        }
+        "E0219" => {
+            "An `as` cast was written between types it can't convert. Casts convert
+between numeric types, and from `bool` or `char` to an integer.
+
+Erroneous example:
+
+    fn is_set(x: i64) -> bool { x as bool }
+
+Compare instead:
+
+    fn is_set(x: i64) -> bool { x != 0 }"
+        }
+        "E0220" => {
+            "A range pattern overlaps the range of an earlier arm, so some values it
+covers can never reach it.
+
+Erroneous example:
+
+    match n {
+        0..=10 => 1,
+        5..=20 => 2,
+        _ => 3,
+    }
+
+Make the ranges disjoint:
+
+    match n {
+        0..=10 => 1,
+        11..=20 => 2,
+        _ => 3,
+    }"
+        }
+        "E0221" => {
+            "Inference would need a type that contains itself, which no type does.
+
+Erroneous example:
+
+    fn main() {
+        let x;
+        x = &x;
+    }
+
+Give the variable a value of a type not built from its own:
+
+    fn main() {
+        let x = 0;
+        let r = &x;
+    }"
+        }
+        "E0222" => {
+            "A function was called with the wrong number of arguments.
+
+Erroneous example:
+
+    fn add(a: i64, b: i64) -> i64 { a + b }
+    fn main() -> i64 { add(1) }
+
+Pass one argument per parameter:
+
+    fn main() -> i64 { add(1, 2) }"
+        }
+        "E0223" => {
+            "A `#[repr]` attribute names a representation Nova doesn't have. The
+known ones are `C`, which lays fields out in order with natural alignment,
+and `packed`, which leaves out padding.
+
+Erroneous example:
+
+    #[repr(transparent)]
+    struct Meters { value: f64 }
+
+Use a known representation, or leave the attribute out to let the
+compiler order fields:
+
+    #[repr(C)]
+    struct Meters { value: f64 }"
+        }
+        "E0224" => {
+            "A `for` loop binds something other than a single name. Destructuring
+patterns aren't supported there yet.
+
+Erroneous example:
+
+    for (i, x) in pairs { }
+
+Bind one name, and take it apart with `let` in the body:
+
+    for pair in pairs {
+        let (i, x) = pair;
+    }"
+        }
+        "E0225" => {
+            "An integer literal is outside the range of the type it was given.
+
+Erroneous example:
+
+    let small: u8 = 256;
+
+Use a wider type, or a value the type can hold:
+
+    let small: u16 = 256;"
+        }
+        "E0226" => {
+            "A format string interpolates a value. Strings don't exist at runtime
+yet, so there is nothing to format the value into.
+
+Erroneous example:
+
+    let greeting = f\"hello {name}\";
+
+Only literal text (and `{{`/`}}` for braces) is allowed for now:
+
+    let greeting = f\"hello {{name}}\";"
+        }
+        "E0227" => {
+            "A `&mut` reference is taken to a place that isn't mutable. Changing
+the value through the reference would change something declared
+unchangeable.
+
+Erroneous example:
+
+    let x = 1;
+    let r = &mut x;
+
+Declare the binding `mut`:
+
+    let mut x = 1;
+    let r = &mut x;"
+        }
+        "E0228" => {
+            "An expression uses a feature the compiler parses but can't check or
+compile yet, such as a struct literal or a macro call.
+
+Erroneous example:
+
+    let p = Point { x: 1, y: 2 };
+
+Write the code without the feature for now, for example with a tuple:
+
+    let p = (1, 2);"
+        }
+        "E0300" => {
+            "The program needs more of something than a WASM module can hold, such
+as more functions, locals or data than the limits allow.
+
+The message says what and by how much. Split large functions, or reduce
+the number of items the program defines."
+        }
+        "E0400" => {
+            "A `mod` declaration names a module with no file. For `mod util;` in
+`src/main.nova`, the compiler looks for `src/util.nova`, then
+`src/util/mod.nova`.
+
+Erroneous example:
+
+    mod util;
+
+with neither file present. Create the file, or correct the module's name."
+        }
+        "E9999" => {
+            "A diagnostic that doesn't have its own code yet. Its message describes
+the problem, such as an empty range pattern like `10..=1` whose lower bound
+exceeds its upper bound."
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
 impl std::fmt::Display for NovaError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}: {}", self.code(), self.message())
@@ -176,26 +1102,335 @@ impl std::error::Error for NovaError {}
 
 /// Report an error with nice formatting
 pub fn report(source: &str, filename: &str, error: NovaError) {
-    let span = error.span();
+    report_at(source, filename, &error, error.span());
+}
+
+/// Report an error at `span` (relative to `source`) on stderr
+fn report_at(source: &str, filename: &str, error: &NovaError, span: Span) {
+    let color = COLOR.load(Ordering::Relaxed);
+    write_report(source, filename, error, span, color, io::stderr()).unwrap();
+}
+
+/// Write the report for an error at `span` to `out`
+///
+/// An error on a synthetic node (a dummy span) is reported against the whole
+/// file, without a label pointing at its start.
+fn write_report(
+    source: &str,
+    filename: &str,
+    error: &NovaError,
+    span: Span,
+    color: bool,
+    out: impl Write,
+) -> io::Result<()> {
     let message = error.message();
     let code = error.code();
 
-    Report::build(ReportKind::Error, filename, span.start() as usize)
+    let kind = match error.severity() {
+        Severity::Error => ReportKind::Error,
+        Severity::Warning => ReportKind::Warning,
+    };
+    let mut report = Report::build(kind, filename, span.start() as usize)
+        .with_config(Config::default().with_color(color))
         .with_code(code)
-        .with_message(&message)
-        .with_label(
-            Label::new((filename, span.start() as usize..span.end() as usize))
-                .with_message(&message)
-                .with_color(Color::Red),
+        .with_message(&message);
+    if !span.is_dummy() {
+        let mut label = Label::new((filename, span.start() as usize..span.end() as usize))
+            .with_message(&message);
+        if color {
+            label = label.with_color(Color::Red);
+        }
+        report = report.with_label(label);
+    }
+    report.finish().write((filename, Source::from(source)), out)
+}
+
+/// Report an error whose span points into a `SourceMap`
+///
+/// Errors without a location in any file (e.g. I/O errors) are printed plainly.
+pub fn report_in(source_map: &SourceMap, error: NovaError) {
+    let color = COLOR.load(Ordering::Relaxed);
+    write_in(source_map, &error, color, io::stderr()).unwrap();
+}
+
+/// Write the report for an error whose span points into a `SourceMap`
+fn write_in(
+    source_map: &SourceMap,
+    error: &NovaError,
+    color: bool,
+    mut out: impl Write,
+) -> io::Result<()> {
+    match source_map.lookup(error.span()) {
+        Some((file, local)) => write_report(
+            source_map.source(file),
+            &file.name,
+            error,
+            local,
+            color,
+            out,
+        ),
+        _ => writeln!(out, "Error: {}", error),
+    }
+}
+
+/// Report multiple errors in source order, each in the file it occurred in
+///
+/// An error reported twice at the same place is only printed once.
+pub fn report_all(source_map: &SourceMap, errors: Vec<NovaError>) {
+    let color = COLOR.load(Ordering::Relaxed);
+    write_all(source_map, errors, color, io::stderr()).unwrap();
+}
+
+/// Write the reports for multiple errors, see `report_all`
+fn write_all(
+    source_map: &SourceMap,
+    errors: Vec<NovaError>,
+    color: bool,
+    mut out: impl Write,
+) -> io::Result<()> {
+    let diagnostics: Diagnostics = errors.into_iter().collect();
+    for error in diagnostics.into_vec() {
+        write_in(source_map, &error, color, &mut out)?;
+    }
+    Ok(())
+}
+
+/// Errors collected from a recovering pass, kept in source order
+///
+/// Errors are ordered by where their span starts, then by severity (errors
+/// before warnings), then by code.
+/// Adding an error identical to one already collected (same code, span and
+/// message) does nothing.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    errors: Vec<NovaError>,
+}
+
+impl Diagnostics {
+    /// Creates an empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an error in source order, unless an identical one is present.
+    pub fn push(&mut self, error: NovaError) {
+        let key = order_key(&error);
+        let index = self.errors.partition_point(|e| order_key(e) < key);
+        let duplicate = self.errors[index..]
+            .iter()
+            .take_while(|e| order_key(e) == key)
+            .any(|e| e.message() == error.message());
+        if !duplicate {
+            self.errors.insert(index, error);
+        }
+    }
+
+    /// Whether no errors have been collected
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The number of distinct errors collected
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// The collected errors, in source order
+    pub fn into_vec(self) -> Vec<NovaError> {
+        self.errors
+    }
+}
+
+/// Where an error goes among others: by start, then severity, then code,
+/// then end
+fn order_key(error: &NovaError) -> (u32, Severity, &'static str, u32) {
+    let span = error.span();
+    (span.start(), error.severity(), error.code(), span.end())
+}
+
+impl Extend<NovaError> for Diagnostics {
+    fn extend<I: IntoIterator<Item = NovaError>>(&mut self, errors: I) {
+        for error in errors {
+            self.push(error);
+        }
+    }
+}
+
+impl FromIterator<NovaError> for Diagnostics {
+    fn from_iter<I: IntoIterator<Item = NovaError>>(errors: I) -> Self {
+        let mut diagnostics = Self::new();
+        diagnostics.extend(errors);
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(color: bool) -> String {
+        let source = "let x = $;";
+        let error = NovaError::InvalidCharacter {
+            char: '$',
+            span: Span::new(8, 9),
+        };
+        let mut out = Vec::new();
+        write_report(source, "test.nova", &error, error.span(), color, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_report_without_color_has_no_escape_codes() {
+        let report = render(false);
+        assert!(report.contains("E0001"), "{}", report);
+        assert!(!report.contains('\x1b'), "{:?}", report);
+    }
+
+    #[test]
+    fn test_report_with_color_has_escape_codes() {
+        assert!(render(true).contains('\x1b'));
+    }
+
+    #[test]
+    fn test_report_with_dummy_span_has_no_label() {
+        let error = NovaError::Custom {
+            message: "synthetic".to_string(),
+            span: Span::dummy(),
+        };
+        let mut out = Vec::new();
+        write_report(
+            "let x = 1;",
+            "test.nova",
+            &error,
+            error.span(),
+            false,
+            &mut out,
         )
-        .finish()
-        .print((filename, Source::from(source)))
         .unwrap();
-}
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.contains("synthetic"), "{}", report);
+        assert!(!report.contains("let x"), "{}", report);
+        assert!(!report.contains("1:1"), "{}", report);
+    }
+
+    #[test]
+    fn test_report_all_sorts_and_deduplicates() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("a.nova", "let x = $;").unwrap();
+        let base = source_map.add_file("b.nova", "let y = @;").unwrap();
+        let invalid = |char, start: u32| NovaError::InvalidCharacter {
+            char,
+            span: Span::new(start, start + 1),
+        };
+        let errors = vec![
+            invalid('@', base + 8),
+            invalid('$', 8),
+            invalid('@', base + 8),
+        ];
+        let mut out = Vec::new();
+        write_all(&source_map, errors, false, &mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        assert_eq!(report.matches("E0001").count(), 2, "{}", report);
+        let first = report.find("a.nova").unwrap();
+        let second = report.find("b.nova").unwrap();
+        assert!(first < second, "{}", report);
+    }
+
+    #[test]
+    fn test_errors_sort_before_warnings() {
+        assert!(Severity::Error < Severity::Warning);
+        let error = NovaError::InvalidCharacter {
+            char: '$',
+            span: Span::new(3, 4),
+        };
+        assert_eq!(error.severity(), Severity::Error);
+    }
+
+    #[test]
+    fn test_diagnostics_keep_distinct_errors_at_one_span() {
+        let span = Span::new(3, 4);
+        let diagnostics: Diagnostics = [
+            NovaError::UndefinedVariable {
+                name: "b".to_string(),
+                span,
+            },
+            NovaError::InvalidCharacter { char: '$', span },
+            NovaError::UndefinedVariable {
+                name: "a".to_string(),
+                span,
+            },
+            NovaError::InvalidCharacter { char: '$', span },
+        ]
+        .into_iter()
+        .collect();
+        let codes: Vec<_> = diagnostics.into_vec().iter().map(|e| e.code()).collect();
+        assert_eq!(codes, ["E0001", "E0201", "E0201"]);
+    }
+
+    #[test]
+    fn test_explain_known_code() {
+        let text = explain("E0100").unwrap();
+        assert!(!text.is_empty());
+        assert!(text.contains("Erroneous example"), "{}", text);
+    }
+
+    #[test]
+    fn test_explain_unknown_code() {
+        assert_eq!(explain("E0999"), None);
+        assert_eq!(explain("e0100"), None);
+        assert_eq!(explain(""), None);
+    }
+
+    #[test]
+    fn test_every_code_is_explained() {
+        let span = Span::new(0, 1);
+        let errors = [
+            NovaError::UnterminatedString { span },
+            NovaError::TypeMismatch {
+                expected: String::new(),
+                found: String::new(),
+                span,
+            },
+            NovaError::ForPatternNotName { span },
+            NovaError::IntLiteralOutOfRange {
+                value: 0,
+                ty: String::new(),
+                span,
+            },
+            NovaError::FormatInterpolation { span },
+            NovaError::UnsupportedExpression {
+                what: "Struct literals",
+                span,
+            },
+            NovaError::BorrowImmutableAsMut {
+                name: String::new(),
+                span,
+            },
+            NovaError::ModuleLimitExceeded {
+                what: "functions",
+                count: 2,
+                max: 1,
+            },
+            NovaError::Custom {
+                message: String::new(),
+                span,
+            },
+        ];
+        for error in errors {
+            assert!(explain(error.code()).is_some(), "{}", error.code());
+        }
+        // And every code in between
+        let codes = (1..=6)
+            .chain([10, 11, 100, 101])
+            .chain(200..=228)
+            .chain([300, 400]);
+        for code in codes.map(|n| format!("E{:04}", n)) {
+            assert!(explain(&code).is_some(), "{}", code);
+        }
+    }
 
-/// Report multiple errors
-pub fn report_all(source: &str, filename: &str, errors: Vec<NovaError>) {
-    for error in errors {
-        report(source, filename, error);
+    #[test]
+    fn test_no_color_flag_disables_color() {
+        assert!(!should_color(true));
     }
 }
@@ -0,0 +1,226 @@
+//! String interning for identifiers
+//!
+//! Identifiers are interned into `Symbol`s: 4-byte handles into a global
+//! string table. The parser allocates each distinct name once, and the type
+//! checker compares names as integers instead of strings.
+//!
+//! # Design
+//!
+//! The table is process-global (behind a `Mutex`) so symbols stay valid
+//! across threads, e.g. when parsing runs on a spawned thread. Interned
+//! strings live for the rest of the process, which is fine for a
+//! compiler that exits after one compilation.
+//!
+//! # Benchmark
+//!
+//! ```bash
+//! cargo test --release bench_intern -- --ignored --nocapture
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+/// An interned string.
+///
+/// Two symbols are equal if and only if they were interned from equal strings.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Interns a string, returning its symbol.
+    pub fn intern(s: &str) -> Symbol {
+        interner().lock().unwrap().intern(s)
+    }
+
+    /// Returns the string this symbol was interned from.
+    pub fn as_str(self) -> &'static str {
+        interner().lock().unwrap().resolve(self)
+    }
+
+    /// Returns the raw table index of this symbol.
+    #[allow(dead_code)]
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// A string table mapping strings to symbols and back.
+pub struct Interner {
+    map: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+            strings: Vec::new(),
+        }
+    }
+
+    /// Interns a string, allocating only the first time it is seen.
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(&sym) = self.map.get(s) {
+            return sym;
+        }
+        let sym = Symbol(self.strings.len() as u32);
+        let stored: &'static str = Box::leak(s.to_owned().into_boxed_str());
+        self.map.insert(stored, sym);
+        self.strings.push(stored);
+        sym
+    }
+
+    /// Returns the string for a symbol.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the symbol was produced by a different interner.
+    pub fn resolve(&self, sym: Symbol) -> &'static str {
+        self.strings[sym.0 as usize]
+    }
+
+    /// Returns the number of distinct strings interned.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns true if nothing has been interned.
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The process-global interner backing `Symbol`.
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| Mutex::new(Interner::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Item, PatternKind, Stmt};
+    use crate::lexer::lex;
+    use crate::parser::parse;
+    use std::mem::size_of;
+
+    #[test]
+    fn symbol_is_4_bytes() {
+        assert_eq!(size_of::<Symbol>(), 4);
+    }
+
+    #[test]
+    fn equal_strings_intern_to_equal_symbols() {
+        let a = Symbol::intern("counter");
+        let b = Symbol::intern("counter");
+        let c = Symbol::intern("other");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn symbol_round_trips() {
+        let sym = Symbol::intern("round_trip");
+        assert_eq!(sym.as_str(), "round_trip");
+        assert_eq!(sym, "round_trip");
+        assert_eq!(format!("{}", sym), "round_trip");
+        assert_eq!(format!("{:?}", sym), "\"round_trip\"");
+    }
+
+    #[test]
+    fn local_interner_allocates_once_per_string() {
+        let mut interner = Interner::new();
+        let a = interner.intern("x");
+        let b = interner.intern("x");
+        interner.intern("y");
+        assert_eq!(a, b);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.resolve(a), "x");
+    }
+
+    #[test]
+    fn parsed_identifiers_share_symbols() {
+        let source = "fn main() { let x = 1; let x = 2; }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let names: Vec<Symbol> = match &program.items[0] {
+            Item::Function(f) => f
+                .body
+                .stmts
+                .iter()
+                .filter_map(|s| match s {
+                    Stmt::Let(l) => match &l.pattern.kind {
+                        PatternKind::Ident(ident, _) => Some(ident.name),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            _ => panic!("Expected function"),
+        };
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], names[1]);
+    }
+
+    /// Parses and checks a large program to measure identifier handling.
+    #[test]
+    #[ignore]
+    fn bench_intern_large_program() {
+        let mut source = String::new();
+        for i in 0..2_000 {
+            source.push_str(&format!(
+                "fn f{i}(a: i64, b: i64) -> i64 {{ let sum = a + b; let diff = a - b; sum * diff }}\n"
+            ));
+        }
+
+        let start = std::time::Instant::now();
+        let tokens = lex(&source).unwrap();
+        let lexed = start.elapsed();
+        let program = parse(&source, tokens).unwrap();
+        let parsed = start.elapsed();
+        crate::types::check(&program).unwrap();
+        let checked = start.elapsed();
+
+        println!(
+            "{} bytes: lex {:?}, parse {:?}, check {:?}",
+            source.len(),
+            lexed,
+            parsed - lexed,
+            checked - parsed
+        );
+    }
+}
@@ -10,16 +10,32 @@
 
 #![allow(dead_code)]
 
-use crate::ast::{BinOp, Literal, UnaryOp};
+use crate::ast::{BinOp, Literal, Pattern, PatternKind, UnaryOp};
+use crate::intern::Symbol;
 use crate::types::{
-    TypeInfo, TypedBlock, TypedExpr, TypedExprKind, TypedFunction, TypedItem, TypedProgram,
-    TypedStmt,
+    path_symbol, shared_variant_names, DefaultInt, Repr, TypeInfo, TypedBlock, TypedEnum,
+    TypedExpr, TypedExprKind, TypedFunction, TypedItem, TypedMatchArm, TypedProgram, TypedStmt,
 };
+use std::collections::HashMap;
 
 /// An IR module (corresponds to a program)
 #[derive(Debug)]
 pub struct Module {
     pub functions: Vec<Function>,
+    /// Storage for `static` items, referred to by index
+    pub globals: Vec<Global>,
+    /// The type integers are lowered to (`I32` or `I64`)
+    pub int_type: IrType,
+}
+
+/// A module-level variable backing a `static`
+#[derive(Debug)]
+pub struct Global {
+    pub name: String,
+    pub ty: IrType,
+    pub mutable: bool,
+    /// The initial value, a `ConstInt`, `ConstFloat` or `ConstBool`
+    pub init: InstructionKind,
 }
 
 /// An IR function
@@ -29,6 +45,8 @@ pub struct Function {
     pub params: Vec<(String, IrType)>,
     pub return_type: IrType,
     pub blocks: Vec<BasicBlock>,
+    /// The source function's `///` comment, for the WAT output
+    pub doc: Option<String>,
 }
 
 /// A basic block (sequence of instructions ending in a terminator)
@@ -55,28 +73,30 @@ pub struct Instruction {
 }
 
 /// Instruction kinds
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum InstructionKind {
-    // Constants
-    ConstInt(i64),
+    // Constants, an integer of type `I32` or `I64`
+    ConstInt(i64, IrType),
     ConstFloat(f64),
     ConstBool(bool),
+    /// A string's address and length in bytes, a pair of `I32`s
     ConstString(String),
 
     // Binary operations
     Add(ValueId, ValueId),
     Sub(ValueId, ValueId),
     Mul(ValueId, ValueId),
-    Div(ValueId, ValueId),
-    Rem(ValueId, ValueId),
+    // Integer operands are taken as signed if the flag is set
+    Div(ValueId, ValueId, bool),
+    Rem(ValueId, ValueId, bool),
 
-    // Comparisons
+    // Comparisons, the ordered ones with a signed flag like `Div`'s
     Eq(ValueId, ValueId),
     Ne(ValueId, ValueId),
-    Lt(ValueId, ValueId),
-    Le(ValueId, ValueId),
-    Gt(ValueId, ValueId),
-    Ge(ValueId, ValueId),
+    Lt(ValueId, ValueId, bool),
+    Le(ValueId, ValueId, bool),
+    Gt(ValueId, ValueId, bool),
+    Ge(ValueId, ValueId, bool),
 
     // Logical
     And(ValueId, ValueId),
@@ -88,24 +108,67 @@ pub enum InstructionKind {
     BitOr(ValueId, ValueId),
     BitXor(ValueId, ValueId),
     Shl(ValueId, ValueId),
-    Shr(ValueId, ValueId),
+    Shr(ValueId, ValueId, bool), // arithmetic if signed, else logical
 
     // Unary
     Neg(ValueId),
 
+    // The value converted to an integer or float type of another size,
+    // taking the integer operand (or, from a float, the integer result)
+    // as signed if the flag is set
+    Convert(ValueId, IrType, bool),
+
     // Memory
     Alloca(IrType),
-    Load(ValueId),
-    Store(ValueId, ValueId),
+    Load(ValueId, IrType),             // address, type loaded
+    Store(ValueId, ValueId, IrType),   // address, value, type stored
+    FieldPtr(ValueId, u32),            // address plus a byte offset
+    ElementPtr(ValueId, ValueId, u32), // address plus an index times an element size
+
+    // Linear memory size, in 64 KiB pages
+    MemorySize,
+    MemoryGrow(ValueId), // pages to add; gives the old size, or -1
 
     // Function calls
     Call(String, Vec<ValueId>),
 
+    // A multi-value tuple of its elements, and one of them
+    Tuple(Vec<ValueId>),
+    TupleGet(ValueId, u32),
+
     // Phi nodes (for SSA)
     Phi(Vec<(BlockId, ValueId)>),
 
     // Get parameter
     GetParam(usize),
+
+    // Globals, by index into `Module::globals`
+    GlobalGet(usize),
+    GlobalSet(usize, ValueId),
+}
+
+impl InstructionKind {
+    /// The values the instruction reads, including a phi's incoming values
+    pub fn operands(&self) -> Vec<ValueId> {
+        use InstructionKind::*;
+        match self {
+            ConstInt(..) | ConstFloat(_) | ConstString(_) | ConstBool(_) => Vec::new(),
+            Alloca(_) | GetParam(_) | GlobalGet(_) | MemorySize => Vec::new(),
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b, _) | Rem(a, b, _) => vec![*a, *b],
+            Eq(a, b) | Ne(a, b) => vec![*a, *b],
+            Lt(a, b, _) | Le(a, b, _) | Gt(a, b, _) | Ge(a, b, _) => vec![*a, *b],
+            And(a, b) | Or(a, b) => vec![*a, *b],
+            BitAnd(a, b) | BitOr(a, b) | BitXor(a, b) | Shl(a, b) | Shr(a, b, _) => vec![*a, *b],
+            Not(a) | Neg(a) | Load(a, _) | FieldPtr(a, _) | MemoryGrow(a) => vec![*a],
+            Convert(a, ..) => vec![*a],
+            TupleGet(tuple, _) => vec![*tuple],
+            Store(addr, value, _) => vec![*addr, *value],
+            ElementPtr(base, index, _) => vec![*base, *index],
+            Call(_, args) | Tuple(args) => args.clone(),
+            Phi(incoming) => incoming.iter().map(|&(_, v)| v).collect(),
+            GlobalSet(_, value) => vec![*value],
+        }
+    }
 }
 
 /// Block terminator
@@ -117,6 +180,45 @@ pub enum Terminator {
     Unreachable,
 }
 
+impl Terminator {
+    /// The value the terminator reads, if any
+    pub fn operand(&self) -> Option<ValueId> {
+        match self {
+            Terminator::Return(value) => *value,
+            Terminator::CondBranch(cond, _, _) => Some(*cond),
+            Terminator::Branch(_) | Terminator::Unreachable => None,
+        }
+    }
+
+    /// The blocks control can transfer to
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Branch(target) => vec![*target],
+            Terminator::CondBranch(_, then_block, else_block) => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    /// Redirect every edge to `from` so it goes to `to` instead
+    fn retarget(&mut self, from: BlockId, to: BlockId) {
+        match self {
+            Terminator::Branch(target) => {
+                if *target == from {
+                    *target = to;
+                }
+            }
+            Terminator::CondBranch(_, then_block, else_block) => {
+                for target in [then_block, else_block] {
+                    if *target == from {
+                        *target = to;
+                    }
+                }
+            }
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+    }
+}
+
 /// IR type representation
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum IrType {
@@ -126,9 +228,202 @@ pub enum IrType {
     F64,
     Bool,
     Ptr(Box<IrType>),
+    /// A block of memory, such as an enum's tag and payload
+    Aggregate {
+        size: u32,
+        align: u32,
+    },
+    /// Several scalars passed around together, as a WASM multi-value. In
+    /// memory the elements are laid out in order, see `tuple_offsets`.
+    Tuple(Vec<IrType>),
     Void,
 }
 
+impl IrType {
+    /// Size in bytes of a value of this type in memory
+    pub fn size(&self) -> u32 {
+        match self {
+            IrType::I32 | IrType::F32 | IrType::Bool | IrType::Ptr(_) => 4,
+            IrType::I64 | IrType::F64 => 8,
+            IrType::Aggregate { size, .. } => *size,
+            IrType::Tuple(elems) => match (elems.last(), tuple_offsets(elems).last()) {
+                (Some(last), Some(offset)) => align_up(offset + last.size(), self.align()),
+                _ => 0,
+            },
+            IrType::Void => 0,
+        }
+    }
+
+    /// Alignment in bytes of a value of this type in memory
+    pub fn align(&self) -> u32 {
+        match self {
+            IrType::Aggregate { align, .. } => *align,
+            IrType::Tuple(elems) => elems.iter().map(IrType::align).max().unwrap_or(1),
+            IrType::Void => 1,
+            other => other.size(),
+        }
+    }
+
+    /// The types of the WASM values a value of this type is: one per
+    /// element for a tuple, and none for `Void`
+    pub fn scalars(&self) -> Vec<IrType> {
+        match self {
+            IrType::Tuple(elems) => elems.clone(),
+            IrType::Void => Vec::new(),
+            other => vec![other.clone()],
+        }
+    }
+}
+
+/// The offset of each element of a tuple laid out in memory, each aligned
+/// after the one before
+pub fn tuple_offsets(elems: &[IrType]) -> Vec<u32> {
+    let mut offset = 0;
+    elems
+        .iter()
+        .map(|elem| {
+            let start = align_up(offset, elem.align());
+            offset = start + elem.size();
+            start
+        })
+        .collect()
+}
+
+/// Where a struct's fields live in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Name, offset and type of each field, in declaration order
+    pub fields: Vec<(Symbol, u32, IrType)>,
+    pub size: u32,
+    pub align: u32,
+}
+
+impl Layout {
+    /// Lay out `fields`, given in declaration order, as `repr` asks.
+    ///
+    /// `C` puts each field at the next multiple of its alignment and rounds
+    /// the size up to the largest one. `packed` keeps the order but has no
+    /// padding at all, so it's byte-aligned. The default is `C` after
+    /// sorting the fields by decreasing alignment (a stable sort), which
+    /// needs no padding between them.
+    pub fn of(fields: &[(Symbol, IrType)], repr: Repr) -> Layout {
+        let mut order: Vec<usize> = (0..fields.len()).collect();
+        if repr == Repr::Default {
+            order.sort_by_key(|&i| std::cmp::Reverse(fields[i].1.align()));
+        }
+        let mut offsets = vec![0; fields.len()];
+        let mut offset = 0;
+        let mut align = 1;
+        for i in order {
+            let ty = &fields[i].1;
+            let field_align = match repr {
+                Repr::Packed => 1,
+                Repr::Default | Repr::C => ty.align(),
+            };
+            offset = align_up(offset, field_align);
+            offsets[i] = offset;
+            offset += ty.size();
+            align = align.max(field_align);
+        }
+        Layout {
+            fields: fields
+                .iter()
+                .zip(offsets)
+                .map(|((name, ty), offset)| (*name, offset, ty.clone()))
+                .collect(),
+            size: align_up(offset, align),
+            align,
+        }
+    }
+}
+
+/// How an enum is laid out in memory: its tag, then the payload of the
+/// variant it holds, in space sized for the largest variant.
+///
+/// An enum without payloads (`enum Color { Red, Green }`) is just its tag
+/// and isn't kept in memory at all. Payload fields stay in declaration
+/// order; `#[repr(packed)]` drops the padding after the tag and between
+/// fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumLayout {
+    /// The tag's type, the width of an integer
+    pub tag_type: IrType,
+    /// Where the payload starts, after the tag and any padding
+    pub payload_offset: u32,
+    pub payload_size: u32,
+    pub size: u32,
+    pub align: u32,
+}
+
+impl EnumLayout {
+    /// Whether values of the enum are just the tag
+    pub fn is_tag_only(&self) -> bool {
+        self.payload_size == 0
+    }
+
+    /// The memory holding one value of the enum
+    fn memory(&self) -> IrType {
+        IrType::Aggregate {
+            size: self.size,
+            align: self.align,
+        }
+    }
+}
+
+/// A variant's tag and where its fields live in its enum
+#[derive(Debug, Clone)]
+struct VariantLayout {
+    enum_name: Symbol,
+    tag: i64,
+    /// Name, offset from the start of the enum, and type of each field
+    fields: Vec<(Symbol, u32, IrType)>,
+    /// The checker's type of each field
+    field_types: Vec<TypeInfo>,
+}
+
+/// The parts of an array or slice a slice pattern matches against
+struct SliceParts {
+    /// Address of the first element
+    data: ValueId,
+    len: ValueId,
+    elem: IrType,
+    elem_ty: TypeInfo,
+}
+
+/// The sub-patterns of a variant pattern, each with the index of the field
+/// it matches
+fn variant_subpatterns<'a>(
+    pattern: &'a Pattern,
+    variant: &VariantLayout,
+) -> Vec<(&'a Pattern, usize)> {
+    match &pattern.kind {
+        PatternKind::TupleStruct(_, subpatterns) => subpatterns
+            .iter()
+            .take_while(|sub| !matches!(sub.kind, PatternKind::Rest(_)))
+            .take(variant.fields.len())
+            .zip(0..)
+            .collect(),
+        PatternKind::Struct(_, field_patterns) => field_patterns
+            .iter()
+            .filter_map(|fp| {
+                let i = variant.fields.iter().position(|f| f.0 == fp.name.name)?;
+                Some((fp.pattern.as_ref()?, i))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether values of `ty` compare, divide and shift as unsigned
+fn is_unsigned(ty: &TypeInfo) -> bool {
+    matches!(ty, TypeInfo::Int(width) if !width.is_signed())
+}
+
+/// Round `offset` up to a multiple of `align`
+fn align_up(offset: u32, align: u32) -> u32 {
+    offset.div_ceil(align) * align
+}
+
 /// Lower typed AST to IR
 pub fn lower(program: &TypedProgram) -> Module {
     let mut lowerer = Lowerer::new();
@@ -140,8 +435,40 @@ struct Lowerer {
     next_value: usize,
     next_block: usize,
     current_block: Vec<Instruction>,
+    /// The ID of the block being built, if it was allocated up front so that
+    /// other blocks could branch to it
+    current_id: Option<BlockId>,
     blocks: Vec<BasicBlock>,
-    locals: Vec<(String, ValueId)>,
+    locals: Vec<(Symbol, ValueId)>,
+    /// Constants with a literal value, inlined where they are used
+    consts: Vec<(Symbol, Literal)>,
+    /// Enum layouts, keyed by enum name
+    enums: HashMap<Symbol, EnumLayout>,
+    /// Enum variants, keyed by `Enum::Variant` and, unless two enums share
+    /// it, `Variant`
+    variants: HashMap<Symbol, VariantLayout>,
+    /// Struct layouts, keyed by struct name
+    // TODO: Lower struct literals and field access through these
+    structs: HashMap<Symbol, Layout>,
+    globals: Vec<Global>,
+    int_type: IrType,
+    /// The loops around the code being lowered, innermost last
+    loops: Vec<LoopTargets>,
+}
+
+/// Where `break` and `continue` branch to in a loop, and the edges taken so
+/// far, each with the values of the locals the loop carries
+struct LoopTargets {
+    header: BlockId,
+    exit: BlockId,
+    /// Locals assigned in the loop that were declared before it
+    carried: Vec<Symbol>,
+    /// A range `for`'s counter and its type, stepped before each edge back
+    counter: Option<(Symbol, IrType)>,
+    /// Edges back to the header
+    continues: Vec<(BlockId, Vec<ValueId>)>,
+    /// Edges to the exit, with the `break` value if there is one
+    breaks: Vec<(BlockId, Vec<ValueId>, Option<ValueId>)>,
 }
 
 impl Lowerer {
@@ -150,8 +477,16 @@ impl Lowerer {
             next_value: 0,
             next_block: 0,
             current_block: Vec::new(),
+            current_id: None,
             blocks: Vec::new(),
             locals: Vec::new(),
+            consts: Vec::new(),
+            enums: HashMap::new(),
+            variants: HashMap::new(),
+            structs: HashMap::new(),
+            globals: Vec::new(),
+            int_type: IrType::I64,
+            loops: Vec::new(),
         }
     }
 
@@ -173,8 +508,22 @@ impl Lowerer {
         result
     }
 
+    /// An integer constant of the default integer type
+    fn const_int(&mut self, n: i64) -> ValueId {
+        self.emit(InstructionKind::ConstInt(n, self.int_type.clone()))
+    }
+
+    /// Continue in block `id`, which was allocated earlier to branch to
+    fn start_block(&mut self, id: BlockId) {
+        debug_assert!(self.current_block.is_empty() && self.current_id.is_none());
+        self.current_id = Some(id);
+    }
+
     fn finish_block(&mut self, terminator: Terminator) -> BlockId {
-        let id = self.fresh_block();
+        let id = match self.current_id.take() {
+            Some(id) => id,
+            None => self.fresh_block(),
+        };
         let block = BasicBlock {
             id,
             instructions: std::mem::take(&mut self.current_block),
@@ -185,31 +534,90 @@ impl Lowerer {
     }
 
     fn lower_program(&mut self, program: &TypedProgram) -> Module {
+        self.int_type = match program.default_int {
+            DefaultInt::I32 => IrType::I32,
+            DefaultInt::I64 => IrType::I64,
+        };
         let mut functions = Vec::new();
 
+        // Lay out every enum before any function uses one
+        for item in &program.items {
+            if let TypedItem::Enum(e) = item {
+                self.layout_enum(e);
+            }
+        }
+        // A variant name two enums share only resolves qualified
+        let enums = program.items.iter().filter_map(|item| match item {
+            TypedItem::Enum(e) => Some(e.variants.iter().map(|v| v.name)),
+            _ => None,
+        });
+        for name in shared_variant_names(enums) {
+            self.variants.remove(&name);
+        }
+
+        for item in &program.items {
+            if let TypedItem::Static(s) = item {
+                let ty = self.lower_type(&s.ty);
+                let init = match s.value {
+                    Literal::Float(n) => InstructionKind::ConstFloat(n),
+                    Literal::Bool(b) => InstructionKind::ConstBool(b),
+                    Literal::Int(n) => InstructionKind::ConstInt(n as i64, ty.clone()),
+                    // The checker only evaluates the literals above
+                    _ => InstructionKind::ConstInt(0, ty.clone()),
+                };
+                self.globals.push(Global {
+                    name: s.name.to_string(),
+                    ty,
+                    mutable: s.mutable,
+                    init,
+                });
+            }
+        }
+
         for item in &program.items {
             match item {
                 TypedItem::Function(f) => {
                     functions.push(self.lower_function(f));
                 }
+                TypedItem::Const(c) => {
+                    // TODO: Evaluate constant expressions that aren't literals
+                    if let TypedExprKind::Literal(lit) = &c.value.kind {
+                        self.consts.push((c.name, lit.clone()));
+                    }
+                }
+                TypedItem::Struct(s) => {
+                    let fields: Vec<_> = s
+                        .fields
+                        .iter()
+                        .map(|(name, ty)| (*name, self.lower_type(ty)))
+                        .collect();
+                    self.structs.insert(s.name, Layout::of(&fields, s.repr));
+                }
+                TypedItem::Enum(_) | TypedItem::Static(_) => {}
             }
         }
 
-        Module { functions }
+        Module {
+            functions,
+            globals: std::mem::take(&mut self.globals),
+            int_type: self.int_type.clone(),
+        }
     }
 
     fn lower_function(&mut self, f: &TypedFunction) -> Function {
         // Reset state
         self.blocks.clear();
         self.current_block.clear();
+        self.current_id = None;
         self.locals.clear();
+        self.loops.clear();
         self.next_value = 0;
         self.next_block = 0;
 
         // Add parameters to locals
         for (i, (name, _ty)) in f.params.iter().enumerate() {
             let value = self.emit(InstructionKind::GetParam(i));
-            self.locals.push((name.clone(), value));
+            self.locals.push((*name, value));
         }
 
         // Lower body
@@ -218,16 +626,19 @@ impl Lowerer {
         // Finish with return
         self.finish_block(Terminator::Return(result));
 
-        Function {
-            name: f.name.clone(),
+        let mut function = Function {
+            name: f.name.to_string(),
             params: f
                 .params
                 .iter()
-                .map(|(n, t)| (n.clone(), self.lower_type(t)))
+                .map(|(n, t)| (n.to_string(), self.lower_type(t)))
                 .collect(),
             return_type: self.lower_type(&f.return_type),
             blocks: std::mem::take(&mut self.blocks),
-        }
+            doc: f.doc.clone(),
+        };
+        simplify_cfg(&mut function);
+        function
     }
 
     fn lower_block(&mut self, block: &TypedBlock) -> Option<ValueId> {
@@ -242,10 +653,10 @@ impl Lowerer {
 
     fn lower_stmt(&mut self, stmt: &TypedStmt) -> Option<ValueId> {
         match stmt {
-            TypedStmt::Let { name, ty: _, value } => {
+            TypedStmt::Let { pattern, ty, value } => {
                 if let Some(expr) = value {
                     let v = self.lower_expr(expr);
-                    self.locals.push((name.clone(), v));
+                    self.bind_pattern(pattern, v, ty);
                 }
                 None
             }
@@ -255,46 +666,66 @@ impl Lowerer {
 
     fn lower_expr(&mut self, expr: &TypedExpr) -> ValueId {
         match &expr.kind {
-            TypedExprKind::Literal(lit) => match lit {
-                Literal::Int(n) => self.emit(InstructionKind::ConstInt(*n)),
-                Literal::Float(n) => self.emit(InstructionKind::ConstFloat(*n)),
-                Literal::Bool(b) => self.emit(InstructionKind::ConstBool(*b)),
-                Literal::String(s) => self.emit(InstructionKind::ConstString(s.clone())),
-                Literal::Char(c) => self.emit(InstructionKind::ConstInt(*c as i64)),
-            },
-            TypedExprKind::Variable(name) => self
-                .locals
-                .iter()
-                .rev()
-                .find(|(n, _)| n == name)
-                .map(|(_, v)| *v)
-                .unwrap_or_else(|| self.emit(InstructionKind::ConstInt(0))),
+            TypedExprKind::Literal(lit) => self.lower_literal(lit, &self.lower_type(&expr.ty)),
+            TypedExprKind::Variable(name) => {
+                if let Some((_, v)) = self.locals.iter().rev().find(|(n, _)| n == name) {
+                    return *v;
+                }
+                if let Some(variant) = self.variants.get(name).cloned() {
+                    if variant.fields.is_empty() {
+                        return self.construct_variant(&variant, &[]);
+                    }
+                }
+                if let Some(index) = self.global(*name) {
+                    return self.emit(InstructionKind::GlobalGet(index));
+                }
+                match self.consts.iter().find(|(n, _)| n == name) {
+                    Some((_, lit)) => self.lower_literal(&lit.clone(), &self.lower_type(&expr.ty)),
+                    None => self.const_int(0),
+                }
+            }
+            TypedExprKind::Binary(left, BinOp::Assign, right) => {
+                if let TypedExprKind::Variable(name) = &left.kind {
+                    if self.locals.iter().any(|(n, _)| n == name) {
+                        // Later reads of the local see the new value
+                        let value = self.lower_expr(right);
+                        self.set_local(*name, value);
+                        return self.const_int(0);
+                    }
+                    if let Some(index) = self.global(*name) {
+                        let value = self.lower_expr(right);
+                        return self.emit(InstructionKind::GlobalSet(index, value));
+                    }
+                }
+                // TODO: Assign to fields, elements and through references
+                self.lower_expr(left);
+                self.lower_expr(right);
+                self.const_int(0)
+            }
             TypedExprKind::Binary(left, op, right) => {
                 let l = self.lower_expr(left);
                 let r = self.lower_expr(right);
+                let signed = !is_unsigned(&left.ty);
                 let kind = match op {
                     BinOp::Add => InstructionKind::Add(l, r),
                     BinOp::Sub => InstructionKind::Sub(l, r),
                     BinOp::Mul => InstructionKind::Mul(l, r),
-                    BinOp::Div => InstructionKind::Div(l, r),
-                    BinOp::Rem => InstructionKind::Rem(l, r),
+                    BinOp::Div => InstructionKind::Div(l, r, signed),
+                    BinOp::Rem => InstructionKind::Rem(l, r, signed),
                     BinOp::Eq => InstructionKind::Eq(l, r),
                     BinOp::Ne => InstructionKind::Ne(l, r),
-                    BinOp::Lt => InstructionKind::Lt(l, r),
-                    BinOp::Le => InstructionKind::Le(l, r),
-                    BinOp::Gt => InstructionKind::Gt(l, r),
-                    BinOp::Ge => InstructionKind::Ge(l, r),
+                    BinOp::Lt => InstructionKind::Lt(l, r, signed),
+                    BinOp::Le => InstructionKind::Le(l, r, signed),
+                    BinOp::Gt => InstructionKind::Gt(l, r, signed),
+                    BinOp::Ge => InstructionKind::Ge(l, r, signed),
                     BinOp::And => InstructionKind::And(l, r),
                     BinOp::Or => InstructionKind::Or(l, r),
                     BinOp::BitAnd => InstructionKind::BitAnd(l, r),
                     BinOp::BitOr => InstructionKind::BitOr(l, r),
                     BinOp::BitXor => InstructionKind::BitXor(l, r),
                     BinOp::Shl => InstructionKind::Shl(l, r),
-                    BinOp::Shr => InstructionKind::Shr(l, r),
-                    BinOp::Assign => {
-                        // TODO: Proper assignment
-                        InstructionKind::ConstInt(0)
-                    }
+                    BinOp::Shr => InstructionKind::Shr(l, r, signed),
+                    BinOp::Assign => unreachable!("assignment is lowered above"),
                 };
                 self.emit(kind)
             }
@@ -308,56 +739,2164 @@ impl Lowerer {
                 self.emit(kind)
             }
             TypedExprKind::Call(func, args) => {
+                if let TypedExprKind::Variable(name) = &func.kind {
+                    if let Some(variant) = self.variants.get(name).cloned() {
+                        let arg_values: Vec<_> = args.iter().map(|a| self.lower_expr(a)).collect();
+                        return self.construct_variant(&variant, &arg_values);
+                    }
+                }
                 let name = match &func.kind {
-                    TypedExprKind::Variable(n) => n.clone(),
+                    TypedExprKind::Variable(n) => n.to_string(),
                     _ => "unknown".to_string(),
                 };
                 let arg_values: Vec<_> = args.iter().map(|a| self.lower_expr(a)).collect();
-                self.emit(InstructionKind::Call(name, arg_values))
+                match (name.as_str(), &arg_values[..]) {
+                    ("memory_size", []) => self.emit(InstructionKind::MemorySize),
+                    ("memory_grow", &[pages]) => self.emit(InstructionKind::MemoryGrow(pages)),
+                    _ => self.emit(InstructionKind::Call(name, arg_values)),
+                }
+            }
+            TypedExprKind::If(cond, then_block, else_expr) => {
+                self.lower_if(cond, then_block, else_expr.as_deref(), &expr.ty)
+            }
+            TypedExprKind::Match(scrutinee, arms) => self.lower_match(scrutinee, arms),
+            TypedExprKind::Block(block) => {
+                self.lower_block(block).unwrap_or_else(|| self.const_int(0))
+            }
+            TypedExprKind::While(cond, body) => self.lower_loop(Some(cond), body, &expr.ty, None),
+            TypedExprKind::For(name, iter, body) => {
+                if let TypedExprKind::Range(start, end, inclusive) = &iter.kind {
+                    return self.lower_range_for(
+                        *name,
+                        start.as_deref(),
+                        end.as_deref(),
+                        *inclusive,
+                        body,
+                    );
+                }
+                // TODO: Iterate over arrays and slices
+                self.lower_expr(iter);
+                self.lower_block(body);
+                self.const_int(0)
+            }
+            TypedExprKind::Loop(body) => self.lower_loop(None, body, &expr.ty, None),
+            TypedExprKind::Break(value) => {
+                let value = value.as_ref().map(|v| self.lower_expr(v));
+                self.leave_iteration(false, value)
+            }
+            TypedExprKind::Continue => self.leave_iteration(true, None),
+            // TODO: Branch out early on `Err`/`None`
+            TypedExprKind::Try(inner) => self.lower_expr(inner),
+            // TODO: Take an address once values can live in memory
+            TypedExprKind::Ref(_, inner) => self.lower_expr(inner),
+            TypedExprKind::Index(base, index) => self.lower_index(base, index),
+            TypedExprKind::Array(elems) => {
+                let values: Vec<_> = elems.iter().map(|e| self.lower_expr(e)).collect();
+                let TypeInfo::Array(elem_ty, _) = &expr.ty else {
+                    unreachable!("array literals have array types");
+                };
+                let elem = self.lower_type(elem_ty);
+                let IrType::Ptr(memory) = self.lower_type(&expr.ty) else {
+                    unreachable!("arrays are pointers");
+                };
+                let ptr = self.emit(InstructionKind::Alloca(*memory));
+                for (i, value) in values.into_iter().enumerate() {
+                    let field = self.emit(InstructionKind::FieldPtr(ptr, i as u32 * elem.size()));
+                    self.emit(InstructionKind::Store(field, value, elem.clone()));
+                }
+                ptr
             }
-            TypedExprKind::If(_cond, then_block, _else_expr) => {
-                // TODO: Proper control flow
-                self.lower_block(then_block)
-                    .unwrap_or_else(|| self.emit(InstructionKind::ConstInt(0)))
+            TypedExprKind::Cast(inner) => {
+                let value = self.lower_expr(inner);
+                self.lower_cast(value, &inner.ty, &expr.ty)
+            }
+            // TODO: Lower ranges used as values, outside `for` and indexing
+            TypedExprKind::Range(start, end, _) => {
+                for bound in [start, end].into_iter().flatten() {
+                    self.lower_expr(bound);
+                }
+                self.const_int(0)
             }
-            TypedExprKind::Block(block) => self
-                .lower_block(block)
-                .unwrap_or_else(|| self.emit(InstructionKind::ConstInt(0))),
             TypedExprKind::Return(value) => {
-                if let Some(v) = value {
-                    self.lower_expr(v)
-                } else {
-                    self.emit(InstructionKind::ConstInt(0))
+                let value = value.as_ref().map(|v| self.lower_expr(v));
+                self.finish_block(Terminator::Return(value));
+                // Code after it is unreachable and goes in a block of its own
+                let rest = self.fresh_block();
+                self.start_block(rest);
+                self.const_int(0)
+            }
+            // TODO: Lower closures to functions with their captures
+            TypedExprKind::Closure(..) => self.const_int(0),
+            TypedExprKind::Tuple(elems) => {
+                let values: Vec<_> = elems.iter().map(|e| self.lower_expr(e)).collect();
+                match self.lower_type(&expr.ty) {
+                    IrType::Ptr(memory) => {
+                        let IrType::Tuple(types) = &*memory else {
+                            unreachable!("tuples in memory keep their element types");
+                        };
+                        let offsets = tuple_offsets(types);
+                        let ptr = self.emit(InstructionKind::Alloca(*memory.clone()));
+                        for ((value, ty), offset) in values.into_iter().zip(types).zip(offsets) {
+                            if *ty != IrType::Void {
+                                let field = self.emit(InstructionKind::FieldPtr(ptr, offset));
+                                self.emit(InstructionKind::Store(field, value, ty.clone()));
+                            }
+                        }
+                        ptr
+                    }
+                    IrType::Tuple(_) => self.emit(InstructionKind::Tuple(values)),
+                    // `()`
+                    _ => self.const_int(0),
                 }
             }
         }
     }
 
-    fn lower_type(&self, ty: &TypeInfo) -> IrType {
-        match ty {
-            TypeInfo::Int => IrType::I64,
-            TypeInfo::Float => IrType::F64,
-            TypeInfo::Bool => IrType::Bool,
-            TypeInfo::Unit | TypeInfo::Never => IrType::Void,
-            _ => IrType::I64, // Default
+    /// Lower `for name in start..end` as if it were
+    ///
+    /// ```text
+    /// let mut name = start;
+    /// while name < end { body; name = name + 1; }
+    /// ```
+    ///
+    /// with `<=` for `..=`, `end` evaluated once before the loop, and the
+    /// step also taken on `continue`. `start..` only ends with a `break`.
+    fn lower_range_for(
+        &mut self,
+        name: Symbol,
+        start: Option<&TypedExpr>,
+        end: Option<&TypedExpr>,
+        inclusive: bool,
+        body: &TypedBlock,
+    ) -> ValueId {
+        let scope = self.locals.len();
+        let ty = match start.or(end) {
+            Some(bound) => self.lower_type(&bound.ty),
+            None => self.int_type.clone(),
+        };
+        let start_value = match start {
+            Some(start) => self.lower_expr(start),
+            None => self.emit(InstructionKind::ConstInt(0, ty.clone())),
+        };
+        let cond = end.map(|end| {
+            // Not a name the source can spell, so the body can't shadow it
+            let bound = Symbol::intern("<end>");
+            let end_value = self.lower_expr(end);
+            self.locals.push((bound, end_value));
+            let variable = |name| {
+                Box::new(TypedExpr {
+                    kind: TypedExprKind::Variable(name),
+                    ty: end.ty.clone(),
+                })
+            };
+            let op = if inclusive { BinOp::Le } else { BinOp::Lt };
+            TypedExpr {
+                kind: TypedExprKind::Binary(variable(name), op, variable(bound)),
+                ty: TypeInfo::Bool,
+            }
+        });
+        self.locals.push((name, start_value));
+        self.lower_loop(cond.as_ref(), body, &TypeInfo::Unit, Some((name, ty)));
+        self.locals.truncate(scope);
+        self.const_int(0)
+    }
+
+    /// Convert `value` of type `from` to `to` as `as` does: integers wrap
+    /// to the target's width, floats saturate at its bounds.
+    fn lower_cast(&mut self, value: ValueId, from: &TypeInfo, to: &TypeInfo) -> ValueId {
+        let (from_ir, to_ir) = (self.lower_type(from), self.lower_type(to));
+        let signed = match (from, to) {
+            // A narrow target saturates at its own bounds, see below
+            (TypeInfo::Float, TypeInfo::Int(width)) => width.is_signed() || width.bits() < 32,
+            (TypeInfo::Int(width), _) => width.is_signed(),
+            // `bool` and `char`
+            _ => false,
+        };
+        let value = match from_ir == to_ir {
+            true => value,
+            false => self.emit(InstructionKind::Convert(value, to_ir, signed)),
+        };
+        // Types narrower than an `I32` keep only their own values
+        let TypeInfo::Int(width) = to else {
+            return value;
+        };
+        if width.bits() >= 32 || from == to {
+            return value;
+        }
+        let (min, max) = width.range();
+        if *from == TypeInfo::Float {
+            let max = self.emit(InstructionKind::ConstInt(max as i64, IrType::I32));
+            let above = self.emit(InstructionKind::Gt(value, max, true));
+            let value = self.select(above, max, value);
+            let min = self.emit(InstructionKind::ConstInt(min as i64, IrType::I32));
+            let below = self.emit(InstructionKind::Lt(value, min, true));
+            return self.select(below, min, value);
+        }
+        // An integer keeps its low bits, sign-extended for a signed type
+        if width.is_signed() {
+            let shift = self.emit(InstructionKind::ConstInt(
+                (32 - width.bits()) as i64,
+                IrType::I32,
+            ));
+            let high = self.emit(InstructionKind::Shl(value, shift));
+            self.emit(InstructionKind::Shr(high, shift, true))
+        } else {
+            let mask = self.emit(InstructionKind::ConstInt(max as i64, IrType::I32));
+            self.emit(InstructionKind::BitAnd(value, mask))
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::lex;
-    use crate::parser::parse;
-    use crate::types::check;
+    /// `a` if `cond` holds, else `b`
+    fn select(&mut self, cond: ValueId, a: ValueId, b: ValueId) -> ValueId {
+        let then_block = self.fresh_block();
+        let else_block = self.fresh_block();
+        let join = self.fresh_block();
+        self.finish_block(Terminator::CondBranch(cond, then_block, else_block));
+        self.start_block(then_block);
+        let then_end = self.finish_block(Terminator::Branch(join));
+        self.start_block(else_block);
+        let else_end = self.finish_block(Terminator::Branch(join));
+        self.start_block(join);
+        self.emit(InstructionKind::Phi(vec![(then_end, a), (else_end, b)]))
+    }
 
-    #[test]
-    fn test_lower_simple() {
-        let source = "fn main() { return 42; }";
-        let tokens = lex(source).unwrap();
-        let ast = parse(source, tokens).unwrap();
-        let typed = check(&ast).unwrap();
-        let ir = lower(&typed);
-        assert_eq!(ir.functions.len(), 1);
+    /// Lower `while cond { body }`, or `loop { body }` without a `cond`:
+    ///
+    /// ```text
+    /// entry:  branch header
+    /// header: phis; ...; branch cond, body, exit
+    /// body:   ...; branch header (the latch)
+    /// exit:   phis
+    /// ```
+    ///
+    /// `continue` branches to the header like the latch does, and `break`
+    /// to the exit like a false `cond`. Locals assigned in the loop get a
+    /// `Phi` in the header with an edge from the entry and each `continue`,
+    /// and one in the exit if the edges into it disagree. A `loop` with a
+    /// `break` value joins the values in a `Phi` before those. A `counter`
+    /// (a range `for`'s) is carried too, and stepped by one on the way back.
+    fn lower_loop(
+        &mut self,
+        cond: Option<&TypedExpr>,
+        body: &TypedBlock,
+        ty: &TypeInfo,
+        counter: Option<(Symbol, IrType)>,
+    ) -> ValueId {
+        let mut assigned = Vec::new();
+        if let Some(cond) = cond {
+            assigned_in_expr(cond, &mut assigned);
+        }
+        assigned_in_block(body, &mut assigned);
+        let mut carried: Vec<Symbol> = counter.iter().map(|(name, _)| *name).collect();
+        for name in assigned {
+            if !carried.contains(&name) && self.locals.iter().any(|(local, _)| *local == name) {
+                carried.push(name);
+            }
+        }
+
+        let header = self.fresh_block();
+        let body_block = self.fresh_block();
+        let exit = self.fresh_block();
+        let entry = self.finish_block(Terminator::Branch(header));
+
+        self.start_block(header);
+        let mut phis = Vec::new();
+        for &local in &carried {
+            let initial = self.local(local);
+            let phi = self.emit(InstructionKind::Phi(vec![(entry, initial)]));
+            self.set_local(local, phi);
+            phis.push(phi);
+        }
+        let mut targets = LoopTargets {
+            header,
+            exit,
+            carried,
+            counter: counter.clone(),
+            continues: Vec::new(),
+            breaks: Vec::new(),
+        };
+        match cond {
+            Some(cond) => {
+                let cond = self.lower_expr(cond);
+                let values = targets.carried.iter().map(|&n| self.local(n)).collect();
+                let from = self.finish_block(Terminator::CondBranch(cond, body_block, exit));
+                targets.breaks.push((from, values, None));
+            }
+            None => {
+                self.finish_block(Terminator::Branch(body_block));
+            }
+        }
+
+        self.loops.push(targets);
+        self.start_block(body_block);
+        let scope = self.locals.len();
+        self.lower_block(body);
+        self.locals.truncate(scope);
+        if let Some((counter, ty)) = &counter {
+            self.step(*counter, ty);
+        }
+        let latch = self
+            .loops
+            .last()
+            .unwrap()
+            .carried
+            .iter()
+            .map(|&n| self.local(n))
+            .collect();
+        let from = self.finish_block(Terminator::Branch(header));
+        let mut targets = self.loops.pop().unwrap();
+        targets.continues.push((from, latch));
+
+        // Complete the header's phis now that every edge back is known
+        let header_block = self.blocks.iter_mut().find(|b| b.id == header).unwrap();
+        for instr in &mut header_block.instructions {
+            let Some(i) = phis.iter().position(|&phi| phi == instr.result) else {
+                continue;
+            };
+            if let InstructionKind::Phi(edges) = &mut instr.kind {
+                edges.extend(
+                    targets
+                        .continues
+                        .iter()
+                        .map(|(from, values)| (*from, values[i])),
+                );
+            }
+        }
+
+        self.start_block(exit);
+        let values: Vec<_> = targets
+            .breaks
+            .iter()
+            .filter_map(|(from, _, value)| value.map(|v| (*from, v)))
+            .collect();
+        let result = match self.lower_type(ty) {
+            IrType::Void => None,
+            _ if values.is_empty() || values.len() < targets.breaks.len() => None,
+            _ => Some(self.emit(InstructionKind::Phi(values))),
+        };
+        for (i, &local) in targets.carried.iter().enumerate() {
+            let incoming: Vec<_> = targets
+                .breaks
+                .iter()
+                .map(|(from, values, _)| (*from, values[i]))
+                .collect();
+            match incoming.first() {
+                // Without a `break` the exit is unreachable
+                None => {}
+                Some(&(_, first)) if incoming.iter().all(|&(_, v)| v == first) => {
+                    self.set_local(local, first);
+                }
+                Some(_) => {
+                    let phi = self.emit(InstructionKind::Phi(incoming));
+                    self.set_local(local, phi);
+                }
+            }
+        }
+        result.unwrap_or_else(|| self.const_int(0))
+    }
+
+    /// Lower `break` (with its value, if any) or `continue`: branch out of
+    /// the innermost loop's body, carrying its locals' current values.
+    /// Code after it is unreachable and goes in a block of its own.
+    fn leave_iteration(&mut self, to_header: bool, value: Option<ValueId>) -> ValueId {
+        let Some(targets) = self.loops.last() else {
+            return value.unwrap_or_else(|| self.const_int(0));
+        };
+        if let (true, Some((counter, ty))) = (to_header, targets.counter.clone()) {
+            self.step(counter, &ty);
+        }
+        let targets = self.loops.last().unwrap();
+        let values = targets.carried.iter().map(|&n| self.local(n)).collect();
+        let target = if to_header {
+            targets.header
+        } else {
+            targets.exit
+        };
+        let from = self.finish_block(Terminator::Branch(target));
+        let targets = self.loops.last_mut().unwrap();
+        match to_header {
+            true => targets.continues.push((from, values)),
+            false => targets.breaks.push((from, values, value)),
+        }
+        let rest = self.fresh_block();
+        self.start_block(rest);
+        self.const_int(0)
+    }
+
+    /// Add one to a range `for`'s counter
+    fn step(&mut self, counter: Symbol, ty: &IrType) {
+        let one = self.emit(InstructionKind::ConstInt(1, ty.clone()));
+        let next = self.emit(InstructionKind::Add(self.local(counter), one));
+        self.set_local(counter, next);
+    }
+
+    /// Lower `if` to a diamond:
+    ///
+    /// ```text
+    /// entry: ...; branch cond, then, else
+    /// then:  ...; branch join
+    /// else:  ...; branch join
+    /// join:  phi [then: value] [else: value]
+    /// ```
+    ///
+    /// Without an `else`, the false edge goes straight to the join. An `if`
+    /// of type `()` has no value to join, but locals assigned in either
+    /// branch still get a `Phi` after the one for the value.
+    fn lower_if(
+        &mut self,
+        cond: &TypedExpr,
+        then_block: &TypedBlock,
+        else_expr: Option<&TypedExpr>,
+        ty: &TypeInfo,
+    ) -> ValueId {
+        let cond = self.lower_expr(cond);
+        let mut assigned = Vec::new();
+        assigned_in_block(then_block, &mut assigned);
+        if let Some(else_expr) = else_expr {
+            assigned_in_expr(else_expr, &mut assigned);
+        }
+        let mut carried: Vec<Symbol> = Vec::new();
+        for name in assigned {
+            if !carried.contains(&name) && self.locals.iter().any(|(local, _)| *local == name) {
+                carried.push(name);
+            }
+        }
+        let before: Vec<_> = carried.iter().map(|&n| self.local(n)).collect();
+
+        let then_id = self.fresh_block();
+        let else_id = else_expr.map(|_| self.fresh_block());
+        let join = self.fresh_block();
+        let entry = self.finish_block(Terminator::CondBranch(
+            cond,
+            then_id,
+            else_id.unwrap_or(join),
+        ));
+
+        self.start_block(then_id);
+        let then_value = self.lower_block(then_block);
+        let then_locals: Vec<_> = carried.iter().map(|&n| self.local(n)).collect();
+        let then_end = self.finish_block(Terminator::Branch(join));
+        for (&name, &value) in carried.iter().zip(&before) {
+            self.set_local(name, value);
+        }
+
+        let (else_end, else_value) = match (else_expr, else_id) {
+            (Some(else_expr), Some(else_id)) => {
+                self.start_block(else_id);
+                let value = self.lower_expr(else_expr);
+                (self.finish_block(Terminator::Branch(join)), Some(value))
+            }
+            _ => (entry, None),
+        };
+        let else_locals: Vec<_> = carried.iter().map(|&n| self.local(n)).collect();
+
+        self.start_block(join);
+        let result = match (then_value, else_value) {
+            (Some(then_value), Some(else_value)) if self.lower_type(ty) != IrType::Void => self
+                .emit(InstructionKind::Phi(vec![
+                    (then_end, then_value),
+                    (else_end, else_value),
+                ])),
+            _ => self.const_int(0),
+        };
+        for (i, &name) in carried.iter().enumerate() {
+            let value = match (then_locals[i], else_locals[i]) {
+                (t, e) if t == e => t,
+                (t, e) => self.emit(InstructionKind::Phi(vec![(then_end, t), (else_end, e)])),
+            };
+            self.set_local(name, value);
+        }
+        result
+    }
+
+    /// Lower `base[index]`: a load of the element, or for a range index a
+    /// new slice. A slice is a pointer to its first element and a length.
+    fn lower_index(&mut self, base: &TypedExpr, index: &TypedExpr) -> ValueId {
+        let value = self.lower_expr(base);
+        let mut base_ty = &base.ty;
+        while let TypeInfo::Reference(_, inner) = base_ty {
+            base_ty = inner;
+        }
+        let (elem, len) = match base_ty {
+            TypeInfo::Array(elem, len) => (self.lower_type(elem), Some(*len)),
+            TypeInfo::Slice(elem) => (self.lower_type(elem), None),
+            _ => {
+                self.lower_expr(index);
+                return self.const_int(0);
+            }
+        };
+        let (_, len_offset) = self.slice_layout();
+        // An array value is the address of its elements
+        let data = match len {
+            Some(_) => value,
+            None => self.emit(InstructionKind::Load(value, IrType::I32)),
+        };
+
+        // TODO: Check indices against the length
+        let TypedExprKind::Range(start, end, inclusive) = &index.kind else {
+            let index = self.lower_expr(index);
+            let addr = self.emit(InstructionKind::ElementPtr(data, index, elem.size()));
+            return self.emit(InstructionKind::Load(addr, elem));
+        };
+        // A missing bound has the type of the other
+        let ty = match start.as_deref().or(end.as_deref()) {
+            Some(bound) => self.lower_type(&bound.ty),
+            None => self.int_type.clone(),
+        };
+        let start = match start {
+            Some(start) => self.lower_expr(start),
+            None => self.emit(InstructionKind::ConstInt(0, ty.clone())),
+        };
+        let mut end = match (end, len) {
+            (Some(end), _) => self.lower_expr(end),
+            (None, Some(len)) => self.emit(InstructionKind::ConstInt(len as i64, ty.clone())),
+            (None, None) => self.load_field(value, len_offset, &self.int_type.clone()),
+        };
+        if *inclusive {
+            let one = self.emit(InstructionKind::ConstInt(1, ty));
+            end = self.emit(InstructionKind::Add(end, one));
+        }
+        let ptr = self.emit(InstructionKind::ElementPtr(data, start, elem.size()));
+        let len = self.emit(InstructionKind::Sub(end, start));
+        self.new_slice(ptr, len)
+    }
+
+    /// The type of a slice, and the offset of its length after the pointer
+    fn slice_layout(&self) -> (IrType, u32) {
+        let len_offset = align_up(IrType::I32.size(), self.int_type.align());
+        let memory = IrType::Aggregate {
+            size: len_offset + self.int_type.size(),
+            align: self.int_type.align(),
+        };
+        (IrType::Ptr(Box::new(memory)), len_offset)
+    }
+
+    /// The current value of a local
+    fn local(&self, name: Symbol) -> ValueId {
+        let (_, value) = self.locals.iter().rev().find(|(n, _)| *n == name).unwrap();
+        *value
+    }
+
+    /// Give the innermost local called `name` a new value
+    fn set_local(&mut self, name: Symbol, value: ValueId) {
+        if let Some(local) = self.locals.iter_mut().rev().find(|(n, _)| *n == name) {
+            local.1 = value;
+        }
+    }
+
+    /// Lower a `match` to a test per arm, each branching to the arm's block
+    /// or on to the next test, with the arms' values joined by a `Phi`.
+    ///
+    /// A variant pattern compares the scrutinee's tag with the variant's;
+    /// the arm then loads the fields it binds from the payload.
+    fn lower_match(&mut self, scrutinee: &TypedExpr, arms: &[TypedMatchArm]) -> ValueId {
+        let value = self.lower_expr(scrutinee);
+        if arms.is_empty() {
+            // An empty match has type `!` and never produces a value
+            return self.const_int(0);
+        }
+        let layout = match &scrutinee.ty {
+            TypeInfo::Named(name) => self.enums.get(&Symbol::intern(name)).cloned(),
+            _ => None,
+        };
+        let tag = layout.map(|layout| self.load_tag(value, &layout));
+
+        let join = self.fresh_block();
+        let mut incoming = Vec::new();
+        for arm in arms {
+            let next = self.fresh_block();
+            let test = self.pattern_test(&arm.pattern, value, &scrutinee.ty, tag);
+            // The guard sees the names the pattern binds, so it's only
+            // evaluated once the pattern matched
+            let cond = match &arm.guard {
+                Some(guard) => {
+                    let guard_test = |this: &mut Self| {
+                        let scope = this.locals.len();
+                        this.bind_pattern(&arm.pattern, value, &scrutinee.ty);
+                        let cond = this.lower_expr(guard);
+                        this.locals.truncate(scope);
+                        Some(cond)
+                    };
+                    match test {
+                        Some(test) => Some(self.and_then(test, guard_test)),
+                        None => guard_test(self),
+                    }
+                }
+                None => test,
+            };
+            if let Some(cond) = cond {
+                self.branch_to_new_block(cond, next);
+            }
+            let scope = self.locals.len();
+            self.bind_pattern(&arm.pattern, value, &scrutinee.ty);
+            let result = self.lower_expr(&arm.body);
+            let from = self.finish_block(Terminator::Branch(join));
+            incoming.push((from, result));
+            self.locals.truncate(scope);
+            self.start_block(next);
+        }
+        // TODO: Drop this block once matches are checked for exhaustiveness
+        self.finish_block(Terminator::Unreachable);
+        self.start_block(join);
+        self.emit(InstructionKind::Phi(incoming))
+    }
+
+    /// End the current block with a branch on `cond`, continuing in a new
+    /// block if it holds and going to `otherwise` if it doesn't
+    fn branch_to_new_block(&mut self, cond: ValueId, otherwise: BlockId) {
+        let then_block = self.fresh_block();
+        self.finish_block(Terminator::CondBranch(cond, then_block, otherwise));
+        self.start_block(then_block);
+    }
+
+    /// `cond && rest`, with `rest` (`true` if it's `None`) only emitted
+    /// and evaluated if `cond` holds
+    fn and_then(
+        &mut self,
+        cond: ValueId,
+        rest: impl FnOnce(&mut Self) -> Option<ValueId>,
+    ) -> ValueId {
+        let then_block = self.fresh_block();
+        let else_block = self.fresh_block();
+        let join = self.fresh_block();
+        self.finish_block(Terminator::CondBranch(cond, then_block, else_block));
+        self.start_block(then_block);
+        let then_value = match rest(self) {
+            Some(value) => value,
+            None => self.emit(InstructionKind::ConstBool(true)),
+        };
+        let then_end = self.finish_block(Terminator::Branch(join));
+        self.start_block(else_block);
+        let else_value = self.emit(InstructionKind::ConstBool(false));
+        let else_end = self.finish_block(Terminator::Branch(join));
+        self.start_block(join);
+        self.emit(InstructionKind::Phi(vec![
+            (then_end, then_value),
+            (else_end, else_value),
+        ]))
+    }
+
+    /// The conjunction of `tests`, or `None` if there are none
+    fn all_of(&mut self, tests: Vec<ValueId>) -> Option<ValueId> {
+        tests
+            .into_iter()
+            .reduce(|a, b| self.emit(InstructionKind::And(a, b)))
+    }
+
+    /// Emit the test of whether `value` of type `ty` (with tag `tag`, for an
+    /// enum) matches `pattern`, or return `None` if it always does.
+    ///
+    /// A part of the value is only loaded once the tests it depends on
+    /// passed: an enum's fields once its tag matched, a slice's elements
+    /// once its length did.
+    fn pattern_test(
+        &mut self,
+        pattern: &Pattern,
+        value: ValueId,
+        ty: &TypeInfo,
+        tag: Option<ValueId>,
+    ) -> Option<ValueId> {
+        if let Some(variant) = self.pattern_variant(pattern) {
+            let layout = match ty {
+                TypeInfo::Named(name) => self.enums.get(&Symbol::intern(name)).cloned()?,
+                _ => return None,
+            };
+            let tag = tag.unwrap_or_else(|| self.load_tag(value, &layout));
+            let expected = self.const_int(variant.tag);
+            let tag_test = self.emit(InstructionKind::Eq(tag, expected));
+            let subpatterns: Vec<_> = variant_subpatterns(pattern, &variant)
+                .into_iter()
+                .filter(|(sub, _)| !self.always_matches(sub))
+                .collect();
+            if subpatterns.is_empty() {
+                return Some(tag_test);
+            }
+            return Some(self.and_then(tag_test, |this| {
+                let mut tests = Vec::new();
+                for (sub, i) in subpatterns {
+                    let (_, offset, field_ty) = &variant.fields[i];
+                    let field = this.load_field(value, *offset, field_ty);
+                    tests.extend(this.pattern_test(sub, field, &variant.field_types[i], None));
+                }
+                this.all_of(tests)
+            }));
+        }
+        match &pattern.kind {
+            PatternKind::Literal(lit) => {
+                let expected = self.lower_literal(lit, &self.lower_type(ty));
+                Some(self.emit(InstructionKind::Eq(value, expected)))
+            }
+            PatternKind::Range(lo, hi, inclusive) => {
+                let ir_ty = self.lower_type(ty);
+                let signed = !is_unsigned(ty);
+                let mut tests = Vec::new();
+                if let Some(PatternKind::Literal(lit)) = lo.as_deref().map(|p| &p.kind) {
+                    let lo = self.lower_literal(lit, &ir_ty);
+                    tests.push(self.emit(InstructionKind::Ge(value, lo, signed)));
+                }
+                if let Some(PatternKind::Literal(lit)) = hi.as_deref().map(|p| &p.kind) {
+                    let hi = self.lower_literal(lit, &ir_ty);
+                    tests.push(self.emit(if *inclusive {
+                        InstructionKind::Le(value, hi, signed)
+                    } else {
+                        InstructionKind::Lt(value, hi, signed)
+                    }));
+                }
+                self.all_of(tests)
+            }
+            PatternKind::Or(alternatives) => {
+                let mut tests = Vec::new();
+                for alt in alternatives {
+                    // One alternative that always matches makes the whole
+                    // pattern match
+                    tests.push(self.pattern_test(alt, value, ty, tag)?);
+                }
+                tests
+                    .into_iter()
+                    .reduce(|a, b| self.emit(InstructionKind::Or(a, b)))
+            }
+            PatternKind::Ref(_, inner) => self.pattern_test(inner, value, ty, tag),
+            PatternKind::Tuple(subpatterns) => {
+                let TypeInfo::Tuple(elem_tys) = ty else {
+                    return None;
+                };
+                let ir_ty = self.lower_type(ty);
+                let mut tests = Vec::new();
+                for (i, (sub, elem_ty)) in subpatterns.iter().zip(elem_tys).enumerate() {
+                    // TODO: Line up the elements after a `..` from the end
+                    if matches!(sub.kind, PatternKind::Rest(_)) {
+                        break;
+                    }
+                    if !self.always_matches(sub) {
+                        let elem = self.tuple_element(value, &ir_ty, i);
+                        tests.extend(self.pattern_test(sub, elem, elem_ty, None));
+                    }
+                }
+                self.all_of(tests)
+            }
+            PatternKind::Slice(subpatterns) => {
+                let slice = self.slice_parts(value, ty)?;
+                let rest = subpatterns
+                    .iter()
+                    .position(|sub| matches!(sub.kind, PatternKind::Rest(_)));
+                let fixed = self.const_int((subpatterns.len() - rest.is_some() as usize) as i64);
+                let len_test = self.emit(match rest {
+                    Some(_) => InstructionKind::Ge(slice.len, fixed, false),
+                    None => InstructionKind::Eq(slice.len, fixed),
+                });
+                if subpatterns.iter().all(|sub| self.always_matches(sub)) {
+                    return Some(len_test);
+                }
+                Some(self.and_then(len_test, |this| {
+                    let mut tests = Vec::new();
+                    for (i, sub) in subpatterns.iter().enumerate() {
+                        if !this.always_matches(sub) {
+                            let elem = this.slice_element(&slice, subpatterns.len(), rest, i);
+                            tests.extend(this.pattern_test(sub, elem, &slice.elem_ty, None));
+                        }
+                    }
+                    this.all_of(tests)
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `pattern` matches any value, so needs no test
+    fn always_matches(&self, pattern: &Pattern) -> bool {
+        match &pattern.kind {
+            _ if self.pattern_variant(pattern).is_some() => false,
+            PatternKind::Wildcard | PatternKind::Ident(..) | PatternKind::Rest(_) => true,
+            PatternKind::Ref(_, inner) => self.always_matches(inner),
+            PatternKind::Tuple(subpatterns) => subpatterns.iter().all(|p| self.always_matches(p)),
+            _ => false,
+        }
+    }
+
+    /// Element `i` of a tuple of IR type `ty`, a multi-value or the
+    /// address of one in memory
+    fn tuple_element(&mut self, value: ValueId, ty: &IrType, i: usize) -> ValueId {
+        match ty {
+            IrType::Ptr(inner) => {
+                let IrType::Tuple(elems) = &**inner else {
+                    unreachable!("tuples in memory are pointers to tuples");
+                };
+                self.load_field(value, tuple_offsets(elems)[i], &elems[i])
+            }
+            _ => self.emit(InstructionKind::TupleGet(value, i as u32)),
+        }
+    }
+
+    /// The elements and length of an array or slice `value` of type `ty`
+    fn slice_parts(&mut self, value: ValueId, mut ty: &TypeInfo) -> Option<SliceParts> {
+        while let TypeInfo::Reference(_, inner) = ty {
+            ty = inner;
+        }
+        let (elem_ty, len) = match ty {
+            TypeInfo::Array(elem, len) => (&**elem, Some(*len)),
+            TypeInfo::Slice(elem) => (&**elem, None),
+            _ => return None,
+        };
+        // An array value is the address of its elements
+        let (data, len) = match len {
+            Some(len) => (value, self.const_int(len as i64)),
+            None => {
+                let (_, len_offset) = self.slice_layout();
+                let data = self.emit(InstructionKind::Load(value, IrType::I32));
+                (
+                    data,
+                    self.load_field(value, len_offset, &self.int_type.clone()),
+                )
+            }
+        };
+        Some(SliceParts {
+            data,
+            len,
+            elem: self.lower_type(elem_ty),
+            elem_ty: elem_ty.clone(),
+        })
+    }
+
+    /// The element that sub-pattern `i` of a slice pattern of `count`
+    /// sub-patterns, with a `..` at `rest`, matches. Those after the `..`
+    /// count from the end.
+    fn slice_element(
+        &mut self,
+        slice: &SliceParts,
+        count: usize,
+        rest: Option<usize>,
+        i: usize,
+    ) -> ValueId {
+        let index = match rest {
+            Some(rest) if i > rest => {
+                let from_end = self.const_int((count - i) as i64);
+                self.emit(InstructionKind::Sub(slice.len, from_end))
+            }
+            _ => self.const_int(i as i64),
+        };
+        let addr = self.emit(InstructionKind::ElementPtr(
+            slice.data,
+            index,
+            slice.elem.size(),
+        ));
+        self.emit(InstructionKind::Load(addr, slice.elem.clone()))
+    }
+
+    /// A new slice of `len` elements starting at `ptr`
+    fn new_slice(&mut self, ptr: ValueId, len: ValueId) -> ValueId {
+        let (slice, len_offset) = self.slice_layout();
+        let IrType::Ptr(memory) = slice else {
+            unreachable!("slices are pointers");
+        };
+        let result = self.emit(InstructionKind::Alloca(*memory));
+        self.emit(InstructionKind::Store(result, ptr, IrType::I32));
+        let len_field = self.emit(InstructionKind::FieldPtr(result, len_offset));
+        self.emit(InstructionKind::Store(
+            len_field,
+            len,
+            self.int_type.clone(),
+        ));
+        result
+    }
+
+    /// Bind the names in `pattern`, which matched `value` of type `ty`
+    fn bind_pattern(&mut self, pattern: &Pattern, value: ValueId, ty: &TypeInfo) {
+        let variant = self.pattern_variant(pattern);
+        match (&pattern.kind, variant) {
+            (PatternKind::Ident(ident, _), None) => self.locals.push((ident.name, value)),
+            (PatternKind::TupleStruct(..), Some(variant)) => {
+                for (sub, i) in variant_subpatterns(pattern, &variant) {
+                    if !matches!(sub.kind, PatternKind::Wildcard) {
+                        let (_, offset, field_ty) = &variant.fields[i];
+                        let field = self.load_field(value, *offset, field_ty);
+                        self.bind_pattern(sub, field, &variant.field_types[i]);
+                    }
+                }
+            }
+            (PatternKind::Struct(_, field_patterns), Some(variant)) => {
+                for fp in field_patterns {
+                    let Some(i) = variant.fields.iter().position(|f| f.0 == fp.name.name) else {
+                        continue;
+                    };
+                    let (_, offset, field_ty) = &variant.fields[i];
+                    let field = self.load_field(value, *offset, field_ty);
+                    match &fp.pattern {
+                        Some(sub) => self.bind_pattern(sub, field, &variant.field_types[i]),
+                        None => self.locals.push((fp.name.name, field)),
+                    }
+                }
+            }
+            (PatternKind::Tuple(subpatterns), None) => {
+                let TypeInfo::Tuple(elem_tys) = ty else {
+                    return;
+                };
+                let ir_ty = self.lower_type(ty);
+                for (i, (sub, elem_ty)) in subpatterns.iter().zip(elem_tys).enumerate() {
+                    match sub.kind {
+                        PatternKind::Wildcard => continue,
+                        // TODO: Line up the elements after a `..` from the end
+                        PatternKind::Rest(_) => break,
+                        _ => {}
+                    }
+                    let elem = self.tuple_element(value, &ir_ty, i);
+                    self.bind_pattern(sub, elem, elem_ty);
+                }
+            }
+            (PatternKind::Slice(subpatterns), None) => {
+                let Some(slice) = self.slice_parts(value, ty) else {
+                    return;
+                };
+                let rest = subpatterns
+                    .iter()
+                    .position(|sub| matches!(sub.kind, PatternKind::Rest(_)));
+                for (i, sub) in subpatterns.iter().enumerate() {
+                    match &sub.kind {
+                        PatternKind::Wildcard | PatternKind::Rest(None) => {}
+                        // `tail @ ..` is a slice of the elements in between
+                        PatternKind::Rest(Some(name)) => {
+                            let start = self.const_int(i as i64);
+                            let ptr = self.emit(InstructionKind::ElementPtr(
+                                slice.data,
+                                start,
+                                slice.elem.size(),
+                            ));
+                            let others = self.const_int(subpatterns.len() as i64 - 1);
+                            let len = self.emit(InstructionKind::Sub(slice.len, others));
+                            let tail = self.new_slice(ptr, len);
+                            self.locals.push((name.name, tail));
+                        }
+                        _ => {
+                            let elem = self.slice_element(&slice, subpatterns.len(), rest, i);
+                            self.bind_pattern(sub, elem, &slice.elem_ty);
+                        }
+                    }
+                }
+            }
+            (PatternKind::Ref(_, inner), _) => self.bind_pattern(inner, value, ty),
+            _ => {}
+        }
+    }
+
+    /// The enum variant a pattern names, if any
+    fn pattern_variant(&self, pattern: &Pattern) -> Option<VariantLayout> {
+        let name = match &pattern.kind {
+            PatternKind::Ident(ident, _) => ident.name,
+            PatternKind::Path(path)
+            | PatternKind::TupleStruct(path, _)
+            | PatternKind::Struct(path, _) => path_symbol(path),
+            _ => return None,
+        };
+        self.variants.get(&name).cloned()
+    }
+
+    /// Compute an enum's layout and register it and its variants
+    fn layout_enum(&mut self, e: &TypedEnum) {
+        let tag_type = self.int_type.clone();
+        let repr = match e.repr {
+            Repr::Packed => Repr::Packed,
+            Repr::Default | Repr::C => Repr::C,
+        };
+        let mut payload_size = 0;
+        let mut payload_align = 1;
+        let mut variants = Vec::new();
+        for variant in &e.variants {
+            let fields: Vec<_> = variant
+                .fields
+                .iter()
+                .map(|(name, ty)| (*name, self.lower_type(ty)))
+                .collect();
+            let payload = Layout::of(&fields, repr);
+            payload_size = payload_size.max(payload.size);
+            payload_align = payload_align.max(payload.align);
+            variants.push((variant, payload.fields));
+        }
+        let tag_align = match repr {
+            Repr::Packed => 1,
+            _ => tag_type.align(),
+        };
+
+        let payload_offset = align_up(tag_type.size(), payload_align);
+        let align = tag_align.max(payload_align);
+        let layout = EnumLayout {
+            size: align_up(payload_offset + payload_size, align),
+            tag_type,
+            payload_offset,
+            payload_size,
+            align,
+        };
+        for (variant, mut fields) in variants {
+            for (_, offset, _) in &mut fields {
+                *offset += payload_offset;
+            }
+            let variant_layout = VariantLayout {
+                enum_name: e.name,
+                tag: variant.discriminant,
+                fields,
+                field_types: variant.fields.iter().map(|(_, ty)| ty.clone()).collect(),
+            };
+            let qualified = Symbol::intern(&format!("{}::{}", e.name, variant.name));
+            self.variants.insert(qualified, variant_layout.clone());
+            self.variants.insert(variant.name, variant_layout);
+        }
+        self.enums.insert(e.name, layout);
+    }
+
+    /// Build a value of an enum variant from its field values
+    fn construct_variant(&mut self, variant: &VariantLayout, args: &[ValueId]) -> ValueId {
+        let layout = self.enums[&variant.enum_name].clone();
+        let tag = self.const_int(variant.tag);
+        if layout.is_tag_only() {
+            return tag;
+        }
+        // TODO: Heap-allocate values that outlive the function
+        let ptr = self.emit(InstructionKind::Alloca(layout.memory()));
+        self.emit(InstructionKind::Store(ptr, tag, layout.tag_type));
+        for ((_, offset, ty), arg) in variant.fields.iter().zip(args) {
+            let field = self.emit(InstructionKind::FieldPtr(ptr, *offset));
+            self.emit(InstructionKind::Store(field, *arg, ty.clone()));
+        }
+        ptr
+    }
+
+    /// The tag of an enum value, which is at its start
+    fn load_tag(&mut self, value: ValueId, layout: &EnumLayout) -> ValueId {
+        if layout.is_tag_only() {
+            value
+        } else {
+            self.emit(InstructionKind::Load(value, layout.tag_type.clone()))
+        }
+    }
+
+    /// Load the field at `offset` from the enum value at `ptr`
+    fn load_field(&mut self, ptr: ValueId, offset: u32, ty: &IrType) -> ValueId {
+        let field = self.emit(InstructionKind::FieldPtr(ptr, offset));
+        self.emit(InstructionKind::Load(field, ty.clone()))
+    }
+
+    /// The index of the global a name refers to, unless a local shadows it
+    fn global(&self, name: Symbol) -> Option<usize> {
+        if self.locals.iter().any(|(n, _)| *n == name) {
+            return None;
+        }
+        self.globals.iter().position(|g| g.name == name.as_str())
+    }
+
+    /// Lower a literal of type `ty`
+    fn lower_literal(&mut self, lit: &Literal, ty: &IrType) -> ValueId {
+        match lit {
+            // The low 64 bits: a `u64` above `i64::MAX` keeps its bit pattern
+            Literal::Int(n) => self.emit(InstructionKind::ConstInt(*n as i64, ty.clone())),
+            Literal::Float(n) => self.emit(InstructionKind::ConstFloat(*n)),
+            Literal::Bool(b) => self.emit(InstructionKind::ConstBool(*b)),
+            Literal::String(s) => self.emit(InstructionKind::ConstString(s.clone())),
+            Literal::Char(c) => self.emit(InstructionKind::ConstInt(*c as i64, ty.clone())),
+            // TODO: Give byte strings their own data segment
+            Literal::Bytes(b) => self.emit(InstructionKind::ConstString(
+                String::from_utf8_lossy(b).into_owned(),
+            )),
+        }
+    }
+
+    fn lower_type(&self, ty: &TypeInfo) -> IrType {
+        match ty {
+            // TODO: Lower 128-bit integers to a pair of `I64`s
+            TypeInfo::Int(width) if width.bits() > 32 => IrType::I64,
+            TypeInfo::Int(_) => IrType::I32,
+            TypeInfo::Float => IrType::F64,
+            TypeInfo::Bool => IrType::Bool,
+            // Address and length, in bytes
+            TypeInfo::String => IrType::Tuple(vec![IrType::I32, IrType::I32]),
+            TypeInfo::Unit | TypeInfo::Never => IrType::Void,
+            TypeInfo::Array(elem, len) => {
+                let elem = self.lower_type(elem);
+                IrType::Ptr(Box::new(IrType::Aggregate {
+                    size: elem.size() * *len as u32,
+                    align: elem.align(),
+                }))
+            }
+            TypeInfo::Slice(_) => self.slice_layout().0,
+            TypeInfo::Tuple(elems) => {
+                let elems: Vec<_> = elems.iter().map(|e| self.lower_type(e)).collect();
+                // A tuple holding tuples (or `()`) is too big to be one
+                // multi-value, so it's passed by address
+                match elems
+                    .iter()
+                    .any(|e| matches!(e, IrType::Tuple(_) | IrType::Void))
+                {
+                    true => IrType::Ptr(Box::new(IrType::Tuple(elems))),
+                    false => IrType::Tuple(elems),
+                }
+            }
+            TypeInfo::Reference(_, inner)
+                if matches!(**inner, TypeInfo::Array(..) | TypeInfo::Slice(_)) =>
+            {
+                self.lower_type(inner)
+            }
+            TypeInfo::Named(name) => match self.enums.get(&Symbol::intern(name)) {
+                Some(layout) if layout.is_tag_only() => layout.tag_type.clone(),
+                Some(layout) => IrType::Ptr(Box::new(layout.memory())),
+                None => IrType::I64,
+            },
+            _ => IrType::I64, // Default
+        }
+    }
+}
+
+/// Collect the variables assigned anywhere in `block`
+fn assigned_in_block(block: &TypedBlock, out: &mut Vec<Symbol>) {
+    for stmt in &block.stmts {
+        match stmt {
+            TypedStmt::Let { value, .. } => {
+                if let Some(value) = value {
+                    assigned_in_expr(value, out);
+                }
+            }
+            TypedStmt::Expr(expr) => assigned_in_expr(expr, out),
+        }
+    }
+}
+
+/// Collect the variables assigned anywhere in `expr`
+fn assigned_in_expr(expr: &TypedExpr, out: &mut Vec<Symbol>) {
+    match &expr.kind {
+        TypedExprKind::Binary(left, op, right) => {
+            if let (BinOp::Assign, TypedExprKind::Variable(name)) = (op, &left.kind) {
+                if !out.contains(name) {
+                    out.push(*name);
+                }
+            }
+            assigned_in_expr(left, out);
+            assigned_in_expr(right, out);
+        }
+        TypedExprKind::Literal(_) | TypedExprKind::Variable(_) | TypedExprKind::Continue => {}
+        TypedExprKind::Unary(_, inner)
+        | TypedExprKind::Try(inner)
+        | TypedExprKind::Ref(_, inner)
+        | TypedExprKind::Cast(inner)
+        | TypedExprKind::Break(Some(inner))
+        | TypedExprKind::Return(Some(inner))
+        | TypedExprKind::Closure(_, inner) => assigned_in_expr(inner, out),
+        TypedExprKind::Break(None) | TypedExprKind::Return(None) => {}
+        TypedExprKind::Call(func, args) => {
+            assigned_in_expr(func, out);
+            for arg in args {
+                assigned_in_expr(arg, out);
+            }
+        }
+        TypedExprKind::Tuple(elems) | TypedExprKind::Array(elems) => {
+            for elem in elems {
+                assigned_in_expr(elem, out);
+            }
+        }
+        TypedExprKind::If(cond, then_block, else_expr) => {
+            assigned_in_expr(cond, out);
+            assigned_in_block(then_block, out);
+            if let Some(else_expr) = else_expr {
+                assigned_in_expr(else_expr, out);
+            }
+        }
+        TypedExprKind::Match(scrutinee, arms) => {
+            assigned_in_expr(scrutinee, out);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    assigned_in_expr(guard, out);
+                }
+                assigned_in_expr(&arm.body, out);
+            }
+        }
+        TypedExprKind::Block(block) | TypedExprKind::Loop(block) => assigned_in_block(block, out),
+        TypedExprKind::While(cond, body) | TypedExprKind::For(_, cond, body) => {
+            assigned_in_expr(cond, out);
+            assigned_in_block(body, out);
+        }
+        TypedExprKind::Range(start, end, _) => {
+            for bound in [start, end].into_iter().flatten() {
+                assigned_in_expr(bound, out);
+            }
+        }
+        TypedExprKind::Index(base, index) => {
+            assigned_in_expr(base, out);
+            assigned_in_expr(index, out);
+        }
+    }
+}
+
+/// Simplify a function's control-flow graph before codegen.
+///
+/// Merges a block ending in `Branch` into its target when it is the target's
+/// only predecessor, and removes empty blocks that just branch elsewhere.
+/// The first block is the entry and is never removed. Blocks whose removal
+/// would change the meaning of a `Phi` are left alone.
+pub fn simplify_cfg(func: &mut Function) {
+    while merge_fallthrough(func) || remove_empty_block(func) {}
+}
+
+/// Number of incoming edges per block, counting function entry as one
+fn predecessor_counts(func: &Function) -> HashMap<BlockId, usize> {
+    let mut counts = HashMap::new();
+    if let Some(entry) = func.blocks.first() {
+        counts.insert(entry.id, 1);
+    }
+    for block in &func.blocks {
+        for succ in block.terminator.successors() {
+            *counts.entry(succ).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Whether any `Phi` in the function has an incoming edge from `id`
+fn phi_mentions(func: &Function, id: BlockId) -> bool {
+    func.blocks.iter().flat_map(|b| &b.instructions).any(|i| {
+        matches!(&i.kind, InstructionKind::Phi(incoming) if incoming.iter().any(|(b, _)| *b == id))
+    })
+}
+
+/// Merge one fallthrough pair, returning whether anything changed
+fn merge_fallthrough(func: &mut Function) -> bool {
+    let counts = predecessor_counts(func);
+    let pair = func.blocks.iter().enumerate().find_map(|(i, block)| {
+        let Terminator::Branch(target) = block.terminator else {
+            return None;
+        };
+        let j = func.blocks.iter().position(|b| b.id == target)?;
+        let starts_with_phi = matches!(
+            func.blocks[j].instructions.first(),
+            Some(Instruction {
+                kind: InstructionKind::Phi(_),
+                ..
+            })
+        );
+        (i != j && j != 0 && counts.get(&target) == Some(&1) && !starts_with_phi).then_some((i, j))
+    });
+    let Some((i, j)) = pair else {
+        return false;
+    };
+
+    let absorbed = func.blocks.remove(j);
+    let i = if j < i { i - 1 } else { i };
+    let into = func.blocks[i].id;
+    func.blocks[i].instructions.extend(absorbed.instructions);
+    func.blocks[i].terminator = absorbed.terminator;
+
+    // Edges that left the absorbed block now leave the merged one
+    for instr in func.blocks.iter_mut().flat_map(|b| &mut b.instructions) {
+        if let InstructionKind::Phi(incoming) = &mut instr.kind {
+            for (pred, _) in incoming.iter_mut() {
+                if *pred == absorbed.id {
+                    *pred = into;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Remove one empty forwarding block, returning whether anything changed
+fn remove_empty_block(func: &mut Function) -> bool {
+    let found = func
+        .blocks
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find_map(|(i, block)| match block.terminator {
+            Terminator::Branch(target)
+                if block.instructions.is_empty()
+                    && target != block.id
+                    && !phi_mentions(func, block.id) =>
+            {
+                Some((i, target))
+            }
+            _ => None,
+        });
+    let Some((i, target)) = found else {
+        return false;
+    };
+
+    let removed = func.blocks.remove(i).id;
+    for block in &mut func.blocks {
+        block.terminator.retarget(removed, target);
+    }
+    true
+}
+
+/// Render each function's control-flow graph as Graphviz DOT.
+///
+/// Every function is a cluster of its blocks. A block's node lists its
+/// instructions and terminator, and its edges go to the terminator's
+/// targets, labelled `true` and `false` for a conditional branch. Render
+/// with `dot -Tsvg`.
+pub fn to_dot(module: &Module) -> String {
+    let mut out = String::from("digraph module {\n    node [shape=box, fontname=monospace];\n");
+    for (f, func) in module.functions.iter().enumerate() {
+        let node = |block: BlockId| format!("f{}_b{}", f, block.0);
+        out.push_str(&format!("    subgraph cluster_{} {{\n", f));
+        out.push_str(&format!("        label={};\n", dot_string(&func.name)));
+        for block in &func.blocks {
+            let mut label = format!("b{}:\n", block.id.0);
+            for instr in &block.instructions {
+                label.push_str(&format!("v{} = {:?}\n", instr.result.0, instr.kind));
+            }
+            label.push_str(&format!("{:?}\n", block.terminator));
+            out.push_str(&format!(
+                "        {} [label={}];\n",
+                node(block.id),
+                dot_string(&label)
+            ));
+        }
+        for block in &func.blocks {
+            let edges: Vec<_> = match block.terminator {
+                Terminator::CondBranch(_, then_block, else_block) => {
+                    vec![
+                        (then_block, " [label=true]"),
+                        (else_block, " [label=false]"),
+                    ]
+                }
+                _ => block
+                    .terminator
+                    .successors()
+                    .into_iter()
+                    .map(|b| (b, ""))
+                    .collect(),
+            };
+            for (target, attrs) in edges {
+                out.push_str(&format!(
+                    "        {} -> {}{};\n",
+                    node(block.id),
+                    node(target),
+                    attrs
+                ));
+            }
+        }
+        out.push_str("    }\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// `text` as a quoted DOT string, with lines left-justified
+fn dot_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\l");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+    use crate::types::check;
+
+    #[test]
+    fn test_assoc_const_is_inlined() {
+        let source = "struct P {} impl P { const N: i64 = 7; } fn main() -> i64 { P::N }";
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let ir = lower(&check(&ast).unwrap());
+        let instructions = &ir.functions[0].blocks[0].instructions;
+        assert!(matches!(
+            instructions[0].kind,
+            InstructionKind::ConstInt(7, _)
+        ));
+    }
+
+    #[test]
+    fn test_unit_variant_lowers_to_discriminant() {
+        let source = "enum E { A = 3, B } fn f() -> E { E::B } fn g() -> E { A }";
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let ir = lower(&check(&ast).unwrap());
+        let tags: Vec<_> = ir
+            .functions
+            .iter()
+            .map(|f| &f.blocks[0].instructions[0].kind)
+            .collect();
+        assert!(matches!(
+            tags[..],
+            [
+                InstructionKind::ConstInt(4, _),
+                InstructionKind::ConstInt(3, _)
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_variants_sharing_a_name_keep_their_own_tags() {
+        let source = "enum A { X = 1 } enum B { Y, X = 5 }
+            fn a() -> A { A::X } fn b() -> B { B::X } fn y() -> B { Y }";
+        let ir = lower_source(source);
+        let tags: Vec<_> = ir
+            .functions
+            .iter()
+            .map(|f| &f.blocks[0].instructions[0].kind)
+            .collect();
+        assert!(matches!(
+            tags[..],
+            [
+                InstructionKind::ConstInt(1, _),
+                InstructionKind::ConstInt(5, _),
+                InstructionKind::ConstInt(0, _)
+            ]
+        ));
+    }
+
+    fn lower_source(source: &str) -> Module {
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        lower(&check(&ast).unwrap())
+    }
+
+    fn instructions(f: &Function) -> impl Iterator<Item = &InstructionKind> {
+        f.blocks
+            .iter()
+            .flat_map(|b| &b.instructions)
+            .map(|i| &i.kind)
+    }
+
+    #[test]
+    fn test_statics_lower_to_globals() {
+        let source = "static mut COUNTER: i64 = 0; static RATE: f64 = 0.5;
+            fn bump() { COUNTER = COUNTER + 1; }
+            fn shadow(COUNTER: i64) -> i64 { COUNTER }";
+        let ir = lower_source(source);
+        assert_eq!(ir.globals.len(), 2);
+        assert!(ir.globals[0].mutable && !ir.globals[1].mutable);
+        assert_eq!(ir.globals[1].ty, IrType::F64);
+        assert!(matches!(ir.globals[1].init, InstructionKind::ConstFloat(r) if r == 0.5));
+
+        let bump: Vec<_> = instructions(&ir.functions[0]).collect();
+        assert!(matches!(bump[0], InstructionKind::GlobalGet(0)));
+        assert!(matches!(
+            bump.last(),
+            Some(InstructionKind::GlobalSet(0, _))
+        ));
+        let shadow = instructions(&ir.functions[1]);
+        assert!(!shadow
+            .into_iter()
+            .any(|i| matches!(i, InstructionKind::GlobalGet(_))));
+    }
+
+    #[test]
+    fn test_enum_construction_stores_tag_and_payload() {
+        let source = "enum E { A(i64), B(bool, f64), C } fn f() -> E { E::B(true, 1.5) }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        // An 8-byte tag, then a payload sized for `B`: a bool padded to 8 and an f64
+        let memory = IrType::Aggregate { size: 24, align: 8 };
+        assert_eq!(f.return_type, IrType::Ptr(Box::new(memory.clone())));
+        let stores: Vec<_> = instructions(f)
+            .filter_map(|kind| match kind {
+                InstructionKind::Store(_, _, ty) => Some(ty.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(stores, [IrType::I64, IrType::Bool, IrType::F64]);
+        let offsets: Vec<_> = instructions(f)
+            .filter_map(|kind| match kind {
+                InstructionKind::FieldPtr(_, offset) => Some(*offset),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(offsets, [8, 16]);
+        assert!(
+            instructions(f).any(|kind| matches!(kind, InstructionKind::Alloca(m) if *m == memory))
+        );
+        assert!(instructions(f).any(|kind| matches!(kind, InstructionKind::ConstInt(1, _))));
+    }
+
+    #[test]
+    fn test_packed_enum_has_no_padding() {
+        let source = "#[repr(packed)] enum E { A(i32, i64), B } fn f() -> E { E::A(1, 2) }";
+        let f = &lower_source(source).functions[0];
+        // An 8-byte tag, a 4-byte i32 and an i64 straight after it
+        let memory = IrType::Aggregate { size: 20, align: 1 };
+        assert_eq!(f.return_type, IrType::Ptr(Box::new(memory)));
+        let offsets: Vec<_> = instructions(f)
+            .filter_map(|kind| match kind {
+                InstructionKind::FieldPtr(_, offset) => Some(*offset),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(offsets, [8, 12]);
+    }
+
+    /// The layout of the struct called `name` in `source`
+    fn struct_layout(source: &str, name: &str) -> Layout {
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let mut lowerer = Lowerer::new();
+        lowerer.lower_program(&check(&ast).unwrap());
+        lowerer.structs[&Symbol::intern(name)].clone()
+    }
+
+    fn offsets(layout: &Layout) -> Vec<u32> {
+        layout.fields.iter().map(|(_, offset, _)| *offset).collect()
+    }
+
+    #[test]
+    fn test_repr_c_struct_follows_c_rules() {
+        let layout = struct_layout("#[repr(C)] struct S { a: i32, b: i64, c: i32 }", "S");
+        // Declaration order, `b` aligned to 8, and the size rounded up to 8
+        assert_eq!(offsets(&layout), [0, 8, 16]);
+        assert_eq!((layout.size, layout.align), (24, 8));
+    }
+
+    #[test]
+    fn test_packed_struct_has_no_padding() {
+        let layout = struct_layout("#[repr(packed)] struct S { a: i32, b: i64, c: i32 }", "S");
+        assert_eq!(offsets(&layout), [0, 4, 12]);
+        assert_eq!((layout.size, layout.align), (16, 1));
+        // `packed` wins over `C`, in either order
+        let both = struct_layout("#[repr(C, packed)] struct S { a: i32, b: i64 }", "S");
+        assert_eq!(offsets(&both), [0, 4]);
+        let both = struct_layout(
+            "#[repr(packed)] #[repr(C)] struct S { a: i32, b: i64 }",
+            "S",
+        );
+        assert_eq!(offsets(&both), [0, 4]);
+    }
+
+    #[test]
+    fn test_default_struct_layout_sorts_by_alignment() {
+        let layout = struct_layout("struct S { a: i32, b: i64, c: i32 }", "S");
+        // `b` first, then `a` and `c` in declaration order
+        assert_eq!(offsets(&layout), [8, 0, 12]);
+        assert_eq!((layout.size, layout.align), (16, 8));
+        let names: Vec<_> = layout
+            .fields
+            .iter()
+            .map(|(n, _, _)| n.to_string())
+            .collect();
+        assert_eq!(names, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_match_branches_on_enum_tag() {
+        let source = "enum O { S(i64), N = 5 } \
+                      fn f(o: O) -> i64 { match o { O::S(x) => x, O::N => 0, } }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        let kind = |v: ValueId| {
+            let mut all = f.blocks.iter().flat_map(|b| &b.instructions);
+            &all.find(|i| i.result == v).unwrap().kind
+        };
+
+        // The entry block loads the tag from the start of `o` and tests it
+        let Terminator::CondBranch(cond, some, _) = f.blocks[0].terminator else {
+            panic!(
+                "Expected a branch on the tag, got {:?}",
+                f.blocks[0].terminator
+            );
+        };
+        let InstructionKind::Eq(tag, expected) = kind(cond) else {
+            panic!("Expected a tag comparison");
+        };
+        assert!(matches!(kind(*tag), InstructionKind::Load(_, IrType::I64)));
+        assert!(matches!(kind(*expected), InstructionKind::ConstInt(0, _)));
+
+        // `N`'s test uses its explicit discriminant
+        assert!(instructions(f).any(|kind| matches!(kind, InstructionKind::ConstInt(5, _))));
+
+        // The `S` arm loads `x` from the payload
+        let some = f.blocks.iter().find(|b| b.id == some).unwrap();
+        assert!(matches!(
+            some.instructions[..],
+            [
+                Instruction {
+                    kind: InstructionKind::FieldPtr(_, 8),
+                    ..
+                },
+                Instruction {
+                    kind: InstructionKind::Load(_, IrType::I64),
+                    ..
+                },
+            ]
+        ));
+
+        // Both arms' values meet in a phi
+        assert!(instructions(f).any(|kind| matches!(kind, InstructionKind::Phi(v) if v.len() == 2)));
+    }
+
+    #[test]
+    fn test_match_binds_struct_variant_fields() {
+        let source = "enum S { Rect { w: i64, h: i64 }, Dot } \
+                      fn f(s: S) -> i64 { match s { S::Rect { h, w: width } => width - h, Dot => 0 } }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        // Each field is loaded once from its offset in the payload
+        let mut offsets: Vec<_> = instructions(f)
+            .filter_map(|kind| match kind {
+                InstructionKind::FieldPtr(_, offset) => Some(*offset),
+                _ => None,
+            })
+            .collect();
+        offsets.sort();
+        assert_eq!(offsets, [8, 16]);
+        assert!(instructions(f).any(|kind| matches!(kind, InstructionKind::Sub(..))));
+    }
+
+    #[test]
+    fn test_match_on_literals_tests_each_arm() {
+        let ir = lower_source("fn f(n: i64) -> i64 { match n { 1 => 10, 2 => 20, _ => 30, } }");
+        let f = &ir.functions[0];
+        let tests = instructions(f)
+            .filter(|kind| matches!(kind, InstructionKind::Eq(..)))
+            .count();
+        assert_eq!(tests, 2);
+        assert!(instructions(f).any(|kind| matches!(kind, InstructionKind::Phi(v) if v.len() == 3)));
+    }
+
+    #[test]
+    fn test_match_on_or_pattern_tests_each_alternative() {
+        let ir = lower_source("fn f(n: i64) -> i64 { match n { 1 | 2 | 3 => 10, _ => 0 } }");
+        let f = &ir.functions[0];
+        let count =
+            |test: fn(&InstructionKind) -> bool| instructions(f).filter(|k| test(k)).count();
+        assert_eq!(count(|k| matches!(k, InstructionKind::Eq(..))), 3);
+        assert_eq!(count(|k| matches!(k, InstructionKind::Or(..))), 2);
+        // An alternative that always matches needs no test
+        let ir = lower_source("fn f(n: i64) -> i64 { match n { 1 | _ => 10 } }");
+        assert!(!instructions(&ir.functions[0]).any(|k| matches!(k, InstructionKind::Or(..))));
+    }
+
+    #[test]
+    fn test_match_on_ranges_tests_both_bounds() {
+        let ir = lower_source(
+            "fn f(n: i64) -> i64 { match n { -9..=0 => 1, 1..10 => 2, 10.. => 3, _ => 4 } }",
+        );
+        let f = &ir.functions[0];
+        let count =
+            |test: fn(&InstructionKind) -> bool| instructions(f).filter(|k| test(k)).count();
+        assert_eq!(count(|k| matches!(k, InstructionKind::Ge(..))), 3);
+        assert_eq!(count(|k| matches!(k, InstructionKind::Le(..))), 1);
+        assert_eq!(count(|k| matches!(k, InstructionKind::Lt(..))), 1);
+        assert_eq!(count(|k| matches!(k, InstructionKind::And(..))), 2);
+    }
+
+    #[test]
+    fn test_match_on_slices_tests_length_then_elements() {
+        let source = "fn f(s: &[i64]) -> i64 { match s { [] => 0, [1, ..] => 1, [.., x] => x } }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        let kind = |v: ValueId| {
+            let mut all = f.blocks.iter().flat_map(|b| &b.instructions);
+            &all.find(|i| i.result == v).unwrap().kind
+        };
+        // `[]` needs exactly no elements, `[1, ..]` at least one
+        assert!(
+            instructions(f).any(|k| matches!(k, InstructionKind::Eq(_, n)
+            if matches!(kind(*n), InstructionKind::ConstInt(0, _))))
+        );
+        assert!(instructions(f).any(|k| matches!(k, InstructionKind::Ge(..))));
+        // The first element is only loaded in the block after the length test
+        let entry = &f.blocks[0].instructions;
+        assert!(!entry
+            .iter()
+            .any(|i| matches!(i.kind, InstructionKind::ElementPtr(..))));
+        assert!(instructions(f).any(|k| matches!(k, InstructionKind::ElementPtr(..))));
+        // `x` is the last element, at `len - 1`
+        assert!(instructions(f).any(|k| matches!(k, InstructionKind::Sub(..))));
+
+        // An array has a known length
+        let ir = lower_source("fn f() -> i64 { match [1, 2, 3] { [] => 0, _ => 1 } }");
+        let f = &ir.functions[0];
+        let consts: Vec<_> = instructions(f)
+            .filter_map(|k| match k {
+                InstructionKind::ConstInt(n, _) => Some(*n),
+                _ => None,
+            })
+            .collect();
+        assert!(consts.contains(&3) && consts.contains(&0));
+    }
+
+    #[test]
+    fn test_match_tests_variant_sub_patterns() {
+        let source = "enum O { No, Yes(i64) } \
+                      fn f(o: O) -> i64 { match o { O::Yes(5) => 1, O::Yes(x) => x, O::No => 0 } }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        // The payload is compared with 5 once the tag matched
+        assert!(instructions(f).any(|k| matches!(k, InstructionKind::ConstInt(5, _))));
+        let equalities = instructions(f)
+            .filter(|k| matches!(k, InstructionKind::Eq(..)))
+            .count();
+        assert_eq!(equalities, 4);
+        let entry = &f.blocks[0].instructions;
+        assert!(!entry
+            .iter()
+            .any(|i| matches!(i.kind, InstructionKind::FieldPtr(..))));
+    }
+
+    fn block(id: usize, values: &[usize], terminator: Terminator) -> BasicBlock {
+        BasicBlock {
+            id: BlockId(id),
+            instructions: values
+                .iter()
+                .map(|&v| Instruction {
+                    result: ValueId(v),
+                    kind: InstructionKind::ConstInt(v as i64, IrType::I64),
+                })
+                .collect(),
+            terminator,
+        }
+    }
+
+    fn function(blocks: Vec<BasicBlock>) -> Function {
+        Function {
+            name: "f".to_string(),
+            params: Vec::new(),
+            return_type: IrType::Void,
+            blocks,
+            doc: None,
+        }
+    }
+
+    #[test]
+    fn test_fallthrough_chain_collapses() {
+        let mut f = function(vec![
+            block(0, &[0], Terminator::Branch(BlockId(2))),
+            block(1, &[2], Terminator::Return(Some(ValueId(2)))),
+            block(2, &[], Terminator::Branch(BlockId(3))),
+            block(3, &[1], Terminator::Branch(BlockId(1))),
+        ]);
+        simplify_cfg(&mut f);
+
+        assert_eq!(f.blocks.len(), 1);
+        let results: Vec<_> = f.blocks[0]
+            .instructions
+            .iter()
+            .map(|i| i.result.0)
+            .collect();
+        assert_eq!(results, [0, 1, 2]);
+        assert!(matches!(
+            f.blocks[0].terminator,
+            Terminator::Return(Some(ValueId(2)))
+        ));
+    }
+
+    #[test]
+    fn test_join_block_is_kept() {
+        // Both arms reach the join, so it has two predecessors
+        let mut f = function(vec![
+            block(
+                0,
+                &[0],
+                Terminator::CondBranch(ValueId(0), BlockId(1), BlockId(2)),
+            ),
+            block(1, &[], Terminator::Branch(BlockId(3))),
+            block(2, &[1], Terminator::Branch(BlockId(3))),
+            block(3, &[2], Terminator::Return(None)),
+        ]);
+        simplify_cfg(&mut f);
+
+        // The empty arm is bypassed, the other arm and the join remain
+        let ids: Vec<_> = f.blocks.iter().map(|b| b.id.0).collect();
+        assert_eq!(ids, [0, 2, 3]);
+        assert!(matches!(
+            f.blocks[0].terminator,
+            Terminator::CondBranch(_, BlockId(3), BlockId(2))
+        ));
+    }
+
+    #[test]
+    fn test_phi_edges_follow_merge() {
+        let phi = Instruction {
+            result: ValueId(9),
+            kind: InstructionKind::Phi(vec![(BlockId(2), ValueId(1)), (BlockId(0), ValueId(0))]),
+        };
+        let mut join = block(3, &[], Terminator::Return(Some(ValueId(9))));
+        join.instructions.push(phi);
+        let mut f = function(vec![
+            block(
+                0,
+                &[0],
+                Terminator::CondBranch(ValueId(0), BlockId(1), BlockId(3)),
+            ),
+            block(1, &[], Terminator::Branch(BlockId(2))),
+            block(2, &[1], Terminator::Branch(BlockId(3))),
+            join,
+        ]);
+        simplify_cfg(&mut f);
+
+        // Block 2 was merged into block 1, so the phi's edge now comes from 1
+        let ids: Vec<_> = f.blocks.iter().map(|b| b.id.0).collect();
+        assert_eq!(ids, [0, 1, 3]);
+        let InstructionKind::Phi(incoming) = &f.blocks[2].instructions[0].kind else {
+            panic!("Expected phi");
+        };
+        assert_eq!(incoming[0], (BlockId(1), ValueId(1)));
+    }
+
+    #[test]
+    fn test_declared_integer_widths_lower_to_their_types() {
+        let ir = lower_source("fn f(a: i32, b: u8, c: i64, d: usize) -> i32 { a }");
+        let f = &ir.functions[0];
+        let params: Vec<_> = f.params.iter().map(|(_, ty)| ty.clone()).collect();
+        assert_eq!(params, [IrType::I32, IrType::I32, IrType::I64, IrType::I32]);
+        assert_eq!(f.return_type, IrType::I32);
+        // The default integer type only decides what nothing else does
+        assert_eq!(ir.int_type, IrType::I64);
+    }
+
+    #[test]
+    fn test_tuples_of_scalars_are_multi_values() {
+        let ir = lower_source("fn swap(a: i32, b: i32) -> (i32, i32) { (b, a) }");
+        let f = &ir.functions[0];
+        assert_eq!(f.return_type, IrType::Tuple(vec![IrType::I32, IrType::I32]));
+        let [.., Instruction {
+            result,
+            kind: InstructionKind::Tuple(elems),
+        }] = &f.blocks[0].instructions[..]
+        else {
+            panic!("Expected a tuple, got {:?}", f.blocks[0].instructions);
+        };
+        assert_eq!(elems, &[ValueId(1), ValueId(0)]);
+        assert!(matches!(f.blocks[0].terminator, Terminator::Return(Some(v)) if v == *result));
+    }
+
+    #[test]
+    fn test_nested_tuples_are_stored_in_memory() {
+        let ir = lower_source("fn f() -> ((i64, i64), bool) { ((1, 2), true) }");
+        let f = &ir.functions[0];
+        let inner = IrType::Tuple(vec![IrType::I64, IrType::I64]);
+        let layout = IrType::Tuple(vec![inner.clone(), IrType::Bool]);
+        assert_eq!(f.return_type, IrType::Ptr(Box::new(layout.clone())));
+        assert_eq!((layout.size(), layout.align()), (24, 8));
+        // The inner tuple is stored whole, the bool after it
+        let stores: Vec<_> = instructions(f)
+            .filter_map(|kind| match kind {
+                InstructionKind::Store(_, _, ty) => Some(ty.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(stores, [inner, IrType::Bool]);
+        assert!(instructions(f).any(|kind| matches!(kind, InstructionKind::FieldPtr(_, 16))));
+    }
+
+    #[test]
+    fn test_cfg_dot_has_a_node_per_block_and_an_edge_per_target() {
+        let source = "enum O { S(i64), N }
+            fn f(o: O) -> i64 { match o { S(x) => x, N => 0 } }
+            fn g() -> i64 { 1 }";
+        let ir = lower_source(source);
+        let dot = to_dot(&ir);
+        assert!(dot.starts_with("digraph module {"), "{dot}");
+        assert_eq!(dot.matches("subgraph cluster_").count(), 2);
+        // `f`: entry, one test per arm and its body, the trap and the join.
+        // `g` is one block
+        assert_eq!(dot.matches("[label=\"b").count(), 7, "{dot}");
+        assert_eq!(dot.matches(" -> ").count(), 6, "{dot}");
+        assert_eq!(dot.matches("[label=true]").count(), 2);
+        assert!(
+            dot.contains("f1_b0 [label=\"b0:\\lv0 = ConstInt(1, I64)\\l"),
+            "{dot}"
+        );
+    }
+
+    #[test]
+    fn test_cfg_dot_of_an_if_is_a_diamond() {
+        let ir = lower_source("fn f(c: bool) -> i64 { if c { 1 } else { 2 } }");
+        let dot = to_dot(&ir);
+        // Entry, both arms and the join
+        assert_eq!(dot.matches("[label=\"b").count(), 4, "{dot}");
+        assert_eq!(dot.matches(" -> ").count(), 4, "{dot}");
+        assert_eq!(dot.matches("[label=true]").count(), 1, "{dot}");
+        assert_eq!(dot.matches("[label=false]").count(), 1, "{dot}");
+    }
+
+    #[test]
+    fn test_lower_simple() {
+        let source = "fn main() -> i64 { return 42; }";
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check(&ast).unwrap();
+        let ir = lower(&typed);
+        assert_eq!(ir.functions.len(), 1);
+    }
+
+    #[test]
+    fn test_return_ends_its_block() {
+        let ir = lower_source("fn k(a: i64) -> i64 { if a > 5 { return 7; } a }");
+        let f = &ir.functions[0];
+        let returns = f
+            .blocks
+            .iter()
+            .filter(|b| matches!(b.terminator, Terminator::Return(Some(_))))
+            .count();
+        // The early `return 7` and the final `a`
+        assert_eq!(returns, 2);
+    }
+
+    #[test]
+    fn test_if_else_branches_to_a_join() {
+        let ir = lower_source("fn f(c: bool) -> i64 { if c { 1 } else { 2 } }");
+        let f = &ir.functions[0];
+        let Terminator::CondBranch(_, then_block, else_block) = f.blocks[0].terminator else {
+            panic!("Expected a branch on `c`, got {:?}", f.blocks[0].terminator);
+        };
+        let arm = |id: BlockId| f.blocks.iter().find(|b| b.id == id).unwrap();
+        let (&Terminator::Branch(join), &Terminator::Branch(other)) =
+            (&arm(then_block).terminator, &arm(else_block).terminator)
+        else {
+            panic!("Expected both arms to branch to the join");
+        };
+        assert_eq!(join, other);
+        assert!(matches!(
+            &arm(join).instructions[0].kind,
+            InstructionKind::Phi(incoming)
+                if incoming.iter().map(|(b, _)| *b).eq([then_block, else_block])
+        ));
+    }
+
+    #[test]
+    fn test_if_joins_assigned_locals() {
+        let ir = lower_source("fn f(c: bool) -> i64 { let mut x = 1; if c { x = 2; } x }");
+        let f = &ir.functions[0];
+        let Terminator::CondBranch(_, _, join) = f.blocks[0].terminator else {
+            panic!("Expected a branch on `c`, got {:?}", f.blocks[0].terminator);
+        };
+        // The false edge skips to the join, where `x` is 1 or 2
+        let join = f.blocks.iter().find(|b| b.id == join).unwrap();
+        let Terminator::Return(Some(x)) = join.terminator else {
+            panic!("Expected to return `x`, got {:?}", join.terminator);
+        };
+        let phi = join.instructions.iter().find(|i| i.result == x).unwrap();
+        let InstructionKind::Phi(incoming) = &phi.kind else {
+            panic!("Expected `x` to be a phi, got {:?}", phi.kind);
+        };
+        assert_eq!(incoming.len(), 2);
+        assert_eq!(incoming[1].0, f.blocks[0].id);
+    }
+
+    #[test]
+    fn test_range_for_is_an_induction_loop() {
+        let source = "fn sum(n: i64) -> i64 {
+            let mut total = 0;
+            for i in 0..n { total = total + i; }
+            total
+        }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        let header = f
+            .blocks
+            .iter()
+            .find(|b| matches!(b.terminator, Terminator::CondBranch(..)))
+            .expect("a loop header");
+        // The counter and `total`, each from the entry and the latch
+        let phis: Vec<_> = header
+            .instructions
+            .iter()
+            .filter_map(|i| match &i.kind {
+                InstructionKind::Phi(edges) => Some((i.result, edges.len())),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(phis[..], [(_, 2), (_, 2)]));
+        assert!(header
+            .instructions
+            .iter()
+            .any(|i| matches!(i.kind, InstructionKind::Lt(counter, ..) if counter == phis[0].0)));
+        // The body branches back to the header
+        assert!(f.blocks.iter().any(|b| b.id != header.id
+            && matches!(b.terminator, Terminator::Branch(to) if to == header.id)));
+        // After the loop, `total` is the header's phi
+        let returned = f.blocks.iter().find_map(|b| match b.terminator {
+            Terminator::Return(value) => value,
+            _ => None,
+        });
+        assert_eq!(returned, Some(phis[1].0));
+    }
+
+    #[test]
+    fn test_let_tuple_takes_each_element() {
+        let source = "fn pair() -> (i64, bool) { (1, true) }
+            fn first() -> i64 { let (x, _) = pair(); x }";
+        let ir = lower_source(source);
+        let f = &ir.functions[1];
+        let instrs: Vec<_> = f.blocks.iter().flat_map(|b| &b.instructions).collect();
+        let call = instrs
+            .iter()
+            .find(|i| matches!(i.kind, InstructionKind::Call(..)))
+            .expect("a call");
+        // `x` is the call's first element, and it's what's returned
+        let x = instrs
+            .iter()
+            .find(|i| matches!(i.kind, InstructionKind::TupleGet(t, 0) if t == call.result))
+            .expect("the first element");
+        assert!(matches!(
+            f.blocks.last().unwrap().terminator,
+            Terminator::Return(Some(value)) if value == x.result
+        ));
+    }
+
+    #[test]
+    fn test_inclusive_range_for_compares_with_le() {
+        let source = "fn sum() -> i64 {
+            let mut total = 0;
+            for i in 1..=5 { total = total + i; }
+            total
+        }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        let header = f
+            .blocks
+            .iter()
+            .find(|b| matches!(b.terminator, Terminator::CondBranch(..)))
+            .expect("a loop header");
+        let counter = header.instructions[0].result;
+        assert!(header
+            .instructions
+            .iter()
+            .any(|i| matches!(i.kind, InstructionKind::Le(c, ..) if c == counter)));
+        // The counter starts at 1 and steps by 1 on the latch
+        let InstructionKind::Phi(edges) = &header.instructions[0].kind else {
+            panic!("Expected the counter phi, got {:?}", header.instructions[0]);
+        };
+        let def = |v: ValueId| {
+            f.blocks
+                .iter()
+                .flat_map(|b| &b.instructions)
+                .find(|i| i.result == v)
+                .map(|i| &i.kind)
+        };
+        assert!(matches!(
+            def(edges[0].1),
+            Some(InstructionKind::ConstInt(1, _))
+        ));
+        assert!(matches!(
+            def(edges[1].1),
+            Some(InstructionKind::Add(c, _)) if *c == counter
+        ));
+    }
+
+    #[test]
+    fn test_while_loop_carries_locals_through_its_header() {
+        let source = "fn count() -> i64 {
+            let mut i = 0;
+            while i < 10 { i = i + 1; }
+            i
+        }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        let header = f
+            .blocks
+            .iter()
+            .find(|b| matches!(b.terminator, Terminator::CondBranch(..)))
+            .expect("a loop header");
+        // `i` is 0 from the entry and `i + 1` from the latch
+        let Some(InstructionKind::Phi(edges)) = header.instructions.first().map(|i| &i.kind) else {
+            panic!("expected a phi for `i` in {:?}", header);
+        };
+        let i = header.instructions[0].result;
+        assert_eq!(edges.len(), 2);
+        let latch = f.blocks.iter().find(|b| b.id == edges[1].0).unwrap();
+        assert!(matches!(latch.terminator, Terminator::Branch(to) if to == header.id));
+        assert!(latch
+            .instructions
+            .iter()
+            .any(|instr| instr.result == edges[1].1
+                && matches!(instr.kind, InstructionKind::Add(a, _) if a == i)));
+        // The condition reads the phi, and the loop's exit returns it
+        assert!(header
+            .instructions
+            .iter()
+            .any(|instr| matches!(instr.kind, InstructionKind::Lt(a, ..) if a == i)));
+        let returned = f.blocks.iter().find_map(|b| match b.terminator {
+            Terminator::Return(value) => value,
+            _ => None,
+        });
+        assert_eq!(returned, Some(i));
+    }
+
+    #[test]
+    fn test_break_and_continue_leave_the_iteration() {
+        let source = "fn f() -> i64 {
+            let mut i = 0;
+            while true {
+                i = i + 1;
+                if i < 3 { continue; }
+                if i == 5 { break; }
+            }
+            i
+        }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        let header = f
+            .blocks
+            .iter()
+            .find(|b| {
+                matches!(
+                    b.instructions.first().map(|i| &i.kind),
+                    Some(InstructionKind::Phi(_))
+                )
+            })
+            .expect("a loop header");
+        let Terminator::CondBranch(_, _, exit) = header.terminator else {
+            panic!("expected the header to test the condition");
+        };
+        // `continue` and the latch go back to the header...
+        let back = f
+            .blocks
+            .iter()
+            .filter(|b| matches!(b.terminator, Terminator::Branch(to) if to == header.id))
+            .count();
+        assert!(back >= 2, "expected continue and the latch in {:?}", f);
+        // ...and after `break` or a false condition, `i` is one of two values
+        let exit = f.blocks.iter().find(|b| b.id == exit).unwrap();
+        assert!(matches!(
+            &exit.instructions[0].kind,
+            InstructionKind::Phi(edges) if edges.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_loop_joins_its_break_values() {
+        let source =
+            "fn f() -> i64 { let mut i = 0; loop { i = i + 1; if i > 3 { break i * 10; } } }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        let returned = f
+            .blocks
+            .iter()
+            .find_map(|b| match b.terminator {
+                Terminator::Return(value) => value,
+                _ => None,
+            })
+            .unwrap();
+        let value = f
+            .blocks
+            .iter()
+            .flat_map(|b| &b.instructions)
+            .find(|i| i.result == returned)
+            .unwrap();
+        // One `break`, so one edge
+        assert!(matches!(&value.kind, InstructionKind::Phi(edges) if edges.len() == 1));
+    }
+
+    #[test]
+    fn test_slicing_builds_pointer_and_length() {
+        let source = "fn f(arr: &[i64; 4]) -> i64 { let s = arr[1..3]; s[0] }";
+        let ir = lower_source(source);
+        let f = &ir.functions[0];
+        let element_ptrs = instructions(f)
+            .filter(|kind| matches!(kind, InstructionKind::ElementPtr(_, _, 8)))
+            .count();
+        assert_eq!(element_ptrs, 2); // `arr[1..]` and `s[0]`
+        assert!(instructions(f).any(|kind| matches!(kind, InstructionKind::Sub(..))));
+        let slice = IrType::Aggregate { size: 16, align: 8 };
+        assert!(
+            instructions(f).any(|kind| matches!(kind, InstructionKind::Alloca(m) if *m == slice))
+        );
+        let stores: Vec<_> = instructions(f)
+            .filter_map(|kind| match kind {
+                InstructionKind::Store(_, _, ty) => Some(ty.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(stores, [IrType::I32, IrType::I64]);
+        assert!(instructions(f).any(|kind| matches!(kind, InstructionKind::Load(_, IrType::I64))));
     }
 }
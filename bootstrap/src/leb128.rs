@@ -0,0 +1,152 @@
+//! LEB128 decoding, for reading emitted WASM bytes back
+//!
+//! The code generator only ever encodes; these decoders let the WAT writer
+//! and tests read the bytes back. They follow the WASM binary format: an
+//! N-bit value takes at most `ceil(N / 7)` bytes, and the unused high bits
+//! of a maximal-length encoding must be zero (unsigned) or copies of the
+//! sign bit (signed). Padded encodings within that length, like
+//! `[0x80, 0x00]` for 0, are valid.
+
+/// Why a byte sequence is not a valid LEB128 value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended before a byte without the continuation bit
+    Unterminated,
+    /// More bytes than the type allows, or stray bits beyond its width
+    Overlong,
+}
+
+/// Decodes an unsigned 32-bit LEB128 value, returning it and its length.
+pub fn decode_u32(bytes: &[u8]) -> Result<(u32, usize), DecodeError> {
+    let (value, len) = decode(bytes, 32, false)?;
+    Ok((value as u32, len))
+}
+
+/// Decodes a signed 32-bit LEB128 value, returning it and its length.
+pub fn decode_i32(bytes: &[u8]) -> Result<(i32, usize), DecodeError> {
+    let (value, len) = decode(bytes, 32, true)?;
+    Ok((value as i32, len))
+}
+
+/// Decodes a signed 64-bit LEB128 value, returning it and its length.
+pub fn decode_i64(bytes: &[u8]) -> Result<(i64, usize), DecodeError> {
+    let (value, len) = decode(bytes, 64, true)?;
+    Ok((value as i64, len))
+}
+
+/// Decodes a `bits`-wide value; signed results are sign-extended to 64 bits.
+fn decode(bytes: &[u8], bits: u32, signed: bool) -> Result<(u64, usize), DecodeError> {
+    let max_len = bits.div_ceil(7) as usize;
+    let mut result: u64 = 0;
+    let mut shift = 0;
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        let payload = (byte & 0x7F) as u64;
+        let last = byte & 0x80 == 0;
+
+        if i + 1 == max_len {
+            if !last {
+                return Err(DecodeError::Overlong);
+            }
+            // Bits of the final byte that fall outside the type's width
+            let used = bits - shift;
+            let unused = payload >> used;
+            let expected = if signed && (payload >> (used - 1)) & 1 == 1 {
+                0x7F >> used
+            } else {
+                0
+            };
+            if unused != expected {
+                return Err(DecodeError::Overlong);
+            }
+        }
+
+        result |= payload << shift;
+        shift += 7;
+
+        if last {
+            if signed && shift < 64 && byte & 0x40 != 0 {
+                result |= u64::MAX << shift;
+            }
+            if signed && bits < 64 {
+                // Sign-extend from `bits` so `as i32` keeps the value
+                let value = ((result << (64 - bits)) as i64) >> (64 - bits);
+                result = value as u64;
+            }
+            return Ok((result, i + 1));
+        }
+    }
+    Err(DecodeError::Unterminated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_known_unsigned() {
+        assert_eq!(decode_u32(&[0x00]), Ok((0, 1)));
+        assert_eq!(decode_u32(&[0xE5, 0x8E, 0x26]), Ok((624_485, 3)));
+        assert_eq!(
+            decode_u32(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+            Ok((u32::MAX, 5))
+        );
+        // Trailing bytes after the value are not consumed
+        assert_eq!(decode_u32(&[0x7F, 0x01]), Ok((127, 1)));
+    }
+
+    #[test]
+    fn test_decode_known_signed() {
+        assert_eq!(decode_i32(&[0x7F]), Ok((-1, 1)));
+        assert_eq!(decode_i32(&[0xC0, 0xBB, 0x78]), Ok((-123_456, 3)));
+        assert_eq!(
+            decode_i32(&[0x80, 0x80, 0x80, 0x80, 0x78]),
+            Ok((i32::MIN, 5))
+        );
+        assert_eq!(
+            decode_i32(&[0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+            Ok((i32::MAX, 5))
+        );
+
+        let mut min = vec![0x80; 9];
+        min.push(0x7F);
+        assert_eq!(decode_i64(&min), Ok((i64::MIN, 10)));
+        let mut max = vec![0xFF; 9];
+        max.push(0x00);
+        assert_eq!(decode_i64(&max), Ok((i64::MAX, 10)));
+    }
+
+    #[test]
+    fn test_padded_encoding_is_accepted() {
+        assert_eq!(decode_u32(&[0x80, 0x00]), Ok((0, 2)));
+        assert_eq!(decode_i32(&[0xFF, 0x7F]), Ok((-1, 2)));
+    }
+
+    #[test]
+    fn test_reject_overlong() {
+        // Six bytes for a 32-bit value
+        assert_eq!(
+            decode_u32(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]),
+            Err(DecodeError::Overlong)
+        );
+        // Bits beyond 32 in the fifth byte
+        assert_eq!(
+            decode_u32(&[0xFF, 0xFF, 0xFF, 0xFF, 0x1F]),
+            Err(DecodeError::Overlong)
+        );
+        // Unused bits that don't match the sign bit
+        assert_eq!(
+            decode_i32(&[0x80, 0x80, 0x80, 0x80, 0x70]),
+            Err(DecodeError::Overlong)
+        );
+        let mut bytes = vec![0x80; 9];
+        bytes.push(0x01);
+        assert_eq!(decode_i64(&bytes), Err(DecodeError::Overlong));
+    }
+
+    #[test]
+    fn test_reject_unterminated() {
+        assert_eq!(decode_u32(&[]), Err(DecodeError::Unterminated));
+        assert_eq!(decode_i64(&[0x80, 0x80]), Err(DecodeError::Unterminated));
+    }
+}
@@ -27,7 +27,7 @@
 //!
 //! **Good first issues in this module:**
 //!
-//! - [ ] Add raw string literals (`r"..."`, `r#"..."#`)
+//! - [x] Add raw string literals (`r"..."`, `r#"..."#`)
 //! - [ ] Improve error messages for unterminated strings
 //! - [ ] Add byte literals (`b'x'`, `b"bytes"`)
 //! - [ ] Handle Unicode escapes (`\u{1F600}`)
@@ -56,6 +56,14 @@ const MAX_NESTING_DEPTH: usize = 256;
 // Public API
 // ============================================================================
 
+/// Lexer settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LexOptions {
+    /// Emit a `Newline` token wherever a line break may end a statement
+    /// (experimental, see [`Lexer::significant_newline`]).
+    pub newline_terminated: bool,
+}
+
 /// Lex source code into tokens.
 ///
 /// Returns a vector of tokens ending with EOF.
@@ -67,36 +75,159 @@ const MAX_NESTING_DEPTH: usize = 256;
 /// - Source exceeds MAX_SOURCE_SIZE (4GB)
 /// - Block comment nesting exceeds MAX_NESTING_DEPTH (256)
 /// - Invalid characters or unterminated literals
+#[allow(dead_code)] // the CLI uses `lex_with_options`
 pub fn lex(source: &str) -> Result<Vec<Token>, NovaError> {
+    lex_at(source, 0)
+}
+
+/// Lex source code whose first byte is at offset `base`.
+///
+/// Used when several files share one span address space (see `SourceMap`):
+/// every token and error span is shifted by `base`.
+pub fn lex_at(source: &str, base: u32) -> Result<Vec<Token>, NovaError> {
+    lex_with_options(source, base, &LexOptions::default())
+}
+
+/// Lex source code at offset `base` with custom options.
+pub fn lex_with_options(
+    source: &str,
+    base: u32,
+    options: &LexOptions,
+) -> Result<Vec<Token>, NovaError> {
     // Security: Check source size limit
-    if source.len() > MAX_SOURCE_SIZE {
+    if source.len() > MAX_SOURCE_SIZE || base as usize + source.len() > u32::MAX as usize {
         return Err(NovaError::SourceTooLarge {
             size: source.len(),
             max: MAX_SOURCE_SIZE,
         });
     }
-    let mut lexer = Lexer::new(source);
+    let mut lexer = Lexer::new(source, base, *options);
     lexer.lex_all()
 }
 
+/// Whitespace or a comment: source the parser never sees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+/// What a piece of trivia is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// A run of spaces, tabs and line breaks
+    Whitespace,
+    /// `// ...`, up to but not including the line break
+    LineComment,
+    /// `/* ... */`, with any nested comments inside
+    BlockComment,
+}
+
+/// A token or the trivia before one, as [`lex_with_trivia`] returns them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenOrTrivia {
+    Token(Token),
+    Trivia(Trivia),
+}
+
+/// Lex source code, keeping whitespace and comments.
+///
+/// For the formatter and other source-to-source tools. The tokens are the
+/// ones [`lex`] returns, with the trivia between them interleaved in
+/// source order, so the spans cover the source without gaps or overlaps.
+/// Trivia lives only in this wrapper; `Token` stays 12 bytes.
+pub fn lex_with_trivia(source: &str) -> Result<Vec<TokenOrTrivia>, NovaError> {
+    let tokens = lex(source)?;
+    let mut out = Vec::with_capacity(tokens.len() * 2);
+    let mut pos = 0;
+    for token in tokens {
+        let start = token.span().start() as usize;
+        push_trivia(source, pos, start, &mut out);
+        pos = token.span().end() as usize;
+        out.push(TokenOrTrivia::Token(token));
+    }
+    Ok(out)
+}
+
+/// Split `source[start..end]`, which the lexer skipped, into trivia
+fn push_trivia(source: &str, start: usize, end: usize, out: &mut Vec<TokenOrTrivia>) {
+    let mut pos = start;
+    while pos < end {
+        let rest = &source[pos..end];
+        let (kind, len) = if rest.starts_with("//") {
+            (
+                TriviaKind::LineComment,
+                rest.find('\n').unwrap_or(rest.len()),
+            )
+        } else if rest.starts_with("/*") {
+            (TriviaKind::BlockComment, block_comment_len(rest))
+        } else {
+            let len = rest
+                .find(|c| !matches!(c, ' ' | '\t' | '\n' | '\r'))
+                .unwrap_or(rest.len());
+            // Only whitespace and comments are skipped, but never get stuck
+            (
+                TriviaKind::Whitespace,
+                if len == 0 { rest.len() } else { len },
+            )
+        };
+        let span = Span::new(pos as u32, (pos + len) as u32);
+        out.push(TokenOrTrivia::Trivia(Trivia { kind, span }));
+        pos += len;
+    }
+}
+
+/// The length of the block comment at the start of `text`, nested ones
+/// included (all of `text` if it's unterminated)
+fn block_comment_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        match &bytes[i..i + 2] {
+            b"/*" => {
+                depth += 1;
+                i += 2;
+            }
+            b"*/" => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    text.len()
+}
+
 /// The lexer state
 struct Lexer<'a> {
     source: &'a str,
     chars: std::iter::Peekable<std::str::CharIndices<'a>>,
     start: usize,
     current: usize,
+    /// Offset of the source's first byte in the span address space
+    base: u32,
     /// Deferred error from whitespace/comment skipping
     pending_error: Option<NovaError>,
+    options: LexOptions,
+    /// Open `(`, `[` and `{`, innermost last (newline-terminated mode only)
+    delimiters: Vec<TokenKind>,
 }
 
 impl<'a> Lexer<'a> {
-    fn new(source: &'a str) -> Self {
+    fn new(source: &'a str, base: u32, options: LexOptions) -> Self {
         Self {
             source,
             chars: source.char_indices().peekable(),
             start: 0,
             current: 0,
+            base,
             pending_error: None,
+            options,
+            delimiters: Vec::new(),
         }
     }
 
@@ -112,15 +243,24 @@ impl<'a> Lexer<'a> {
                 return Err(err);
             }
 
+            if self.options.newline_terminated {
+                if let Some(newline) = self.significant_newline(tokens.last()) {
+                    tokens.push(newline);
+                }
+            }
+
             self.start = self.current;
 
             match self.advance() {
                 None => {
-                    tokens.push(Token::eof(self.current as u32));
+                    tokens.push(Token::eof(self.base + self.current as u32));
                     break;
                 }
                 Some(c) => {
                     let token = self.lex_token(c)?;
+                    if self.options.newline_terminated {
+                        self.track_delimiter(token.kind());
+                    }
                     tokens.push(token);
                 }
             }
@@ -259,6 +399,21 @@ impl<'a> Lexer<'a> {
             // Character literals
             '\'' => self.lex_char()?,
 
+            // Format string literals
+            'f' if self.check('"') => {
+                self.advance();
+                self.lex_format_string()?
+            }
+
+            // Raw string literals
+            'r' if self.at_raw_body() => self.lex_raw_string(TokenKind::StringLit)?,
+
+            // Raw byte string literals
+            'b' if self.at_raw_string() => {
+                self.advance(); // r
+                self.lex_raw_string(TokenKind::ByteStringLit)?
+            }
+
             // Numbers
             '0'..='9' => self.lex_number()?,
 
@@ -269,15 +424,17 @@ impl<'a> Lexer<'a> {
             _ => {
                 return Err(NovaError::InvalidCharacter {
                     char: c,
-                    span: Span::new(self.start as u32, self.current as u32),
+                    span: self.span_from(self.start),
                 });
             }
         };
 
-        Ok(Token::new(
-            kind,
-            Span::new(self.start as u32, self.current as u32),
-        ))
+        Ok(Token::new(kind, self.span_from(self.start)))
+    }
+
+    /// The span from `start` to the current position
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.base + start as u32, self.base + self.current as u32)
     }
 
     /// Advance and return the next character
@@ -316,6 +473,51 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// A `Newline` token for the line break just skipped, if it ends a statement.
+    ///
+    /// A line break is significant when the previous token could end an
+    /// expression (so never after a binary operator or a comma) and it isn't
+    /// directly inside `(...)` or `[...]`. Inside `{...}` lines count again,
+    /// so a block within a call still separates its statements. The end of
+    /// input counts as a line break.
+    fn significant_newline(&self, last: Option<&Token>) -> Option<Token> {
+        let last = last?;
+        if !ends_expression(last.kind())
+            || matches!(
+                self.delimiters.last(),
+                Some(TokenKind::LParen | TokenKind::LBracket)
+            )
+        {
+            return None;
+        }
+        let gap_start = (last.span().end() - self.base) as usize;
+        let gap = &self.source[gap_start..self.current];
+        let offset = match gap.find('\n') {
+            Some(i) => gap_start + i,
+            None if self.current == self.source.len() => self.current,
+            None => return None,
+        };
+        let len = if offset < self.source.len() { 1 } else { 0 };
+        let start = self.base + offset as u32;
+        Some(Token::new(
+            TokenKind::Newline,
+            Span::new(start, start + len),
+        ))
+    }
+
+    /// Keep the stack of open delimiters up to date
+    fn track_delimiter(&mut self, kind: TokenKind) {
+        match kind {
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => {
+                self.delimiters.push(kind)
+            }
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                self.delimiters.pop();
+            }
+            _ => {}
+        }
+    }
+
     /// Skip whitespace and comments
     fn skip_whitespace_and_comments(&mut self) {
         loop {
@@ -359,10 +561,7 @@ impl<'a> Lexer<'a> {
                                             self.pending_error = Some(NovaError::NestingTooDeep {
                                                 depth,
                                                 max: MAX_NESTING_DEPTH,
-                                                span: Span::new(
-                                                    comment_start as u32,
-                                                    self.current as u32,
-                                                ),
+                                                span: self.span_from(comment_start),
                                             });
                                             return; // Exit, error will be handled in lex_all
                                         }
@@ -389,14 +588,14 @@ impl<'a> Lexer<'a> {
                     // Skip the escaped character
                     if self.advance().is_none() {
                         return Err(NovaError::UnterminatedString {
-                            span: Span::new(self.start as u32, self.current as u32),
+                            span: self.span_from(self.start),
                         });
                     }
                 }
                 Some(_) => {}
                 None => {
                     return Err(NovaError::UnterminatedString {
-                        span: Span::new(self.start as u32, self.current as u32),
+                        span: self.span_from(self.start),
                     });
                 }
             }
@@ -405,6 +604,107 @@ impl<'a> Lexer<'a> {
         Ok(TokenKind::StringLit)
     }
 
+    /// Whether `r`, any number of `#`, then `"` come next
+    fn at_raw_string(&self) -> bool {
+        self.source[self.current..]
+            .strip_prefix('r')
+            .is_some_and(|rest| rest.trim_start_matches('#').starts_with('"'))
+    }
+
+    /// Whether any number of `#`, then `"` come next
+    fn at_raw_body(&self) -> bool {
+        self.source[self.current..]
+            .trim_start_matches('#')
+            .starts_with('"')
+    }
+
+    /// Lex a raw string (after `r`) or raw byte string (after `br`) literal
+    ///
+    /// The body runs to the first `"` followed by as many `#` as opened the
+    /// literal, so `r#"a "quoted" word"#` may contain bare quotes. Nothing
+    /// is escaped: `\` is just a backslash.
+    fn lex_raw_string(&mut self, kind: TokenKind) -> Result<TokenKind, NovaError> {
+        let mut hashes = 0;
+        while self.check('#') {
+            self.advance();
+            hashes += 1;
+        }
+        self.advance(); // opening "
+
+        loop {
+            match self.advance() {
+                Some('"') => {
+                    let mut closing = 0;
+                    while closing < hashes && self.check('#') {
+                        self.advance();
+                        closing += 1;
+                    }
+                    if closing == hashes {
+                        break;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    return Err(NovaError::UnterminatedString {
+                        span: self.span_from(self.start),
+                    });
+                }
+            }
+        }
+
+        Ok(kind)
+    }
+
+    /// Lex a format string literal (after `f"`)
+    ///
+    /// `{expr}` is an interpolation and may itself contain braces and string
+    /// literals; `{{` and `}}` outside an interpolation are literal braces.
+    /// The parser splits the literal into parts, this only finds its end.
+    fn lex_format_string(&mut self) -> Result<TokenKind, NovaError> {
+        let mut depth = 0usize;
+        loop {
+            match self.advance() {
+                Some('"') if depth == 0 => break,
+                Some('"') => {
+                    self.lex_string()?;
+                }
+                // A char literal in an interpolation, like `'}'`
+                Some('\'') if depth > 0 => {
+                    self.lex_char()?;
+                }
+                Some('\\') => {
+                    if self.advance().is_none() {
+                        return Err(NovaError::UnterminatedString {
+                            span: self.span_from(self.start),
+                        });
+                    }
+                }
+                Some('{') if depth == 0 && self.check('{') => {
+                    self.advance();
+                }
+                Some('}') if depth == 0 && self.check('}') => {
+                    self.advance();
+                }
+                Some('{') => depth += 1,
+                Some('}') if depth == 0 => {
+                    return Err(NovaError::InvalidLiteral {
+                        kind: "format string",
+                        span: self.span_from(self.current - 1),
+                    });
+                }
+                Some('}') => depth -= 1,
+                Some(_) => {}
+                None => {
+                    return Err(NovaError::UnterminatedString {
+                        span: self.span_from(self.start),
+                    });
+                }
+            }
+        }
+
+        Ok(TokenKind::FStringLit)
+    }
+
     /// Lex a character literal
     fn lex_char(&mut self) -> Result<TokenKind, NovaError> {
         match self.advance() {
@@ -412,7 +712,7 @@ impl<'a> Lexer<'a> {
                 // Escape sequence
                 if self.advance().is_none() {
                     return Err(NovaError::UnterminatedString {
-                        span: Span::new(self.start as u32, self.current as u32),
+                        span: self.span_from(self.start),
                     });
                 }
             }
@@ -420,13 +720,13 @@ impl<'a> Lexer<'a> {
                 // Empty char literal
                 return Err(NovaError::InvalidCharacter {
                     char: '\'',
-                    span: Span::new(self.start as u32, self.current as u32),
+                    span: self.span_from(self.start),
                 });
             }
             Some(_) => {}
             None => {
                 return Err(NovaError::UnterminatedString {
-                    span: Span::new(self.start as u32, self.current as u32),
+                    span: self.span_from(self.start),
                 });
             }
         }
@@ -434,7 +734,7 @@ impl<'a> Lexer<'a> {
         // Expect closing quote
         if !self.check('\'') {
             return Err(NovaError::UnterminatedString {
-                span: Span::new(self.start as u32, self.current as u32),
+                span: self.span_from(self.start),
             });
         }
         self.advance();
@@ -554,10 +854,86 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Whether a token of this kind can be the last one of an expression
+fn ends_expression(kind: TokenKind) -> bool {
+    kind.is_literal()
+        || matches!(
+            kind,
+            TokenKind::Ident
+                | TokenKind::RParen
+                | TokenKind::RBracket
+                | TokenKind::RBrace
+                | TokenKind::Question
+                | TokenKind::True
+                | TokenKind::False
+                | TokenKind::SelfLower
+                | TokenKind::SelfUpper
+                | TokenKind::Break
+                | TokenKind::Continue
+                | TokenKind::Return
+        )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// The source text of each token and piece of trivia
+    fn pieces(source: &str) -> Vec<(&str, Option<TriviaKind>)> {
+        lex_with_trivia(source)
+            .unwrap()
+            .iter()
+            .map(|piece| {
+                let (span, kind) = match piece {
+                    TokenOrTrivia::Token(t) => (t.span(), None),
+                    TokenOrTrivia::Trivia(t) => (t.span, Some(t.kind)),
+                };
+                (&source[span.start() as usize..span.end() as usize], kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_trivia_keeps_blank_line() {
+        let source = "fn a() {}\n\nfn b() {}\n";
+        let pieces = pieces(source);
+        let close = pieces.iter().position(|p| p.0 == "}").unwrap();
+        assert_eq!(pieces[close + 1], ("\n\n", Some(TriviaKind::Whitespace)));
+        assert_eq!(pieces[close + 2], ("fn", None));
+    }
+
+    #[test]
+    fn test_trivia_covers_source() {
+        let source = "// head\nlet x /* a /* nested */ b */ = 1; // tail\n\t\r\n/* open";
+        let pieces = pieces(source);
+        assert_eq!(pieces.iter().map(|p| p.0).collect::<String>(), source);
+        let comments: Vec<_> = pieces
+            .iter()
+            .filter(|p| {
+                matches!(
+                    p.1,
+                    Some(TriviaKind::LineComment | TriviaKind::BlockComment)
+                )
+            })
+            .map(|p| p.0)
+            .collect();
+        assert_eq!(
+            comments,
+            ["// head", "/* a /* nested */ b */", "// tail", "/* open"]
+        );
+
+        // The tokens are exactly what `lex` gives
+        let tokens: Vec<_> = lex_with_trivia(source)
+            .unwrap()
+            .into_iter()
+            .filter_map(|piece| match piece {
+                TokenOrTrivia::Token(t) => Some(t),
+                TokenOrTrivia::Trivia(_) => None,
+            })
+            .collect();
+        assert_eq!(tokens, lex(source).unwrap());
+    }
+
     #[test]
     fn test_lex_simple() {
         let tokens = lex("let x = 42").unwrap();
@@ -575,6 +951,179 @@ mod tests {
         assert_eq!(tokens[0].kind(), TokenKind::StringLit);
     }
 
+    #[test]
+    fn test_lex_format_string() {
+        let tokens = lex(r#"f"x = {a + 1}" f"{{not interp}}" f"{g("}")}" f"#).unwrap();
+        assert_eq!(tokens[0].kind(), TokenKind::FStringLit);
+        assert_eq!(tokens[0].span(), Span::new(0, 14));
+        assert_eq!(tokens[1].kind(), TokenKind::FStringLit);
+        assert_eq!(tokens[2].kind(), TokenKind::FStringLit);
+        assert_eq!(tokens[3].kind(), TokenKind::Ident);
+
+        // Braces and quotes in char literals don't end the interpolation
+        let tokens = lex(r#"f"{'}'}" f"{'\''} {'{'}" x"#).unwrap();
+        assert_eq!(tokens[0].span(), Span::new(0, 8));
+        assert_eq!(tokens[1].kind(), TokenKind::FStringLit);
+        assert_eq!(tokens[2].kind(), TokenKind::Ident);
+
+        assert!(matches!(
+            lex(r#"f"a } b""#),
+            Err(NovaError::InvalidLiteral { .. })
+        ));
+        assert!(matches!(
+            lex(r#"f"{a"#),
+            Err(NovaError::UnterminatedString { .. })
+        ));
+    }
+
+    #[test]
+    fn test_lex_raw_string() {
+        let source = r####"r"C:\path" r#"he said "hi""# r##"a "# b"## r##"#"## rest"####;
+        let tokens = lex(source).unwrap();
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::StringLit,
+                TokenKind::StringLit,
+                TokenKind::StringLit,
+                TokenKind::StringLit,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ]
+        );
+        let texts: Vec<_> = tokens[..4]
+            .iter()
+            .map(|t| &source[t.span().start() as usize..t.span().end() as usize])
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                r#"r"C:\path""#,
+                r##"r#"he said "hi""#"##,
+                r###"r##"a "# b"##"###,
+                r###"r##"#"##"###
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_r_without_a_quote_is_an_identifier() {
+        let kinds: Vec<_> = lex("r rx r + 1")
+            .unwrap()
+            .iter()
+            .map(|t| t.kind())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::Plus,
+                TokenKind::IntLit,
+                TokenKind::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_unterminated_raw_string() {
+        for source in [r#"r"abc"#, r##"r#"abc""##, r###"r##"abc"#"###, r#"r#"\"#] {
+            assert!(
+                matches!(lex(source), Err(NovaError::UnterminatedString { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_lex_raw_byte_string() {
+        let source = r###"br"a\b" br#"say "hi""# br##"x"#y"## bread"###;
+        let tokens = lex(source).unwrap();
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::ByteStringLit,
+                TokenKind::ByteStringLit,
+                TokenKind::ByteStringLit,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ]
+        );
+        let texts: Vec<_> = tokens[..3]
+            .iter()
+            .map(|t| &source[t.span().start() as usize..t.span().end() as usize])
+            .collect();
+        assert_eq!(
+            texts,
+            [r#"br"a\b""#, r##"br#"say "hi""#"##, r###"br##"x"#y"##"###]
+        );
+    }
+
+    #[test]
+    fn test_lex_unterminated_raw_byte_string() {
+        for source in [r#"br"abc"#, r##"br#"abc""##, r###"br##"abc"#"###] {
+            assert!(
+                matches!(lex(source), Err(NovaError::UnterminatedString { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    fn lex_newlines(source: &str) -> Vec<TokenKind> {
+        let options = LexOptions {
+            newline_terminated: true,
+        };
+        lex_with_options(source, 0, &options)
+            .unwrap()
+            .iter()
+            .map(|t| t.kind())
+            .collect()
+    }
+
+    #[test]
+    fn test_lex_newline_terminated() {
+        use TokenKind::*;
+        assert_eq!(
+            lex_newlines("let x = 1\nlet y = 2\n"),
+            [Let, Ident, Eq, IntLit, Newline, Let, Ident, Eq, IntLit, Newline, Eof]
+        );
+        // The end of input ends the last line, comments don't hide a newline
+        assert_eq!(
+            lex_newlines("x // note\ny"),
+            [Ident, Newline, Ident, Newline, Eof]
+        );
+        // Blank lines collapse into one terminator
+        assert_eq!(
+            lex_newlines("x\n\n\ny"),
+            [Ident, Newline, Ident, Newline, Eof]
+        );
+    }
+
+    #[test]
+    fn test_lex_newline_not_significant() {
+        use TokenKind::*;
+        // After a binary operator or comma, and inside parens or brackets
+        assert_eq!(lex_newlines("a +\nb"), [Ident, Plus, Ident, Newline, Eof]);
+        assert_eq!(
+            lex_newlines("f(a,\nb\n)"),
+            [Ident, LParen, Ident, Comma, Ident, RParen, Newline, Eof]
+        );
+        assert_eq!(
+            lex_newlines("[a\n]"),
+            [LBracket, Ident, RBracket, Newline, Eof]
+        );
+        // A block inside parens separates its lines again
+        assert_eq!(
+            lex_newlines("f({\na\nb\n})"),
+            [Ident, LParen, LBrace, Ident, Newline, Ident, Newline, RBrace, RParen, Newline, Eof]
+        );
+        // The default mode never emits newlines
+        assert!(!lex("a\nb\n").unwrap().iter().any(|t| t.kind() == Newline));
+    }
+
     #[test]
     fn test_lex_char() {
         let tokens = lex("'a' '\\n'").unwrap();
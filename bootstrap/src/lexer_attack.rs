@@ -67,7 +67,7 @@ mod attack_tests {
         for _ in 0..300 {
             source.push_str("/* ");
         }
-        source.push_str("x");
+        source.push('x');
         for _ in 0..300 {
             source.push_str(" */");
         }
@@ -204,7 +204,7 @@ mod attack_tests {
         for source in cases {
             let _result = lex(source);
             // Should not panic
-            assert!(!std::panic::catch_unwind(|| lex(source)).is_err());
+            assert!(std::panic::catch_unwind(|| lex(source)).is_ok());
         }
     }
 
@@ -215,7 +215,7 @@ mod attack_tests {
 
         for source in cases {
             // Should not panic
-            assert!(!std::panic::catch_unwind(|| lex(source)).is_err());
+            assert!(std::panic::catch_unwind(|| lex(source)).is_ok());
         }
     }
 
@@ -304,7 +304,7 @@ mod attack_tests {
         for source in chars {
             let _result = lex(source);
             // Should not panic
-            assert!(!std::panic::catch_unwind(|| lex(source)).is_err());
+            assert!(std::panic::catch_unwind(|| lex(source)).is_ok());
         }
     }
 
@@ -0,0 +1,99 @@
+//! The Nova bootstrap compiler as a library
+//!
+//! The `nova` binary is a thin command line over these modules; see
+//! `main.rs` for the architecture. [`compile_str`] runs the whole pipeline
+//! on one in-memory file, which is what the fuzz targets in `fuzz/` drive.
+
+pub mod ast;
+pub mod cfg;
+pub mod codegen;
+pub mod error;
+pub mod intern;
+pub mod ir;
+mod leb128;
+pub mod lexer;
+#[cfg(test)]
+mod lexer_attack;
+pub mod locals;
+pub mod parser;
+#[cfg(test)]
+mod parser_attack;
+pub mod resolve;
+pub mod source_map;
+pub mod span;
+#[cfg(test)]
+mod span_attack;
+pub mod timing;
+pub mod token;
+#[cfg(test)]
+mod token_attack;
+pub mod types;
+pub mod wat;
+
+use error::NovaError;
+
+/// Compile a single source file, with no `mod` files or options, to WASM.
+///
+/// The source goes through the pipeline the `nova` binary runs, with the
+/// default configuration for `#[cfg(...)]`.
+///
+/// Any input, however malformed, should give back either the module or
+/// the errors found in it; a panic is a bug, which `fuzz/` hunts for.
+pub fn compile_str(source: &str) -> Result<Vec<u8>, Vec<NovaError>> {
+    let mut resolver = resolve::Resolver::new(parser::ParseOptions::default());
+    let ast = resolver.resolve_str("<input>", source)?;
+    let typed = types::check(&ast).map_err(|e| vec![e])?;
+    let ir = ir::lower(&typed);
+    codegen::generate(&ir).map_err(|e| vec![e])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_str() {
+        let wasm = compile_str("fn main() -> i64 { 1 + 2 }").unwrap();
+        assert_eq!(&wasm[..4], b"\0asm");
+        let errors = compile_str("fn f( { }\nfn g() -> i64 { true }").unwrap_err();
+        assert!(!errors.is_empty());
+    }
+
+    #[test]
+    fn test_compile_str_strips_disabled_items() {
+        assert!(compile_str("#[cfg(test)] fn f() -> i64 { true } fn g() {}").is_ok());
+        let errors = compile_str("mod m; fn g() {}").unwrap_err();
+        assert!(matches!(errors[..], [NovaError::ModuleNotFound { .. }]));
+    }
+
+    #[test]
+    fn test_compile_str_checks_inline_modules() {
+        assert!(compile_str("mod m { fn g() { let x: i64 = true; } } fn f() {}").is_err());
+        assert!(compile_str("mod m { fn g() -> i64 { 1 } } fn f() -> i64 { m::g() }").is_ok());
+    }
+
+    /// The fuzz target's inputs, picked by hand: each must give `Ok` or
+    /// `Err` rather than a panic (which would fail the test)
+    #[test]
+    fn test_compile_str_hostile_inputs() {
+        let parens = "(".repeat(100_000);
+        let blocks = "fn f() { ".to_string() + &"{".repeat(5_000);
+        let invalid_utf8 = String::from_utf8_lossy(b"fn \xff\xfe() {}");
+        let inputs = [
+            "",
+            "fn main(",
+            "\"unterminated",
+            "fn f() { f\"{x\" }",
+            "enum E { A = 99999999999999999999 }",
+            "fn f() { 1..=; }",
+            "fn f() -> i64 { for i in 0.. { } [1, 2][..] }",
+            "static S: i64 = S;",
+            &parens,
+            &blocks,
+            &invalid_utf8,
+        ];
+        for input in inputs {
+            let _ = compile_str(input);
+        }
+    }
+}
@@ -0,0 +1,417 @@
+//! WASM local slot allocation
+//!
+//! Codegen keeps IR values in WASM locals. One local per value bloats the
+//! locals vector, so values whose live ranges don't overlap share a slot:
+//!
+//! 1. Liveness is computed per block (live-in and live-out sets, iterated to
+//!    a fixed point, so values stay live around loops).
+//! 2. Numbering the instructions in block order turns each value's liveness
+//!    into one range, `def..=last use`, widened to cover every block it is
+//!    live into or out of.
+//! 3. A linear scan hands out slots in order of range start. A slot is free
+//!    again once its value's range has ended, and is only reused for a
+//!    value of the same WASM type.
+//!
+//! A phi's incoming values are read at the end of the block they come from.
+//! Parameters already have locals (`GetParam` reads them), so slots are
+//! numbered after them. A tuple value is kept in a run of locals, one per
+//! element, that it doesn't share.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::ir::{BlockId, Function, InstructionKind, IrType, Module, ValueId};
+
+/// The local each value is kept in
+#[derive(Debug, Clone, Default)]
+pub struct LocalSlots {
+    /// Locals (counting parameters) holding each value that has any. A
+    /// tuple takes a run of locals, one per element.
+    slots: HashMap<ValueId, Range<u32>>,
+    /// Type of each local after the parameters
+    locals: Vec<IrType>,
+    /// Locals of the parameter read by each `GetParam`
+    param_values: HashMap<ValueId, Range<u32>>,
+    /// Type of each value, parameters included
+    types: HashMap<ValueId, IrType>,
+}
+
+impl LocalSlots {
+    /// The (first) local index holding `value`, if it has a slot
+    pub fn slot(&self, value: ValueId) -> Option<u32> {
+        self.slots.get(&value).map(|locals| locals.start)
+    }
+
+    /// The locals to read `value` from: its slot, or for a `GetParam` the
+    /// parameter's own locals. Values without any (such as the result of a
+    /// call to a function returning nothing) give an empty range.
+    pub fn locals_of(&self, value: ValueId) -> Range<u32> {
+        self.slots
+            .get(&value)
+            .or_else(|| self.param_values.get(&value))
+            .cloned()
+            .unwrap_or(0..0)
+    }
+
+    /// The types of the locals to declare after the parameters
+    pub fn locals(&self) -> &[IrType] {
+        &self.locals
+    }
+
+    /// The type of `value`, if it produces one
+    pub fn type_of(&self, value: ValueId) -> Option<&IrType> {
+        self.types.get(&value)
+    }
+}
+
+/// Assign a local to every value in `func` that produces one.
+pub fn allocate(func: &Function, module: &Module) -> LocalSlots {
+    let ranges = live_ranges(func);
+    let types = value_types(func, module);
+
+    let mut order: Vec<_> = ranges.iter().map(|(&v, &range)| (range, v)).collect();
+    order.sort_by_key(|&((start, _), v)| (start, v.0));
+
+    // Each parameter takes a local per WASM value it's passed as
+    let mut params = Vec::new();
+    let mut first = 0;
+    for (_, ty) in &func.params {
+        let width = ty.scalars().len() as u32;
+        params.push(first..first + width);
+        first += width;
+    }
+    let mut result = LocalSlots::default();
+    for instr in func.blocks.iter().flat_map(|b| &b.instructions) {
+        if let InstructionKind::GetParam(index) = instr.kind {
+            result
+                .param_values
+                .insert(instr.result, params[index].clone());
+        }
+    }
+    // Slots in use, with the position their value is last read at
+    let mut active: Vec<(usize, u32)> = Vec::new();
+    let mut free: Vec<u32> = Vec::new();
+    for ((start, end), value) in order {
+        let Some(ty) = types.get(&value) else {
+            continue;
+        };
+        if result.param_values.contains_key(&value) {
+            continue;
+        }
+        // A tuple gets a fresh run of locals, which isn't shared
+        if let IrType::Tuple(elems) = ty {
+            let slot = first + result.locals.len() as u32;
+            result.locals.extend(elems.iter().cloned());
+            result.slots.insert(value, slot..slot + elems.len() as u32);
+            continue;
+        }
+        // A value read by the instruction that defines another can hand its
+        // slot over: operands are read before the result is written
+        active.retain(|&(last, slot)| {
+            if last > start {
+                return true;
+            }
+            free.push(slot);
+            false
+        });
+        let wasm_type = |slot: u32| wasm_class(&result.locals[(slot - first) as usize]);
+        let reused = free
+            .iter()
+            .position(|&slot| wasm_type(slot) == wasm_class(ty));
+        let slot = match reused {
+            Some(i) => free.swap_remove(i),
+            None => {
+                result.locals.push(ty.clone());
+                first + result.locals.len() as u32 - 1
+            }
+        };
+        result.slots.insert(value, slot..slot + 1);
+        active.push((end, slot));
+    }
+    result.types = types;
+    result
+}
+
+/// Types that share a WASM value type can share a local
+fn wasm_class(ty: &IrType) -> IrType {
+    match ty {
+        IrType::I64 | IrType::F32 | IrType::F64 => ty.clone(),
+        _ => IrType::I32,
+    }
+}
+
+/// The range of instruction positions over which each value is live
+fn live_ranges(func: &Function) -> HashMap<ValueId, (usize, usize)> {
+    let (live_in, live_out) = liveness(func);
+    let mut ranges: HashMap<ValueId, (usize, usize)> = HashMap::new();
+    let mut extend = |value: ValueId, at: usize| {
+        let range = ranges.entry(value).or_insert((at, at));
+        range.0 = range.0.min(at);
+        range.1 = range.1.max(at);
+    };
+
+    let mut position = 0;
+    for block in &func.blocks {
+        let start = position;
+        for value in &live_in[&block.id] {
+            extend(*value, start);
+        }
+        for instr in &block.instructions {
+            extend(instr.result, position);
+            // Phi operands are read in the predecessors, see `liveness`
+            if !matches!(instr.kind, InstructionKind::Phi(_)) {
+                for operand in instr.kind.operands() {
+                    extend(operand, position);
+                }
+            }
+            position += 1;
+        }
+        // The terminator has a position of its own, after the instructions
+        if let Some(operand) = block.terminator.operand() {
+            extend(operand, position);
+        }
+        for value in &live_out[&block.id] {
+            extend(*value, position);
+        }
+        position += 1;
+    }
+    ranges
+}
+
+type LiveSets = HashMap<BlockId, HashSet<ValueId>>;
+
+/// The values live on entry to and exit from each block
+fn liveness(func: &Function) -> (LiveSets, LiveSets) {
+    // Values read before being defined in the block, and values defined in it
+    let mut uses: LiveSets = HashMap::new();
+    let mut defs: LiveSets = HashMap::new();
+    // Values a block must keep live on exit for a successor's phis
+    let mut phi_uses: LiveSets = HashMap::new();
+    for block in &func.blocks {
+        let used = uses.entry(block.id).or_default();
+        let defined = defs.entry(block.id).or_default();
+        for instr in &block.instructions {
+            match &instr.kind {
+                InstructionKind::Phi(incoming) => {
+                    for &(pred, value) in incoming {
+                        phi_uses.entry(pred).or_default().insert(value);
+                    }
+                }
+                kind => {
+                    for operand in kind.operands() {
+                        if !defined.contains(&operand) {
+                            used.insert(operand);
+                        }
+                    }
+                }
+            }
+            defined.insert(instr.result);
+        }
+        if let Some(operand) = block.terminator.operand() {
+            if !defined.contains(&operand) {
+                used.insert(operand);
+            }
+        }
+    }
+
+    let mut live_in: LiveSets = func.blocks.iter().map(|b| (b.id, HashSet::new())).collect();
+    let mut live_out: LiveSets = live_in.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in func.blocks.iter().rev() {
+            let mut out = phi_uses.get(&block.id).cloned().unwrap_or_default();
+            for succ in block.terminator.successors() {
+                out.extend(live_in.get(&succ).into_iter().flatten());
+            }
+            let mut inn = uses[&block.id].clone();
+            inn.extend(out.difference(&defs[&block.id]));
+            if inn != live_in[&block.id] || out != live_out[&block.id] {
+                changed = true;
+                live_in.insert(block.id, inn);
+                live_out.insert(block.id, out);
+            }
+        }
+    }
+    (live_in, live_out)
+}
+
+/// The type of every value that produces one
+///
+/// Stores produce no value.
+fn value_types(func: &Function, module: &Module) -> HashMap<ValueId, IrType> {
+    use InstructionKind::*;
+    let int = &module.int_type;
+    let mut types: HashMap<ValueId, IrType> = HashMap::new();
+    for instr in func.blocks.iter().flat_map(|b| &b.instructions) {
+        let operand_type = |types: &HashMap<ValueId, IrType>, v: &ValueId| {
+            types.get(v).cloned().unwrap_or_else(|| int.clone())
+        };
+        let ty = match &instr.kind {
+            ConstInt(_, ty) => ty.clone(),
+            MemorySize | MemoryGrow(_) => IrType::I32,
+            ConstFloat(_) => IrType::F64,
+            ConstBool(_) | Eq(..) | Ne(..) | Lt(..) | Le(..) | Gt(..) | Ge(..) => IrType::Bool,
+            And(..) | Or(..) | Not(_) => IrType::Bool,
+            ConstString(_) => IrType::Tuple(vec![IrType::I32, IrType::I32]),
+            Alloca(_) | FieldPtr(..) | ElementPtr(..) => IrType::I32,
+            Add(a, _) | Sub(a, _) | Mul(a, _) | Div(a, ..) | Rem(a, ..) | Neg(a) => {
+                operand_type(&types, a)
+            }
+            BitAnd(a, _) | BitOr(a, _) | BitXor(a, _) | Shl(a, _) | Shr(a, ..) => {
+                operand_type(&types, a)
+            }
+            Load(_, ty) | Convert(_, ty, _) => ty.clone(),
+            GlobalGet(index) => module.globals[*index].ty.clone(),
+            Call(name, _) => match module.functions.iter().find(|f| f.name == *name) {
+                Some(f) if f.return_type == IrType::Void => continue,
+                Some(f) => f.return_type.clone(),
+                None => int.clone(),
+            },
+            Tuple(elems) => IrType::Tuple(elems.iter().map(|v| operand_type(&types, v)).collect()),
+            TupleGet(tuple, index) => match operand_type(&types, tuple) {
+                IrType::Tuple(elems) => elems[*index as usize].clone(),
+                _ => int.clone(),
+            },
+            Phi(incoming) => match incoming.first() {
+                Some((_, v)) => operand_type(&types, v),
+                None => continue,
+            },
+            GetParam(index) => match func.params.get(*index) {
+                Some((_, ty)) => ty.clone(),
+                None => continue,
+            },
+            Store(..) | GlobalSet(..) => continue,
+        };
+        types.insert(instr.result, ty);
+    }
+    types
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{BasicBlock, Instruction, Terminator};
+
+    fn instr(result: usize, kind: InstructionKind) -> Instruction {
+        Instruction {
+            result: ValueId(result),
+            kind,
+        }
+    }
+
+    fn module(blocks: Vec<BasicBlock>) -> Module {
+        Module {
+            functions: vec![Function {
+                name: "f".to_string(),
+                params: vec![("n".to_string(), IrType::I64)],
+                return_type: IrType::I64,
+                blocks,
+                doc: None,
+            }],
+            globals: Vec::new(),
+            int_type: IrType::I64,
+        }
+    }
+
+    fn block(id: usize, instructions: Vec<Instruction>, terminator: Terminator) -> BasicBlock {
+        BasicBlock {
+            id: BlockId(id),
+            instructions,
+            terminator,
+        }
+    }
+
+    #[test]
+    fn test_dead_temporary_slot_is_reused() {
+        use InstructionKind::*;
+        let v = ValueId;
+        let m = module(vec![block(
+            0,
+            vec![
+                instr(0, ConstInt(1, IrType::I64)),
+                instr(1, ConstInt(2, IrType::I64)),
+                instr(2, Add(v(0), v(1))), // 0 and 1 are dead after this
+                instr(3, ConstInt(3, IrType::I64)),
+                instr(4, Mul(v(2), v(3))),
+            ],
+            Terminator::Return(Some(v(4))),
+        )]);
+        let slots = allocate(&m.functions[0], &m);
+        let slot = |i| slots.slot(v(i)).unwrap();
+        // The parameter is local 0
+        assert!(slots.locals().len() < 5);
+        assert!((0..5).all(|i| slot(i) >= 1));
+        // `0` and `1` are live together, as are `2` and `3`
+        assert_ne!(slot(0), slot(1));
+        assert_ne!(slot(2), slot(3));
+        // `3` starts after `0` and `1` end, so it takes one of their slots
+        assert!(slot(3) == slot(0) || slot(3) == slot(1));
+        assert_eq!(slots.locals().len(), 2);
+    }
+
+    #[test]
+    fn test_slots_are_only_shared_within_a_type() {
+        use InstructionKind::*;
+        let v = ValueId;
+        let m = module(vec![block(
+            0,
+            vec![
+                instr(0, ConstInt(1, IrType::I64)),
+                instr(1, Neg(v(0))),
+                instr(2, ConstFloat(1.5)),
+                instr(3, ConstBool(true)),
+            ],
+            Terminator::Return(Some(v(1))),
+        )]);
+        let slots = allocate(&m.functions[0], &m);
+        assert_eq!(slots.slot(v(0)), slots.slot(v(1)));
+        assert_ne!(slots.slot(v(2)), slots.slot(v(0)));
+        assert_eq!(
+            slots.locals(),
+            [IrType::I64, IrType::F64, IrType::Bool].as_slice()
+        );
+    }
+
+    #[test]
+    fn test_value_used_in_loop_stays_live_across_back_edge() {
+        use InstructionKind::*;
+        let v = ValueId;
+        // 0: step = 1; loop { 1: i = phi(0, next); test i < param;
+        //                    2: t = const; next = i + step }
+        let m = module(vec![
+            block(
+                0,
+                vec![
+                    instr(0, ConstInt(1, IrType::I64)),
+                    instr(1, ConstInt(0, IrType::I64)),
+                ],
+                Terminator::Branch(BlockId(1)),
+            ),
+            block(
+                1,
+                vec![
+                    instr(2, Phi(vec![(BlockId(0), v(1)), (BlockId(2), v(6))])),
+                    instr(3, GetParam(0)),
+                    instr(4, Lt(v(2), v(3), true)),
+                ],
+                Terminator::CondBranch(v(4), BlockId(2), BlockId(3)),
+            ),
+            block(
+                2,
+                vec![
+                    instr(5, ConstInt(7, IrType::I64)),
+                    instr(6, Add(v(2), v(0))),
+                ],
+                Terminator::Branch(BlockId(1)),
+            ),
+            block(3, vec![], Terminator::Return(Some(v(2)))),
+        ]);
+        let slots = allocate(&m.functions[0], &m);
+        // `step` is read on every iteration, so the temporary in the body
+        // can't take its slot even though it comes after `step`'s last read
+        assert_ne!(slots.slot(v(5)), slots.slot(v(0)));
+        assert_ne!(slots.slot(v(6)), slots.slot(v(0)));
+        assert_eq!(slots.slot(v(3)), None);
+    }
+}
@@ -51,34 +51,139 @@
 //! cargo run -- lex FILE   # See tokens
 //! cargo run -- parse FILE # See AST
 //! cargo run -- compile FILE -o out.wasm
+//! cargo run -- explain E0200
 //! ```
 
-mod ast;
-mod codegen;
-mod error;
-mod ir;
-mod lexer;
-#[cfg(test)]
-mod lexer_attack;
-mod parser;
-#[cfg(test)]
-mod parser_attack;
-mod span;
-#[cfg(test)]
-mod span_attack;
-mod token;
-#[cfg(test)]
-mod token_attack;
-mod types;
+use nova::{cfg, codegen, error, ir, lexer, parser, resolve, timing, types, wat};
 
 use std::env;
 use std::fs;
 use std::path::Path;
 use std::process;
+use std::thread;
+
+/// Default stack size for the compiler thread, in MiB
+const DEFAULT_STACK_SIZE_MB: usize = 16;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // The parser recurses once per nesting level, so compilation runs on a
+    // thread whose stack size (and therefore depth limit) is configurable
+    let stack_size_mb = take_stack_size(&mut args);
+    let stack_size = stack_size_mb * 1024 * 1024;
+    let options = parser::ParseOptions::for_stack_size(stack_size);
+    error::set_color(error::should_color(take_flag(&mut args, "--no-color")));
+
+    let compiler = thread::Builder::new()
+        .name("nova".to_string())
+        .stack_size(stack_size)
+        .spawn(move || run(&args, &options));
+    match compiler.map(|handle| handle.join()) {
+        Ok(Ok(())) => {}
+        Ok(Err(_)) => process::exit(101),
+        Err(e) => {
+            eprintln!("Error: could not start compiler thread: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
+/// Remove `--stack-size <MiB>` from the arguments, returning its value
+fn take_stack_size(args: &mut Vec<String>) -> usize {
+    let Some(pos) = args.iter().position(|a| a == "--stack-size") else {
+        return DEFAULT_STACK_SIZE_MB;
+    };
+    let value = args.get(pos + 1).and_then(|v| v.parse::<usize>().ok());
+    match value {
+        Some(mb) if mb > 0 => {
+            args.drain(pos..pos + 2);
+            mb
+        }
+        _ => {
+            eprintln!("Error: --stack-size expects a size in MiB");
+            process::exit(1);
+        }
+    }
+}
+
+/// Remove `flag` from the arguments, returning whether it was present
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let Some(pos) = args.iter().position(|a| a == flag) else {
+        return false;
+    };
+    args.remove(pos);
+    true
+}
+
+/// Remove `--newline-terminated` from the arguments, returning the lexer mode
+fn take_lex_options(args: &mut Vec<String>) -> lexer::LexOptions {
+    lexer::LexOptions {
+        newline_terminated: take_flag(args, "--newline-terminated"),
+    }
+}
+
+/// Remove every `--cfg <spec>` from the arguments, collecting the specs
+fn take_cfg(args: &mut Vec<String>) -> cfg::CfgSet {
+    let mut cfg = cfg::CfgSet::new();
+    while let Some(pos) = args.iter().position(|a| a == "--cfg") {
+        match args.get(pos + 1) {
+            Some(spec) => cfg.enable(spec),
+            None => {
+                eprintln!("Error: --cfg expects a name or key=\"value\"");
+                process::exit(1);
+            }
+        }
+        args.drain(pos..pos + 2);
+    }
+    cfg
+}
+
+/// Remove `--default-int=<i32|i64>` from the arguments, returning its value
+fn take_default_int(args: &mut Vec<String>) -> types::DefaultInt {
+    let Some(pos) = args.iter().position(|a| a.starts_with("--default-int")) else {
+        return types::DefaultInt::default();
+    };
+    let arg = args.remove(pos);
+    match arg
+        .strip_prefix("--default-int=")
+        .and_then(types::DefaultInt::from_name)
+    {
+        Some(default_int) => default_int,
+        None => {
+            eprintln!("Error: --default-int expects `i32` or `i64` (e.g. --default-int=i32)");
+            process::exit(1);
+        }
+    }
+}
+
+/// The intermediate results `compile` prints to stdout
+#[derive(Debug, Clone, Copy, Default)]
+struct Dumps {
+    /// `--dump-ir`: the IR module
+    ir: bool,
+    /// `--dump-cfg`: each function's control-flow graph, as Graphviz DOT
+    cfg: bool,
+    /// `--dump-wat`: the WASM module in the text format
+    wat: bool,
+}
+
+impl Dumps {
+    fn any(self) -> bool {
+        self.ir || self.cfg || self.wat
+    }
+}
+
+/// Remove `--dump-ir`, `--dump-cfg` and `--dump-wat` from the arguments
+fn take_dumps(args: &mut Vec<String>) -> Dumps {
+    Dumps {
+        ir: take_flag(args, "--dump-ir"),
+        cfg: take_flag(args, "--dump-cfg"),
+        wat: take_flag(args, "--dump-wat"),
+    }
+}
+
+fn run(args: &[String], options: &parser::ParseOptions) {
     if args.len() < 2 {
         eprintln!("Nova Bootstrap Compiler v0.0.1");
         eprintln!();
@@ -88,14 +193,30 @@ fn main() {
         eprintln!("  compile <file.nova>    Compile a Nova source file");
         eprintln!("  lex <file.nova>        Show tokens (debug)");
         eprintln!("  parse <file.nova>      Show AST (debug)");
+        eprintln!("  explain <CODE>         Describe an error code, like E0200");
         eprintln!("  help                   Show this message");
+        eprintln!();
+        eprintln!("Options:");
+        eprintln!(
+            "  --stack-size <MiB>     Compiler stack size (default: {}); deeper nesting needs more",
+            DEFAULT_STACK_SIZE_MB
+        );
+        eprintln!("  --default-int=<i32|i64> Type of unsuffixed integer literals (default: i64)");
+        eprintln!("  --cfg <name>           Enable `#[cfg(name)]` items (repeatable)");
+        eprintln!("  --no-color             Plain diagnostics (also set by NO_COLOR)");
+        eprintln!("  --newline-terminated   End statements at line breaks (experimental)");
+        eprintln!("  --time                 Print how long each compile phase took");
+        eprintln!("  --dump-ir              Print the IR of a compiled file");
+        eprintln!("  --dump-cfg             Print its control-flow graphs as Graphviz DOT");
+        eprintln!("  --dump-wat             Print its WASM as text, with doc comments");
         process::exit(1);
     }
 
     match args[1].as_str() {
-        "compile" => cmd_compile(&args[2..]),
+        "compile" => cmd_compile(&args[2..], options),
         "lex" => cmd_lex(&args[2..]),
-        "parse" => cmd_parse(&args[2..]),
+        "parse" => cmd_parse(&args[2..], options),
+        "explain" | "--explain" => cmd_explain(&args[2..]),
         "help" | "--help" | "-h" => {
             eprintln!("Nova Bootstrap Compiler v0.0.1");
             eprintln!("https://github.com/nova-lang/nova");
@@ -108,7 +229,15 @@ fn main() {
     }
 }
 
-fn cmd_compile(args: &[String]) {
+fn cmd_compile(args: &[String], options: &parser::ParseOptions) {
+    let mut args = args.to_vec();
+    let check_options = types::CheckOptions {
+        default_int: take_default_int(&mut args),
+    };
+    let cfg = take_cfg(&mut args);
+    let lex_options = take_lex_options(&mut args);
+    let time = take_flag(&mut args, "--time");
+    let dumps = take_dumps(&mut args);
     if args.is_empty() {
         eprintln!("Error: No input file specified");
         eprintln!("Usage: nova compile <file.nova>");
@@ -116,59 +245,92 @@ fn cmd_compile(args: &[String]) {
     }
 
     let path = Path::new(&args[0]);
-    let source = match fs::read_to_string(path) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error reading {}: {}", path.display(), e);
-            process::exit(1);
-        }
-    };
-
-    // Lex
-    let tokens = match lexer::lex(&source) {
-        Ok(t) => t,
-        Err(e) => {
-            error::report(&source, path.to_str().unwrap_or("input"), e);
-            process::exit(1);
-        }
-    };
-
-    // Parse
-    let ast = match parser::parse(&source, tokens) {
-        Ok(a) => a,
-        Err(e) => {
-            error::report(&source, path.to_str().unwrap_or("input"), e);
-            process::exit(1);
-        }
-    };
-
-    // Type check
-    let typed_ast = match types::check(&ast) {
-        Ok(t) => t,
-        Err(e) => {
-            error::report(&source, path.to_str().unwrap_or("input"), e);
+    let mut resolver = resolve::Resolver::new(*options)
+        .with_cfg(cfg)
+        .with_lex_options(lex_options)
+        .with_times(timing::PhaseTimes::new(time));
+    let (wasm, times) = match compile(path, &mut resolver, &check_options, dumps) {
+        Ok(output) => output,
+        Err(errors) => {
+            error::report_all(resolver.source_map(), errors);
             process::exit(1);
         }
     };
 
-    // Generate IR
-    let ir = ir::lower(&typed_ast);
-
-    // Generate WASM
-    let wasm = codegen::generate(&ir);
-
     // Write output
     let output_path = path.with_extension("wasm");
     match fs::write(&output_path, wasm) {
+        // Keep stdout to the dump, so it can be piped to `dot`
+        Ok(()) if dumps.any() => eprintln!("Wrote {}", output_path.display()),
         Ok(()) => println!("Wrote {}", output_path.display()),
         Err(e) => {
             eprintln!("Error writing {}: {}", output_path.display(), e);
             process::exit(1);
         }
     }
+    if time {
+        // Timing is best-effort diagnostics; a closed stderr isn't an error
+        let _ = times.write_report(std::io::stderr());
+    }
+}
+
+/// Compile `path` and the modules it declares to WASM, printing the IR as
+/// `dumps` asks.
+///
+/// Also returns how long each phase took, if the resolver was timing.
+fn compile(
+    path: &Path,
+    resolver: &mut resolve::Resolver,
+    check_options: &types::CheckOptions,
+    dumps: Dumps,
+) -> Result<(Vec<u8>, timing::PhaseTimes), Vec<error::NovaError>> {
+    // Lex and parse the entry file and every module it declares
+    let ast = resolver.resolve(path)?;
+    let mut times = resolver.times().clone();
+
+    let typed_ast = times
+        .time("check", || types::check_with_options(&ast, check_options))
+        .map_err(|e| vec![e])?;
+    let ir = times.time("lower", || ir::lower(&typed_ast));
+    if dumps.ir {
+        println!("{:#?}", ir);
+    }
+    if dumps.cfg {
+        print!("{}", ir::to_dot(&ir));
+    }
+    let wasm = times
+        .time("codegen", || codegen::generate(&ir))
+        .map_err(|e| vec![e])?;
+    if dumps.wat {
+        print!("{}", wat::to_wat(&wasm, &ir));
+    }
+    Ok((wasm, times))
+}
+
+fn cmd_explain(args: &[String]) {
+    let Some(code) = args.first() else {
+        eprintln!("Error: No error code specified");
+        eprintln!("Usage: nova explain <CODE>");
+        process::exit(1);
+    };
+    // `e0200` and `0200` mean E0200 too
+    let code = code.to_ascii_uppercase();
+    let code = match code.starts_with('E') {
+        true => code,
+        false => format!("E{}", code),
+    };
+    match error::explain(&code) {
+        Some(text) => println!("{}", text),
+        None => {
+            eprintln!("Error: {} is not a Nova error code", code);
+            process::exit(1);
+        }
+    }
 }
 
 fn cmd_lex(args: &[String]) {
+    let mut args = args.to_vec();
+    let lex_options = take_lex_options(&mut args);
     if args.is_empty() {
         eprintln!("Error: No input file specified");
         process::exit(1);
@@ -183,10 +345,10 @@ fn cmd_lex(args: &[String]) {
         }
     };
 
-    match lexer::lex(&source) {
+    match lexer::lex_with_options(&source, 0, &lex_options) {
         Ok(tokens) => {
             for token in tokens {
-                println!("{:?}", token);
+                println!("{} @ {}", token.display_with_source(&source), token.span());
             }
         }
         Err(e) => {
@@ -196,7 +358,9 @@ fn cmd_lex(args: &[String]) {
     }
 }
 
-fn cmd_parse(args: &[String]) {
+fn cmd_parse(args: &[String], options: &parser::ParseOptions) {
+    let mut args = args.to_vec();
+    let lex_options = take_lex_options(&mut args);
     if args.is_empty() {
         eprintln!("Error: No input file specified");
         process::exit(1);
@@ -211,7 +375,7 @@ fn cmd_parse(args: &[String]) {
         }
     };
 
-    let tokens = match lexer::lex(&source) {
+    let tokens = match lexer::lex_with_options(&source, 0, &lex_options) {
         Ok(t) => t,
         Err(e) => {
             error::report(&source, path.to_str().unwrap_or("input"), e);
@@ -219,7 +383,7 @@ fn cmd_parse(args: &[String]) {
         }
     };
 
-    match parser::parse(&source, tokens) {
+    match parser::parse_with_options(&source, tokens, options) {
         Ok(ast) => {
             println!("{:#?}", ast);
         }
@@ -229,3 +393,31 @@ fn cmd_parse(args: &[String]) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_flag_keeps_output() {
+        let dir = env::temp_dir().join(format!("nova-time-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.nova");
+        fs::write(&path, "fn main() -> i64 { 1 + 2 }\n").unwrap();
+
+        let compile_with = |time: bool| {
+            let mut resolver = resolve::Resolver::new(parser::ParseOptions::default())
+                .with_times(timing::PhaseTimes::new(time));
+            let options = types::CheckOptions::default();
+            compile(&path, &mut resolver, &options, Dumps::default()).unwrap()
+        };
+        let (plain, untimed) = compile_with(false);
+        let (timed, times) = compile_with(true);
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(plain, timed);
+        assert!(untimed.phases().is_empty());
+        let phases: Vec<_> = times.phases().iter().map(|(name, _)| *name).collect();
+        assert_eq!(phases, ["lex", "parse", "check", "lower", "codegen"]);
+    }
+}
@@ -64,10 +64,58 @@ use crate::token::{Span, Token, TokenKind};
 /// Set to 64 to stay well within typical 2MB test thread stack limits
 const MAX_EXPR_DEPTH: usize = 64;
 
+/// Binding power of `=` and the compound assignments, the loosest operators
+const ASSIGN_BP: u8 = 1;
+
+/// Binding power of `..` and `..=`, between assignment and `||`
+const RANGE_BP: u8 = 1;
+
+/// Binding power of `as`, between `*` and the unary operators:
+/// `-x as T` is `(-x) as T` and `a * b as T` is `a * (b as T)`
+const CAST_BP: u8 = 13;
+
 /// Maximum block nesting depth (prevents stack overflow)
 /// Set to 64 to stay well within typical 2MB test thread stack limits
 const MAX_BLOCK_DEPTH: usize = 64;
 
+/// Stack budget per nesting level: 64 levels in a 2MB test thread stack
+const STACK_BYTES_PER_LEVEL: usize = 2 * 1024 * 1024 / 64;
+
+/// Parser limits.
+///
+/// The defaults are safe on any thread (including 2MB test threads). When
+/// the parser runs on a thread with a known, larger stack, use
+/// [`ParseOptions::for_stack_size`] to allow deeper nesting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Maximum expression nesting depth
+    pub max_expr_depth: usize,
+    /// Maximum block nesting depth
+    pub max_block_depth: usize,
+}
+
+impl ParseOptions {
+    /// Limits scaled to a thread stack of `stack_size` bytes.
+    ///
+    /// Never goes below the defaults.
+    pub fn for_stack_size(stack_size: usize) -> Self {
+        let levels = (stack_size / STACK_BYTES_PER_LEVEL).max(MAX_EXPR_DEPTH);
+        Self {
+            max_expr_depth: levels,
+            max_block_depth: levels.max(MAX_BLOCK_DEPTH),
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            max_expr_depth: MAX_EXPR_DEPTH,
+            max_block_depth: MAX_BLOCK_DEPTH,
+        }
+    }
+}
+
 // ============================================================================
 // Public API
 // ============================================================================
@@ -78,33 +126,129 @@ const MAX_BLOCK_DEPTH: usize = 64;
 ///
 /// * `source` - The original source code (needed to extract literal values)
 /// * `tokens` - The tokens produced by the lexer
+#[allow(dead_code)] // the CLI uses `parse_with_options`
 pub fn parse(source: &str, tokens: Vec<Token>) -> Result<Program, NovaError> {
-    let mut parser = Parser::new(source, tokens);
+    parse_with_options(source, tokens, &ParseOptions::default())
+}
+
+/// Parse tokens into an AST with custom limits.
+///
+/// Only raise the limits above the defaults when running on a thread whose
+/// stack is large enough (see [`ParseOptions::for_stack_size`]).
+pub fn parse_with_options(
+    source: &str,
+    tokens: Vec<Token>,
+    options: &ParseOptions,
+) -> Result<Program, NovaError> {
+    let mut parser = Parser::new(source, tokens, *options);
     parser.parse_program()
 }
 
+/// Parse tokens into an AST, reporting every syntax error instead of just
+/// the first.
+///
+/// After an error the parser skips to the next item and carries on, so
+/// the errors come back in source order.
+pub fn parse_recovering(
+    source: &str,
+    tokens: Vec<Token>,
+    options: &ParseOptions,
+) -> Result<Program, Vec<NovaError>> {
+    let mut parser = Parser::new(source, tokens, *options);
+    parser.parse_program_recovering()
+}
+
+/// Resolves escape sequences in string literal text.
+fn unescape(text: &str) -> String {
+    // TODO: Proper escape sequence handling
+    text.replace("\\n", "\n")
+        .replace("\\t", "\t")
+        .replace("\\r", "\r")
+        .replace("\\\\", "\\")
+        .replace("\\\"", "\"")
+        .replace("\\'", "'")
+}
+
+/// Index of the `}` closing an interpolation whose body starts at `start`.
+///
+/// Skips nested braces and string literals, mirroring the lexer.
+fn interpolation_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 1;
+    let mut in_string = false;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' => in_string = !in_string,
+            // Skip a char literal, whose char may be a brace or an escape
+            b'\'' if !in_string => {
+                i += 1;
+                if bytes.get(i) == Some(&b'\\') {
+                    i += 2;
+                }
+                while i < bytes.len() && bytes[i] != b'\'' {
+                    i += 1;
+                }
+            }
+            b'{' if !in_string => depth += 1,
+            b'}' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
 /// The parser state.
 struct Parser<'a> {
     /// The source code (for extracting literal values via span)
     source: &'a str,
-    /// The token stream
+    /// The token stream, without `Newline` tokens
     tokens: Vec<Token>,
+    /// Whether a `Newline` token came right before each token in `tokens`
+    newline_before: Vec<bool>,
     /// Current position in token stream
     current: usize,
     /// Current expression nesting depth (for security limits)
     expr_depth: usize,
     /// Current block nesting depth (for security limits)
     block_depth: usize,
+    /// Whether `Path {` may start a struct literal; off in the head of an
+    /// `if`, `while`, `for` or `match`, where the `{` starts the body
+    struct_literals: bool,
+    /// Nesting limits
+    options: ParseOptions,
 }
 
 impl<'a> Parser<'a> {
-    fn new(source: &'a str, tokens: Vec<Token>) -> Self {
+    fn new(source: &'a str, tokens: Vec<Token>, options: ParseOptions) -> Self {
+        // Newlines are only significant where a statement may end, so
+        // they're kept aside instead of cluttering every lookahead
+        let mut kept = Vec::with_capacity(tokens.len());
+        let mut newline_before = Vec::with_capacity(tokens.len());
+        let mut newline = false;
+        for token in tokens {
+            if token.kind() == TokenKind::Newline {
+                newline = true;
+            } else {
+                kept.push(token);
+                newline_before.push(std::mem::take(&mut newline));
+            }
+        }
         Self {
             source,
-            tokens,
+            tokens: kept,
+            newline_before,
             current: 0,
             expr_depth: 0,
             block_depth: 0,
+            struct_literals: true,
+            options,
         }
     }
 
@@ -125,8 +269,50 @@ impl<'a> Parser<'a> {
         self.text(token.span())
     }
 
+    /// Validates the placement of `_` digit separators in a numeric literal.
+    ///
+    /// Every `_` must sit between two digits of the literal's radix, so it may
+    /// not lead (`0x_FF`), trail (`1000_`), double up (`1__000`), or touch a
+    /// decimal point or exponent (`1_.5`, `1e_5`). A literal like `_1000`
+    /// never reaches here: the lexer treats it as an identifier.
+    fn check_digit_separators(&self, span: Span) -> Result<(), NovaError> {
+        let text = self.text(span);
+        let bytes = text.as_bytes();
+        let lower = text.to_ascii_lowercase();
+        let (radix, digits_start) = if lower.starts_with("0x") {
+            (16, 2)
+        } else if lower.starts_with("0b") {
+            (2, 2)
+        } else if lower.starts_with("0o") {
+            (8, 2)
+        } else {
+            (10, 0)
+        };
+        let is_digit = |b: u8| (b as char).is_digit(radix);
+
+        for (i, &b) in bytes.iter().enumerate() {
+            if b != b'_' {
+                continue;
+            }
+            let prev_ok = i > digits_start && is_digit(bytes[i - 1]);
+            let next_ok = bytes.get(i + 1).is_some_and(|&n| is_digit(n));
+            if !prev_ok || !next_ok {
+                let pos = span.start() + i as u32;
+                return Err(NovaError::InvalidDigitSeparator {
+                    span: Span::new(pos, pos + 1),
+                });
+            }
+        }
+        Ok(())
+    }
+
     /// Parses an integer from the source at the given span.
-    fn parse_int(&self, span: Span) -> Result<i64, NovaError> {
+    ///
+    /// The value is parsed as an `i128`, which holds every value of the
+    /// integer types up to 64 bits, signed or not. Whether it fits the type
+    /// it ends up with is the type checker's concern.
+    fn parse_int(&self, span: Span) -> Result<i128, NovaError> {
+        self.check_digit_separators(span)?;
         let text = self.text(span);
 
         // Handle different bases
@@ -143,7 +329,7 @@ impl<'a> Parser<'a> {
         // Remove underscores
         let clean: String = text.chars().filter(|c| *c != '_').collect();
 
-        i64::from_str_radix(&clean, radix).map_err(|_| NovaError::InvalidLiteral {
+        i128::from_str_radix(&clean, radix).map_err(|_| NovaError::InvalidLiteral {
             kind: "integer",
             span,
         })
@@ -151,6 +337,7 @@ impl<'a> Parser<'a> {
 
     /// Parses a float from the source at the given span.
     fn parse_float(&self, span: Span) -> Result<f64, NovaError> {
+        self.check_digit_separators(span)?;
         let text = self.text(span);
         let clean: String = text.chars().filter(|c| *c != '_').collect();
         clean.parse().map_err(|_| NovaError::InvalidLiteral {
@@ -160,17 +347,88 @@ impl<'a> Parser<'a> {
     }
 
     /// Extracts a string literal (removes quotes and unescapes).
+    ///
+    /// A raw string, `r"..."` or `r#"..."#`, is taken verbatim.
     fn parse_string(&self, span: Span) -> String {
         let text = self.text(span);
+        if let Some(raw) = text.strip_prefix('r') {
+            // Strip the `#`s and quote on each side
+            let hashes = raw.bytes().take_while(|&b| b == b'#').count();
+            return raw[hashes + 1..raw.len() - 1 - hashes].to_string();
+        }
         // Remove surrounding quotes
-        let inner = &text[1..text.len() - 1];
-        // TODO: Proper escape sequence handling
-        inner
-            .replace("\\n", "\n")
-            .replace("\\t", "\t")
-            .replace("\\r", "\r")
-            .replace("\\\\", "\\")
-            .replace("\\\"", "\"")
+        unescape(&text[1..text.len() - 1])
+    }
+
+    /// The bytes of a raw byte string literal, taken verbatim.
+    fn parse_byte_string(&self, span: Span) -> Vec<u8> {
+        let text = self.text(span);
+        // Strip `br`, then the `#`s and quote on each side
+        let hashes = text[2..].bytes().take_while(|&b| b == b'#').count();
+        text.as_bytes()[3 + hashes..text.len() - 1 - hashes].to_vec()
+    }
+
+    /// Splits a format string literal into text and interpolated expressions.
+    ///
+    /// Each `{expr}` is lexed and parsed on its own, with spans pointing back
+    /// into the literal. The lexer has already checked that braces balance.
+    fn parse_format_string(&self, span: Span) -> Result<Vec<FormatPart>, NovaError> {
+        // Skip the `f"` prefix and the closing quote
+        let body_start = span.start() as usize + 2;
+        let body = &self.source[body_start..span.end() as usize - 1];
+        let bytes = body.as_bytes();
+
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut run_start = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'{' | b'}' if bytes.get(i + 1) == Some(&bytes[i]) => {
+                    text.push_str(&body[run_start..=i]);
+                    i += 2;
+                    run_start = i;
+                }
+                b'{' => {
+                    text.push_str(&body[run_start..i]);
+                    if !text.is_empty() {
+                        parts.push(FormatPart::Literal(unescape(&std::mem::take(&mut text))));
+                    }
+                    let end = interpolation_end(bytes, i + 1);
+                    let base = (body_start + i + 1) as u32;
+                    parts.push(FormatPart::Expr(
+                        self.parse_interpolation(&body[i + 1..end], base)?,
+                    ));
+                    i = end + 1;
+                    run_start = i;
+                }
+                _ => i += 1,
+            }
+        }
+        text.push_str(&body[run_start..]);
+        if !text.is_empty() {
+            parts.push(FormatPart::Literal(unescape(&text)));
+        }
+        Ok(parts)
+    }
+
+    /// Parses the expression inside `{...}`, which starts at offset `base`.
+    fn parse_interpolation(&self, text: &str, base: u32) -> Result<Expr, NovaError> {
+        let tokens = crate::lexer::lex_at(text, base)?;
+        let mut parser = Parser::new(self.source, tokens, self.options);
+        // Nested literals count towards the enclosing nesting limits
+        parser.expr_depth = self.expr_depth;
+        parser.block_depth = self.block_depth;
+        let expr = parser.parse_expr()?;
+        if !parser.is_at_end() {
+            return Err(NovaError::UnexpectedToken {
+                expected: "`}` to close the interpolation".to_string(),
+                found: parser.peek().kind(),
+                span: parser.peek().span(),
+            });
+        }
+        Ok(expr)
     }
 
     // ========================================================================
@@ -188,16 +446,150 @@ impl<'a> Parser<'a> {
         Ok(Program { items })
     }
 
-    /// Parse a top-level item.
+    /// Parse a complete program, recovering from errors item by item.
+    fn parse_program_recovering(&mut self) -> Result<Program, Vec<NovaError>> {
+        let mut items = Vec::new();
+        let mut errors = Vec::new();
+
+        while !self.is_at_end() {
+            let start = self.current;
+            match self.parse_item() {
+                Ok(item) => items.push(item),
+                Err(e) => {
+                    errors.push(e);
+                    self.synchronize(start);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(Program { items })
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Skip the rest of an item that failed to parse, starting at token
+    /// `start`.
+    ///
+    /// Stops before the next token that begins an item, or after the `}`
+    /// that closes the braces the item opened. At least one token is always
+    /// skipped, so every call makes progress.
+    fn synchronize(&mut self, start: usize) {
+        // An error leaves the depth counters wherever it was raised
+        self.expr_depth = 0;
+        self.block_depth = 0;
+
+        if self.current == start {
+            self.advance();
+        }
+        let mut depth: isize = self.tokens[start..self.current]
+            .iter()
+            .map(|t| match t.kind() {
+                TokenKind::LBrace => 1,
+                TokenKind::RBrace => -1,
+                _ => 0,
+            })
+            .sum();
+
+        while !self.is_at_end() {
+            match self.peek().kind() {
+                TokenKind::Fn
+                | TokenKind::Struct
+                | TokenKind::Enum
+                | TokenKind::Impl
+                | TokenKind::Trait
+                | TokenKind::Use
+                | TokenKind::Static
+                | TokenKind::Mod
+                | TokenKind::Pub
+                | TokenKind::Hash => return,
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace => {
+                    depth -= 1;
+                    if depth <= 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Parse a top-level item with its attributes.
     fn parse_item(&mut self) -> Result<Item, NovaError> {
+        let attrs = self.parse_attributes()?;
+        let vis_span = self.peek().span();
+        let vis = self.parse_visibility()?;
+        let mut item = self.parse_item_kind()?;
+        *item.attrs_mut() = attrs;
+        if vis != Visibility::Private {
+            *item
+                .vis_mut()
+                .ok_or_else(|| Self::misplaced_pub(vis_span))? = vis;
+        }
+        Ok(item)
+    }
+
+    /// Parse an optional `pub`, `pub(crate)` or `pub(in crate)`.
+    ///
+    /// TODO: Accept `pub(in path)` for other modules once visibility is
+    /// checked
+    fn parse_visibility(&mut self) -> Result<Visibility, NovaError> {
+        if !self.check(TokenKind::Pub) {
+            return Ok(Visibility::Private);
+        }
+        self.advance();
+        if !self.check(TokenKind::LParen) {
+            return Ok(Visibility::Public);
+        }
+        self.advance();
+        if self.check(TokenKind::In) {
+            self.advance();
+        }
+        let scope = self.parse_path()?;
+        if scope.segments.len() != 1 || scope.segments[0].ident.name != "crate" {
+            return Err(NovaError::UnexpectedToken {
+                expected: "`crate`".to_string(),
+                found: TokenKind::Ident,
+                span: scope.span,
+            });
+        }
+        self.expect(TokenKind::RParen)?;
+        Ok(Visibility::Crate)
+    }
+
+    /// The error for `in` anywhere but a `for` header or `pub(in path)`
+    fn misplaced_in(span: Span) -> NovaError {
+        NovaError::Custom {
+            message: "Unexpected keyword `in`".to_string(),
+            span,
+        }
+    }
+
+    /// The error for `pub` on something that has no visibility
+    fn misplaced_pub(span: Span) -> NovaError {
+        NovaError::Custom {
+            message: "`pub` is only allowed on functions, methods, structs, enums and traits"
+                .to_string(),
+            span,
+        }
+    }
+
+    /// Parse an item after its attributes.
+    fn parse_item_kind(&mut self) -> Result<Item, NovaError> {
         match self.peek().kind() {
-            TokenKind::Fn => self.parse_function().map(Item::Function),
+            TokenKind::Fn | TokenKind::Unsafe => self.parse_function().map(Item::Function),
             TokenKind::Struct => self.parse_struct().map(Item::Struct),
             TokenKind::Enum => self.parse_enum().map(Item::Enum),
             TokenKind::Impl => self.parse_impl().map(Item::Impl),
             TokenKind::Trait => self.parse_trait().map(Item::Trait),
             TokenKind::Use => self.parse_use().map(Item::Use),
             TokenKind::Type => self.parse_type_alias().map(Item::TypeAlias),
+            TokenKind::Static => self.parse_static().map(Item::Static),
+            TokenKind::Mod => self.parse_mod().map(Item::Mod),
             _ => Err(NovaError::UnexpectedToken {
                 expected: "item".to_string(),
                 found: self.peek().kind(),
@@ -206,9 +598,152 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Parse a function definition.
+    /// Parse the attributes before an item: `#[name]`, `#[name(...)]`,
+    /// and `///` doc comments.
+    ///
+    /// `cfg` arguments are parsed into a predicate and `repr` ones into a
+    /// list of names; other attributes keep their arguments as tokens.
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, NovaError> {
+        let mut attrs = self.doc_comments();
+        while self.check(TokenKind::Hash) {
+            let start = self.advance().span();
+            self.expect(TokenKind::LBracket)?;
+            let path = self.parse_path()?;
+            let kind = if path.segments.len() == 1 && path.segments[0].ident.name == "cfg" {
+                self.expect(TokenKind::LParen)?;
+                let predicate = self.parse_cfg_predicate()?;
+                self.expect(TokenKind::RParen)?;
+                AttributeKind::Cfg(predicate)
+            } else if path.segments.len() == 1 && path.segments[0].ident.name == "repr" {
+                AttributeKind::Repr(self.parse_repr_hints()?)
+            } else if self.check(TokenKind::LParen) {
+                let (_, args, _) = self.parse_token_group()?;
+                AttributeKind::Other(path, Some(args))
+            } else {
+                AttributeKind::Other(path, None)
+            };
+            let end = self.expect(TokenKind::RBracket)?.span();
+            attrs.push(Attribute {
+                kind,
+                span: start.merge(end),
+            });
+            attrs.extend(self.doc_comments());
+        }
+        Ok(attrs)
+    }
+
+    /// The `///` lines right before the next token, one `Doc` attribute
+    /// each. The lexer skips comments, so they're read from the source
+    /// between the previous token and the next.
+    fn doc_comments(&self) -> Vec<Attribute> {
+        let gap_start = match self.current {
+            0 => 0,
+            i => self.tokens[i - 1].span().end() as usize,
+        };
+        let gap_end = self.peek().span().start() as usize;
+        let mut docs = Vec::new();
+        let mut offset = gap_start;
+        for line in self.source[gap_start..gap_end].split_inclusive('\n') {
+            let start = offset + (line.len() - line.trim_start().len());
+            offset += line.len();
+            let line = line.trim();
+            match line.strip_prefix("///") {
+                Some(text) if !text.starts_with('/') => docs.push(Attribute {
+                    kind: AttributeKind::Doc(text.strip_prefix(' ').unwrap_or(text).to_string()),
+                    span: Span::new(start as u32, (start + line.len()) as u32),
+                }),
+                // Other comments don't break up a doc comment
+                _ if line.is_empty() || line.starts_with("//") => {}
+                // Only the comment right before the token (the gap before
+                // a file's first token spans any files before it)
+                _ => docs.clear(),
+            }
+        }
+        docs
+    }
+
+    /// Parse a `cfg` predicate: `name`, `key = "value"`, `all(...)`,
+    /// `any(...)` or `not(...)`.
+    fn parse_cfg_predicate(&mut self) -> Result<CfgPredicate, NovaError> {
+        let name = self.parse_ident()?;
+        if self.check(TokenKind::Eq) {
+            self.advance();
+            let value = self.expect(TokenKind::StringLit)?.span();
+            return Ok(CfgPredicate::KeyValue(name, self.parse_string(value)));
+        }
+        if !self.check(TokenKind::LParen) {
+            return Ok(CfgPredicate::Flag(name));
+        }
+        if !matches!(name.name.as_str(), "all" | "any" | "not") {
+            return Err(NovaError::UnexpectedToken {
+                expected: "`all`, `any` or `not` before `(`".to_string(),
+                found: TokenKind::Ident,
+                span: name.span,
+            });
+        }
+
+        // Security: nested predicates count towards the expression depth limit
+        self.expr_depth += 1;
+        if self.expr_depth > self.options.max_expr_depth {
+            let span = self.peek().span();
+            self.expr_depth -= 1;
+            return Err(NovaError::NestingTooDeep {
+                depth: self.expr_depth,
+                max: self.options.max_expr_depth,
+                span,
+            });
+        }
+        let args = self.parse_cfg_args();
+        self.expr_depth -= 1;
+        let mut args = args?;
+
+        match name.name.as_str() {
+            "all" => Ok(CfgPredicate::All(args)),
+            "any" => Ok(CfgPredicate::Any(args)),
+            _ if args.len() == 1 => Ok(CfgPredicate::Not(Box::new(args.remove(0)))),
+            _ => Err(NovaError::Custom {
+                message: "`not` takes exactly one predicate".to_string(),
+                span: name.span,
+            }),
+        }
+    }
+
+    /// Parse `(predicate, ...)` after `all`, `any` or `not`.
+    fn parse_cfg_args(&mut self) -> Result<Vec<CfgPredicate>, NovaError> {
+        self.expect(TokenKind::LParen)?;
+        let mut args = Vec::new();
+        while !self.check(TokenKind::RParen) && !self.is_at_end() {
+            args.push(self.parse_cfg_predicate()?);
+            if !self.check(TokenKind::RParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RParen)?;
+        Ok(args)
+    }
+
+    /// Parse the parenthesized, comma-separated names after `repr`
+    fn parse_repr_hints(&mut self) -> Result<Vec<Ident>, NovaError> {
+        self.expect(TokenKind::LParen)?;
+        let mut hints = Vec::new();
+        while !self.check(TokenKind::RParen) && !self.is_at_end() {
+            hints.push(self.parse_ident()?);
+            if !self.check(TokenKind::RParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RParen)?;
+        Ok(hints)
+    }
+
+    /// Parse a function definition, which may be `unsafe fn`.
     fn parse_function(&mut self) -> Result<Function, NovaError> {
-        let start = self.expect(TokenKind::Fn)?.span();
+        let start = self.peek().span();
+        let is_unsafe = self.check(TokenKind::Unsafe);
+        if is_unsafe {
+            self.advance();
+        }
+        self.expect(TokenKind::Fn)?;
 
         let name = self.parse_ident()?;
         let generics = self.parse_generics()?;
@@ -240,6 +775,9 @@ impl<'a> Parser<'a> {
             return_type,
             where_clause,
             body,
+            vis: Visibility::Private,
+            is_unsafe,
+            attrs: Vec::new(),
             span,
         })
     }
@@ -248,12 +786,31 @@ impl<'a> Parser<'a> {
     fn parse_params(&mut self) -> Result<Vec<Param>, NovaError> {
         let mut params = Vec::new();
 
+        // The first parameter's attributes come before we know if it's `self`
+        let mut first_attrs = Some(self.parse_attributes()?);
+        if let Some(mut param) = self.parse_self_param()? {
+            param.attrs = first_attrs.take().unwrap_or_default();
+            params.push(param);
+            if !self.check(TokenKind::RParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+
         while !self.check(TokenKind::RParen) && !self.is_at_end() {
-            let pattern = self.parse_pattern()?;
+            let attrs = match first_attrs.take() {
+                Some(attrs) => attrs,
+                None => self.parse_attributes()?,
+            };
+            let pattern = self.parse_single_pattern()?;
             self.expect(TokenKind::Colon)?;
             let ty = self.parse_type()?;
             let span = pattern.span.merge(ty.span);
-            params.push(Param { pattern, ty, span });
+            params.push(Param {
+                pattern,
+                ty,
+                attrs,
+                span,
+            });
 
             if !self.check(TokenKind::RParen) {
                 self.expect(TokenKind::Comma)?;
@@ -263,16 +820,65 @@ impl<'a> Parser<'a> {
         Ok(params)
     }
 
+    /// Parse a leading `self`, `mut self`, `&self` or `&mut self` parameter.
+    ///
+    /// It becomes a parameter named `self` of type `Self`, `&Self` or
+    /// `&mut Self`, as if written `self: &Self`.
+    fn parse_self_param(&mut self) -> Result<Option<Param>, NovaError> {
+        let kind_at = |p: &Self, offset: usize| p.tokens.get(p.current + offset).map(|t| t.kind());
+        let (reference, mutable, len) = match (kind_at(self, 0), kind_at(self, 1), kind_at(self, 2))
+        {
+            (Some(TokenKind::SelfLower), _, _) => (false, false, 1),
+            (Some(TokenKind::Mut), Some(TokenKind::SelfLower), _) => (false, true, 2),
+            (Some(TokenKind::Amp), Some(TokenKind::SelfLower), _) => (true, false, 2),
+            (Some(TokenKind::Amp), Some(TokenKind::Mut), Some(TokenKind::SelfLower)) => {
+                (true, true, 3)
+            }
+            _ => return Ok(None),
+        };
+
+        let start = self.peek().span();
+        for _ in 1..len {
+            self.advance();
+        }
+        let self_span = self.advance().span();
+        let span = start.merge(self_span);
+
+        let self_type = Type {
+            kind: TypeKind::SelfType,
+            span: self_span,
+        };
+        let (ty, binding_mut) = if reference {
+            let ty = Type {
+                kind: TypeKind::Reference(mutable, Box::new(self_type)),
+                span,
+            };
+            (ty, false)
+        } else {
+            (self_type, mutable)
+        };
+        let pattern = Pattern {
+            kind: PatternKind::Ident(Ident::new("self", self_span), binding_mut),
+            span,
+        };
+        Ok(Some(Param {
+            pattern,
+            ty,
+            attrs: Vec::new(),
+            span,
+        }))
+    }
+
     /// Parse a block.
     fn parse_block(&mut self) -> Result<Block, NovaError> {
         // Security: Check block depth limit
         self.block_depth += 1;
-        if self.block_depth > MAX_BLOCK_DEPTH {
+        if self.block_depth > self.options.max_block_depth {
             let span = self.peek().span();
             self.block_depth -= 1;
             return Err(NovaError::NestingTooDeep {
                 depth: self.block_depth,
-                max: MAX_BLOCK_DEPTH,
+                max: self.options.max_block_depth,
                 span,
             });
         }
@@ -281,7 +887,7 @@ impl<'a> Parser<'a> {
         let mut stmts = Vec::new();
 
         while !self.check(TokenKind::RBrace) && !self.is_at_end() {
-            stmts.push(self.parse_stmt()?);
+            stmts.push(self.with_struct_literals(true, Self::parse_stmt)?);
         }
 
         let end = self.expect(TokenKind::RBrace)?.span();
@@ -300,12 +906,20 @@ impl<'a> Parser<'a> {
             TokenKind::Fn | TokenKind::Struct | TokenKind::Enum => {
                 self.parse_item().map(Stmt::Item)
             }
+            TokenKind::Unsafe if self.next_is(TokenKind::Fn) => self.parse_item().map(Stmt::Item),
+            kind if Self::is_block_like_start(kind) => {
+                let expr = self.parse_block_like_stmt_expr()?;
+                let has_semi = self.end_expr_stmt();
+                let span = expr.span;
+                Ok(Stmt::Expr(ExprStmt {
+                    expr,
+                    has_semi,
+                    span,
+                }))
+            }
             _ => {
                 let expr = self.parse_expr()?;
-                let has_semi = self.check(TokenKind::Semi);
-                if has_semi {
-                    self.advance();
-                }
+                let has_semi = self.end_expr_stmt();
                 let span = expr.span;
                 Ok(Stmt::Expr(ExprStmt {
                     expr,
@@ -316,6 +930,77 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Whether a significant newline separates the current token from the
+    /// previous one (only ever true in newline-terminated mode).
+    fn at_newline(&self) -> bool {
+        self.newline_before
+            .get(self.current)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Whether the statement ends before the current token, as after a bare
+    /// `return` or `break`.
+    fn at_stmt_end(&self) -> bool {
+        self.check(TokenKind::Semi) || self.check(TokenKind::RBrace) || self.at_newline()
+    }
+
+    /// Consume the `;` ending a statement (or, in newline-terminated mode, a
+    /// line break before the next token), returning where the statement ends.
+    fn expect_terminator(&mut self) -> Result<Span, NovaError> {
+        if !self.check(TokenKind::Semi) && self.at_newline() && self.current > 0 {
+            return Ok(self.tokens[self.current - 1].span());
+        }
+        Ok(self.expect(TokenKind::Semi)?.span())
+    }
+
+    /// Whether an expression statement ends here, consuming a `;` if present.
+    ///
+    /// A line break counts like `;` in newline-terminated mode, except right
+    /// before the `}` of a block, where the expression is the block's value.
+    fn end_expr_stmt(&mut self) -> bool {
+        if self.check(TokenKind::Semi) {
+            self.advance();
+            return true;
+        }
+        self.at_newline() && !self.check(TokenKind::RBrace)
+    }
+
+    /// Returns true if `kind` starts a block-like expression.
+    fn is_block_like_start(kind: TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::LBrace
+                | TokenKind::If
+                | TokenKind::Match
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Loop
+                | TokenKind::Unsafe
+        )
+    }
+
+    /// Parse an expression statement that starts with a block-like expression.
+    ///
+    /// Follows Rust's rule: a block, `unsafe` block, `if`, `match`, `while`,
+    /// `for` or `loop` at the start of a statement is a complete statement on
+    /// its own, so a
+    /// following binary operator begins a new statement. `{ 1 } - 2` is
+    /// therefore the block `{ 1 }` followed by the statement `-2`, not a
+    /// subtraction.
+    ///
+    /// Method calls, field access and `?` still continue the expression
+    /// (`match x { .. }.len() + 1`), and once they do, the result is an
+    /// ordinary expression that binary operators may extend.
+    fn parse_block_like_stmt_expr(&mut self) -> Result<Expr, NovaError> {
+        let expr = self.parse_primary()?;
+        if !self.at_newline() && (self.check(TokenKind::Dot) || self.check(TokenKind::Question)) {
+            self.parse_expr_bp_rest(expr, 0)
+        } else {
+            Ok(expr)
+        }
+    }
+
     /// Parse a let statement.
     fn parse_let_stmt(&mut self) -> Result<LetStmt, NovaError> {
         let start = self.expect(TokenKind::Let)?.span();
@@ -336,7 +1021,7 @@ impl<'a> Parser<'a> {
             None
         };
 
-        let end = self.expect(TokenKind::Semi)?.span();
+        let end = self.expect_terminator()?;
 
         Ok(LetStmt {
             pattern,
@@ -354,12 +1039,12 @@ impl<'a> Parser<'a> {
     fn parse_expr(&mut self) -> Result<Expr, NovaError> {
         // Security: Check expression depth limit
         self.expr_depth += 1;
-        if self.expr_depth > MAX_EXPR_DEPTH {
+        if self.expr_depth > self.options.max_expr_depth {
             let span = self.peek().span();
             self.expr_depth -= 1;
             return Err(NovaError::NestingTooDeep {
                 depth: self.expr_depth,
-                max: MAX_EXPR_DEPTH,
+                max: self.options.max_expr_depth,
                 span,
             });
         }
@@ -369,11 +1054,27 @@ impl<'a> Parser<'a> {
     }
 
     /// Parse expression with binding power (Pratt parsing).
+    ///
+    /// In expression position `<` is always the less-than operator, never
+    /// the start of generic arguments: `a < b > c` is `(a < b) > c`, and
+    /// `f(a < b, c > (d))` passes two comparisons. Generic arguments on an
+    /// expression path must be written with a turbofish, `f::<T>(x)`, which
+    /// `parse_path` handles before we ever see the `<`. This keeps the
+    /// grammar free of the lookahead Rust needs for the same ambiguity.
     fn parse_expr_bp(&mut self, min_bp: u8) -> Result<Expr, NovaError> {
         // Parse prefix
-        let mut lhs = self.parse_prefix()?;
+        let lhs = self.parse_prefix()?;
+        self.parse_expr_bp_rest(lhs, min_bp)
+    }
 
+    /// Continue a Pratt parse with an already-parsed left-hand side.
+    fn parse_expr_bp_rest(&mut self, mut lhs: Expr, min_bp: u8) -> Result<Expr, NovaError> {
         loop {
+            // A significant newline ends the expression: `a\n-b` is two
+            if self.at_newline() {
+                break;
+            }
+
             // Check for postfix or infix
             let op = match self.peek().kind() {
                 // Binary operators
@@ -395,32 +1096,78 @@ impl<'a> Parser<'a> {
                 TokenKind::LtEq => Some((BinOp::Le, 8, 9)),
                 TokenKind::Gt => Some((BinOp::Gt, 8, 9)),
                 TokenKind::GtEq => Some((BinOp::Ge, 8, 9)),
-                TokenKind::Eq => Some((BinOp::Assign, 1, 0)), // Right associative
+                TokenKind::Eq => Some((BinOp::Assign, ASSIGN_BP, ASSIGN_BP - 1)), // Right associative
                 _ => None,
             };
 
-            if let Some((op, l_bp, r_bp)) = op {
-                if l_bp < min_bp {
+            // `x op= y` is sugar for `x = x op y`, and as right associative
+            // as `=`. TODO: Evaluate the place once, for `a[f()] += 1`
+            if let Some(op) = Self::compound_assign_op(self.peek().kind()) {
+                if ASSIGN_BP < min_bp {
                     break;
                 }
-
                 self.advance();
-                let rhs = self.parse_expr_bp(r_bp)?;
+                let rhs = self.parse_expr_bp(ASSIGN_BP - 1)?;
                 let span = lhs.span.merge(rhs.span);
+                let value = Expr {
+                    kind: ExprKind::Binary(Box::new(lhs.clone()), op, Box::new(rhs)),
+                    span,
+                };
                 lhs = Expr {
-                    kind: ExprKind::Binary(Box::new(lhs), op, Box::new(rhs)),
+                    kind: ExprKind::Binary(Box::new(lhs), BinOp::Assign, Box::new(value)),
                     span,
                 };
                 continue;
             }
 
-            // Postfix operators
-            match self.peek().kind() {
-                TokenKind::LParen => {
-                    // Function call
-                    self.advance();
-                    let args = self.parse_args()?;
-                    let end = self.expect(TokenKind::RParen)?.span();
+            // A cast's right side is a type, not an expression
+            if self.check(TokenKind::As) {
+                if CAST_BP < min_bp {
+                    break;
+                }
+                self.advance();
+                let ty = self.parse_type()?;
+                let span = lhs.span.merge(ty.span);
+                lhs = Expr {
+                    kind: ExprKind::Cast(Box::new(lhs), ty),
+                    span,
+                };
+                continue;
+            }
+
+            // Ranges bind looser than `||` and tighter than `=`
+            if matches!(self.peek().kind(), TokenKind::DotDot | TokenKind::DotDotEq) {
+                if min_bp > RANGE_BP {
+                    break;
+                }
+                lhs = self.parse_range(Some(lhs))?;
+                continue;
+            }
+
+            if let Some((op, l_bp, r_bp)) = op {
+                if l_bp < min_bp {
+                    break;
+                }
+
+                self.advance();
+                let rhs = self.parse_expr_bp(r_bp)?;
+                let span = lhs.span.merge(rhs.span);
+                lhs = Expr {
+                    kind: ExprKind::Binary(Box::new(lhs), op, Box::new(rhs)),
+                    span,
+                };
+                continue;
+            }
+
+            // Postfix operators bind tighter than any prefix or binary
+            // operator, so they apply whatever `min_bp` is and chain left:
+            // `a.b()?` is `((a.b)())?`
+            match self.peek().kind() {
+                TokenKind::LParen => {
+                    // Function call
+                    self.advance();
+                    let args = self.parse_args()?;
+                    let end = self.expect(TokenKind::RParen)?.span();
                     let span = lhs.span.merge(end);
                     lhs = Expr {
                         kind: ExprKind::Call(Box::new(lhs), args),
@@ -430,7 +1177,7 @@ impl<'a> Parser<'a> {
                 TokenKind::LBracket => {
                     // Index
                     self.advance();
-                    let index = self.parse_expr()?;
+                    let index = self.with_struct_literals(true, Self::parse_expr)?;
                     let end = self.expect(TokenKind::RBracket)?.span();
                     let span = lhs.span.merge(end);
                     lhs = Expr {
@@ -464,9 +1211,63 @@ impl<'a> Parser<'a> {
         Ok(lhs)
     }
 
+    /// The operator a compound assignment token like `+=` applies
+    fn compound_assign_op(kind: TokenKind) -> Option<BinOp> {
+        Some(match kind {
+            TokenKind::PlusEq => BinOp::Add,
+            TokenKind::MinusEq => BinOp::Sub,
+            TokenKind::StarEq => BinOp::Mul,
+            TokenKind::SlashEq => BinOp::Div,
+            TokenKind::PercentEq => BinOp::Rem,
+            TokenKind::AmpEq => BinOp::BitAnd,
+            TokenKind::PipeEq => BinOp::BitOr,
+            TokenKind::CaretEq => BinOp::BitXor,
+            TokenKind::LtLtEq => BinOp::Shl,
+            TokenKind::GtGtEq => BinOp::Shr,
+            _ => return None,
+        })
+    }
+
+    /// Parse a range from its `..` or `..=` on, after its start (if any).
+    ///
+    /// The end is optional for `..` (`a..`, `..`), and is left off when the
+    /// next token can't start an expression. A `{` ends the range too, so
+    /// `for i in 0.. { }` loops forever rather than parsing `{ }` as the end.
+    fn parse_range(&mut self, start: Option<Expr>) -> Result<Expr, NovaError> {
+        let op = self.advance();
+        let inclusive = op.kind() == TokenKind::DotDotEq;
+        let has_end = !(self.at_newline()
+            || matches!(
+                self.peek().kind(),
+                TokenKind::RParen
+                    | TokenKind::RBracket
+                    | TokenKind::RBrace
+                    | TokenKind::LBrace
+                    | TokenKind::Comma
+                    | TokenKind::Semi
+                    | TokenKind::FatArrow
+                    | TokenKind::Eof
+            ));
+        let end = if has_end || inclusive {
+            Some(self.parse_expr_bp(RANGE_BP + 1)?)
+        } else {
+            None
+        };
+        let first = start.as_ref().map_or(op.span(), |s| s.span);
+        let last = end.as_ref().map_or(op.span(), |e| e.span);
+        Ok(Expr {
+            kind: ExprKind::Range(start.map(Box::new), end.map(Box::new), inclusive),
+            span: first.merge(last),
+        })
+    }
+
     /// Parse a prefix expression (primary or unary).
     fn parse_prefix(&mut self) -> Result<Expr, NovaError> {
         match self.peek().kind() {
+            TokenKind::DotDot | TokenKind::DotDotEq => self.parse_range(None),
+            // Only here, in prefix position, does `|` start a closure;
+            // after an operand it's bitwise or
+            TokenKind::Pipe | TokenKind::PipePipe => self.parse_closure(),
             TokenKind::Minus => {
                 let start = self.advance().span();
                 let expr = self.parse_expr_bp(14)?; // High precedence for unary
@@ -542,6 +1343,32 @@ impl<'a> Parser<'a> {
                     span,
                 })
             }
+            TokenKind::CharLit => {
+                self.advance();
+                // The lexer checked there is exactly one char (or escape)
+                let text = self.text(span);
+                let value = unescape(&text[1..text.len() - 1]);
+                Ok(Expr {
+                    kind: ExprKind::Literal(Literal::Char(value.chars().next().unwrap_or('\0'))),
+                    span,
+                })
+            }
+            TokenKind::ByteStringLit => {
+                self.advance();
+                let value = self.parse_byte_string(span);
+                Ok(Expr {
+                    kind: ExprKind::Literal(Literal::Bytes(value)),
+                    span,
+                })
+            }
+            TokenKind::FStringLit => {
+                self.advance();
+                let parts = self.parse_format_string(span)?;
+                Ok(Expr {
+                    kind: ExprKind::FormatString(parts),
+                    span,
+                })
+            }
             TokenKind::True => {
                 self.advance();
                 Ok(Expr {
@@ -558,11 +1385,38 @@ impl<'a> Parser<'a> {
             }
             TokenKind::Ident => {
                 let path = self.parse_path()?;
+                let next = self.tokens.get(self.current + 1).map(|t| t.kind());
+                if self.check(TokenKind::Bang)
+                    && matches!(
+                        next,
+                        Some(TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace)
+                    )
+                {
+                    return self.parse_macro_call(path);
+                }
+                if self.struct_literals && self.check(TokenKind::LBrace) && !self.at_newline() {
+                    return self.parse_struct_lit(path);
+                }
                 Ok(Expr {
                     span: path.span,
                     kind: ExprKind::Path(path),
                 })
             }
+            TokenKind::SelfLower => {
+                self.advance();
+                let ident = Ident::new("self", span);
+                Ok(Expr {
+                    kind: ExprKind::Path(Path {
+                        segments: vec![PathSegment {
+                            ident,
+                            generics: Vec::new(),
+                            span,
+                        }],
+                        span,
+                    }),
+                    span,
+                })
+            }
             TokenKind::LParen => {
                 let start = self.advance().span();
                 if self.check(TokenKind::RParen) {
@@ -573,7 +1427,7 @@ impl<'a> Parser<'a> {
                         span: start.merge(end),
                     })
                 } else {
-                    let expr = self.parse_expr()?;
+                    let expr = self.with_struct_literals(true, Self::parse_expr)?;
                     if self.check(TokenKind::Comma) {
                         // Tuple
                         let mut exprs = vec![expr];
@@ -582,7 +1436,7 @@ impl<'a> Parser<'a> {
                             if self.check(TokenKind::RParen) {
                                 break;
                             }
-                            exprs.push(self.parse_expr()?);
+                            exprs.push(self.with_struct_literals(true, Self::parse_expr)?);
                         }
                         let end = self.expect(TokenKind::RParen)?.span();
                         Ok(Expr {
@@ -600,7 +1454,7 @@ impl<'a> Parser<'a> {
                 let start = self.advance().span();
                 let mut exprs = Vec::new();
                 while !self.check(TokenKind::RBracket) && !self.is_at_end() {
-                    exprs.push(self.parse_expr()?);
+                    exprs.push(self.with_struct_literals(true, Self::parse_expr)?);
                     if !self.check(TokenKind::RBracket) {
                         self.expect(TokenKind::Comma)?;
                     }
@@ -618,13 +1472,22 @@ impl<'a> Parser<'a> {
                     kind: ExprKind::Block(block),
                 })
             }
+            TokenKind::Unsafe => {
+                let start = self.advance().span();
+                let block = self.parse_block()?;
+                Ok(Expr {
+                    span: start.merge(block.span),
+                    kind: ExprKind::Unsafe(block),
+                })
+            }
             TokenKind::If => self.parse_if_expr(),
             TokenKind::Match => self.parse_match_expr(),
             TokenKind::While => self.parse_while_expr(),
             TokenKind::For => self.parse_for_expr(),
+            TokenKind::Loop => self.parse_loop_expr(),
             TokenKind::Return => {
                 let start = self.advance().span();
-                let value = if !self.check(TokenKind::Semi) && !self.check(TokenKind::RBrace) {
+                let value = if !self.at_stmt_end() {
                     Some(Box::new(self.parse_expr()?))
                 } else {
                     None
@@ -641,7 +1504,7 @@ impl<'a> Parser<'a> {
             }
             TokenKind::Break => {
                 let start = self.advance().span();
-                let value = if !self.check(TokenKind::Semi) && !self.check(TokenKind::RBrace) {
+                let value = if !self.at_stmt_end() {
                     Some(Box::new(self.parse_expr()?))
                 } else {
                     None
@@ -658,11 +1521,25 @@ impl<'a> Parser<'a> {
             }
             TokenKind::Continue => {
                 let span = self.advance().span();
+                // Unlike `break`, `continue` never carries a value
+                let ends = self.at_stmt_end()
+                    || matches!(
+                        self.peek().kind(),
+                        TokenKind::Comma | TokenKind::RParen | TokenKind::RBracket | TokenKind::Eof
+                    );
+                if !ends {
+                    return Err(NovaError::UnexpectedToken {
+                        expected: "`;` (`continue` takes no value)".to_string(),
+                        found: self.peek().kind(),
+                        span: self.peek().span(),
+                    });
+                }
                 Ok(Expr {
                     kind: ExprKind::Continue,
                     span,
                 })
             }
+            TokenKind::In => Err(Self::misplaced_in(span)),
             _ => Err(NovaError::UnexpectedToken {
                 expected: "expression".to_string(),
                 found: kind,
@@ -680,8 +1557,7 @@ impl<'a> Parser<'a> {
         if self.peek().kind() == TokenKind::Ident {
             let token = self.advance();
             let span = token.span();
-            let name = self.text(span).to_string();
-            Ok(Ident { name, span })
+            Ok(Ident::new(self.text(span), span))
         } else {
             Err(NovaError::UnexpectedToken {
                 expected: "identifier".to_string(),
@@ -798,8 +1674,102 @@ impl<'a> Parser<'a> {
                     span,
                 })
             }
+            TokenKind::SelfUpper => {
+                let span = self.advance().span();
+                if !self.check(TokenKind::ColonColon) {
+                    return Ok(Type {
+                        kind: TypeKind::SelfType,
+                        span,
+                    });
+                }
+                // An associated type, `Self::Item`, is a path from `Self`
+                self.advance();
+                let mut path = self.parse_path()?;
+                path.segments.insert(
+                    0,
+                    PathSegment {
+                        ident: Ident::new("Self", span),
+                        generics: Vec::new(),
+                        span,
+                    },
+                );
+                path.span = span.merge(path.span);
+                Ok(Type {
+                    span: path.span,
+                    kind: TypeKind::Path(path),
+                })
+            }
+            // `_` lexes as an identifier, but check for both
+            TokenKind::Underscore | TokenKind::Ident if self.text(start) == "_" => {
+                let span = self.advance().span();
+                Ok(Type {
+                    kind: TypeKind::Infer,
+                    span,
+                })
+            }
+            TokenKind::Fn => {
+                // Function type: fn(T, U) -> R
+                self.advance();
+                self.expect(TokenKind::LParen)?;
+                let mut params = Vec::new();
+                while !self.check(TokenKind::RParen) && !self.is_at_end() {
+                    params.push(self.parse_type()?);
+                    if !self.check(TokenKind::RParen) {
+                        self.expect(TokenKind::Comma)?;
+                    }
+                }
+                let mut end = self.expect(TokenKind::RParen)?.span();
+                let ret = if self.check(TokenKind::Arrow) {
+                    self.advance();
+                    let ty = self.parse_type()?;
+                    end = ty.span;
+                    Some(Box::new(ty))
+                } else {
+                    None
+                };
+                Ok(Type {
+                    kind: TypeKind::Fn(params, ret),
+                    span: start.merge(end),
+                })
+            }
+            TokenKind::Impl => {
+                // Opaque type: impl Trait1 + Trait2
+                self.advance();
+                let bounds = self.parse_type_bounds()?;
+                let end = bounds.last().map(|b| b.span).unwrap_or(start);
+                Ok(Type {
+                    kind: TypeKind::ImplTrait(bounds),
+                    span: start.merge(end),
+                })
+            }
+            // `dyn` is a contextual keyword: only special before a trait path
+            TokenKind::Ident
+                if self.text(start) == "dyn"
+                    && matches!(
+                        self.tokens.get(self.current + 1).map(|t| t.kind()),
+                        Some(TokenKind::Ident)
+                    ) =>
+            {
+                self.advance();
+                let bounds = self.parse_type_bounds()?;
+                let end = bounds.last().map(|b| b.span).unwrap_or(start);
+                Ok(Type {
+                    kind: TypeKind::DynTrait(bounds),
+                    span: start.merge(end),
+                })
+            }
             TokenKind::Ident => {
-                let path = self.parse_path()?;
+                let mut path = self.parse_path()?;
+                // Type arguments don't need a turbofish in type position: `Result<T, E>`
+                if self.check(TokenKind::Lt) {
+                    let generics = self.parse_generic_args()?;
+                    let end = self.tokens[self.current - 1].span();
+                    if let Some(last) = path.segments.last_mut() {
+                        last.generics = generics;
+                        last.span = last.span.merge(end);
+                    }
+                    path.span = path.span.merge(end);
+                }
                 Ok(Type {
                     span: path.span,
                     kind: TypeKind::Path(path),
@@ -813,28 +1783,120 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Parse a pattern.
+    /// Parse one or more trait bounds separated by `+`.
+    ///
+    /// Syntax: `Trait1 + Trait2`
+    fn parse_type_bounds(&mut self) -> Result<Vec<Type>, NovaError> {
+        let mut bounds = vec![self.parse_type()?];
+        while self.check(TokenKind::Plus) {
+            self.advance();
+            bounds.push(self.parse_type()?);
+        }
+        Ok(bounds)
+    }
+
+    /// Parse a pattern, which may have alternatives: `1 | 2 | 3`.
+    ///
+    /// A leading `|` is allowed, as in `| A | B`. Parameters, where `|`
+    /// ends a closure's list, use [`Self::parse_single_pattern`].
     fn parse_pattern(&mut self) -> Result<Pattern, NovaError> {
+        if self.check(TokenKind::Pipe) {
+            self.advance();
+        }
+        let first = self.parse_single_pattern()?;
+        if !self.check(TokenKind::Pipe) {
+            return Ok(first);
+        }
+        let mut alternatives = vec![first];
+        while self.check(TokenKind::Pipe) {
+            self.advance();
+            alternatives.push(self.parse_single_pattern()?);
+        }
+        let span = alternatives[0]
+            .span
+            .merge(alternatives[alternatives.len() - 1].span);
+        Ok(Pattern {
+            kind: PatternKind::Or(alternatives),
+            span,
+        })
+    }
+
+    /// Parse a pattern without top-level alternatives.
+    fn parse_single_pattern(&mut self) -> Result<Pattern, NovaError> {
         let start = self.peek().span();
 
         match self.peek().kind() {
             TokenKind::Ident => {
-                let token = self.advance();
-                let span = token.span();
-                let name = self.text(span).to_string();
+                let next = self.tokens.get(self.current + 1).map(|t| t.kind());
+                let starts_path = matches!(
+                    next,
+                    Some(TokenKind::ColonColon | TokenKind::LParen | TokenKind::LBrace)
+                );
+                if !starts_path {
+                    let token = self.advance();
+                    let span = token.span();
+                    return Ok(Pattern {
+                        kind: PatternKind::Ident(Ident::new(self.text(span), span), false),
+                        span,
+                    });
+                }
+
+                // Constructor pattern: `Color::Red`, `Some(x)`, `Point(a, b)`,
+                // `Point { x, y: 0 }`
+                let path = self.parse_path()?;
+                if self.check(TokenKind::LBrace) {
+                    self.parse_struct_pattern(path, start)
+                } else if self.check(TokenKind::LParen) {
+                    self.advance();
+                    let mut fields = Vec::new();
+                    while !self.check(TokenKind::RParen) && !self.is_at_end() {
+                        fields.push(self.parse_pattern()?);
+                        if !self.check(TokenKind::RParen) {
+                            self.expect(TokenKind::Comma)?;
+                        }
+                    }
+                    let end = self.expect(TokenKind::RParen)?.span();
+                    Ok(Pattern {
+                        kind: PatternKind::TupleStruct(path, fields),
+                        span: start.merge(end),
+                    })
+                } else {
+                    let span = path.span;
+                    Ok(Pattern {
+                        kind: PatternKind::Path(path),
+                        span,
+                    })
+                }
+            }
+            TokenKind::LParen => {
+                self.advance();
+                let mut elems = Vec::new();
+                let mut trailing_comma = false;
+                while !self.check(TokenKind::RParen) && !self.is_at_end() {
+                    elems.push(self.parse_pattern()?);
+                    trailing_comma = self.check(TokenKind::Comma);
+                    if !self.check(TokenKind::RParen) {
+                        self.expect(TokenKind::Comma)?;
+                    }
+                }
+                let end = self.expect(TokenKind::RParen)?.span();
+                // `(p)` is `p` in parentheses, `(p,)` a one-element tuple
+                if elems.len() == 1 && !trailing_comma {
+                    return Ok(elems.remove(0));
+                }
                 Ok(Pattern {
-                    kind: PatternKind::Ident(Ident { name, span }, false),
-                    span,
+                    kind: PatternKind::Tuple(elems),
+                    span: start.merge(end),
                 })
             }
+            TokenKind::LBracket => self.parse_slice_pattern(start),
             TokenKind::Mut => {
                 self.advance();
                 if self.peek().kind() == TokenKind::Ident {
                     let token = self.advance();
                     let span = token.span();
-                    let name = self.text(span).to_string();
                     Ok(Pattern {
-                        kind: PatternKind::Ident(Ident { name, span }, true),
+                        kind: PatternKind::Ident(Ident::new(self.text(span), span), true),
                         span: start.merge(span),
                     })
                 } else {
@@ -845,15 +1907,15 @@ impl<'a> Parser<'a> {
                     })
                 }
             }
-            TokenKind::IntLit => {
-                let token = self.advance();
-                let span = token.span();
-                let value = self.parse_int(span)?;
-                Ok(Pattern {
-                    kind: PatternKind::Literal(Literal::Int(value)),
-                    span,
-                })
+            TokenKind::IntLit | TokenKind::Minus => {
+                let lo = self.parse_int_pattern()?;
+                if matches!(self.peek().kind(), TokenKind::DotDot | TokenKind::DotDotEq) {
+                    self.parse_range_pattern(Some(lo), start)
+                } else {
+                    Ok(lo)
+                }
             }
+            TokenKind::DotDot | TokenKind::DotDotEq => self.parse_range_pattern(None, start),
             TokenKind::StringLit => {
                 let token = self.advance();
                 let span = token.span();
@@ -884,6 +1946,7 @@ impl<'a> Parser<'a> {
                     span,
                 })
             }
+            TokenKind::In => Err(Self::misplaced_in(start)),
             _ => {
                 // Default wildcard for unrecognized patterns
                 let span = self.advance().span();
@@ -895,13 +1958,155 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse the fields of a struct pattern after its path.
+    ///
+    /// Syntax: `Path { field: pattern, field, ... }`, where a bare `field`
+    /// binds the field to a variable of the same name
+    fn parse_struct_pattern(&mut self, path: Path, start: Span) -> Result<Pattern, NovaError> {
+        self.expect(TokenKind::LBrace)?;
+        let mut fields = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            let name = self.parse_ident()?;
+            let pattern = if self.check(TokenKind::Colon) {
+                self.advance();
+                Some(self.parse_pattern()?)
+            } else {
+                None
+            };
+            let span = pattern
+                .as_ref()
+                .map_or(name.span, |p| name.span.merge(p.span));
+            fields.push(FieldPattern {
+                name,
+                pattern,
+                span,
+            });
+            if !self.check(TokenKind::RBrace) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        let end = self.expect(TokenKind::RBrace)?.span();
+        Ok(Pattern {
+            kind: PatternKind::Struct(path, fields),
+            span: start.merge(end),
+        })
+    }
+
+    /// Parse a slice pattern: `[a, b]`, `[head, tail @ ..]` or `[..]`.
+    ///
+    /// At most one element may be a rest pattern, `..` or `name @ ..`.
+    fn parse_slice_pattern(&mut self, start: Span) -> Result<Pattern, NovaError> {
+        self.expect(TokenKind::LBracket)?;
+        let mut elems = Vec::new();
+        let mut has_rest = false;
+        while !self.check(TokenKind::RBracket) && !self.is_at_end() {
+            let elem = match self.parse_rest_pattern()? {
+                Some(rest) if has_rest => {
+                    return Err(NovaError::Custom {
+                        message: "A slice pattern can only have one `..`".to_string(),
+                        span: rest.span,
+                    });
+                }
+                Some(rest) => {
+                    has_rest = true;
+                    rest
+                }
+                None => self.parse_pattern()?,
+            };
+            elems.push(elem);
+            if !self.check(TokenKind::RBracket) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        let end = self.expect(TokenKind::RBracket)?.span();
+        Ok(Pattern {
+            kind: PatternKind::Slice(elems),
+            span: start.merge(end),
+        })
+    }
+
+    /// Parse the rest element of a slice pattern if one comes next.
+    ///
+    /// `..` is a rest only when the element ends right after it; `..5` is
+    /// still a range.
+    fn parse_rest_pattern(&mut self) -> Result<Option<Pattern>, NovaError> {
+        let kind_at = |offset: usize| self.tokens.get(self.current + offset).map(|t| t.kind());
+        let ends_at = |offset: usize| {
+            matches!(
+                kind_at(offset),
+                Some(TokenKind::Comma | TokenKind::RBracket)
+            )
+        };
+        let bare = kind_at(0) == Some(TokenKind::DotDot) && ends_at(1);
+        let named = kind_at(0) == Some(TokenKind::Ident)
+            && kind_at(1) == Some(TokenKind::At)
+            && kind_at(2) == Some(TokenKind::DotDot)
+            && ends_at(3);
+
+        if bare {
+            let span = self.advance().span();
+            return Ok(Some(Pattern {
+                kind: PatternKind::Rest(None),
+                span,
+            }));
+        }
+        if !named {
+            return Ok(None);
+        }
+        let name = self.parse_ident()?;
+        self.advance(); // @
+        let end = self.advance().span();
+        Ok(Some(Pattern {
+            span: name.span.merge(end),
+            kind: PatternKind::Rest(Some(name)),
+        }))
+    }
+
+    /// Parse an integer literal pattern, which may be negative: `-40`.
+    fn parse_int_pattern(&mut self) -> Result<Pattern, NovaError> {
+        let start = self.peek().span();
+        let negative = self.check(TokenKind::Minus);
+        if negative {
+            self.advance();
+        }
+        let span = self.expect(TokenKind::IntLit)?.span();
+        let value = self.parse_int(span)?;
+        Ok(Pattern {
+            kind: PatternKind::Literal(Literal::Int(if negative { -value } else { value })),
+            span: start.merge(span),
+        })
+    }
+
+    /// Parse the rest of a range pattern after its optional lower bound.
+    ///
+    /// Syntax: `lo..hi`, `lo..=hi`, `lo..` or `..=hi`
+    fn parse_range_pattern(
+        &mut self,
+        lo: Option<Pattern>,
+        start: Span,
+    ) -> Result<Pattern, NovaError> {
+        let op = self.advance();
+        let inclusive = op.kind() == TokenKind::DotDotEq;
+        let has_end = matches!(self.peek().kind(), TokenKind::IntLit | TokenKind::Minus);
+        let hi = if has_end || inclusive || lo.is_none() {
+            Some(self.parse_int_pattern()?)
+        } else {
+            None
+        };
+        let end = hi.as_ref().map_or(op.span(), |hi| hi.span);
+        Ok(Pattern {
+            kind: PatternKind::Range(lo.map(Box::new), hi.map(Box::new), inclusive),
+            span: start.merge(end),
+        })
+    }
+
     // ========================================================================
     // Stub implementations (TODO: implement by contributors)
     // ========================================================================
 
     /// Parse generic parameters.
     ///
-    /// Syntax: `<T, U: Trait1 + Trait2, V>`
+    /// Syntax: `<T, U: Trait1 + Trait2, V>`, with an optional trailing comma
     fn parse_generics(&mut self) -> Result<Vec<GenericParam>, NovaError> {
         if !self.check(TokenKind::Lt) {
             return Ok(vec![]);
@@ -953,7 +2158,7 @@ impl<'a> Parser<'a> {
 
     /// Parse generic arguments (type arguments).
     ///
-    /// Syntax: `<Type1, Type2>`
+    /// Syntax: `<Type1, Type2>`, with an optional trailing comma
     fn parse_generic_args(&mut self) -> Result<Vec<Type>, NovaError> {
         if !self.check(TokenKind::Lt) {
             return Ok(vec![]);
@@ -1028,10 +2233,45 @@ impl<'a> Parser<'a> {
         let name = self.parse_ident()?;
         let generics = self.parse_generics()?;
 
+        // Tuple struct: `struct Point(i64, i64);`, unit struct: `struct Marker;`
+        if self.check(TokenKind::LParen) || self.check(TokenKind::Semi) {
+            let mut fields = Vec::new();
+            if self.check(TokenKind::LParen) {
+                self.advance();
+                while !self.check(TokenKind::RParen) && !self.is_at_end() {
+                    let attrs = self.parse_attributes()?;
+                    let ty = self.parse_type()?;
+                    // Positional fields are named by index, as in `p.0`
+                    let index = fields.len().to_string();
+                    fields.push(Field {
+                        name: Ident::new(&index, ty.span),
+                        span: ty.span,
+                        ty,
+                        attrs,
+                    });
+                    if !self.check(TokenKind::RParen) {
+                        self.expect(TokenKind::Comma)?;
+                    }
+                }
+                self.expect(TokenKind::RParen)?;
+            }
+            let end = self.expect(TokenKind::Semi)?.span();
+            return Ok(StructDef {
+                name,
+                generics,
+                fields,
+                is_tuple: true,
+                vis: Visibility::Private,
+                attrs: Vec::new(),
+                span: start.merge(end),
+            });
+        }
+
         self.expect(TokenKind::LBrace)?;
         let mut fields = Vec::new();
 
         while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            let attrs = self.parse_attributes()?;
             let field_name = self.parse_ident()?;
             self.expect(TokenKind::Colon)?;
             let ty = self.parse_type()?;
@@ -1039,6 +2279,7 @@ impl<'a> Parser<'a> {
             fields.push(Field {
                 name: field_name,
                 ty,
+                attrs,
                 span: field_span,
             });
 
@@ -1060,6 +2301,9 @@ impl<'a> Parser<'a> {
             name,
             generics,
             fields,
+            is_tuple: false,
+            vis: Visibility::Private,
+            attrs: Vec::new(),
             span: start.merge(end),
         })
     }
@@ -1079,6 +2323,7 @@ impl<'a> Parser<'a> {
         while !self.check(TokenKind::RBrace) && !self.is_at_end() {
             let variant_name = self.parse_ident()?;
             let variant_start = variant_name.span;
+            let mut variant_end = variant_name.span;
 
             let fields = if self.check(TokenKind::LParen) {
                 // Tuple variant: Variant(Type1, Type2)
@@ -1090,13 +2335,14 @@ impl<'a> Parser<'a> {
                         self.expect(TokenKind::Comma)?;
                     }
                 }
-                self.expect(TokenKind::RParen)?;
+                variant_end = self.expect(TokenKind::RParen)?.span();
                 VariantFields::Tuple(types)
             } else if self.check(TokenKind::LBrace) {
                 // Struct variant: Variant { field: Type }
                 self.advance();
                 let mut fields = Vec::new();
                 while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+                    let attrs = self.parse_attributes()?;
                     let field_name = self.parse_ident()?;
                     self.expect(TokenKind::Colon)?;
                     let ty = self.parse_type()?;
@@ -1104,6 +2350,7 @@ impl<'a> Parser<'a> {
                     fields.push(Field {
                         name: field_name,
                         ty,
+                        attrs,
                         span: field_span,
                     });
 
@@ -1117,17 +2364,28 @@ impl<'a> Parser<'a> {
                         });
                     }
                 }
-                self.expect(TokenKind::RBrace)?;
+                variant_end = self.expect(TokenKind::RBrace)?.span();
                 VariantFields::Struct(fields)
             } else {
                 // Unit variant: Variant
                 VariantFields::Unit
             };
 
-            let variant_end = self.peek().span();
-            variants.push(Variant {
-                name: variant_name,
-                fields,
+            // Unit variant with a discriminant: Variant = 1
+            let discriminant = if matches!(fields, VariantFields::Unit) && self.check(TokenKind::Eq)
+            {
+                self.advance();
+                let value = self.parse_expr()?;
+                variant_end = value.span;
+                Some(value)
+            } else {
+                None
+            };
+
+            variants.push(Variant {
+                name: variant_name,
+                fields,
+                discriminant,
                 span: variant_start.merge(variant_end),
             });
 
@@ -1149,33 +2407,379 @@ impl<'a> Parser<'a> {
             name,
             generics,
             variants,
+            vis: Visibility::Private,
+            attrs: Vec::new(),
+            span: start.merge(end),
+        })
+    }
+
+    /// Parse a module.
+    ///
+    /// Syntax: `mod name;` (the module body lives in `name.nova`) or
+    /// `mod name { items }`. Inline bodies count towards the block depth.
+    fn parse_mod(&mut self) -> Result<ModDecl, NovaError> {
+        let start = self.expect(TokenKind::Mod)?.span();
+        let name = self.parse_ident()?;
+        if self.check(TokenKind::Semi) {
+            let end = self.advance().span();
+            return Ok(ModDecl {
+                name,
+                items: None,
+                attrs: Vec::new(),
+                span: start.merge(end),
+            });
+        }
+
+        self.block_depth += 1;
+        if self.block_depth > self.options.max_block_depth {
+            let span = self.peek().span();
+            self.block_depth -= 1;
+            return Err(NovaError::NestingTooDeep {
+                depth: self.block_depth,
+                max: self.options.max_block_depth,
+                span,
+            });
+        }
+        self.expect(TokenKind::LBrace)?;
+        let mut items = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            items.push(self.parse_item()?);
+        }
+        let end = self.expect(TokenKind::RBrace)?.span();
+        self.block_depth -= 1;
+        Ok(ModDecl {
+            name,
+            items: Some(items),
+            attrs: Vec::new(),
+            span: start.merge(end),
+        })
+    }
+
+    /// Parse a static item.
+    ///
+    /// Syntax: `static NAME: Type = expr;` or `static mut NAME: Type = expr;`
+    fn parse_static(&mut self) -> Result<StaticDef, NovaError> {
+        let start = self.expect(TokenKind::Static)?.span();
+        let mutable = self.check(TokenKind::Mut);
+        if mutable {
+            self.advance();
+        }
+        let name = self.parse_ident()?;
+        self.expect(TokenKind::Colon)?;
+        let ty = self.parse_type()?;
+        self.expect(TokenKind::Eq)?;
+        let value = self.parse_expr()?;
+        let end = self.expect(TokenKind::Semi)?.span();
+        Ok(StaticDef {
+            name,
+            mutable,
+            ty,
+            value,
+            attrs: Vec::new(),
             span: start.merge(end),
         })
     }
 
+    /// Parse an inherent or trait impl block.
+    ///
+    /// Syntax: `impl<T> Type { ... }` or `impl<T> Trait for Type { ... }`
     fn parse_impl(&mut self) -> Result<ImplBlock, NovaError> {
-        // TODO: Implement impl block parsing
-        todo!("Impl parsing not yet implemented")
+        // TODO: Where clauses
+        let start = self.expect(TokenKind::Impl)?.span();
+        let generics = self.parse_generics()?;
+        let first = self.parse_type()?;
+        let (trait_, self_type) = if self.check(TokenKind::For) {
+            self.advance();
+            (Some(first), self.parse_type()?)
+        } else {
+            (None, first)
+        };
+
+        self.expect(TokenKind::LBrace)?;
+        let mut items = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            items.push(self.parse_impl_item()?);
+        }
+        let end = self.expect(TokenKind::RBrace)?.span();
+
+        Ok(ImplBlock {
+            generics,
+            trait_,
+            self_type,
+            items,
+            attrs: Vec::new(),
+            span: start.merge(end),
+        })
+    }
+
+    /// Parse a method, associated const or associated type in an impl.
+    fn parse_impl_item(&mut self) -> Result<ImplItem, NovaError> {
+        let attrs = self.parse_attributes()?;
+        let vis_span = self.peek().span();
+        let vis = self.parse_visibility()?;
+        let is_fn = self.check(TokenKind::Fn) || self.check(TokenKind::Unsafe);
+        if vis != Visibility::Private && !is_fn {
+            return Err(Self::misplaced_pub(vis_span));
+        }
+        match self.peek().kind() {
+            TokenKind::Const => {
+                let start = self.advance().span();
+                let name = self.parse_ident()?;
+                self.expect(TokenKind::Colon)?;
+                let ty = self.parse_type()?;
+                self.expect(TokenKind::Eq)?;
+                let value = self.parse_expr()?;
+                let end = self.expect(TokenKind::Semi)?.span();
+                Ok(ImplItem::Const(AssocConst {
+                    name,
+                    ty,
+                    value,
+                    attrs,
+                    span: start.merge(end),
+                }))
+            }
+            TokenKind::Type => {
+                let start = self.advance().span();
+                let name = self.parse_ident()?;
+                self.expect(TokenKind::Eq)?;
+                let ty = self.parse_type()?;
+                let end = self.expect(TokenKind::Semi)?.span();
+                Ok(ImplItem::Type(AssocType {
+                    name,
+                    ty,
+                    attrs,
+                    span: start.merge(end),
+                }))
+            }
+            TokenKind::Fn | TokenKind::Unsafe => {
+                let mut method = self.parse_function()?;
+                method.vis = vis;
+                method.attrs = attrs;
+                Ok(ImplItem::Function(method))
+            }
+            _ => Err(NovaError::UnexpectedToken {
+                expected: "`fn`, `const` or `type`".to_string(),
+                found: self.peek().kind(),
+                span: self.peek().span(),
+            }),
+        }
     }
 
+    /// Parse a trait definition.
+    ///
+    /// Syntax: `trait Name<T>: Super1 + Super2 { ... }`
     fn parse_trait(&mut self) -> Result<TraitDef, NovaError> {
-        // TODO: Implement trait parsing
-        todo!("Trait parsing not yet implemented")
+        // TODO: Where clauses
+        let start = self.expect(TokenKind::Trait)?.span();
+        let name = self.parse_ident()?;
+        let generics = self.parse_generics()?;
+        let bounds = if self.check(TokenKind::Colon) {
+            self.advance();
+            self.parse_type_bounds()?
+        } else {
+            Vec::new()
+        };
+
+        self.expect(TokenKind::LBrace)?;
+        let mut items = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            items.push(self.parse_trait_item()?);
+        }
+        let end = self.expect(TokenKind::RBrace)?.span();
+
+        Ok(TraitDef {
+            name,
+            generics,
+            bounds,
+            items,
+            vis: Visibility::Private,
+            attrs: Vec::new(),
+            span: start.merge(end),
+        })
+    }
+
+    /// Parse a method, associated const or associated type in a trait.
+    ///
+    /// Each may leave out its default: a method ends in `;` instead of a
+    /// body, a const or type has no `= ...`.
+    fn parse_trait_item(&mut self) -> Result<TraitItem, NovaError> {
+        match self.peek().kind() {
+            TokenKind::Fn => {
+                let start = self.advance().span();
+                let name = self.parse_ident()?;
+                let generics = self.parse_generics()?;
+                self.expect(TokenKind::LParen)?;
+                let params = self.parse_params()?;
+                self.expect(TokenKind::RParen)?;
+                let return_type = if self.check(TokenKind::Arrow) {
+                    self.advance();
+                    Some(self.parse_type()?)
+                } else {
+                    None
+                };
+                let (default_body, end) = if self.check(TokenKind::Semi) {
+                    (None, self.advance().span())
+                } else {
+                    let body = self.parse_block()?;
+                    let end = body.span;
+                    (Some(body), end)
+                };
+                Ok(TraitItem::Function(TraitFunction {
+                    name,
+                    generics,
+                    params,
+                    return_type,
+                    default_body,
+                    span: start.merge(end),
+                }))
+            }
+            TokenKind::Const => {
+                let start = self.advance().span();
+                let name = self.parse_ident()?;
+                self.expect(TokenKind::Colon)?;
+                let ty = self.parse_type()?;
+                let default = if self.check(TokenKind::Eq) {
+                    self.advance();
+                    Some(self.parse_expr()?)
+                } else {
+                    None
+                };
+                let end = self.expect(TokenKind::Semi)?.span();
+                Ok(TraitItem::Const(TraitConst {
+                    name,
+                    ty,
+                    default,
+                    span: start.merge(end),
+                }))
+            }
+            TokenKind::Type => {
+                let start = self.advance().span();
+                let name = self.parse_ident()?;
+                let bounds = if self.check(TokenKind::Colon) {
+                    self.advance();
+                    self.parse_type_bounds()?
+                } else {
+                    Vec::new()
+                };
+                let default = if self.check(TokenKind::Eq) {
+                    self.advance();
+                    Some(self.parse_type()?)
+                } else {
+                    None
+                };
+                let end = self.expect(TokenKind::Semi)?.span();
+                Ok(TraitItem::Type(TraitType {
+                    name,
+                    bounds,
+                    default,
+                    span: start.merge(end),
+                }))
+            }
+            _ => Err(NovaError::UnexpectedToken {
+                expected: "`fn`, `const` or `type`".to_string(),
+                found: self.peek().kind(),
+                span: self.peek().span(),
+            }),
+        }
     }
 
     fn parse_use(&mut self) -> Result<UseStmt, NovaError> {
-        // TODO: Implement use statement parsing
-        todo!("Use parsing not yet implemented")
+        let start = self.expect(TokenKind::Use)?.span();
+        let tree = self.parse_use_tree()?;
+        let end = self.expect(TokenKind::Semi)?.span();
+        Ok(UseStmt {
+            tree,
+            attrs: Vec::new(),
+            span: start.merge(end),
+        })
+    }
+
+    /// Parse the tree of a `use`.
+    ///
+    /// Syntax: `a::b`, `a::b as c` or `a::{tree, tree}`
+    fn parse_use_tree(&mut self) -> Result<UseTree, NovaError> {
+        // Not `parse_path`: a group's `{` follows the last `::`, and import
+        // paths take no generics
+        let mut segments = Vec::new();
+        let group = loop {
+            let ident = self.parse_ident()?;
+            segments.push(PathSegment {
+                span: ident.span,
+                ident,
+                generics: Vec::new(),
+            });
+            if !self.check(TokenKind::ColonColon) {
+                break false;
+            }
+            self.advance();
+            if self.check(TokenKind::LBrace) {
+                break true;
+            }
+        };
+        let path = Path {
+            span: segments[0].span.merge(segments[segments.len() - 1].span),
+            segments,
+        };
+
+        if group {
+            self.expect(TokenKind::LBrace)?;
+            if self.check(TokenKind::RBrace) {
+                return Err(NovaError::UnexpectedToken {
+                    expected: "a path to import".to_string(),
+                    found: self.peek().kind(),
+                    span: self.peek().span(),
+                });
+            }
+            let mut trees = Vec::new();
+            while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+                trees.push(self.parse_use_tree()?);
+                if !self.check(TokenKind::RBrace) {
+                    self.expect(TokenKind::Comma)?;
+                }
+            }
+            let end = self.expect(TokenKind::RBrace)?.span();
+            return Ok(UseTree {
+                span: path.span.merge(end),
+                path,
+                kind: UseTreeKind::Group(trees),
+            });
+        }
+
+        let alias = if self.check(TokenKind::As) {
+            self.advance();
+            Some(self.parse_ident()?)
+        } else {
+            None
+        };
+        Ok(UseTree {
+            span: path.span.merge(alias.map_or(path.span, |a| a.span)),
+            path,
+            kind: UseTreeKind::Simple(alias),
+        })
     }
 
+    /// Parse a type alias.
+    ///
+    /// Syntax: `type Name = Type;` or `type Name<T> = Type;`
     fn parse_type_alias(&mut self) -> Result<TypeAlias, NovaError> {
-        // TODO: Implement type alias parsing
-        todo!("Type alias parsing not yet implemented")
+        let start = self.expect(TokenKind::Type)?.span();
+        let name = self.parse_ident()?;
+        let generics = self.parse_generics()?;
+        self.expect(TokenKind::Eq)?;
+        let ty = self.parse_type()?;
+        let end = self.expect(TokenKind::Semi)?.span();
+        Ok(TypeAlias {
+            name,
+            generics,
+            ty,
+            attrs: Vec::new(),
+            span: start.merge(end),
+        })
     }
 
     fn parse_if_expr(&mut self) -> Result<Expr, NovaError> {
         let start = self.expect(TokenKind::If)?.span();
-        let cond = self.parse_expr()?;
+        let cond = self.parse_head_expr()?;
         let then_block = self.parse_block()?;
         let else_expr = if self.check(TokenKind::Else) {
             self.advance();
@@ -1207,9 +2811,16 @@ impl<'a> Parser<'a> {
     /// Syntax: `match expr { pattern => expr, pattern if guard => expr, ... }`
     fn parse_match_expr(&mut self) -> Result<Expr, NovaError> {
         let start = self.expect(TokenKind::Match)?.span();
-        let scrutinee = self.parse_expr()?;
+        let scrutinee = self.parse_head_expr()?;
 
-        self.expect(TokenKind::LBrace)?;
+        if !self.check(TokenKind::LBrace) {
+            return Err(NovaError::UnexpectedToken {
+                expected: "`{` to start the match arms".to_string(),
+                found: self.peek().kind(),
+                span: self.peek().span(),
+            });
+        }
+        self.advance();
         let mut arms = Vec::new();
 
         while !self.check(TokenKind::RBrace) && !self.is_at_end() {
@@ -1224,7 +2835,7 @@ impl<'a> Parser<'a> {
                 None
             };
 
-            self.expect(TokenKind::FatArrow)?;
+            self.expect_fat_arrow()?;
             let body = self.parse_expr()?;
             let arm_span = arm_start.merge(body.span);
 
@@ -1255,9 +2866,31 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Expect the `=>` between a match arm's pattern (or guard) and body.
+    ///
+    /// `=` and `->` are easy slips for it, so they get a suggestion.
+    fn expect_fat_arrow(&mut self) -> Result<Token, NovaError> {
+        let span = self.peek().span();
+        match self.peek().kind() {
+            TokenKind::FatArrow => Ok(self.advance()),
+            found @ (TokenKind::Eq | TokenKind::Arrow) => Err(NovaError::Custom {
+                message: format!(
+                    "Use `=>` instead of `{}` between a match arm's pattern and body",
+                    found
+                ),
+                span,
+            }),
+            found => Err(NovaError::UnexpectedToken {
+                expected: "`=>` after the match arm's pattern".to_string(),
+                found,
+                span,
+            }),
+        }
+    }
+
     fn parse_while_expr(&mut self) -> Result<Expr, NovaError> {
         let start = self.expect(TokenKind::While)?.span();
-        let cond = self.parse_expr()?;
+        let cond = self.parse_head_expr()?;
         let body = self.parse_block()?;
         let span = start.merge(body.span);
         Ok(Expr {
@@ -1270,7 +2903,13 @@ impl<'a> Parser<'a> {
         let start = self.expect(TokenKind::For)?.span();
         let pattern = self.parse_pattern()?;
         self.expect(TokenKind::In)?;
-        let iter = self.parse_expr()?;
+        if self.check(TokenKind::In) {
+            return Err(NovaError::Custom {
+                message: "`in` written twice in `for` loop".to_string(),
+                span: self.peek().span(),
+            });
+        }
+        let iter = self.parse_head_expr()?;
         let body = self.parse_block()?;
         let span = start.merge(body.span);
         Ok(Expr {
@@ -1279,55 +2918,268 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_args(&mut self) -> Result<Vec<Expr>, NovaError> {
-        let mut args = Vec::new();
-        while !self.check(TokenKind::RParen) && !self.is_at_end() {
-            args.push(self.parse_expr()?);
-            if !self.check(TokenKind::RParen) {
-                self.expect(TokenKind::Comma)?;
-            }
-        }
-        Ok(args)
+    /// Parse the expression before the body of an `if`, `while`, `for` or
+    /// `match`, where `x {` is `x` and then the body. A struct literal
+    /// there has to be in parentheses: `if p == (Point { x: 0, y: 0 }) {}`.
+    fn parse_head_expr(&mut self) -> Result<Expr, NovaError> {
+        self.with_struct_literals(false, Self::parse_expr)
     }
 
-    // ========================================================================
-    // Token helpers
-    // ========================================================================
-
-    fn peek(&self) -> &Token {
-        self.tokens
-            .get(self.current)
-            .unwrap_or_else(|| self.tokens.last().expect("Token stream is empty"))
+    /// Run `f` with struct literals allowed or not, restoring the previous
+    /// setting afterwards. Brackets of any kind allow them again.
+    fn with_struct_literals<T>(
+        &mut self,
+        allowed: bool,
+        f: impl FnOnce(&mut Self) -> Result<T, NovaError>,
+    ) -> Result<T, NovaError> {
+        let outer = std::mem::replace(&mut self.struct_literals, allowed);
+        let result = f(self);
+        self.struct_literals = outer;
+        result
     }
 
-    fn advance(&mut self) -> Token {
-        let token = *self.peek();
-        if !self.is_at_end() {
-            self.current += 1;
-        }
-        token
+    /// Parse a macro invocation after its path, keeping its arguments as
+    /// raw tokens for expansion to interpret.
+    ///
+    /// Syntax: `path!(tokens)`, `path![tokens]` or `path!{tokens}`, where
+    /// brackets inside must nest properly.
+    fn parse_macro_call(&mut self, path: Path) -> Result<Expr, NovaError> {
+        self.expect(TokenKind::Bang)?;
+        let (delimiter, tokens, end) = self.parse_token_group()?;
+        Ok(Expr {
+            span: path.span.merge(end),
+            kind: ExprKind::MacroCall {
+                path,
+                delimiter,
+                tokens,
+            },
+        })
     }
 
-    fn check(&self, kind: TokenKind) -> bool {
-        self.peek().kind() == kind
+    /// Parse a delimited group of token trees, returning its delimiter, the
+    /// tokens between the outer delimiters and the span of the closing one.
+    fn parse_token_group(&mut self) -> Result<(Delimiter, Vec<Token>, Span), NovaError> {
+        let open = self.advance();
+        let delimiter = match open.kind() {
+            TokenKind::LParen => Delimiter::Paren,
+            TokenKind::LBracket => Delimiter::Bracket,
+            _ => Delimiter::Brace,
+        };
+        // The closing bracket each open one is waiting for, innermost last
+        let mut expected = vec![Self::closing(open.kind())];
+        let mut tokens = Vec::new();
+        let end = loop {
+            let token = self.peek();
+            match token.kind() {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => {
+                    expected.push(Self::closing(token.kind()));
+                }
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                    let want = expected.pop();
+                    if want != Some(token.kind()) {
+                        return Err(NovaError::UnexpectedToken {
+                            expected: format!("`{}`", want.map_or("", TokenKind::as_str)),
+                            found: token.kind(),
+                            span: token.span(),
+                        });
+                    }
+                    if expected.is_empty() {
+                        break self.advance().span();
+                    }
+                }
+                TokenKind::Eof => {
+                    let want = expected.last().copied();
+                    return Err(NovaError::UnexpectedToken {
+                        expected: format!("`{}`", want.map_or("", TokenKind::as_str)),
+                        found: TokenKind::Eof,
+                        span: token.span(),
+                    });
+                }
+                _ => {}
+            }
+            tokens.push(self.advance());
+        };
+        Ok((delimiter, tokens, end))
     }
 
-    fn expect(&mut self, kind: TokenKind) -> Result<Token, NovaError> {
-        if self.check(kind) {
-            Ok(self.advance())
-        } else {
-            Err(NovaError::UnexpectedToken {
-                expected: kind.to_string(),
-                found: self.peek().kind(),
-                span: self.peek().span(),
-            })
+    /// The bracket that closes `open`
+    fn closing(open: TokenKind) -> TokenKind {
+        match open {
+            TokenKind::LParen => TokenKind::RParen,
+            TokenKind::LBracket => TokenKind::RBracket,
+            _ => TokenKind::RBrace,
         }
     }
 
-    fn is_at_end(&self) -> bool {
-        self.peek().kind() == TokenKind::Eof
+    /// Parse the fields of a struct literal after its path.
+    ///
+    /// Syntax: `Path { field: expr, field, ... }`, where a bare `field` is
+    /// short for `field: field`
+    fn parse_struct_lit(&mut self, path: Path) -> Result<Expr, NovaError> {
+        self.expect(TokenKind::LBrace)?;
+        let fields = self.with_struct_literals(true, |p| {
+            let mut fields = Vec::new();
+            while !p.check(TokenKind::RBrace) && !p.is_at_end() {
+                let name = p.parse_ident()?;
+                let value = if p.check(TokenKind::Colon) {
+                    p.advance();
+                    p.parse_expr()?
+                } else {
+                    Expr {
+                        kind: ExprKind::Path(Path {
+                            segments: vec![PathSegment {
+                                ident: name,
+                                generics: Vec::new(),
+                                span: name.span,
+                            }],
+                            span: name.span,
+                        }),
+                        span: name.span,
+                    }
+                };
+                fields.push(FieldInit {
+                    span: name.span.merge(value.span),
+                    name,
+                    value,
+                });
+                if !p.check(TokenKind::RBrace) {
+                    p.expect(TokenKind::Comma)?;
+                }
+            }
+            Ok(fields)
+        })?;
+        let end = self.expect(TokenKind::RBrace)?.span();
+        Ok(Expr {
+            span: path.span.merge(end),
+            kind: ExprKind::StructLit(path, fields),
+        })
     }
-}
+
+    /// Parse a closure.
+    ///
+    /// Syntax: `|param, param: Type| expr`, `|| expr` with no parameters, or
+    /// `|params| -> Type { ... }`, whose body must then be a block. An
+    /// unannotated parameter's type is `_`, left to inference.
+    fn parse_closure(&mut self) -> Result<Expr, NovaError> {
+        let start = self.peek().span();
+        let mut params = Vec::new();
+        if self.check(TokenKind::PipePipe) {
+            self.advance();
+        } else {
+            self.expect(TokenKind::Pipe)?;
+            while !self.check(TokenKind::Pipe) && !self.is_at_end() {
+                let pattern = self.parse_single_pattern()?;
+                let ty = if self.check(TokenKind::Colon) {
+                    self.advance();
+                    self.parse_type()?
+                } else {
+                    Type {
+                        kind: TypeKind::Infer,
+                        span: pattern.span,
+                    }
+                };
+                let span = pattern.span.merge(ty.span);
+                params.push(Param {
+                    pattern,
+                    ty,
+                    attrs: Vec::new(),
+                    span,
+                });
+                if !self.check(TokenKind::Pipe) {
+                    self.expect(TokenKind::Comma)?;
+                }
+            }
+            self.expect(TokenKind::Pipe)?;
+        }
+
+        let (return_type, body) = if self.check(TokenKind::Arrow) {
+            self.advance();
+            let return_type = self.parse_type()?;
+            let block = self.parse_block()?;
+            let body = Expr {
+                span: block.span,
+                kind: ExprKind::Block(block),
+            };
+            (Some(return_type), body)
+        } else {
+            (None, self.parse_expr()?)
+        };
+        Ok(Expr {
+            span: start.merge(body.span),
+            kind: ExprKind::Closure(params, return_type, Box::new(body)),
+        })
+    }
+
+    /// Parse an infinite loop, left only by `break` or `return`.
+    ///
+    /// Syntax: `loop { ... }`
+    fn parse_loop_expr(&mut self) -> Result<Expr, NovaError> {
+        let start = self.expect(TokenKind::Loop)?.span();
+        let body = self.parse_block()?;
+        Ok(Expr {
+            span: start.merge(body.span),
+            kind: ExprKind::Loop(body),
+        })
+    }
+
+    fn parse_args(&mut self) -> Result<Vec<Expr>, NovaError> {
+        let mut args = Vec::new();
+        while !self.check(TokenKind::RParen) && !self.is_at_end() {
+            args.push(self.with_struct_literals(true, Self::parse_expr)?);
+            if !self.check(TokenKind::RParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        Ok(args)
+    }
+
+    // ========================================================================
+    // Token helpers
+    // ========================================================================
+
+    fn peek(&self) -> &Token {
+        self.tokens
+            .get(self.current)
+            .unwrap_or_else(|| self.tokens.last().expect("Token stream is empty"))
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = *self.peek();
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        token
+    }
+
+    fn check(&self, kind: TokenKind) -> bool {
+        self.peek().kind() == kind
+    }
+
+    /// Whether the token after the current one is `kind`
+    fn next_is(&self, kind: TokenKind) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|t| t.kind() == kind)
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<Token, NovaError> {
+        if self.check(kind) {
+            Ok(self.advance())
+        } else if self.check(TokenKind::In) {
+            Err(Self::misplaced_in(self.peek().span()))
+        } else {
+            Err(NovaError::UnexpectedToken {
+                expected: kind.to_string(),
+                found: self.peek().kind(),
+                span: self.peek().span(),
+            })
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().kind() == TokenKind::Eof
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -1366,6 +3218,285 @@ mod tests {
         assert_eq!(program.items.len(), 1);
     }
 
+    /// Parses `source` as the initializer of a `let` and returns its parts
+    fn format_parts(source: &str) -> (String, Vec<FormatPart>) {
+        let program_source = format!("fn main() {{ let s = {}; }}", source);
+        let tokens = lex(&program_source).unwrap();
+        let program = parse(&program_source, tokens).unwrap();
+        let Item::Function(f) = &program.items[0] else {
+            panic!("Expected function");
+        };
+        match &f.body.stmts[0] {
+            Stmt::Let(LetStmt {
+                value:
+                    Some(Expr {
+                        kind: ExprKind::FormatString(parts),
+                        ..
+                    }),
+                ..
+            }) => (program_source.clone(), parts.clone()),
+            other => panic!("Expected format string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_format_string_interpolation() {
+        let (source, parts) = format_parts(r#"f"x = {a + 1}!""#);
+        assert_eq!(parts.len(), 3);
+        assert!(matches!(&parts[0], FormatPart::Literal(s) if s == "x = "));
+        match &parts[1] {
+            FormatPart::Expr(expr) => {
+                assert!(matches!(expr.kind, ExprKind::Binary(_, BinOp::Add, _)));
+                let span = expr.span;
+                assert_eq!(&source[span.start() as usize..span.end() as usize], "a + 1");
+            }
+            other => panic!("Expected interpolation, got {:?}", other),
+        }
+        assert!(matches!(&parts[2], FormatPart::Literal(s) if s == "!"));
+    }
+
+    #[test]
+    fn test_parse_format_string_escaped_braces() {
+        let (_, parts) = format_parts(r#"f"{{not interp}}""#);
+        assert_eq!(parts.len(), 1);
+        assert!(matches!(&parts[0], FormatPart::Literal(s) if s == "{not interp}"));
+    }
+
+    #[test]
+    fn test_parse_format_string_nested_braces() {
+        let (_, parts) = format_parts(r#"f"{ { g("}") } }\n""#);
+        assert_eq!(parts.len(), 2);
+        assert!(matches!(
+            &parts[0],
+            FormatPart::Expr(Expr {
+                kind: ExprKind::Block(_),
+                ..
+            })
+        ));
+        assert!(matches!(&parts[1], FormatPart::Literal(s) if s == "\n"));
+    }
+
+    #[test]
+    fn test_parse_format_string_char_literals() {
+        let (_, parts) = format_parts(r#"f"{'}'}:{'\''}""#);
+        assert_eq!(parts.len(), 3);
+        assert!(matches!(
+            &parts[0],
+            FormatPart::Expr(Expr {
+                kind: ExprKind::Literal(Literal::Char('}')),
+                ..
+            })
+        ));
+        assert!(matches!(&parts[1], FormatPart::Literal(s) if s == ":"));
+        assert!(matches!(
+            &parts[2],
+            FormatPart::Expr(Expr {
+                kind: ExprKind::Literal(Literal::Char('\'')),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_format_string_rejects_bad_interpolation() {
+        for source in [r#"fn main() { f"{}"; }"#, r#"fn main() { f"{a b}"; }"#] {
+            let tokens = lex(source).unwrap();
+            assert!(parse(source, tokens).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parse_cfg_attribute() {
+        let source = r#"#[inline] #[cfg(all(test, not(os = "wasi")))] fn t() {}"#;
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let attrs = program.items[0].attrs();
+        assert_eq!(attrs.len(), 2);
+        assert!(
+            matches!(&attrs[0].kind, AttributeKind::Other(path, None) if path_text(path) == "inline")
+        );
+        match &attrs[1].kind {
+            AttributeKind::Cfg(CfgPredicate::All(args)) => {
+                assert!(matches!(&args[0], CfgPredicate::Flag(name) if name.name == "test"));
+                match &args[1] {
+                    CfgPredicate::Not(inner) => assert!(matches!(
+                        &**inner,
+                        CfgPredicate::KeyValue(key, value) if key.name == "os" && value == "wasi"
+                    )),
+                    other => panic!("Expected not(...), got {:?}", other),
+                }
+            }
+            other => panic!("Expected cfg(all(...)), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_repr_hints() {
+        let source = "#[repr(C, packed)] struct P { x: i64 } #[repr(C)] enum E { A }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let hints = |i: usize| -> Vec<String> {
+            match &program.items[i].attrs()[0].kind {
+                AttributeKind::Repr(hints) => hints.iter().map(|h| h.name.to_string()).collect(),
+                other => panic!("Expected repr, got {:?}", other),
+            }
+        };
+        assert_eq!(hints(0), ["C", "packed"]);
+        assert_eq!(hints(1), ["C"]);
+        assert_eq!(
+            parse_errors("#[repr(C packed)] struct P { x: i64 }").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_parse_attributes_in_order() {
+        let source = "#[derive(Debug, Clone)] #[repr(C)] #[tool::skip] struct P { x: i64 }
+                      fn plain() {}
+                      #[inline] #[allow(dead_code, reason(\"(x)\"))] fn f() {}
+                      #[doc(hidden)] impl P {}";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let attrs = |i: usize| -> Vec<(String, Option<usize>)> {
+            program.items[i]
+                .attrs()
+                .iter()
+                .map(|attr| match &attr.kind {
+                    AttributeKind::Other(path, args) => {
+                        (path_text(path), args.as_ref().map(Vec::len))
+                    }
+                    AttributeKind::Repr(hints) => ("repr".to_string(), Some(hints.len())),
+                    other => panic!("Expected a plain attribute, got {:?}", other),
+                })
+                .collect()
+        };
+        let owned = |name: &str, args| (name.to_string(), args);
+        assert_eq!(
+            attrs(0),
+            [
+                owned("derive", Some(3)),
+                owned("repr", Some(1)),
+                owned("tool::skip", None)
+            ]
+        );
+        assert!(program.items[1].attrs().is_empty());
+        assert_eq!(attrs(2), [owned("inline", None), owned("allow", Some(6))]);
+        assert_eq!(attrs(3), [owned("doc", Some(1))]);
+        assert_span(
+            source,
+            program.items[0].attrs()[0].span,
+            "#[derive(Debug, Clone)]",
+        );
+        // Arguments are kept as tokens, in order
+        let AttributeKind::Other(_, Some(args)) = &program.items[0].attrs()[0].kind else {
+            unreachable!()
+        };
+        let kinds: Vec<_> = args.iter().map(|t| t.kind()).collect();
+        assert_eq!(
+            kinds,
+            [TokenKind::Ident, TokenKind::Comma, TokenKind::Ident]
+        );
+        assert_eq!(parse_errors("#[derive(Debug] fn f() {}").len(), 1);
+    }
+
+    #[test]
+    fn test_parse_doc_comments() {
+        let source = "/// Not f's doc
+                      fn plain() {}
+                      /// First line
+                      // not part of the doc
+                      ///
+                      #[inline]
+                      ///    indented
+                      //// not a doc comment
+                      fn f() {}
+                      impl P { /// A method
+                      fn m() {} }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let docs = |attrs: &[Attribute]| -> Vec<String> {
+            attrs
+                .iter()
+                .filter_map(|attr| match &attr.kind {
+                    AttributeKind::Doc(text) => Some(text.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(docs(program.items[0].attrs()), ["Not f's doc"]);
+        assert_eq!(
+            docs(program.items[1].attrs()),
+            ["First line", "", "   indented"]
+        );
+        assert_eq!(
+            Attribute::doc(program.items[1].attrs()).as_deref(),
+            Some("First line\n\n   indented")
+        );
+        assert_span(source, program.items[1].attrs()[0].span, "/// First line");
+        let Item::Impl(imp) = &program.items[2] else {
+            panic!("Expected an impl");
+        };
+        let ImplItem::Function(m) = &imp.items[0] else {
+            panic!("Expected a method");
+        };
+        assert_eq!(docs(&m.attrs), ["A method"]);
+    }
+
+    #[test]
+    fn test_parse_field_and_param_attributes() {
+        let source = "struct P { #[inline] x: i64, y: i64 } struct T(#[skip] i64);
+                      enum E { V { #[a] #[b] z: i64 } }
+                      impl P { fn m(#[ref] &self, #[wasm_export] n: i64, m: i64) {} }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let names = |attrs: &[Attribute]| -> Vec<String> {
+            attrs
+                .iter()
+                .map(|attr| match &attr.kind {
+                    AttributeKind::Other(path, _) => path_text(path),
+                    other => panic!("Expected a plain attribute, got {:?}", other),
+                })
+                .collect()
+        };
+        let (Item::Struct(p), Item::Struct(t), Item::Enum(e), Item::Impl(imp)) = (
+            &program.items[0],
+            &program.items[1],
+            &program.items[2],
+            &program.items[3],
+        ) else {
+            panic!("Expected two structs, an enum and an impl");
+        };
+        assert_eq!(names(&p.fields[0].attrs), ["inline"]);
+        assert!(p.fields[1].attrs.is_empty());
+        assert_span(source, p.fields[0].span, "x: i64");
+        assert_eq!(names(&t.fields[0].attrs), ["skip"]);
+        let VariantFields::Struct(fields) = &e.variants[0].fields else {
+            panic!("Expected a struct variant");
+        };
+        assert_eq!(names(&fields[0].attrs), ["a", "b"]);
+        let ImplItem::Function(m) = &imp.items[0] else {
+            panic!("Expected a method");
+        };
+        let params: Vec<_> = m.params.iter().map(|p| names(&p.attrs)).collect();
+        assert_eq!(
+            params,
+            [vec!["ref"], vec!["wasm_export"], Vec::<&str>::new()]
+        );
+    }
+
+    #[test]
+    fn test_parse_cfg_rejects_malformed_predicates() {
+        for source in [
+            "#[cfg(foo(bar))] fn t() {}",
+            "#[cfg(not(a, b))] fn t() {}",
+            "#[cfg(a = b)] fn t() {}",
+            "#[cfg(test)] fn t() {",
+        ] {
+            let tokens = lex(source).unwrap();
+            assert!(parse(source, tokens).is_err(), "{}", source);
+        }
+    }
+
     #[test]
     fn test_parse_underscore_pattern() {
         let source = "fn main() { let _ = 42; }";
@@ -1499,6 +3630,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_enum_discriminants() {
+        let source = "enum E { A = 1, B, C = 2 + 1 }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match &program.items[0] {
+            Item::Enum(e) => {
+                let explicit: Vec<_> = e
+                    .variants
+                    .iter()
+                    .map(|v| v.discriminant.is_some())
+                    .collect();
+                assert_eq!(explicit, [true, false, true]);
+                assert_span(source, e.variants[2].span, "C = 2 + 1");
+            }
+            _ => panic!("Expected enum"),
+        }
+    }
+
     #[test]
     fn test_parse_match_simple() {
         let source = "fn main() { match x { 1 => 10, 2 => 20 } }";
@@ -1531,6 +3681,30 @@ mod tests {
         assert!(result.is_ok(), "Match with guard should parse");
     }
 
+    #[test]
+    fn test_match_arm_arrow_suggestion() {
+        for (source, slip) in [
+            ("fn main() { match x { 1 = 2 } }", "="),
+            ("fn main() { match x { 1 => 2, _ -> 3 } }", "->"),
+        ] {
+            match parse(source, lex(source).unwrap()) {
+                Err(NovaError::Custom { message, span }) => {
+                    assert!(message.contains("Use `=>`"), "{message}");
+                    assert_span(source, span, slip);
+                }
+                other => panic!("{source}: expected a suggestion, got {:?}", other),
+            }
+        }
+        let source = "fn main() { match x { 1 2 } }";
+        match parse(source, lex(source).unwrap()) {
+            Err(NovaError::UnexpectedToken { expected, span, .. }) => {
+                assert!(expected.contains("`=>`"), "{expected}");
+                assert_span(source, span, "2");
+            }
+            other => panic!("Expected a missing `=>`, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_parse_generic_function() {
         let source = "fn identity<T>(x: T) -> T { x }";
@@ -1562,6 +3736,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_generics_trailing_comma() {
+        let source = "fn f<T, U: Display,>(x: Vec<T,>) -> Map<T, Vec<U,>,> { }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let Item::Function(f) = &program.items[0] else {
+            panic!("Expected function");
+        };
+        assert_eq!(f.generics.len(), 2);
+        let args = |ty: &Type| match &ty.kind {
+            TypeKind::Path(path) => path.segments[0].generics.clone(),
+            _ => panic!("Expected path type"),
+        };
+        assert_eq!(args(&f.params[0].ty).len(), 1);
+        let ret = args(f.return_type.as_ref().unwrap());
+        assert_eq!(ret.len(), 2);
+        assert_eq!(args(&ret[1]).len(), 1);
+        assert_eq!(
+            sexp(&body_expr("fn f() { g::<i32,>(x) }")),
+            sexp(&body_expr("fn f() { g::<i32>(x) }"))
+        );
+
+        for source in [
+            "fn f<,>() { }",
+            "fn f<,T>() { }",
+            "fn f<T U>() { }",
+            "fn f<T,,>() { }",
+            "fn f(x: Vec<,>) { }",
+            "fn f(x: Map<i32 i32>) { }",
+            "fn f(x: Vec<i32,,>) { }",
+        ] {
+            assert!(parse(source, lex(source).unwrap()).is_err(), "{source}");
+        }
+    }
+
     #[test]
     fn test_parse_generic_with_bound() {
         let source = "fn print<T: Display>(x: T) { }";
@@ -1589,4 +3798,1696 @@ mod tests {
             _ => panic!("Expected function"),
         }
     }
+
+    #[test]
+    fn test_parse_fn_type() {
+        let source = "fn apply(f: fn(i32, bool) -> i32) { }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match &program.items[0] {
+            Item::Function(f) => match &f.params[0].ty.kind {
+                TypeKind::Fn(params, ret) => {
+                    assert_eq!(params.len(), 2);
+                    assert!(ret.is_some());
+                }
+                _ => panic!("Expected fn type"),
+            },
+            _ => panic!("Expected function"),
+        }
+    }
+
+    #[test]
+    fn test_parse_impl_and_dyn_trait_types() {
+        let source = "fn f(a: impl Display + Debug, b: &dyn Display) { }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match &program.items[0] {
+            Item::Function(f) => {
+                match &f.params[0].ty.kind {
+                    TypeKind::ImplTrait(bounds) => assert_eq!(bounds.len(), 2),
+                    _ => panic!("Expected impl trait type"),
+                }
+                match &f.params[1].ty.kind {
+                    TypeKind::Reference(false, inner) => {
+                        assert!(matches!(inner.kind, TypeKind::DynTrait(ref b) if b.len() == 1))
+                    }
+                    _ => panic!("Expected reference to dyn trait"),
+                }
+            }
+            _ => panic!("Expected function"),
+        }
+    }
+
+    #[test]
+    fn test_parse_dyn_as_plain_type_name() {
+        // `dyn` is only contextual: on its own it is an ordinary path
+        let source = "fn f(a: dyn) { }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match &program.items[0] {
+            Item::Function(f) => assert!(matches!(f.params[0].ty.kind, TypeKind::Path(_))),
+            _ => panic!("Expected function"),
+        }
+    }
+
+    #[test]
+    fn test_parse_infer_type() {
+        let source = "fn f() { let x: _ = 1; let v: Vec<_> = w; }";
+        let stmts = block_stmts(source);
+        let types: Vec<_> = stmts
+            .iter()
+            .map(|s| match s {
+                Stmt::Let(l) => &l.ty.as_ref().unwrap().kind,
+                _ => panic!("Expected let"),
+            })
+            .collect();
+        assert!(matches!(types[0], TypeKind::Infer));
+        match types[1] {
+            TypeKind::Path(path) => {
+                assert!(matches!(path.segments[0].generics[0].kind, TypeKind::Infer))
+            }
+            _ => panic!("Expected path type"),
+        }
+    }
+
+    fn block_stmts(source: &str) -> Vec<Stmt> {
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match program.items.into_iter().next() {
+            Some(Item::Function(f)) => f.body.stmts,
+            _ => panic!("Expected function"),
+        }
+    }
+
+    #[test]
+    fn test_block_stmt_then_unary_is_two_statements() {
+        let stmts = block_stmts("fn main() { { 1 } - 2 }");
+        assert_eq!(stmts.len(), 2);
+        match &stmts[0] {
+            Stmt::Expr(e) => assert!(matches!(e.expr.kind, ExprKind::Block(_))),
+            _ => panic!("Expected block statement"),
+        }
+        match &stmts[1] {
+            Stmt::Expr(e) => assert!(matches!(e.expr.kind, ExprKind::Unary(UnaryOp::Neg, _))),
+            _ => panic!("Expected negation statement"),
+        }
+    }
+
+    #[test]
+    fn test_if_stmt_then_deref_is_two_statements() {
+        let stmts = block_stmts("fn main() { if c { a } else { b } *p = 1; }");
+        assert_eq!(stmts.len(), 2);
+        match &stmts[0] {
+            Stmt::Expr(e) => assert!(matches!(e.expr.kind, ExprKind::If(..))),
+            _ => panic!("Expected if statement"),
+        }
+    }
+
+    #[test]
+    fn test_block_stmt_method_call_continues() {
+        let stmts = block_stmts("fn main() { match x { _ => y }.len() + 1; }");
+        assert_eq!(stmts.len(), 1);
+        match &stmts[0] {
+            Stmt::Expr(e) => {
+                assert!(e.has_semi);
+                assert!(matches!(e.expr.kind, ExprKind::Binary(_, BinOp::Add, _)));
+            }
+            _ => panic!("Expected expression statement"),
+        }
+    }
+
+    #[test]
+    fn test_block_in_expression_position_is_operand() {
+        let stmts = block_stmts("fn main() { let x = { 1 } - 2; }");
+        assert_eq!(stmts.len(), 1);
+        match &stmts[0] {
+            Stmt::Let(l) => assert!(matches!(
+                l.value.as_ref().unwrap().kind,
+                ExprKind::Binary(_, BinOp::Sub, _)
+            )),
+            _ => panic!("Expected let statement"),
+        }
+    }
+
+    fn separator_error_at(source: &str) -> u32 {
+        let tokens = lex(source).unwrap();
+        match parse(source, tokens) {
+            Err(NovaError::InvalidDigitSeparator { span }) => {
+                assert_eq!(&source[span.start() as usize..span.end() as usize], "_");
+                span.start()
+            }
+            other => panic!("Expected InvalidDigitSeparator, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_valid_digit_separators() {
+        for lit in [
+            "1_000_000",
+            "0xFF_FF",
+            "0b1010_1010",
+            "0o7_7",
+            "1_000.000_1",
+            "1e1_0",
+        ] {
+            let source = format!("fn main() {{ let x = {}; }}", lit);
+            let tokens = lex(&source).unwrap();
+            assert!(parse(&source, tokens).is_ok(), "{} should parse", lit);
+        }
+    }
+
+    #[test]
+    fn test_trailing_digit_separator() {
+        assert_eq!(separator_error_at("fn main() { let x = 1000_; }"), 24);
+    }
+
+    #[test]
+    fn test_doubled_digit_separator() {
+        assert_eq!(separator_error_at("fn main() { let x = 1__000; }"), 21);
+    }
+
+    #[test]
+    fn test_digit_separator_after_base_prefix() {
+        assert_eq!(separator_error_at("fn main() { let x = 0x_FF; }"), 22);
+    }
+
+    #[test]
+    fn test_digit_separator_between_digits_of_the_radix() {
+        // The lexer stops at the first digit outside the radix, so check
+        // the text directly
+        let source = "0b1_2 0o7_8 0b1_1 0o7_7";
+        let parser = Parser::new(source, Vec::new(), ParseOptions::default());
+        let check = |start| parser.check_digit_separators(Span::new(start, start + 5));
+        assert!(matches!(
+            check(0),
+            Err(NovaError::InvalidDigitSeparator { .. })
+        ));
+        assert!(matches!(
+            check(6),
+            Err(NovaError::InvalidDigitSeparator { .. })
+        ));
+        assert!(check(12).is_ok() && check(18).is_ok());
+    }
+
+    #[test]
+    fn test_digit_separator_next_to_decimal_point() {
+        assert_eq!(separator_error_at("fn main() { let x = 1_.5; }"), 21);
+        assert_eq!(separator_error_at("fn main() { let x = 1.5_; }"), 23);
+    }
+
+    #[test]
+    fn test_digit_separator_next_to_exponent() {
+        assert_eq!(separator_error_at("fn main() { let x = 1_e5; }"), 21);
+    }
+
+    #[test]
+    fn test_digit_separator_in_pattern() {
+        assert_eq!(separator_error_at("fn main() { match x { 1_ => 0 } }"), 23);
+    }
+
+    fn let_value(source: &str) -> Expr {
+        match block_stmts(source).into_iter().next() {
+            Some(Stmt::Let(l)) => l.value.unwrap(),
+            _ => panic!("Expected let statement"),
+        }
+    }
+
+    #[test]
+    fn test_int_literals_beyond_i64() {
+        let int = |source: &str| match let_value(source).kind {
+            ExprKind::Literal(Literal::Int(n)) => n,
+            other => panic!("Expected an integer literal, got {:?}", other),
+        };
+        assert_eq!(
+            int("fn main() { let x = 18446744073709551615; }"),
+            u64::MAX as i128
+        );
+        // `i64::MIN` is the negation of a literal one past `i64::MAX`
+        assert_eq!(
+            int("fn main() { let x = 9223372036854775808; }"),
+            -(i64::MIN as i128)
+        );
+        let source = "fn main() { let x = 0x1_0000_0000_0000_0000_0000_0000_0000_0000; }";
+        let err = parse(source, lex(source).unwrap()).unwrap_err();
+        assert!(matches!(
+            err,
+            NovaError::InvalidLiteral {
+                kind: "integer",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_if_as_binary_rhs() {
+        let value = let_value("fn main() { let y = 1 + if c { 2 } else { 3 }; }");
+        match value.kind {
+            ExprKind::Binary(lhs, BinOp::Add, rhs) => {
+                assert!(matches!(lhs.kind, ExprKind::Literal(Literal::Int(1))));
+                assert!(matches!(rhs.kind, ExprKind::If(..)));
+            }
+            _ => panic!("Expected addition"),
+        }
+    }
+
+    #[test]
+    fn test_match_as_binary_rhs() {
+        let value = let_value("fn main() { let y = x * match n { 0 => 1, _ => 2 }; }");
+        match value.kind {
+            ExprKind::Binary(_, BinOp::Mul, rhs) => {
+                assert!(matches!(rhs.kind, ExprKind::Match(_, ref arms) if arms.len() == 2))
+            }
+            _ => panic!("Expected multiplication"),
+        }
+    }
+
+    #[test]
+    fn test_if_as_binary_lhs_in_expression_position() {
+        let value = let_value("fn main() { let y = if c { 1 } else { 2 } + 3; }");
+        match value.kind {
+            ExprKind::Binary(lhs, BinOp::Add, _) => assert!(matches!(lhs.kind, ExprKind::If(..))),
+            _ => panic!("Expected addition"),
+        }
+    }
+
+    fn match_patterns(source: &str) -> Vec<Pattern> {
+        match let_value(source).kind {
+            ExprKind::Match(_, arms) => arms.into_iter().map(|a| a.pattern).collect(),
+            _ => panic!("Expected match"),
+        }
+    }
+
+    #[test]
+    fn test_parse_tuple_patterns() {
+        let elems = |p: &Pattern| match &p.kind {
+            PatternKind::Tuple(elems) => elems.clone(),
+            other => panic!("Expected a tuple pattern, got {other:?}"),
+        };
+        let source = "fn main() { let ((a, b), c) = p; let (x,) = t; let () = u; let (y) = v; }";
+        let lets: Vec<_> = block_stmts(source)
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Let(l) => l.pattern.clone(),
+                _ => panic!("Expected a let"),
+            })
+            .collect();
+        let outer = elems(&lets[0]);
+        assert_eq!(outer.len(), 2);
+        assert_eq!(elems(&outer[0]).len(), 2);
+        assert_span(source, lets[0].span, "((a, b), c)");
+        assert_eq!(elems(&lets[1]).len(), 1);
+        assert!(elems(&lets[2]).is_empty());
+        assert!(matches!(lets[3].kind, PatternKind::Ident(..)));
+
+        let source = "fn f((a, b): (i32, i32), (mut c, _, d): (i32, i32, i32)) { }";
+        let program = parse(source, lex(source).unwrap()).unwrap();
+        let Item::Function(f) = &program.items[0] else {
+            panic!("Expected function");
+        };
+        assert_eq!(elems(&f.params[0].pattern).len(), 2);
+        assert_eq!(elems(&f.params[1].pattern).len(), 3);
+        let source = "fn main() { let (a b) = p; }";
+        assert!(parse(source, lex(source).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_parse_slice_patterns() {
+        let elems = |p: &Pattern| match &p.kind {
+            PatternKind::Slice(elems) => elems.clone(),
+            other => panic!("Expected a slice pattern, got {other:?}"),
+        };
+        let rest_name = |p: &Pattern| match &p.kind {
+            PatternKind::Rest(name) => name.as_ref().map(|n| n.name.as_str().to_string()),
+            other => panic!("Expected a rest pattern, got {other:?}"),
+        };
+        let source = "fn main() { let y = match s { [] => 0, [..] => 1, [a, b] => 2, \
+                      [head, tail @ ..] => 3, [.., last] => 4, [..5, x] => 5 }; }";
+        let pats = match_patterns(source);
+        assert!(elems(&pats[0]).is_empty());
+        assert_span(source, pats[0].span, "[]");
+
+        let lone = elems(&pats[1]);
+        assert_eq!(lone.len(), 1);
+        assert_eq!(rest_name(&lone[0]), None);
+
+        assert_eq!(elems(&pats[2]).len(), 2);
+
+        let head_tail = elems(&pats[3]);
+        assert!(matches!(head_tail[0].kind, PatternKind::Ident(..)));
+        assert_eq!(rest_name(&head_tail[1]).as_deref(), Some("tail"));
+        assert_span(source, head_tail[1].span, "tail @ ..");
+
+        assert_eq!(rest_name(&elems(&pats[4])[0]), None);
+        // `..5` is a range, not a rest
+        assert!(matches!(elems(&pats[5])[0].kind, PatternKind::Range(..)));
+
+        for source in [
+            "fn main() { match s { [.., x, ..] => 0 } }",
+            "fn main() { match s { [a @ .., ..] => 0 } }",
+        ] {
+            let errors = parse_errors(source);
+            assert!(
+                matches!(&errors[..], [NovaError::Custom { message, .. }] if message.contains("`..`")),
+                "{source}: {errors:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_or_patterns() {
+        let alternatives = |p: &Pattern| match &p.kind {
+            PatternKind::Or(alts) => alts.len(),
+            _ => 1,
+        };
+        let source = "fn main() { let y = match n { 1 | 2 | 3 => 0, | A | B => 1, Some(4 | 5) => 2, x => 3 }; }";
+        let pats = match_patterns(source);
+        let counts: Vec<_> = pats.iter().map(alternatives).collect();
+        assert_eq!(counts, [3, 2, 1, 1]);
+        assert_span(source, pats[0].span, "1 | 2 | 3");
+        assert_span(source, pats[1].span, "A | B");
+        let PatternKind::TupleStruct(_, fields) = &pats[2].kind else {
+            panic!("Expected a tuple-struct pattern");
+        };
+        assert_eq!(alternatives(&fields[0]), 2);
+
+        let Stmt::Let(l) = &block_stmts("fn main() { let A(x) | B(x) = v; }")[0] else {
+            panic!("Expected a let");
+        };
+        assert_eq!(alternatives(&l.pattern), 2);
+        // A closure's `|` still ends its parameters
+        assert_eq!(
+            sexp(&body_expr("fn f() { |a, b| a | b }")),
+            "(|a b| (BitOr a b))"
+        );
+    }
+
+    #[test]
+    fn test_parse_struct_patterns() {
+        let source = "fn main() { let y = match s { Shape::Rect { w, h: 0 } => w, \
+                      Line { from: Point(a, _), to } => a, P {} => 0 }; }";
+        let pats = match_patterns(source);
+        let fields = |p: &Pattern| match &p.kind {
+            PatternKind::Struct(path, fields) => (path_text(path), fields.clone()),
+            other => panic!("Expected struct pattern, got {:?}", other),
+        };
+        let (path, rect) = fields(&pats[0]);
+        assert_eq!(path, "Shape::Rect");
+        assert_eq!(rect[0].name.name, "w");
+        assert!(rect[0].pattern.is_none());
+        assert!(matches!(
+            rect[1].pattern.as_ref().map(|p| &p.kind),
+            Some(PatternKind::Literal(Literal::Int(0)))
+        ));
+        assert_span(source, pats[0].span, "Shape::Rect { w, h: 0 }");
+        assert_span(source, rect[1].span, "h: 0");
+
+        let (path, line) = fields(&pats[1]);
+        assert_eq!(path, "Line");
+        assert!(matches!(
+            line[0].pattern.as_ref().map(|p| &p.kind),
+            Some(PatternKind::TupleStruct(_, sub)) if sub.len() == 2
+        ));
+        assert!(line[1].pattern.is_none());
+        assert!(fields(&pats[2]).1.is_empty());
+    }
+
+    #[test]
+    fn test_parse_range_patterns() {
+        let source = "fn main() { let y = match t { -40..=0 => 1, 1..30 => 2, 100.. => 3, ..=-50 => 4, -1 => 5, _ => 6 }; }";
+        let pats = match_patterns(source);
+        let int = |p: &Option<Box<Pattern>>| match p.as_deref().map(|p| &p.kind) {
+            Some(PatternKind::Literal(Literal::Int(n))) => Some(*n),
+            None => None,
+            other => panic!("Expected an integer bound, got {:?}", other),
+        };
+        let ranges: Vec<_> = pats[..4]
+            .iter()
+            .map(|p| match &p.kind {
+                PatternKind::Range(lo, hi, inclusive) => (int(lo), int(hi), *inclusive),
+                other => panic!("Expected a range pattern, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (Some(-40), Some(0), true),
+                (Some(1), Some(30), false),
+                (Some(100), None, false),
+                (None, Some(-50), true),
+            ]
+        );
+        assert_span(source, pats[0].span, "-40..=0");
+        assert_span(source, pats[2].span, "100..");
+        assert!(matches!(
+            pats[4].kind,
+            PatternKind::Literal(Literal::Int(-1))
+        ));
+    }
+
+    #[test]
+    fn test_parse_tuple_struct_pattern() {
+        let pats = match_patterns("fn main() { let y = match o { Some(x) => x, None => 0 }; }");
+        match &pats[0].kind {
+            PatternKind::TupleStruct(path, fields) => {
+                assert_eq!(path.segments[0].ident.name, "Some");
+                assert_eq!(fields.len(), 1);
+                assert!(matches!(fields[0].kind, PatternKind::Ident(..)));
+            }
+            _ => panic!("Expected tuple-struct pattern"),
+        }
+        assert!(matches!(pats[1].kind, PatternKind::Ident(..)));
+    }
+
+    #[test]
+    fn test_parse_path_and_nested_patterns() {
+        let pats = match_patterns(
+            "fn main() { let y = match c { Color::Red => 1, Shape::Pair(Point(a, _), 0) => a }; }",
+        );
+        match &pats[0].kind {
+            PatternKind::Path(path) => assert_eq!(path.segments.len(), 2),
+            _ => panic!("Expected path pattern"),
+        }
+        match &pats[1].kind {
+            PatternKind::TupleStruct(path, fields) => {
+                assert_eq!(path.segments[1].ident.name, "Pair");
+                assert!(
+                    matches!(fields[0].kind, PatternKind::TupleStruct(_, ref f) if f.len() == 2)
+                );
+                assert!(matches!(
+                    fields[1].kind,
+                    PatternKind::Literal(Literal::Int(0))
+                ));
+            }
+            _ => panic!("Expected tuple-struct pattern"),
+        }
+    }
+
+    #[test]
+    fn test_parse_tuple_and_unit_structs() {
+        let source = "struct Point(i64, f64); struct Marker;";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match &program.items[0] {
+            Item::Struct(s) => {
+                assert!(s.is_tuple);
+                assert_eq!(s.fields.len(), 2);
+                assert_eq!(s.fields[1].name.name, "1");
+            }
+            _ => panic!("Expected struct"),
+        }
+        match &program.items[1] {
+            Item::Struct(s) => assert!(s.is_tuple && s.fields.is_empty()),
+            _ => panic!("Expected struct"),
+        }
+    }
+
+    /// Parse on a thread with the given stack size and matching limits
+    fn parse_on_stack(source: String, stack_size: usize) -> Result<Program, NovaError> {
+        std::thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn(move || {
+                let tokens = lex(&source).unwrap();
+                parse_with_options(&source, tokens, &ParseOptions::for_stack_size(stack_size))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    fn nested_parens(depth: usize) -> String {
+        format!(
+            "fn main() {{ let x = {}42{}; }}",
+            "(".repeat(depth),
+            ")".repeat(depth)
+        )
+    }
+
+    #[test]
+    fn test_default_options_are_test_safe() {
+        let options = ParseOptions::default();
+        assert_eq!(options.max_expr_depth, 64);
+        assert_eq!(options.max_block_depth, 64);
+        assert_eq!(ParseOptions::for_stack_size(2 * 1024 * 1024), options);
+        assert_eq!(ParseOptions::for_stack_size(0), options);
+    }
+
+    #[test]
+    fn test_deep_expression_with_enlarged_stack() {
+        let source = nested_parens(200);
+        let tokens = lex(&source).unwrap();
+        assert!(matches!(
+            parse(&source, tokens),
+            Err(NovaError::NestingTooDeep { max: 64, .. })
+        ));
+
+        assert!(parse_on_stack(source, 16 * 1024 * 1024).is_ok());
+    }
+
+    #[test]
+    fn test_enlarged_limit_is_enforced() {
+        let stack_size = 16 * 1024 * 1024;
+        let max = ParseOptions::for_stack_size(stack_size).max_expr_depth;
+        // The full scaled depth fits the stack it was scaled for
+        assert!(parse_on_stack(nested_parens(max - 2), stack_size).is_ok());
+        assert!(matches!(
+            parse_on_stack(nested_parens(max + 2), stack_size),
+            Err(NovaError::NestingTooDeep { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_inherent_impl() {
+        let source = "struct P { x: i64 } impl P { fn new() -> P { } fn zero() -> i64 { 0 } }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match &program.items[1] {
+            Item::Impl(imp) => {
+                assert!(imp.trait_.is_none());
+                assert!(matches!(imp.self_type.kind, TypeKind::Path(_)));
+                assert_eq!(imp.items.len(), 2);
+                let ImplItem::Function(f) = &imp.items[1] else {
+                    panic!("Expected method");
+                };
+                assert_eq!(f.name.name, "zero");
+            }
+            _ => panic!("Expected impl block"),
+        }
+    }
+
+    #[test]
+    fn test_parse_impl_assoc_const_and_type() {
+        let source = "struct P {} impl P { const N: i32 = 5; type Item = i32; fn f() {} }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let Item::Impl(imp) = &program.items[1] else {
+            panic!("Expected impl block");
+        };
+        assert_eq!(imp.items.len(), 3);
+        match &imp.items[0] {
+            ImplItem::Const(c) => {
+                assert_eq!(c.name.name, "N");
+                assert!(matches!(c.value.kind, ExprKind::Literal(Literal::Int(5))));
+                assert_span(source, c.span, "const N: i32 = 5;");
+            }
+            other => panic!("Expected associated const, got {:?}", other),
+        }
+        match &imp.items[1] {
+            ImplItem::Type(t) => {
+                assert_eq!(t.name.name, "Item");
+                assert!(matches!(t.ty.kind, TypeKind::Path(_)));
+            }
+            other => panic!("Expected associated type, got {:?}", other),
+        }
+        assert!(matches!(imp.items[2], ImplItem::Function(_)));
+    }
+
+    #[test]
+    fn test_parse_self_params_and_type() {
+        let source = "struct P {} impl P { fn a(self) {} fn b(&self) -> Self { self } fn c(&mut self, x: i32) {} fn d(mut self) {} }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let Item::Impl(imp) = &program.items[1] else {
+            panic!("Expected impl block");
+        };
+        let methods: Vec<&Function> = imp
+            .items
+            .iter()
+            .map(|item| match item {
+                ImplItem::Function(f) => f,
+                other => panic!("Expected method, got {:?}", other),
+            })
+            .collect();
+
+        let self_param = |f: &Function| match (&f.params[0].pattern.kind, &f.params[0].ty.kind) {
+            (PatternKind::Ident(name, mutable), ty) => {
+                assert_eq!(name.name, "self");
+                (*mutable, ty.clone())
+            }
+            other => panic!("Expected `self` binding, got {:?}", other),
+        };
+        assert!(matches!(
+            self_param(methods[0]),
+            (false, TypeKind::SelfType)
+        ));
+        assert!(matches!(
+            self_param(methods[1]),
+            (false, TypeKind::Reference(false, _))
+        ));
+        assert!(matches!(
+            self_param(methods[2]),
+            (false, TypeKind::Reference(true, _))
+        ));
+        assert!(matches!(self_param(methods[3]), (true, TypeKind::SelfType)));
+        assert_eq!(methods[2].params.len(), 2);
+
+        let b = methods[1];
+        assert!(matches!(
+            b.return_type.as_ref().unwrap().kind,
+            TypeKind::SelfType
+        ));
+        assert!(matches!(
+            &b.body.stmts[0],
+            Stmt::Expr(ExprStmt { expr: Expr { kind: ExprKind::Path(p), .. }, .. })
+                if p.segments[0].ident.name == "self"
+        ));
+    }
+
+    #[test]
+    fn test_parse_self_assoc_type_path() {
+        let source = "impl C { type Item = i64; fn get(&self) -> Self::Item { 1 } }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let Item::Impl(imp) = &program.items[0] else {
+            panic!("Expected impl block");
+        };
+        let ImplItem::Function(get) = &imp.items[1] else {
+            panic!("Expected a method, got {:?}", imp.items[1]);
+        };
+        match &get.return_type.as_ref().unwrap().kind {
+            TypeKind::Path(path) => {
+                let names: Vec<_> = path.segments.iter().map(|s| s.ident.name).collect();
+                assert_eq!(names, ["Self", "Item"]);
+                assert_eq!(path.span, Span::new(43, 53));
+            }
+            other => panic!("Expected a path, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_impl_rejects_other_items() {
+        let source = "struct P {} impl P { let x = 1; }";
+        let tokens = lex(source).unwrap();
+        assert!(parse(source, tokens).is_err());
+    }
+
+    fn first_impl(source: &str) -> ImplBlock {
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match program.items.into_iter().next() {
+            Some(Item::Impl(imp)) => imp,
+            other => panic!("Expected impl, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_empty_impl() {
+        let imp = first_impl("impl Foo {}");
+        assert!(imp.items.is_empty());
+        assert!(imp.trait_.is_none());
+        assert!(imp.generics.is_empty());
+    }
+
+    #[test]
+    fn test_parse_trait_impl() {
+        let source = "impl Display for Point { fn fmt(&self) -> String { s } fn other() {} }";
+        let imp = first_impl(source);
+        match (&imp.trait_, &imp.self_type.kind) {
+            (Some(trait_), TypeKind::Path(self_path)) => {
+                assert_span(source, trait_.span, "Display");
+                assert_eq!(self_path.segments[0].ident.name, "Point");
+            }
+            other => panic!("Expected trait impl, got {:?}", other),
+        }
+        assert_eq!(imp.items.len(), 2);
+        assert!(imp.items.iter().all(|i| matches!(i, ImplItem::Function(_))));
+    }
+
+    #[test]
+    fn test_parse_generic_impl() {
+        let source = "impl<T: Clone, U> From<T> for Wrapper<U> { }";
+        let imp = first_impl(source);
+        let names: Vec<_> = imp.generics.iter().map(|g| g.name.name.as_str()).collect();
+        assert_eq!(names, ["T", "U"]);
+        assert_eq!(imp.generics[0].bounds.len(), 1);
+        assert_span(source, imp.trait_.unwrap().span, "From<T>");
+        assert_span(source, imp.self_type.span, "Wrapper<U>");
+    }
+
+    #[test]
+    fn test_parse_unsafe_fn() {
+        let source = "unsafe fn f() {} pub unsafe fn g() {} fn h() {}";
+        let program = parse(source, lex(source).unwrap()).unwrap();
+        let fns: Vec<_> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Function(f) => f,
+                other => panic!("Expected function, got {:?}", other),
+            })
+            .collect();
+        assert!(fns[0].is_unsafe && fns[1].is_unsafe && !fns[2].is_unsafe);
+        assert_eq!(fns[1].vis, Visibility::Public);
+        assert_span(source, fns[0].span, "unsafe fn f() {}");
+
+        let imp = first_impl("impl S { pub unsafe fn m(&self) {} }");
+        let ImplItem::Function(method) = &imp.items[0] else {
+            panic!("Expected method");
+        };
+        assert!(method.is_unsafe);
+        assert_eq!(method.vis, Visibility::Public);
+
+        let stmts = block_stmts("fn main() { unsafe fn inner() {} }");
+        assert!(matches!(&stmts[0], Stmt::Item(Item::Function(f)) if f.is_unsafe));
+    }
+
+    #[test]
+    fn test_parse_unsafe_block() {
+        let source = "fn main() { unsafe { f() } }";
+        let expr = body_expr(source);
+        let ExprKind::Unsafe(block) = &expr.kind else {
+            panic!("Expected unsafe block, got {:?}", expr.kind);
+        };
+        assert_eq!(block.stmts.len(), 1);
+        assert_span(source, expr.span, "unsafe { f() }");
+        // A block-like statement, so `- 1` starts a new one
+        assert_eq!(block_stmts("fn main() { unsafe { 1 } - 1 }").len(), 2);
+        let value = let_value("fn main() { let x = 1 + unsafe { 2 }; }");
+        assert!(matches!(value.kind, ExprKind::Binary(_, BinOp::Add, _)));
+    }
+
+    #[test]
+    fn test_parse_static() {
+        let source = "static mut COUNTER: i32 = 0;\nstatic LIMIT: i64 = 1 << 4;";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let statics: Vec<_> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Static(s) => s,
+                other => panic!("Expected static, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(statics[0].name.name, "COUNTER");
+        assert!(statics[0].mutable);
+        assert_span(source, statics[0].span, "static mut COUNTER: i32 = 0;");
+        assert_eq!(statics[1].name.name, "LIMIT");
+        assert!(!statics[1].mutable);
+        assert!(matches!(
+            statics[1].value.kind,
+            ExprKind::Binary(_, BinOp::Shl, _)
+        ));
+    }
+
+    fn first_type_alias(source: &str) -> Result<TypeAlias, NovaError> {
+        let tokens = lex(source).unwrap();
+        match parse(source, tokens)?.items.into_iter().next() {
+            Some(Item::TypeAlias(a)) => Ok(a),
+            other => panic!("Expected type alias, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_type_alias() {
+        let source = "type Kilometers = i32;";
+        let alias = first_type_alias(source).unwrap();
+        assert_eq!(alias.name.name, "Kilometers");
+        assert!(alias.generics.is_empty());
+        assert!(matches!(&alias.ty.kind, TypeKind::Path(p) if p.segments[0].ident.name == "i32"));
+        assert_span(source, alias.span, source);
+    }
+
+    #[test]
+    fn test_parse_generic_type_alias() {
+        let alias = first_type_alias("type Pair<T> = (T, T);").unwrap();
+        assert_eq!(alias.generics.len(), 1);
+        assert!(matches!(&alias.ty.kind, TypeKind::Tuple(types) if types.len() == 2));
+
+        let alias = first_type_alias("type Map<K, V: Hash> = HashMap<K, V>;").unwrap();
+        assert_eq!(alias.generics.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_type_alias_errors() {
+        for source in ["type = i32;", "type A i32;", "type A = i32", "type A = ;"] {
+            assert!(
+                matches!(
+                    first_type_alias(source),
+                    Err(NovaError::UnexpectedToken { .. } | NovaError::UnexpectedEof { .. })
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    fn parse_errors(source: &str) -> Vec<NovaError> {
+        let tokens = lex(source).unwrap();
+        match parse_recovering(source, tokens, &ParseOptions::default()) {
+            Ok(program) => panic!("Expected errors, got {:?}", program),
+            Err(errors) => errors,
+        }
+    }
+
+    #[test]
+    fn test_recovery_reports_each_broken_item() {
+        let source = "fn a() { let x: = 1; }\nstruct S { x: }\nfn ok() { }\nfn b() { if { }";
+        let errors = parse_errors(source);
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| &source[e.span().start() as usize..e.span().end() as usize])
+            .collect();
+        assert_eq!(found, ["=", "}", ""]); // the last is at the end of input
+    }
+
+    #[test]
+    fn test_recovery_skips_stray_tokens() {
+        // Everything up to the next item belongs to the same error
+        assert_eq!(parse_errors("} } fn f() { }").len(), 1);
+        assert_eq!(parse_errors("1 + 2").len(), 1);
+        assert_eq!(parse_errors(&"{".repeat(500)).len(), 1);
+        assert_eq!(parse_errors(&"fn ".repeat(500)).len(), 500);
+    }
+
+    #[test]
+    fn test_recovery_accepts_valid_program() {
+        let source = "fn a() { } struct S { x: i64 } enum E { A, B }";
+        let tokens = lex(source).unwrap();
+        let program = parse_recovering(source, tokens, &ParseOptions::default()).unwrap();
+        assert_eq!(program.items.len(), 3);
+    }
+
+    fn first_trait(source: &str) -> TraitDef {
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match program.items.into_iter().next() {
+            Some(Item::Trait(t)) => t,
+            other => panic!("Expected trait, got {:?}", other),
+        }
+    }
+
+    fn trait_functions(t: &TraitDef) -> Vec<&TraitFunction> {
+        t.items
+            .iter()
+            .filter_map(|item| match item {
+                TraitItem::Function(f) => Some(f),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_trait_methods() {
+        let source = "trait Iter<T> { fn next(&mut self) -> T; fn count(self) -> i64 { 0 } }";
+        let t = first_trait(source);
+        assert_eq!(t.name.name, "Iter");
+        assert_eq!(t.generics.len(), 1);
+        let fns = trait_functions(&t);
+        assert_eq!(fns.len(), 2);
+        assert!(fns[0].default_body.is_none());
+        assert_span(source, fns[0].span, "fn next(&mut self) -> T;");
+        assert!(fns[1].default_body.is_some());
+        assert_span(source, fns[1].span, "fn count(self) -> i64 { 0 }");
+    }
+
+    #[test]
+    fn test_parse_empty_trait_with_supertraits() {
+        let t = first_trait("trait Marker {}");
+        assert!(t.items.is_empty() && t.bounds.is_empty());
+
+        let source = "trait Ord: Eq + PartialOrd { }";
+        let t = first_trait(source);
+        assert_eq!(t.bounds.len(), 2);
+        assert_span(source, t.span, source);
+    }
+
+    #[test]
+    fn test_parse_trait_assoc_items() {
+        let t =
+            first_trait("trait T { const N: i64; const M: i64 = 1; type A: Clone; type B = i64; }");
+        match &t.items[..] {
+            [TraitItem::Const(n), TraitItem::Const(m), TraitItem::Type(a), TraitItem::Type(b)] => {
+                assert!(n.default.is_none() && m.default.is_some());
+                assert!(a.bounds.len() == 1 && a.default.is_none());
+                assert!(b.bounds.is_empty() && b.default.is_some());
+            }
+            other => panic!("Expected consts and types, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_trait_method_needs_body_or_semi() {
+        let source = "trait T { fn f() }";
+        let tokens = lex(source).unwrap();
+        assert!(parse(source, tokens).is_err());
+    }
+
+    fn first_use(source: &str) -> Result<UseStmt, NovaError> {
+        let tokens = lex(source).unwrap();
+        match parse(source, tokens)?.items.into_iter().next() {
+            Some(Item::Use(u)) => Ok(u),
+            other => panic!("Expected use, got {:?}", other),
+        }
+    }
+
+    fn path_text(path: &Path) -> String {
+        let names: Vec<_> = path
+            .segments
+            .iter()
+            .map(|s| s.ident.name.as_str())
+            .collect();
+        names.join("::")
+    }
+
+    #[test]
+    fn test_parse_use_paths() {
+        let source = "use std::collections::HashMap;";
+        let u = first_use(source).unwrap();
+        assert_eq!(path_text(&u.tree.path), "std::collections::HashMap");
+        assert!(matches!(u.tree.kind, UseTreeKind::Simple(None)));
+        assert_span(source, u.span, source);
+
+        let u = first_use("use foo;").unwrap();
+        assert_eq!(path_text(&u.tree.path), "foo");
+
+        let source = "use a::b as c;";
+        let u = first_use(source).unwrap();
+        match u.tree.kind {
+            UseTreeKind::Simple(Some(alias)) => assert_eq!(alias.name, "c"),
+            other => panic!("Expected rename, got {:?}", other),
+        }
+        assert_span(source, u.tree.span, "a::b as c");
+    }
+
+    #[test]
+    fn test_parse_use_group() {
+        let source = "use foo::{bar, baz::qux as q, inner::{x, y},};";
+        let u = first_use(source).unwrap();
+        assert_eq!(path_text(&u.tree.path), "foo");
+        let UseTreeKind::Group(trees) = &u.tree.kind else {
+            panic!("Expected group");
+        };
+        let paths: Vec<_> = trees.iter().map(|t| path_text(&t.path)).collect();
+        assert_eq!(paths, ["bar", "baz::qux", "inner"]);
+        assert!(matches!(&trees[2].kind, UseTreeKind::Group(inner) if inner.len() == 2));
+        assert_span(source, trees[2].span, "inner::{x, y}");
+    }
+
+    #[test]
+    fn test_parse_use_errors() {
+        for source in [
+            "use ::foo;",
+            "use foo::{};",
+            "use foo",
+            "use foo::;",
+            "use foo as;",
+        ] {
+            assert!(
+                matches!(
+                    first_use(source),
+                    Err(NovaError::UnexpectedToken { .. } | NovaError::UnexpectedEof { .. })
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    /// The single expression of the first function's body
+    fn body_expr(source: &str) -> Expr {
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let Item::Function(f) = &program.items[0] else {
+            panic!("Expected function");
+        };
+        match &f.body.stmts[..] {
+            [Stmt::Expr(stmt)] => stmt.expr.clone(),
+            other => panic!("Expected one expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_raw_string() {
+        let string = |source: &str| match body_expr(source).kind {
+            ExprKind::Literal(Literal::String(s)) => s,
+            other => panic!("Expected string, got {:?}", other),
+        };
+        // No escape processing: the backslash stays
+        assert_eq!(string(r#"fn f() { r"C:\path" }"#), r"C:\path");
+        assert_eq!(
+            string(r##"fn f() { r#"he said "hi""# }"##),
+            r#"he said "hi""#
+        );
+        assert_eq!(string(r###"fn f() { r##"a "# b"## }"###), r##"a "# b"##);
+        assert_eq!(string(r#"fn f() { r"" }"#), "");
+    }
+
+    #[test]
+    fn test_parse_raw_byte_string() {
+        let bytes = |source: &str| match body_expr(source).kind {
+            ExprKind::Literal(Literal::Bytes(b)) => b,
+            other => panic!("Expected byte string, got {:?}", other),
+        };
+        assert_eq!(bytes(r#"fn f() { br"abc" }"#), b"abc");
+        // No escape processing: four bytes, including the backslash
+        assert_eq!(bytes(r#"fn f() { br"\x00" }"#), br"\x00");
+        assert_eq!(bytes(r##"fn f() { br#"say "hi""# }"##), br#"say "hi""#);
+        assert_eq!(bytes(r#"fn f() { br"" }"#), b"");
+    }
+
+    fn parse_newlines(source: &str) -> Result<Program, NovaError> {
+        let options = crate::lexer::LexOptions {
+            newline_terminated: true,
+        };
+        let tokens = crate::lexer::lex_with_options(source, 0, &options)?;
+        parse(source, tokens)
+    }
+
+    /// Statements of the first function's body in newline-terminated mode
+    fn newline_stmts(source: &str) -> Vec<Stmt> {
+        match parse_newlines(source).unwrap().items.remove(0) {
+            Item::Function(f) => f.body.stmts,
+            other => panic!("Expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_newline_terminated_lets() {
+        let source = "fn main() {\n    let x = 1\n    let y = 2\n}";
+        let stmts = newline_stmts(source);
+        assert_eq!(stmts.len(), 2);
+        let Stmt::Let(second) = &stmts[1] else {
+            panic!("Expected let, got {:?}", stmts[1]);
+        };
+        // The statement ends at its last token, not at the newline
+        assert_span(source, second.span, "let y = 2");
+
+        // The same source without newlines still needs semicolons
+        assert!(parse_newlines("fn main() { let x = 1 let y = 2 }").is_err());
+        let tokens = lex(source).unwrap();
+        assert!(parse(source, tokens).is_err());
+    }
+
+    #[test]
+    fn test_newline_terminated_exprs() {
+        // A leading `-` starts a new statement, the last line is the value
+        let stmts = newline_stmts("fn f() {\n    g()\n    -1\n    return\n    x +\n        y\n}");
+        assert_eq!(stmts.len(), 4);
+        let exprs: Vec<_> = stmts
+            .iter()
+            .map(|s| match s {
+                Stmt::Expr(e) => (&e.expr.kind, e.has_semi),
+                other => panic!("Expected expression, got {:?}", other),
+            })
+            .collect();
+        assert!(matches!(exprs[0], (ExprKind::Call(..), true)));
+        assert!(matches!(exprs[1], (ExprKind::Unary(..), true)));
+        assert!(matches!(exprs[2], (ExprKind::Return(None), true)));
+        assert!(matches!(exprs[3], (ExprKind::Binary(..), false)));
+
+        // Semicolons still work, and arguments may span lines
+        let stmts = newline_stmts("fn f() { a; b\n    g(1,\n      2)\n}");
+        assert_eq!(stmts.len(), 3);
+    }
+
+    #[test]
+    fn test_continue_takes_no_value() {
+        let source = "fn f() { loop { continue 1; } }";
+        let tokens = lex(source).unwrap();
+        let err = parse(source, tokens).unwrap_err();
+        assert!(matches!(
+            err,
+            NovaError::UnexpectedToken {
+                found: TokenKind::IntLit,
+                ..
+            }
+        ));
+
+        for source in [
+            "fn f() { loop { continue; } }",
+            "fn f() { loop { continue } }",
+            "fn f(x: i64) { loop { match x { _ => continue, } } }",
+        ] {
+            let tokens = lex(source).unwrap();
+            assert!(parse(source, tokens).is_ok(), "{source}");
+        }
+    }
+
+    /// Asserts that `span` covers exactly `expected` in `source`
+    fn assert_span(source: &str, span: Span, expected: &str) {
+        let text = &source[span.start() as usize..span.end() as usize];
+        assert_eq!(text, expected, "span {} in {:?}", span, source);
+    }
+
+    #[test]
+    fn test_item_spans() {
+        let source =
+            "fn main(x: i64) -> i64 { x }\nstruct P { x: i64, y: bool }\nenum E { A, B(i64) }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+
+        let Item::Function(f) = &program.items[0] else {
+            panic!("Expected function");
+        };
+        assert_span(source, f.span, "fn main(x: i64) -> i64 { x }");
+        assert_span(source, f.params[0].span, "x: i64");
+        assert_span(source, f.body.span, "{ x }");
+
+        let Item::Struct(st) = &program.items[1] else {
+            panic!("Expected struct");
+        };
+        assert_span(source, st.span, "struct P { x: i64, y: bool }");
+        assert_span(source, st.fields[1].span, "y: bool");
+
+        let Item::Enum(e) = &program.items[2] else {
+            panic!("Expected enum");
+        };
+        assert_span(source, e.span, "enum E { A, B(i64) }");
+        assert_span(source, e.variants[0].span, "A");
+        assert_span(source, e.variants[1].span, "B(i64)");
+    }
+
+    #[test]
+    fn test_match_arm_spans() {
+        let source = "fn f() { match x { 1 => a + b, n if n > 2 => { c }, _ => d } }";
+        let expr = body_expr(source);
+        assert_span(
+            source,
+            expr.span,
+            "match x { 1 => a + b, n if n > 2 => { c }, _ => d }",
+        );
+        let ExprKind::Match(_, arms) = &expr.kind else {
+            panic!("Expected match, got {:?}", expr.kind);
+        };
+        assert_span(source, arms[0].span, "1 => a + b");
+        assert_span(source, arms[1].span, "n if n > 2 => { c }");
+        assert_span(source, arms[2].span, "_ => d");
+    }
+
+    #[test]
+    fn test_expr_spans() {
+        for text in [
+            "a + b * c",
+            "-x",
+            "!done",
+            "f(a, b)",
+            "p.x",
+            "v.len()",
+            "xs[0]",
+            "r?",
+            "std::mem::swap",
+            "g::<i32>(x)",
+            "if c { 1 } else { 2 }",
+            "if c { 1 } else if d { 2 }",
+            "while c { x; }",
+            "loop { break 1; }",
+            "return x + 1",
+            "(a, b)",
+            "42",
+            r#""str""#,
+        ] {
+            let source = format!("fn f() {{ {} }}", text);
+            assert_span(&source, body_expr(&source).span, text);
+        }
+    }
+
+    /// Renders an expression tree as nested prefix forms, e.g. `(. a b)`
+    fn sexp(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Path(path) => path
+                .segments
+                .iter()
+                .map(|s| s.ident.name.to_string())
+                .collect::<Vec<_>>()
+                .join("::"),
+            ExprKind::Literal(Literal::Int(n)) => n.to_string(),
+            ExprKind::Field(base, field) => format!("(. {} {})", sexp(base), field.name),
+            ExprKind::Call(callee, args) => {
+                let mut out = format!("(call {}", sexp(callee));
+                for arg in args {
+                    out.push(' ');
+                    out.push_str(&sexp(arg));
+                }
+                out + ")"
+            }
+            ExprKind::Index(base, index) => format!("(index {} {})", sexp(base), sexp(index)),
+            ExprKind::Try(inner) => format!("(? {})", sexp(inner)),
+            ExprKind::Unary(op, inner) => format!("({:?} {})", op, sexp(inner)),
+            ExprKind::Ref(mutable, inner) => {
+                format!("({} {})", if *mutable { "&mut" } else { "&" }, sexp(inner))
+            }
+            ExprKind::Binary(l, op, r) => format!("({:?} {} {})", op, sexp(l), sexp(r)),
+            ExprKind::Range(start, end, inclusive) => {
+                let side = |e: &Option<Box<Expr>>| e.as_ref().map_or("_".to_string(), |e| sexp(e));
+                let op = if *inclusive { "..=" } else { ".." };
+                format!("({} {} {})", op, side(start), side(end))
+            }
+            ExprKind::Cast(inner, ty) => match &ty.kind {
+                TypeKind::Path(path) => format!("(as {} {})", sexp(inner), path_text(path)),
+                other => panic!("sexp: unsupported cast target {:?}", other),
+            },
+            ExprKind::Closure(params, _, body) => {
+                let names: Vec<_> = params
+                    .iter()
+                    .map(|p| match &p.pattern.kind {
+                        PatternKind::Ident(ident, _) => ident.name.to_string(),
+                        other => panic!("sexp: unsupported parameter {:?}", other),
+                    })
+                    .collect();
+                format!("(|{}| {})", names.join(" "), sexp(body))
+            }
+            ExprKind::StructLit(path, fields) => {
+                let mut out = format!("(struct {}", path_text(path));
+                for field in fields {
+                    out.push_str(&format!(" ({} {})", field.name.name, sexp(&field.value)));
+                }
+                out + ")"
+            }
+            ExprKind::MacroCall { path, .. } => format!("({}! ..)", path_text(path)),
+            other => panic!("sexp: unsupported expression {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_postfix_try_and_field() {
+        let tree = |text: &str| sexp(&body_expr(&format!("fn f() {{ {} }}", text)));
+        assert_eq!(tree("a?.b"), "(. (? a) b)");
+        assert_eq!(tree("a.b?"), "(? (. a b))");
+        assert_eq!(tree("a??"), "(? (? a))");
+        assert_eq!(tree("a.b.c"), "(. (. a b) c)");
+    }
+
+    #[test]
+    fn test_postfix_chains_lean_left() {
+        let tree = |text: &str| sexp(&body_expr(&format!("fn f() {{ {} }}", text)));
+        assert_eq!(
+            tree("a.b()[0].c?.d(1)"),
+            "(call (. (? (. (index (call (. a b)) 0) c)) d) 1)"
+        );
+        assert_eq!(
+            tree("f(x)(y)[i][j]"),
+            "(index (index (call (call f x) y) i) j)"
+        );
+        assert_eq!(
+            tree("xs[i.j()].k(a[0], b?)?"),
+            "(? (call (. (index xs (call (. i j))) k) (index a 0) (? b)))"
+        );
+        assert_eq!(tree("m::f(1).x"), "(. (call m::f 1) x)");
+    }
+
+    #[test]
+    fn test_postfix_binds_tighter_than_prefix_and_binary() {
+        let tree = |text: &str| sexp(&body_expr(&format!("fn f() {{ {} }}", text)));
+        assert_eq!(tree("-a.b()"), "(Neg (call (. a b)))");
+        assert_eq!(tree("!a?"), "(Not (? a))");
+        assert_eq!(tree("&a[0].b"), "(& (. (index a 0) b))");
+        assert_eq!(
+            tree("a.b + c?.d * e()"),
+            "(Add (. a b) (Mul (. (? c) d) (call e)))"
+        );
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let tree = |text: &str| sexp(&body_expr(&format!("fn f() {{ {} }}", text)));
+        assert_eq!(tree("a..b"), "(.. a b)");
+        assert_eq!(tree("0..=n - 1"), "(..= 0 (Sub n 1))");
+        assert_eq!(tree("a || b..c"), "(.. (Or a b) c)");
+        assert_eq!(tree("x = 1..2"), "(Assign x (.. 1 2))");
+        assert_eq!(tree("xs[1..]"), "(index xs (.. 1 _))");
+        assert_eq!(tree("xs[..n]"), "(index xs (.. _ n))");
+        assert_eq!(tree("f(..)"), "(call f (.. _ _))");
+
+        let source = "fn f() { for i in 0..n { } }";
+        let ExprKind::For(_, iter, _) = body_expr(source).kind else {
+            panic!("Expected for loop");
+        };
+        assert_eq!(sexp(&iter), "(.. 0 n)");
+        assert_span(source, iter.span, "0..n");
+    }
+
+    #[test]
+    fn test_range_bounds_and_inclusive_flag() {
+        for (text, start, end, inclusive) in [
+            ("0..10", true, true, false),
+            ("a..=b", true, true, true),
+            ("..n", false, true, false),
+            ("..=n", false, true, true),
+            ("a..", true, false, false),
+            ("..", false, false, false),
+        ] {
+            let source = format!("fn f() {{ let r = {}; }}", text);
+            let tokens = lex(&source).unwrap();
+            let program = parse(&source, tokens).unwrap();
+            let Item::Function(f) = &program.items[0] else {
+                panic!("Expected function");
+            };
+            let Stmt::Let(LetStmt {
+                value: Some(value), ..
+            }) = &f.body.stmts[0]
+            else {
+                panic!("Expected let");
+            };
+            let ExprKind::Range(s, e, inc) = &value.kind else {
+                panic!("{text}: expected a range, got {:?}", value.kind);
+            };
+            assert_eq!(
+                (s.is_some(), e.is_some(), *inc),
+                (start, end, inclusive),
+                "{text}"
+            );
+            assert_span(&source, value.span, text);
+        }
+    }
+
+    #[test]
+    fn test_inclusive_range_needs_end() {
+        let source = "fn f() { xs[1..=] }";
+        let tokens = lex(source).unwrap();
+        assert!(matches!(
+            parse(source, tokens),
+            Err(NovaError::UnexpectedToken { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compound_assignment_desugars() {
+        let tree = |text: &str| sexp(&body_expr(&format!("fn f() {{ {} }}", text)));
+        assert_eq!(tree("a += 1"), "(Assign a (Add a 1))");
+        assert_eq!(tree("a <<= 2"), "(Assign a (Shl a 2))");
+        assert_eq!(
+            tree("a.b %= c * 2"),
+            "(Assign (. a b) (Rem (. a b) (Mul c 2)))"
+        );
+        assert_eq!(
+            tree("a ^= b |= c"),
+            "(Assign a (BitXor a (Assign b (BitOr b c))))"
+        );
+        assert_eq!(tree("a = b -= 1"), "(Assign a (Assign b (Sub b 1)))");
+        for op in ["+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<=", ">>="] {
+            assert!(
+                tree(&format!("x {} y", op)).starts_with("(Assign x ("),
+                "{op}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_casts() {
+        let tree = |text: &str| sexp(&body_expr(&format!("fn f() {{ {} }}", text)));
+        assert_eq!(tree("x as u8"), "(as x u8)");
+        assert_eq!(tree("-x as i64"), "(as (Neg x) i64)");
+        assert_eq!(tree("a * b as u8"), "(Mul a (as b u8))");
+        assert_eq!(tree("a as i64 + 1"), "(Add (as a i64) 1)");
+        assert_eq!(tree("x as i32 as i64"), "(as (as x i32) i64)");
+        assert_eq!(tree("f(x)? as f64"), "(as (? (call f x)) f64)");
+    }
+
+    /// The macro call `text` in a function body, with its token kinds
+    fn macro_call(text: &str) -> (String, Delimiter, Vec<TokenKind>) {
+        let expr = body_expr(&format!("fn f() {{ {} }}", text));
+        let ExprKind::MacroCall {
+            path,
+            delimiter,
+            tokens,
+        } = expr.kind
+        else {
+            panic!("Expected a macro call, got {:?}", expr.kind);
+        };
+        let kinds = tokens.iter().map(|t| t.kind()).collect();
+        (path_text(&path), delimiter, kinds)
+    }
+
+    #[test]
+    fn test_parse_macro_calls() {
+        use TokenKind::*;
+        assert_eq!(
+            macro_call("println!(\"hi\")"),
+            ("println".to_string(), Delimiter::Paren, vec![StringLit])
+        );
+        assert_eq!(
+            macro_call("vec![1, 2, 3]"),
+            (
+                "vec".to_string(),
+                Delimiter::Bracket,
+                vec![IntLit, Comma, IntLit, Comma, IntLit]
+            )
+        );
+        // Nested brackets of every kind stay in the token tree
+        let (name, delimiter, tokens) = macro_call("m::check!{ (a, [b]) {} }");
+        assert_eq!((name.as_str(), delimiter), ("m::check", Delimiter::Brace));
+        assert_eq!(tokens.len(), 9);
+        assert_eq!(
+            sexp(&body_expr("fn f() { matches!(x, Some(_)) || y }")),
+            "(Or (matches! ..) y)"
+        );
+        // Brackets must match and close
+        for source in ["fn f() { a!(b] }", "fn f() { a![b }", "fn f() { v![(] }"] {
+            let tokens = lex(source).unwrap();
+            assert!(
+                matches!(
+                    parse(source, tokens),
+                    Err(NovaError::UnexpectedToken { .. })
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_closures() {
+        let tree = |text: &str| sexp(&body_expr(&format!("fn f() {{ {} }}", text)));
+        assert_eq!(tree("|| 0"), "(|| 0)");
+        assert_eq!(tree("|x| x"), "(|x| x)");
+        assert_eq!(tree("|x: i32, y: i32| x + y"), "(|x y| (Add x y))");
+        // The body extends as far as it can; `|` after an operand is bitwise or
+        assert_eq!(tree("|x| x | 1"), "(|x| (BitOr x 1))");
+        assert_eq!(tree("a | |x| x"), "(BitOr a (|x| x))");
+        assert_eq!(tree("f(|| 1, |a, b| a)"), "(call f (|| 1) (|a b| a))");
+
+        let closure = body_expr("fn f() { |x, y: i32| -> i64 { x } }");
+        let ExprKind::Closure(params, Some(ret), body) = &closure.kind else {
+            panic!(
+                "Expected a closure with a return type, got {:?}",
+                closure.kind
+            );
+        };
+        assert!(matches!(params[0].ty.kind, TypeKind::Infer));
+        assert!(matches!(params[1].ty.kind, TypeKind::Path(_)));
+        assert!(matches!(ret.kind, TypeKind::Path(_)));
+        assert!(matches!(body.kind, ExprKind::Block(_)));
+        assert_span(
+            "fn f() { |x, y: i32| -> i64 { x } }",
+            closure.span,
+            "|x, y: i32| -> i64 { x }",
+        );
+    }
+
+    #[test]
+    fn test_parse_visibility() {
+        let source = "pub fn f() {} fn g() {} pub(crate) struct S {} pub enum E {} \
+                      pub trait T {} impl S { pub fn new() {} fn helper() {} }";
+        let tokens = lex(source).unwrap();
+        let mut program = parse(source, tokens).unwrap();
+        let visibility: Vec<_> = program
+            .items
+            .iter_mut()
+            .filter_map(|item| item.vis_mut().map(|vis| *vis))
+            .collect();
+        use Visibility::*;
+        assert_eq!(visibility, [Public, Private, Crate, Public, Public]);
+        let Item::Impl(imp) = &program.items[5] else {
+            panic!("Expected an impl");
+        };
+        let methods: Vec<_> = imp
+            .items
+            .iter()
+            .map(|item| match item {
+                ImplItem::Function(f) => f.vis,
+                other => panic!("Expected a method, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(methods, [Public, Private]);
+        let Item::Function(f) = &program.items[0] else {
+            panic!("Expected a function");
+        };
+        assert_span(source, f.span, "fn f() {}");
+    }
+
+    #[test]
+    fn test_misplaced_visibility() {
+        for source in [
+            "pub impl S {}",
+            "pub use a::b;",
+            "impl S { pub const N: i64 = 1; }",
+        ] {
+            assert!(
+                matches!(parse_errors(source)[..], [NovaError::Custom { .. }]),
+                "{source}"
+            );
+        }
+        assert!(matches!(
+            parse_errors("pub(super) fn f() {}")[..],
+            [NovaError::UnexpectedToken { .. }]
+        ));
+        // Recovery resumes at the next `pub`
+        let errors = parse_errors("pub fn f( {} pub fn g() {} pub fn h(");
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_misplaced_in() {
+        for source in [
+            "fn f() { let x = in; }",
+            "fn f() { let x = 1 in 2; }",
+            "fn f(in: i64) {}",
+            "fn f() { match x { in => {} } }",
+            "fn f() { for x in in {} }",
+        ] {
+            match parse(source, lex(source).unwrap()) {
+                Err(NovaError::Custom { message, span }) => {
+                    assert!(message.contains("`in`"), "{source}: {message}");
+                    assert_span(source, span, "in");
+                }
+                other => panic!("{source}: expected a misplaced `in`, got {:?}", other),
+            }
+        }
+        // `in` is fine in a `for` header and in `pub(in crate)`
+        let source = "pub(in crate) fn f() { for x in xs {} }";
+        let program = parse(source, lex(source).unwrap()).unwrap();
+        let Item::Function(f) = &program.items[0] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(f.vis, Visibility::Crate);
+        assert!(parse(
+            "pub(in a::b) fn f() {}",
+            lex("pub(in a::b) fn f() {}").unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_modules() {
+        let source = "mod a; mod b { fn f() {} mod c { struct S; } } mod d {}";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        let modules: Vec<_> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Mod(m) => m,
+                other => panic!("Expected a module, got {:?}", other),
+            })
+            .collect();
+        assert!(modules[0].items.is_none());
+        assert_span(source, modules[0].span, "mod a;");
+        let inner = modules[1].items.as_ref().unwrap();
+        assert!(matches!(inner[..], [Item::Function(_), Item::Mod(_)]));
+        let Item::Mod(c) = &inner[1] else {
+            unreachable!()
+        };
+        assert!(matches!(c.items.as_deref(), Some([Item::Struct(_)])));
+        assert_span(
+            source,
+            modules[1].span,
+            "mod b { fn f() {} mod c { struct S; } }",
+        );
+        assert_eq!(modules[2].items.as_ref().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_deep_module_nesting() {
+        let nested = |depth: usize| "mod m { ".repeat(depth) + &"}".repeat(depth);
+        let source = nested(64);
+        assert!(parse(&source, lex(&source).unwrap()).is_ok());
+        let source = nested(65);
+        assert!(matches!(
+            parse(&source, lex(&source).unwrap()),
+            Err(NovaError::NestingTooDeep { max: 64, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_struct_literals() {
+        let tree = |text: &str| sexp(&body_expr(&format!("fn f() {{ {} }}", text)));
+        assert_eq!(tree("Point { x: 1, y: 2 }"), "(struct Point (x 1) (y 2))");
+        assert_eq!(tree("Point { x, y: b, }"), "(struct Point (x x) (y b))");
+        assert_eq!(tree("geo::Point {}"), "(struct geo::Point)");
+        assert_eq!(
+            tree("Line { a: Point { x, y }, b: f(P { x }) }"),
+            "(struct Line (a (struct Point (x x) (y y))) (b (call f (struct P (x x)))))"
+        );
+        assert_eq!(tree("Point { x: 1 }.x"), "(. (struct Point (x 1)) x)");
+    }
+
+    #[test]
+    fn test_no_struct_literal_in_condition() {
+        // `p {` is the condition and then the body, not a struct literal
+        let ExprKind::If(cond, then_block, _) = body_expr("fn f() { if p { x } }").kind else {
+            panic!("Expected if");
+        };
+        assert_eq!(sexp(&cond), "p");
+        assert_eq!(then_block.stmts.len(), 1);
+        let ExprKind::While(cond, _) = body_expr("fn f() { while a == b { } }").kind else {
+            panic!("Expected while");
+        };
+        assert_eq!(sexp(&cond), "(Eq a b)");
+        let ExprKind::For(_, iter, _) = body_expr("fn f() { for x in xs { } }").kind else {
+            panic!("Expected for");
+        };
+        assert_eq!(sexp(&iter), "xs");
+
+        // Brackets allow them again
+        let ExprKind::If(cond, ..) = body_expr("fn f() { if p == (P { x }) { } }").kind else {
+            panic!("Expected if");
+        };
+        assert_eq!(sexp(&cond), "(Eq p (struct P (x x)))");
+        let ExprKind::Match(scrutinee, _) =
+            body_expr("fn f() { match g(P { x: 1 }) { _ => 0 } }").kind
+        else {
+            panic!("Expected match");
+        };
+        assert_eq!(sexp(&scrutinee), "(call g (struct P (x 1)))");
+    }
+
+    #[test]
+    fn test_bare_lt_is_comparison() {
+        // `(a < b) > c`, not `a` applied to the generic argument `b`
+        let expr = body_expr("fn f() { a < b > c }");
+        let ExprKind::Binary(lhs, BinOp::Gt, rhs) = &expr.kind else {
+            panic!("Expected `>` at the root, got {:?}", expr.kind);
+        };
+        assert!(matches!(lhs.kind, ExprKind::Binary(_, BinOp::Lt, _)));
+        assert!(matches!(rhs.kind, ExprKind::Path(_)));
+    }
+
+    #[test]
+    fn test_bare_lt_in_call_args_is_comparison() {
+        let expr = body_expr("fn f() { g(a < b, c > (d)) }");
+        let ExprKind::Call(_, args) = &expr.kind else {
+            panic!("Expected call, got {:?}", expr.kind);
+        };
+        assert_eq!(args.len(), 2);
+        assert!(matches!(args[0].kind, ExprKind::Binary(_, BinOp::Lt, _)));
+        assert!(matches!(args[1].kind, ExprKind::Binary(_, BinOp::Gt, _)));
+    }
+
+    #[test]
+    fn test_turbofish_is_generic_call() {
+        let expr = body_expr("fn f() { g::<i32>(x) }");
+        let ExprKind::Call(callee, args) = &expr.kind else {
+            panic!("Expected call, got {:?}", expr.kind);
+        };
+        assert_eq!(args.len(), 1);
+        let ExprKind::Path(path) = &callee.kind else {
+            panic!("Expected path callee, got {:?}", callee.kind);
+        };
+        assert_eq!(path.segments[0].generics.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_generic_type_args() {
+        let source = "fn f() -> Result<i32, String> { }";
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+        match &program.items[0] {
+            Item::Function(f) => match &f.return_type.as_ref().unwrap().kind {
+                TypeKind::Path(path) => {
+                    assert_eq!(path.segments[0].ident.name, "Result");
+                    assert_eq!(path.segments[0].generics.len(), 2);
+                    assert_eq!(path.span.end(), source.rfind('>').unwrap() as u32 + 1);
+                }
+                _ => panic!("Expected path type"),
+            },
+            _ => panic!("Expected function"),
+        }
+    }
 }
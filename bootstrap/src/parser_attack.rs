@@ -5,13 +5,14 @@
 //!
 //! # Security Properties Tested
 //!
-//! - Expression nesting depth limit (MAX_EXPR_DEPTH = 64)
-//! - Block nesting depth limit (MAX_BLOCK_DEPTH = 64)
+//! - Expression nesting depth limit (default `ParseOptions`: 64)
+//! - Block nesting depth limit (default `ParseOptions`: 64)
 //! - Graceful handling of malformed syntax
 //! - No panics on edge cases
 
 #![allow(dead_code)]
 
+use crate::ast::{ExprKind, Item, Literal, Stmt};
 use crate::error::NovaError;
 use crate::lexer::lex;
 use crate::parser::parse;
@@ -66,11 +67,11 @@ mod attack_tests {
 
         // Binary operations may or may not exceed depth depending on associativity
         // The key is: no panic
-        assert!(!std::panic::catch_unwind(|| {
+        assert!(std::panic::catch_unwind(|| {
             let tokens = lex(&source).unwrap();
             let _ = parse(&source, tokens);
         })
-        .is_err());
+        .is_ok());
     }
 
     /// Attack: Deeply nested unary operators
@@ -86,11 +87,11 @@ mod attack_tests {
 
         // Should either error with NestingTooDeep or succeed (if optimized)
         // Key: no panic
-        assert!(!std::panic::catch_unwind(|| {
+        assert!(std::panic::catch_unwind(|| {
             let tokens = lex(&source).unwrap();
             let _ = parse(&source, tokens);
         })
-        .is_err());
+        .is_ok());
     }
 
     // ========================================================================
@@ -162,6 +163,25 @@ mod attack_tests {
         assert!(result.is_err(), "Should block deep while nesting");
     }
 
+    /// Attack: Deeply nested `loop`s
+    #[test]
+    fn test_attack_deep_loop_nesting() {
+        let depth = 66;
+        let source = format!(
+            "fn main() {{ {}break{} }}",
+            "loop { ".repeat(depth),
+            " }".repeat(depth)
+        );
+
+        let tokens = lex(&source).unwrap();
+        let result = parse(&source, tokens);
+
+        assert!(
+            matches!(result, Err(NovaError::NestingTooDeep { .. })),
+            "Should block deep loop nesting"
+        );
+    }
+
     // ========================================================================
     // Malformed Syntax Attacks
     // ========================================================================
@@ -221,11 +241,11 @@ mod attack_tests {
 
         // This might parse as expression statement without semi
         // The key is no panic
-        assert!(!std::panic::catch_unwind(|| {
+        assert!(std::panic::catch_unwind(|| {
             let tokens = lex(source).unwrap();
             let _ = parse(source, tokens);
         })
-        .is_err());
+        .is_ok());
     }
 
     // ========================================================================
@@ -287,11 +307,11 @@ mod attack_tests {
         let _result = parse(&source, tokens);
 
         // Should succeed (or fail gracefully)
-        assert!(!std::panic::catch_unwind(|| {
+        assert!(std::panic::catch_unwind(|| {
             let tokens = lex(&source).unwrap();
             let _ = parse(&source, tokens);
         })
-        .is_err());
+        .is_ok());
     }
 
     /// Attack: Many function arguments
@@ -416,6 +436,32 @@ mod attack_tests {
         assert!(result.is_ok(), "Break without value should work");
     }
 
+    /// Attack: `loop` left by a `break` with a value
+    #[test]
+    fn test_attack_loop_break_value() {
+        let source = "fn main() -> i64 { loop { break 5; } }";
+
+        let tokens = lex(source).unwrap();
+        let program = parse(source, tokens).unwrap();
+
+        let Item::Function(main) = &program.items[0] else {
+            panic!("Expected a function");
+        };
+        let [Stmt::Expr(stmt)] = &main.body.stmts[..] else {
+            panic!("Expected one statement");
+        };
+        let ExprKind::Loop(body) = &stmt.expr.kind else {
+            panic!("Expected a loop, got {:?}", stmt.expr.kind);
+        };
+        let [Stmt::Expr(brk)] = &body.stmts[..] else {
+            panic!("Expected a break");
+        };
+        assert!(matches!(
+            &brk.expr.kind,
+            ExprKind::Break(Some(value)) if matches!(value.kind, ExprKind::Literal(Literal::Int(5)))
+        ));
+    }
+
     /// Attack: Return without value
     #[test]
     fn test_attack_return_without_value() {
@@ -456,8 +502,7 @@ mod attack_tests {
     /// Attack: Never type
     #[test]
     fn test_attack_never_type() {
-        // Use while true instead of loop (loop not implemented yet)
-        let source = "fn panic() -> ! { while true {} }";
+        let source = "fn panic() -> ! { loop {} }";
 
         let tokens = lex(source).unwrap();
         let result = parse(source, tokens);
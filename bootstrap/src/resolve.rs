@@ -0,0 +1,463 @@
+//! Module resolution for multi-file programs
+//!
+//! Starting from an entry file, the resolver follows `mod name;`
+//! declarations to `name.nova` (or `name/mod.nova`), lexes and parses each
+//! file into a shared `SourceMap`, and combines everything into one
+//! `Program`. Inline `mod name { ... }` blocks are flattened the same way.
+//!
+//! # Layout
+//!
+//! ```text
+//! main.nova          mod util;        → util.nova
+//! util.nova          mod math;        → util/math.nova or util/math/mod.nova
+//! ```
+//!
+//! Items disabled by `#[cfg(...)]` are dropped as soon as their file is
+//! parsed, so a disabled `mod` is never loaded.
+//!
+//! Items from a module are renamed with their module path, so `fn add` in
+//! `util.nova` becomes `util::add` in the combined program.
+//!
+//! # TODO
+//!
+//! - Resolve unqualified references between items of the same module
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Ident, Item, Program};
+use crate::cfg::CfgSet;
+use crate::error::NovaError;
+use crate::lexer::{lex_with_options, LexOptions};
+use crate::parser::{parse_recovering, ParseOptions};
+use crate::source_map::SourceMap;
+use crate::timing::PhaseTimes;
+use crate::token::Span;
+
+/// Loads a program and all of its modules
+pub struct Resolver {
+    source_map: SourceMap,
+    options: ParseOptions,
+    lex_options: LexOptions,
+    cfg: CfgSet,
+    times: PhaseTimes,
+}
+
+impl Resolver {
+    /// Creates a resolver that parses every file with `options`.
+    pub fn new(options: ParseOptions) -> Self {
+        Self {
+            source_map: SourceMap::new(),
+            options,
+            lex_options: LexOptions::default(),
+            cfg: CfgSet::new(),
+            times: PhaseTimes::default(),
+        }
+    }
+
+    /// Records how long lexing and parsing take, over all files, in `times`.
+    pub fn with_times(mut self, times: PhaseTimes) -> Self {
+        self.times = times;
+        self
+    }
+
+    /// The lexing and parsing times recorded so far.
+    pub fn times(&self) -> &PhaseTimes {
+        &self.times
+    }
+
+    /// Sets how every file is lexed (e.g. newline-terminated statements).
+    pub fn with_lex_options(mut self, lex_options: LexOptions) -> Self {
+        self.lex_options = lex_options;
+        self
+    }
+
+    /// Sets the configuration `#[cfg(...)]` attributes are evaluated in.
+    pub fn with_cfg(mut self, cfg: CfgSet) -> Self {
+        self.cfg = cfg;
+        self
+    }
+
+    /// The files loaded so far (for error reporting).
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// Loads `entry` and every module it declares, recursively.
+    ///
+    /// A file with syntax errors reports all of them; loading stops there.
+    pub fn resolve(&mut self, entry: &Path) -> Result<Program, Vec<NovaError>> {
+        let source = fs::read_to_string(entry).map_err(|e| {
+            vec![NovaError::Custom {
+                message: format!("Error reading {}: {}", entry.display(), e),
+                span: Span::dummy(),
+            }]
+        })?;
+        let dir = entry.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let mut items = Vec::new();
+        self.load(
+            &entry.display().to_string(),
+            &source,
+            Some(&dir),
+            "",
+            &mut items,
+        )?;
+        Ok(Program { items })
+    }
+
+    /// Loads a program from `source` alone, as the file `name`.
+    ///
+    /// Its items go through the same `#[cfg(...)]` stripping and module
+    /// flattening as a file's, but there's no directory to find `mod name;`
+    /// files in, so each of those is reported as not found.
+    pub fn resolve_str(&mut self, name: &str, source: &str) -> Result<Program, Vec<NovaError>> {
+        let mut items = Vec::new();
+        self.load(name, source, None, "", &mut items)?;
+        Ok(Program { items })
+    }
+
+    /// Parses one file and appends its items (and its modules' items).
+    ///
+    /// `dir` is where the file's own `mod` declarations are looked up, if
+    /// anywhere, and `prefix` is its module path (`""` for the entry,
+    /// `"util::"` below).
+    fn load(
+        &mut self,
+        name: &str,
+        source: &str,
+        dir: Option<&Path>,
+        prefix: &str,
+        items: &mut Vec<Item>,
+    ) -> Result<(), Vec<NovaError>> {
+        let base = self
+            .source_map
+            .add_file(name, source)
+            .map_err(|e| vec![e])?;
+        let tokens = self
+            .times
+            .time("lex", || lex_with_options(source, base, &self.lex_options))
+            .map_err(|e| vec![e])?;
+        let program = self.times.time("parse", || {
+            parse_recovering(self.source_map.text(), tokens, &self.options)
+        })?;
+
+        self.add_items(program.items, dir, prefix, items)
+    }
+
+    /// Appends `module_items` under `prefix`, loading the modules they
+    /// declare from `dir` and flattening inline ones.
+    fn add_items(
+        &mut self,
+        module_items: Vec<Item>,
+        dir: Option<&Path>,
+        prefix: &str,
+        items: &mut Vec<Item>,
+    ) -> Result<(), Vec<NovaError>> {
+        for item in self.cfg.strip(module_items) {
+            match item {
+                Item::Mod(mut decl) => {
+                    let name = decl.name.name.as_str();
+                    let module_dir = dir.map(|dir| dir.join(name));
+                    let module_prefix = format!("{}{}::", prefix, name);
+                    match decl.items.take() {
+                        Some(inline) => {
+                            let module_dir = module_dir.as_deref();
+                            self.add_items(inline, module_dir, &module_prefix, items)?;
+                        }
+                        None => {
+                            let (module_path, module_source) =
+                                find_module(dir, name, decl.span).map_err(|e| vec![e])?;
+                            self.load(
+                                &module_path.display().to_string(),
+                                &module_source,
+                                module_dir.as_deref(),
+                                &module_prefix,
+                                items,
+                            )?;
+                        }
+                    }
+                    items.push(Item::Mod(decl));
+                }
+                item => items.push(qualify(item, prefix)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds and reads `name.nova` or `name/mod.nova` in `dir`
+fn find_module(dir: Option<&Path>, name: &str, span: Span) -> Result<(PathBuf, String), NovaError> {
+    let Some(dir) = dir else {
+        return Err(NovaError::ModuleNotFound {
+            name: name.to_string(),
+            path: format!("{}.nova (not compiling from a file)", name),
+            span,
+        });
+    };
+    let file = dir.join(format!("{}.nova", name));
+    let mod_file = dir.join(name).join("mod.nova");
+    for candidate in [&file, &mod_file] {
+        if let Ok(source) = fs::read_to_string(candidate) {
+            return Ok((candidate.clone(), source));
+        }
+    }
+    Err(NovaError::ModuleNotFound {
+        name: name.to_string(),
+        path: format!("{} or {}", file.display(), mod_file.display()),
+        span,
+    })
+}
+
+/// Flattens the inline `mod name { ... }` blocks in `items` under
+/// `prefix`, as the resolver does, for a program that didn't come from
+/// one. A `mod name;` declaration is kept as it is.
+pub fn flatten_inline_modules(items: Vec<Item>, prefix: &str) -> Vec<Item> {
+    let mut flat = Vec::new();
+    for item in items {
+        match item {
+            Item::Mod(mut decl) => {
+                if let Some(inline) = decl.items.take() {
+                    let module_prefix = format!("{}{}::", prefix, decl.name.name);
+                    flat.extend(flatten_inline_modules(inline, &module_prefix));
+                }
+                flat.push(Item::Mod(decl));
+            }
+            item => flat.push(qualify(item, prefix)),
+        }
+    }
+    flat
+}
+
+/// Prefix an item's name with its module path
+fn qualify(item: Item, prefix: &str) -> Item {
+    if prefix.is_empty() {
+        return item;
+    }
+    let rename = |ident: &Ident| Ident::new(&format!("{}{}", prefix, ident.name), ident.span);
+    match item {
+        Item::Function(mut f) => {
+            f.name = rename(&f.name);
+            Item::Function(f)
+        }
+        Item::Struct(mut s) => {
+            s.name = rename(&s.name);
+            Item::Struct(s)
+        }
+        Item::Enum(mut e) => {
+            e.name = rename(&e.name);
+            Item::Enum(e)
+        }
+        Item::Trait(mut t) => {
+            t.name = rename(&t.name);
+            Item::Trait(t)
+        }
+        Item::TypeAlias(mut a) => {
+            a.name = rename(&a.name);
+            Item::TypeAlias(a)
+        }
+        Item::Static(mut s) => {
+            s.name = rename(&s.name);
+            Item::Static(s)
+        }
+        // Impls and imports have no name of their own
+        item => item,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen;
+    use crate::ir;
+    use crate::types;
+
+    /// Write `files` into a fresh temporary directory
+    fn temp_project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nova-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn function_names(program: &Program) -> Vec<String> {
+        program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(f) => Some(f.name.name.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_two_file_program_compiles() {
+        let dir = temp_project(
+            "two-files",
+            &[
+                (
+                    "main.nova",
+                    "mod util;\nfn main() -> i64 { util::add(1, 2) }\n",
+                ),
+                ("util.nova", "fn add(a: i64, b: i64) -> i64 { a + b }\n"),
+            ],
+        );
+        let mut resolver = Resolver::new(ParseOptions::default());
+        let program = resolver.resolve(&dir.join("main.nova")).unwrap();
+        assert_eq!(function_names(&program), vec!["util::add", "main"]);
+        assert_eq!(resolver.source_map().files().len(), 2);
+
+        let typed = types::check(&program).unwrap();
+        let module = ir::lower(&typed);
+        assert!(codegen::generate(&module).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_nested_module_directory() {
+        let dir = temp_project(
+            "nested",
+            &[
+                ("main.nova", "mod util;\nfn main() { }\n"),
+                ("util/mod.nova", "mod math;\n"),
+                ("util/math.nova", "fn square(x: i64) -> i64 { x * x }\n"),
+            ],
+        );
+        let mut resolver = Resolver::new(ParseOptions::default());
+        let program = resolver.resolve(&dir.join("main.nova")).unwrap();
+        assert_eq!(function_names(&program), vec!["util::math::square", "main"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_inline_modules_are_flattened() {
+        let dir = temp_project(
+            "inline",
+            &[
+                (
+                    "main.nova",
+                    "mod util { fn add() {} mod math; #[cfg(test)] fn check() {} }\nfn main() { }\n",
+                ),
+                ("util/math.nova", "fn square(x: i64) -> i64 { x * x }\n"),
+            ],
+        );
+        let mut resolver = Resolver::new(ParseOptions::default());
+        let program = resolver.resolve(&dir.join("main.nova")).unwrap();
+        assert_eq!(
+            function_names(&program),
+            vec!["util::add", "util::math::square", "main"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_calls_within_a_module() {
+        let source = "mod util {
+                fn a() -> i64 { b() + math::c() }
+                fn b() -> i64 { 1 }
+                mod math { fn c() -> i64 { b() + top() } }
+            }
+            fn b() -> i64 { 10 }
+            fn top() -> i64 { 100 }
+            fn main() -> i64 { util::a() + b() }";
+        let dir = temp_project("calls", &[("main.nova", source)]);
+        let mut resolver = Resolver::new(ParseOptions::default());
+        let program = resolver.resolve(&dir.join("main.nova")).unwrap();
+        let typed = types::check(&program).unwrap();
+        let module = ir::lower(&typed);
+        let calls = |name: &str| -> Vec<String> {
+            let f = module.functions.iter().find(|f| f.name == name).unwrap();
+            f.blocks
+                .iter()
+                .flat_map(|b| &b.instructions)
+                .filter_map(|i| match &i.kind {
+                    ir::InstructionKind::Call(callee, _) => Some(callee.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        // A name is looked up in the caller's module first, then the
+        // modules around it
+        assert_eq!(calls("util::a"), ["util::b", "util::math::c"]);
+        assert_eq!(calls("util::math::c"), ["util::b", "top"]);
+        assert_eq!(calls("main"), ["util::a", "b"]);
+        assert!(codegen::generate(&module).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_disabled_module_is_not_loaded() {
+        let dir = temp_project(
+            "cfg",
+            &[("main.nova", "#[cfg(test)]\nmod tests;\nfn main() { }\n")],
+        );
+        let mut resolver = Resolver::new(ParseOptions::default());
+        let program = resolver.resolve(&dir.join("main.nova")).unwrap();
+        assert_eq!(function_names(&program), vec!["main"]);
+
+        let mut cfg = CfgSet::new();
+        cfg.enable("test");
+        let mut resolver = Resolver::new(ParseOptions::default()).with_cfg(cfg);
+        assert!(matches!(
+            resolver.resolve(&dir.join("main.nova")).unwrap_err()[..],
+            [NovaError::ModuleNotFound { .. }]
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_module() {
+        let dir = temp_project("missing", &[("main.nova", "fn main() { }\nmod gone;\n")]);
+        let mut resolver = Resolver::new(ParseOptions::default());
+        match &resolver.resolve(&dir.join("main.nova")).unwrap_err()[..] {
+            [NovaError::ModuleNotFound { name, path, span }] => {
+                assert_eq!(name, "gone");
+                assert!(path.contains("gone.nova"), "{}", path);
+                assert_eq!((span.start(), span.end()), (14, 23));
+            }
+            other => panic!("Expected ModuleNotFound, got {:?}", other),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_spans_map_back_to_module_file() {
+        let dir = temp_project(
+            "spans",
+            &[
+                ("main.nova", "mod util;\n"),
+                ("util.nova", "fn f() { let x = 1$; }\n"),
+            ],
+        );
+        let mut resolver = Resolver::new(ParseOptions::default());
+        let errors = resolver.resolve(&dir.join("main.nova")).unwrap_err();
+        let (file, local) = resolver.source_map().lookup(errors[0].span()).unwrap();
+        assert!(file.name.ends_with("util.nova"));
+        assert_eq!(local.start(), 18);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reports_every_syntax_error_in_a_file() {
+        let dir = temp_project(
+            "recover",
+            &[(
+                "main.nova",
+                "fn a() { let x: = 1; }\nfn b() { }\nfn c() { 1 + ; }\n",
+            )],
+        );
+        let mut resolver = Resolver::new(ParseOptions::default());
+        let errors = resolver.resolve(&dir.join("main.nova")).unwrap_err();
+        let offsets: Vec<_> = errors
+            .iter()
+            .map(|e| resolver.source_map().lookup(e.span()).unwrap().1.start())
+            .collect();
+        assert_eq!(offsets, [16, 47]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+//! Source files sharing one span address space
+//!
+//! When a program spans several files, each file is placed at its own base
+//! offset in a single address space, so a `Span` identifies the file it came
+//! from without growing beyond 8 bytes. The parser reads literal text from
+//! the combined text, and error reporting maps spans back to files.
+//!
+//! ```text
+//! text:  main.nova \n util.nova \n
+//!        ^0          ^base(util)
+//! ```
+
+use crate::error::NovaError;
+use crate::span::Span;
+
+/// A file registered in a `SourceMap`
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// Path of the file, used in diagnostics
+    pub name: String,
+    /// Offset of the file's first byte in the combined text
+    pub base: u32,
+    /// Length of the file in bytes
+    pub len: u32,
+}
+
+/// All source files of a compilation
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    text: String,
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Creates an empty source map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a file, returning its base offset.
+    ///
+    /// Files are separated by a newline so a span never touches two files.
+    pub fn add_file(&mut self, name: &str, source: &str) -> Result<u32, NovaError> {
+        let total = self.text.len() + source.len() + 1;
+        if total > u32::MAX as usize {
+            return Err(NovaError::SourceTooLarge {
+                size: total,
+                max: u32::MAX as usize,
+            });
+        }
+        let base = self.text.len() as u32;
+        self.text.push_str(source);
+        self.text.push('\n');
+        self.files.push(SourceFile {
+            name: name.to_string(),
+            base,
+            len: source.len() as u32,
+        });
+        Ok(base)
+    }
+
+    /// The combined text of all files.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// All registered files, in registration order.
+    #[allow(dead_code)]
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// The source text of a registered file.
+    pub fn source(&self, file: &SourceFile) -> &str {
+        &self.text[file.base as usize..(file.base + file.len) as usize]
+    }
+
+    /// Finds the file containing `span` and the span relative to that file.
+    ///
+    /// A span at the very end of a file (e.g. EOF) belongs to that file.
+    pub fn lookup(&self, span: Span) -> Option<(&SourceFile, Span)> {
+        let index = self
+            .files
+            .partition_point(|f| f.base <= span.start())
+            .checked_sub(1)?;
+        let file = &self.files[index];
+        if span.end() > file.base + file.len {
+            return None;
+        }
+        Some((
+            file,
+            Span::new(span.start() - file.base, span.end() - file.base),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_get_disjoint_bases() {
+        let mut map = SourceMap::new();
+        let a = map.add_file("a.nova", "fn a() {}").unwrap();
+        let b = map.add_file("b.nova", "fn b() {}").unwrap();
+        assert_eq!(a, 0);
+        assert_eq!(b, 10);
+        assert_eq!(map.source(&map.files()[1]), "fn b() {}");
+        assert_eq!(&map.text()[b as usize..b as usize + 4], "fn b");
+    }
+
+    #[test]
+    fn lookup_maps_back_to_file() {
+        let mut map = SourceMap::new();
+        map.add_file("a.nova", "fn a() {}").unwrap();
+        let base = map.add_file("b.nova", "fn b() {}").unwrap();
+
+        let (file, local) = map.lookup(Span::new(base + 3, base + 4)).unwrap();
+        assert_eq!(file.name, "b.nova");
+        assert_eq!(local, Span::new(3, 4));
+
+        let (file, local) = map.lookup(Span::new(9, 9)).unwrap();
+        assert_eq!(file.name, "a.nova");
+        assert_eq!(local, Span::new(9, 9));
+    }
+
+    #[test]
+    fn lookup_rejects_spans_across_files() {
+        let mut map = SourceMap::new();
+        map.add_file("a.nova", "fn a() {}").unwrap();
+        map.add_file("b.nova", "fn b() {}").unwrap();
+        assert!(map.lookup(Span::new(5, 12)).is_none());
+    }
+}
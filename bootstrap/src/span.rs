@@ -111,6 +111,14 @@ impl Span {
     pub const fn dummy() -> Self {
         Self { start: 0, end: 0 }
     }
+
+    /// Returns true if this is the dummy span of a synthetic node.
+    ///
+    /// An empty span at the very start of the source looks the same.
+    #[inline]
+    pub const fn is_dummy(&self) -> bool {
+        self.start == 0 && self.end == 0
+    }
 }
 
 impl fmt::Debug for Span {
@@ -125,6 +133,126 @@ impl fmt::Display for Span {
     }
 }
 
+/// The start offset of every line, for line lookup by binary search
+/// (ADR-007). Line numbers are 1-indexed (ADR-006).
+#[derive(Debug, Clone)]
+#[allow(dead_code)] // for tools; the compiler's own reports go through ariadne
+pub struct LineIndex {
+    /// `line_starts[i]` is the byte offset where line `i + 1` starts
+    line_starts: Vec<u32>,
+}
+
+#[allow(dead_code)]
+impl LineIndex {
+    /// Indexes the lines of `source`.
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        for (i, b) in source.bytes().enumerate() {
+            if b == b'\n' {
+                line_starts.push(i as u32 + 1);
+            }
+        }
+        Self { line_starts }
+    }
+
+    /// Returns the number of lines (an empty source has one).
+    pub fn line_count(&self) -> u32 {
+        self.line_starts.len() as u32
+    }
+
+    /// Returns the line containing `offset`.
+    pub fn line(&self, offset: u32) -> u32 {
+        match self.line_starts.binary_search(&offset) {
+            Ok(exact) => exact as u32 + 1,
+            Err(next) => next as u32,
+        }
+    }
+
+    /// Returns the 1-indexed byte column of `offset` within its line.
+    pub fn column(&self, offset: u32) -> u32 {
+        offset - self.line_starts[self.line(offset) as usize - 1] + 1
+    }
+
+    /// Returns the text of `line` in `source`, without its line break.
+    pub fn line_text<'a>(&self, source: &'a str, line: u32) -> &'a str {
+        let start = self.line_starts[line as usize - 1] as usize;
+        let end = match self.line_starts.get(line as usize) {
+            Some(&next) => next as usize - 1,
+            None => source.len(),
+        };
+        let text = &source[start..end];
+        text.strip_suffix('\r').unwrap_or(text)
+    }
+
+    /// Returns the lines that `span` overlaps, with `context_lines` more
+    /// on each side where the source has them.
+    pub fn excerpt<'a>(&self, source: &'a str, span: Span, context_lines: u32) -> Excerpt<'a> {
+        let first = self.line(span.start());
+        // A span ending right after a line break doesn't reach the next line
+        let last = if span.is_empty() {
+            first
+        } else {
+            self.line(span.end() - 1)
+        };
+        let from = first.saturating_sub(context_lines).max(1);
+        let to = last.saturating_add(context_lines).min(self.line_count());
+        let lines = (from..=to)
+            .map(|number| {
+                let text = self.line_text(source, number);
+                let marked = (first..=last).contains(&number).then(|| {
+                    let start = if number == first {
+                        self.column(span.start())
+                    } else {
+                        1
+                    };
+                    let end = if number == last {
+                        self.column(span.end())
+                    } else {
+                        text.len() as u32 + 1
+                    };
+                    (start, end)
+                });
+                ExcerptLine {
+                    number,
+                    text,
+                    marked,
+                }
+            })
+            .collect();
+        Excerpt { lines }
+    }
+}
+
+/// The source lines around a span (see [`excerpt`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct Excerpt<'a> {
+    pub lines: Vec<ExcerptLine<'a>>,
+}
+
+/// One line of an [`Excerpt`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct ExcerptLine<'a> {
+    /// The 1-indexed line number
+    pub number: u32,
+    /// The line's text, without its line break
+    pub text: &'a str,
+    /// The 1-indexed byte columns the span covers on this line, from `start`
+    /// up to but not including `end`, or `None` for a context line
+    pub marked: Option<(u32, u32)>,
+}
+
+/// Returns the lines of `source` that `span` overlaps, plus `context_lines`
+/// before and after, for tools that want source context without ariadne.
+///
+/// To take several excerpts from one source, build a [`LineIndex`] once and
+/// use [`LineIndex::excerpt`].
+#[allow(dead_code)]
+pub fn excerpt(source: &str, span: Span, context_lines: u32) -> Excerpt<'_> {
+    LineIndex::new(source).excerpt(source, span, context_lines)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -212,6 +340,8 @@ mod tests {
         assert_eq!(dummy.start(), 0);
         assert_eq!(dummy.end(), 0);
         assert!(dummy.is_empty());
+        assert!(dummy.is_dummy());
+        assert!(!Span::new(0, 1).is_dummy());
     }
 
     #[test]
@@ -247,6 +377,57 @@ mod tests {
         assert!(set.contains(&Span::new(0, 10)));
     }
 
+    #[test]
+    fn line_index_lookup() {
+        let index = LineIndex::new("ab\ncd\n");
+        assert_eq!(index.line_count(), 3);
+        assert_eq!(index.line(0), 1);
+        assert_eq!(index.line(2), 1); // the line break belongs to its line
+        assert_eq!(index.line(3), 2);
+        assert_eq!(index.line(6), 3);
+        assert_eq!(index.column(4), 2);
+    }
+
+    #[test]
+    fn excerpt_single_line_span() {
+        let source = "fn main() {\n    let x = $;\n    x\n}\n";
+        let start = source.find('$').unwrap() as u32;
+        let excerpt = excerpt(source, Span::new(start, start + 1), 1);
+        let expected = [
+            (1, "fn main() {", None),
+            (2, "    let x = $;", Some((13, 14))),
+            (3, "    x", None),
+        ];
+        let lines: Vec<_> = excerpt
+            .lines
+            .iter()
+            .map(|l| (l.number, l.text, l.marked))
+            .collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn excerpt_multi_line_span() {
+        let source = "a\nlet s = \"one\r\ntwo\nthree\";\nb";
+        let start = source.find('"').unwrap() as u32;
+        let end = source.rfind('"').unwrap() as u32 + 1;
+        let excerpt = excerpt(source, Span::new(start, end), 5);
+        let lines: Vec<_> = excerpt
+            .lines
+            .iter()
+            .map(|l| (l.number, l.text, l.marked))
+            .collect();
+        // Context stops at the ends of the source; `\r\n` is one line break
+        let expected = [
+            (1, "a", None),
+            (2, "let s = \"one", Some((9, 13))),
+            (3, "two", Some((1, 4))),
+            (4, "three\";", Some((1, 7))),
+            (5, "b", None),
+        ];
+        assert_eq!(lines, expected);
+    }
+
     // Compile-time size assertion (fails at compile time if wrong)
     const _: () = assert!(size_of::<Span>() == 8);
 }
@@ -0,0 +1,96 @@
+//! Per-phase timing for `nova compile --time`
+//!
+//! Each compiler phase runs through [`PhaseTimes::time`]. When timing is
+//! off that is just a call, so the pipeline is the same either way.
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// How long each compiler phase took, in the order the phases first ran
+#[derive(Debug, Clone, Default)]
+pub struct PhaseTimes {
+    enabled: bool,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl PhaseTimes {
+    /// Creates a recorder; a disabled one records nothing.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Runs `f`, adding its duration to `phase`.
+    ///
+    /// A phase that runs several times (e.g. lexing each module) adds up.
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return f();
+        }
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
+        }
+        result
+    }
+
+    /// The recorded phases and their total durations
+    #[allow(dead_code)] // the CLI only prints the report
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    /// Writes a table of the phases and their sum to `out`.
+    pub fn write_report(&self, mut out: impl Write) -> io::Result<()> {
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        writeln!(out, "{:<10} {:>10}", "phase", "time")?;
+        for (phase, elapsed) in &self.phases {
+            writeln!(out, "{:<10} {:>7.3} ms", phase, millis(*elapsed))?;
+        }
+        let total = self.phases.iter().map(|(_, d)| *d).sum();
+        writeln!(out, "{:<10} {:>7.3} ms", "total", millis(total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_records_nothing() {
+        let mut times = PhaseTimes::new(false);
+        assert_eq!(times.time("lex", || 1 + 1), 2);
+        assert!(times.phases().is_empty());
+    }
+
+    #[test]
+    fn test_repeated_phase_adds_up() {
+        let mut times = PhaseTimes::new(true);
+        times.time("lex", || ());
+        times.time("parse", || ());
+        times.time("lex", || ());
+        let names: Vec<_> = times.phases().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["lex", "parse"]);
+    }
+
+    #[test]
+    fn test_report_lists_phases_and_total() {
+        let mut times = PhaseTimes::new(true);
+        times.time("lex", || ());
+        times.time("codegen", || ());
+        let mut out = Vec::new();
+        times.write_report(&mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        let first_words: Vec<_> = report
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(first_words, ["phase", "lex", "codegen", "total"]);
+        assert!(report.lines().skip(1).all(|line| line.ends_with(" ms")));
+    }
+}
@@ -41,7 +41,7 @@ pub enum TokenKind {
     IntLit = 0,
     /// Float literal: `3.14`, `1e10`, `2.5e-3`
     FloatLit = 1,
-    /// String literal: `"hello"`, `"line\nbreak"`
+    /// String literal: `"hello"`, `"line\nbreak"`, `r#"raw "quotes""#`
     StringLit = 2,
     /// Character literal: `'a'`, `'\n'`
     CharLit = 3,
@@ -52,6 +52,13 @@ pub enum TokenKind {
     /// Identifier: `foo`, `_bar`, `Vec3`
     Ident = 4,
 
+    /// Format string literal: `f"x = {x}"`, `f"{{escaped}}"`
+    ///
+    /// Numbered after `Ident` so the existing discriminants stay stable.
+    FStringLit = 5,
+    /// Raw byte string literal: `br"\x00"`, `br#"say "hi""#`
+    ByteStringLit = 6,
+
     // ========================================================================
     // Keywords (alphabetically sorted for binary search potential)
     // ========================================================================
@@ -243,6 +250,8 @@ pub enum TokenKind {
     /// Lexer error (invalid character, unterminated string, etc.)
     #[allow(dead_code)]
     Error = 251,
+    /// A newline that ends a statement (only in newline-terminated mode)
+    Newline = 252,
 }
 
 impl TokenKind {
@@ -257,7 +266,7 @@ impl TokenKind {
     #[inline]
     #[allow(dead_code)]
     pub const fn is_literal(self) -> bool {
-        matches!(self as u8, 0..=3)
+        matches!(self as u8, 0..=3 | 5..=6)
     }
 
     /// Returns true if this token is an operator.
@@ -380,6 +389,8 @@ impl TokenKind {
             TokenKind::FloatLit => "<float>",
             TokenKind::StringLit => "<string>",
             TokenKind::CharLit => "<char>",
+            TokenKind::FStringLit => "<fstring>",
+            TokenKind::ByteStringLit => "<bytestring>",
             TokenKind::Ident => "<ident>",
 
             // Keywords
@@ -475,6 +486,7 @@ impl TokenKind {
             // Special
             TokenKind::Eof => "<eof>",
             TokenKind::Error => "<error>",
+            TokenKind::Newline => "<newline>",
         }
     }
 }
@@ -562,6 +574,22 @@ impl Token {
         self.kind.is_operator()
     }
 
+    /// Formats this token using its text in `source`.
+    ///
+    /// Literals and identifiers show their kind and actual text
+    /// (`IntLit "42"`); every other token shows its fixed spelling (`fn`).
+    /// Falls back to the placeholder (`<int>`) if the span is outside `source`.
+    pub fn display_with_source(&self, source: &str) -> String {
+        if !self.kind.is_literal() && self.kind != TokenKind::Ident {
+            return self.kind.as_str().to_string();
+        }
+        let range = self.span.start() as usize..self.span.end() as usize;
+        match source.get(range) {
+            Some(text) => format!("{:?} {:?}", self.kind, text),
+            None => self.kind.as_str().to_string(),
+        }
+    }
+
     /// Creates an EOF token at the given position.
     #[inline]
     pub const fn eof(pos: u32) -> Self {
@@ -792,4 +820,35 @@ mod tests {
         let b = a; // Copy
         assert_eq!(a, b);
     }
+
+    // ========================================================================
+    // Source-aware display
+    // ========================================================================
+
+    #[test]
+    fn display_with_source_shows_literal_text() {
+        let source = "let x = 42;";
+        let int = Token::new(TokenKind::IntLit, Span::new(8, 10));
+        let ident = Token::new(TokenKind::Ident, Span::new(4, 5));
+        let keyword = Token::new(TokenKind::Let, Span::new(0, 3));
+        assert_eq!(int.display_with_source(source), "IntLit \"42\"");
+        assert_eq!(ident.display_with_source(source), "Ident \"x\"");
+        assert_eq!(keyword.display_with_source(source), "let");
+    }
+
+    #[test]
+    fn display_with_source_escapes_strings() {
+        let source = r#""a\"b""#;
+        let string = Token::new(TokenKind::StringLit, Span::new(0, source.len() as u32));
+        assert_eq!(
+            string.display_with_source(source),
+            format!("StringLit {:?}", source)
+        );
+    }
+
+    #[test]
+    fn display_with_source_out_of_bounds_falls_back() {
+        let token = Token::new(TokenKind::IntLit, Span::new(5, 10));
+        assert_eq!(token.display_with_source("1"), "<int>");
+    }
 }
@@ -13,40 +13,245 @@
 
 use crate::ast::*;
 use crate::error::NovaError;
+use crate::intern::Symbol;
+use crate::resolve::flatten_inline_modules;
+use crate::token::Span;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 
 /// A typed version of the program
 #[derive(Debug)]
 pub struct TypedProgram {
     pub items: Vec<TypedItem>,
+    /// The integer type unsuffixed integer literals default to
+    pub default_int: DefaultInt,
+}
+
+/// The integer type unsuffixed integer literals default to
+///
+/// A literal takes the type of whatever integer it is used as, so this is
+/// only the type of literals nothing else constrains. The backend still uses
+/// this width for integer arithmetic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DefaultInt {
+    I32,
+    #[default]
+    I64,
+}
+
+impl DefaultInt {
+    /// Parses a type name (`i32` or `i64`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "i32" => Some(DefaultInt::I32),
+            "i64" => Some(DefaultInt::I64),
+            _ => None,
+        }
+    }
+
+    /// The integer type this names
+    pub fn width(self) -> IntWidth {
+        match self {
+            DefaultInt::I32 => IntWidth::I32,
+            DefaultInt::I64 => IntWidth::I64,
+        }
+    }
+}
+
+/// The size and signedness of an integer type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntWidth {
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+}
+
+impl IntWidth {
+    /// Parses an integer type name (`i32`, `usize`, ...).
+    pub fn from_name(name: &str) -> Option<Self> {
+        use IntWidth::*;
+        Some(match name {
+            "i8" => I8,
+            "i16" => I16,
+            "i32" => I32,
+            "i64" => I64,
+            "i128" => I128,
+            "isize" => Isize,
+            "u8" => U8,
+            "u16" => U16,
+            "u32" => U32,
+            "u64" => U64,
+            "u128" => U128,
+            "usize" => Usize,
+            _ => return None,
+        })
+    }
+
+    /// Size in bits, with `isize` and `usize` the size of a WASM address
+    pub fn bits(self) -> u32 {
+        use IntWidth::*;
+        match self {
+            I8 | U8 => 8,
+            I16 | U16 => 16,
+            I32 | U32 | Isize | Usize => 32,
+            I64 | U64 => 64,
+            I128 | U128 => 128,
+        }
+    }
+
+    /// Whether values of the type can be negative
+    pub fn is_signed(self) -> bool {
+        use IntWidth::*;
+        matches!(self, I8 | I16 | I32 | I64 | I128 | Isize)
+    }
+
+    /// The smallest and largest values, with `u128` capped at `i128::MAX`
+    pub fn range(self) -> (i128, i128) {
+        let bits = self.bits();
+        match self {
+            IntWidth::I128 => (i128::MIN, i128::MAX),
+            IntWidth::U128 => (0, i128::MAX),
+            _ if !self.is_signed() => (0, (1 << bits) - 1),
+            _ => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+        }
+    }
+}
+
+/// Type checker options
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckOptions {
+    pub default_int: DefaultInt,
+}
+
+/// Per-function results kept between calls to [`check_incremental`]
+///
+/// A function is re-checked when its AST changes (wherever it moved in the
+/// file) or when a top-level name it looked up, such as an enum variant,
+/// an associated constant or a function it calls, now has a different type.
+#[derive(Debug, Default)]
+pub struct CheckCache {
+    functions: HashMap<Symbol, CachedFunction>,
+    /// How many functions the last check actually checked
+    checked: usize,
+}
+
+impl CheckCache {
+    /// How many functions the last check didn't find in the cache
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+}
+
+/// A checked function and what its result depends on
+#[derive(Debug)]
+struct CachedFunction {
+    /// Fingerprint of the function's AST and its impl's `Self`
+    key: u64,
+    /// The top-level names it looked up, with their fingerprints then
+    deps: Vec<(Symbol, u64)>,
+    function: TypedFunction,
 }
 
 /// A typed item
 #[derive(Debug)]
 pub enum TypedItem {
     Function(TypedFunction),
+    Struct(TypedStruct),
+    Enum(TypedEnum),
+    Const(TypedConst),
+    Static(TypedStatic),
 }
 
-/// A typed function
+/// A typed associated constant, named `Type::NAME`
+#[derive(Debug)]
+pub struct TypedConst {
+    pub name: Symbol,
+    pub ty: TypeInfo,
+    pub value: TypedExpr,
+}
+
+/// A typed static item, with its initializer evaluated
+#[derive(Debug, Clone)]
+pub struct TypedStatic {
+    pub name: Symbol,
+    pub mutable: bool,
+    pub ty: TypeInfo,
+    pub value: Literal,
+}
+
+/// A typed struct definition
+#[derive(Debug)]
+pub struct TypedStruct {
+    pub name: Symbol,
+    /// Fields in declaration order (tuple-struct fields are named `0`, `1`, ...)
+    pub fields: Vec<(Symbol, TypeInfo)>,
+    pub repr: Repr,
+}
+
+/// How a struct or enum is laid out in memory, as its `#[repr]` asks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Repr {
+    /// Fields in whichever order needs the least padding
+    #[default]
+    Default,
+    /// `#[repr(C)]`: fields in declaration order, each aligned naturally
+    C,
+    /// `#[repr(packed)]`: fields in declaration order without padding
+    Packed,
+}
+
+/// A typed enum definition
+#[derive(Debug)]
+pub struct TypedEnum {
+    pub name: Symbol,
+    pub variants: Vec<TypedVariant>,
+    pub repr: Repr,
+}
+
+/// A typed enum variant
 #[derive(Debug)]
+pub struct TypedVariant {
+    pub name: Symbol,
+    /// The variant's tag: its explicit `= value`, or one more than the
+    /// previous variant's (the first defaults to 0)
+    pub discriminant: i64,
+    /// Payload fields (tuple-variant fields are named `0`, `1`, ...)
+    pub fields: Vec<(Symbol, TypeInfo)>,
+}
+
+/// A typed function
+#[derive(Debug, Clone)]
 pub struct TypedFunction {
-    pub name: String,
-    pub params: Vec<(String, TypeInfo)>,
+    pub name: Symbol,
+    pub params: Vec<(Symbol, TypeInfo)>,
     pub return_type: TypeInfo,
     pub body: TypedBlock,
+    /// The function's `///` comment
+    pub doc: Option<String>,
 }
 
 /// A typed block
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TypedBlock {
     pub stmts: Vec<TypedStmt>,
     pub ty: TypeInfo,
 }
 
 /// A typed statement
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TypedStmt {
     Let {
-        name: String,
+        pattern: Pattern,
         ty: TypeInfo,
         value: Option<TypedExpr>,
     },
@@ -54,30 +259,52 @@ pub enum TypedStmt {
 }
 
 /// A typed expression
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TypedExpr {
     pub kind: TypedExprKind,
     pub ty: TypeInfo,
 }
 
 /// Typed expression kinds
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TypedExprKind {
     Literal(Literal),
-    Variable(String),
+    Variable(Symbol),
     Binary(Box<TypedExpr>, BinOp, Box<TypedExpr>),
     Unary(UnaryOp, Box<TypedExpr>),
     Call(Box<TypedExpr>, Vec<TypedExpr>),
     If(Box<TypedExpr>, TypedBlock, Option<Box<TypedExpr>>),
+    Match(Box<TypedExpr>, Vec<TypedMatchArm>),
     Block(TypedBlock),
+    While(Box<TypedExpr>, TypedBlock),
+    For(Symbol, Box<TypedExpr>, TypedBlock),
+    Loop(TypedBlock),
+    Break(Option<Box<TypedExpr>>),
+    Continue,
     Return(Option<Box<TypedExpr>>),
+    Try(Box<TypedExpr>),
+    Ref(bool, Box<TypedExpr>),                                   // mutable?
+    Cast(Box<TypedExpr>),                                        // to `ty`
+    Range(Option<Box<TypedExpr>>, Option<Box<TypedExpr>>, bool), // inclusive?
+    Index(Box<TypedExpr>, Box<TypedExpr>),
+    Closure(Vec<(Symbol, TypeInfo)>, Box<TypedExpr>),
+    Tuple(Vec<TypedExpr>),
+    Array(Vec<TypedExpr>),
+}
+
+/// A typed match arm
+#[derive(Debug, Clone)]
+pub struct TypedMatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<TypedExpr>,
+    pub body: TypedExpr,
 }
 
 /// Type information
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TypeInfo {
     // Primitives
-    Int,
+    Int(IntWidth),
     Float,
     Bool,
     String,
@@ -87,6 +314,10 @@ pub enum TypeInfo {
 
     // Compound
     Array(Box<TypeInfo>, usize),
+    /// A view of part of an array, `xs[1..3]`
+    Slice(Box<TypeInfo>),
+    /// `a..b` and friends, by the type of their bounds
+    Range(Box<TypeInfo>),
     Tuple(Vec<TypeInfo>),
     Function(Vec<TypeInfo>, Box<TypeInfo>),
     Reference(bool, Box<TypeInfo>), // mutable?
@@ -94,58 +325,770 @@ pub enum TypeInfo {
     // Named types
     Named(String),
 
+    // Trait objects (bound names only until traits are resolved)
+    ImplTrait(Vec<String>),
+    DynTrait(Vec<String>),
+
     // Generic/unknown
     Variable(usize), // Type variable for inference
     Unknown,
 }
 
+impl TypeInfo {
+    /// The canonical form of this type: every variable `subst` solves
+    /// replaced by its solution, all the way down.
+    ///
+    /// Variables unified with each other end up as the same variable, so
+    /// two types are the same once solved exactly when their canonical forms
+    /// are equal. Compare (and cache) those rather than the types themselves.
+    pub fn resolve_fully(&self, subst: &HashMap<usize, TypeInfo>) -> TypeInfo {
+        map_type(self, &|t| match t {
+            TypeInfo::Variable(var) => subst.get(var).map(|t| t.resolve_fully(subst)),
+            _ => None,
+        })
+    }
+}
+
 /// Type check a program
 pub fn check(program: &Program) -> Result<TypedProgram, NovaError> {
-    let mut checker = TypeChecker::new();
-    checker.check_program(program)
+    check_with_options(program, &CheckOptions::default())
+}
+
+/// Type check a program with custom options
+pub fn check_with_options(
+    program: &Program,
+    options: &CheckOptions,
+) -> Result<TypedProgram, NovaError> {
+    let mut checker = TypeChecker::new(*options);
+    checker.check_program(program, None)
+}
+
+/// Type check a program, reusing the functions in `cache` that can't have
+/// changed since the last call and updating it with the rest
+pub fn check_incremental(
+    program: &Program,
+    options: &CheckOptions,
+    cache: &mut CheckCache,
+) -> Result<TypedProgram, NovaError> {
+    let mut checker = TypeChecker::new(*options);
+    checker.check_program(program, Some(cache))
 }
 
 /// The type checker state
 struct TypeChecker {
-    /// Type environment: name -> type
-    env: Vec<(String, TypeInfo)>,
+    /// Type environment: name -> type, and whether the name can be assigned
+    env: Vec<(Symbol, TypeInfo, Assignable)>,
+    /// The structs and enums declared in the program, keyed by name
+    types: HashMap<Symbol, TypeInfo>,
+    /// Tuple-struct and enum-variant constructors, keyed by path
+    constructors: HashMap<Symbol, Constructor>,
+    /// Associated constants and statics, keyed by path (`Type::NAME`)
+    consts: HashMap<Symbol, TypeInfo>,
+    /// The `static mut` items among `consts`
+    mutable_statics: HashSet<Symbol>,
+    /// Signatures of the top-level functions, keyed by name
+    functions: HashMap<Symbol, TypeInfo>,
+    /// The type parameters of the generic functions among `functions`
+    generics: HashMap<Symbol, Vec<String>>,
     /// Current function's return type
     return_type: Option<TypeInfo>,
+    /// Enclosing loops, innermost last
+    loops: Vec<LoopContext>,
+    /// What `Self` means in the impl being checked, if any
+    self_type: Option<TypeInfo>,
+    /// The associated types of that impl, what `Self::Item` means
+    assoc_types: Vec<(Symbol, TypeInfo)>,
+    /// The module path of the function being checked: `util::`, or empty
+    /// at the top level
+    module: String,
+    /// Top-level names looked up by the function being checked
+    deps: Vec<Symbol>,
     /// Next type variable ID
     next_var: usize,
+    /// The types solved type variables stand for, see `unify`
+    substitution: HashMap<usize, TypeInfo>,
+    /// The variables for the types of integer literals, which only an
+    /// integer type can solve
+    int_vars: HashSet<usize>,
+    /// The integer literals of the item being checked, with their types
+    /// and spans, to range check once the types are solved
+    int_literals: Vec<(i128, TypeInfo, Span)>,
+    /// Checker options
+    options: CheckOptions,
 }
 
 impl TypeChecker {
-    fn new() -> Self {
+    fn new(options: CheckOptions) -> Self {
         Self {
             env: Vec::new(),
+            types: HashMap::new(),
+            constructors: HashMap::new(),
+            consts: HashMap::new(),
+            mutable_statics: HashSet::new(),
+            functions: HashMap::new(),
+            generics: HashMap::new(),
             return_type: None,
+            loops: Vec::new(),
+            self_type: None,
+            assoc_types: Vec::new(),
+            module: String::new(),
+            deps: Vec::new(),
             next_var: 0,
+            substitution: HashMap::new(),
+            int_vars: HashSet::new(),
+            int_literals: Vec::new(),
+            options,
+        }
+    }
+
+    /// Check a complete program, using and refreshing `cache` if given
+    fn check_program(
+        &mut self,
+        program: &Program,
+        mut cache: Option<&mut CheckCache>,
+    ) -> Result<TypedProgram, NovaError> {
+        // A program that didn't go through the resolver still has its
+        // inline modules, whose items are checked as the resolver names them
+        let inline = |item: &Item| matches!(item, Item::Mod(m) if m.items.is_some());
+        let flattened;
+        let program = if program.items.iter().any(inline) {
+            let items = flatten_inline_modules(program.items.clone(), "");
+            flattened = Program { items };
+            &flattened
+        } else {
+            program
+        };
+
+        self.register_signatures(program)?;
+        self.register_constructors(program)?;
+        self.check_recursive_types(program)?;
+        self.check_trait_impls(program)?;
+
+        // Constants first, so every function can refer to them
+        let mut items = self.check_assoc_consts(program)?;
+        items.extend(self.check_statics(program)?);
+
+        if let Some(cache) = cache.as_deref_mut() {
+            cache.checked = 0;
+        }
+
+        for item in &program.items {
+            match item {
+                Item::Function(f) => {
+                    // The resolver names an item in a module `module::name`
+                    self.module = match f.name.name.as_str().rsplit_once("::") {
+                        Some((module, _)) => format!("{}::", module),
+                        None => String::new(),
+                    };
+                    let f = self.check_function_cached(f, f.name.name, &mut cache)?;
+                    self.module.clear();
+                    items.push(TypedItem::Function(f));
+                }
+                Item::Struct(s) => items.push(TypedItem::Struct(self.check_struct(s)?)),
+                Item::Enum(e) => items.push(TypedItem::Enum(self.check_enum(e)?)),
+                Item::Impl(imp) => {
+                    // Methods are flattened into functions named `Type::method`
+                    let self_name = self.impl_self_name(imp);
+                    self.self_type = Some(TypeInfo::Named(self_name.clone()));
+                    self.assoc_types = self.impl_assoc_types(imp)?;
+                    for item in &imp.items {
+                        // Constants were checked up front
+                        let ImplItem::Function(f) = item else {
+                            continue;
+                        };
+                        let name = Symbol::intern(&format!("{}::{}", self_name, f.name.name));
+                        let mut method = self.check_function_cached(f, name, &mut cache)?;
+                        method.name = name;
+                        items.push(TypedItem::Function(method));
+                    }
+                    self.self_type = None;
+                    self.assoc_types.clear();
+                }
+                // TODO: Handle other items (trait, use, type alias)
+                _ => {}
+            }
+        }
+
+        // Forget functions that were removed or renamed. This only happens
+        // once the program checks so that a typo doesn't empty the cache.
+        if let Some(cache) = cache {
+            cache.functions.retain(|name, _| {
+                items
+                    .iter()
+                    .any(|item| matches!(item, TypedItem::Function(f) if f.name == *name))
+            });
+        }
+
+        Ok(TypedProgram {
+            items,
+            default_int: self.options.default_int,
+        })
+    }
+
+    /// Check a function unless the cache has a result that is still valid.
+    ///
+    /// Without a cache this is just `check_function`.
+    fn check_function_cached(
+        &mut self,
+        f: &Function,
+        name: Symbol,
+        cache: &mut Option<&mut CheckCache>,
+    ) -> Result<TypedFunction, NovaError> {
+        let Some(cache) = cache else {
+            return self.check_function(f);
+        };
+        let context = format!("{:?} {:?}", self.self_type, self.assoc_types);
+        let key = fingerprint(&(function_fingerprint(f), context));
+        if let Some(cached) = cache.functions.get(&name) {
+            let deps_unchanged = cached
+                .deps
+                .iter()
+                .all(|&(dep, print)| self.dep_fingerprint(dep) == print);
+            if cached.key == key && deps_unchanged {
+                return Ok(cached.function.clone());
+            }
+        }
+
+        self.deps.clear();
+        let function = self.check_function(f)?;
+        cache.checked += 1;
+        let mut deps: Vec<_> = std::mem::take(&mut self.deps)
+            .into_iter()
+            .map(|dep| (dep, self.dep_fingerprint(dep)))
+            .collect();
+        deps.sort_by_key(|&(dep, _)| dep.as_str());
+        deps.dedup();
+        cache.functions.insert(
+            name,
+            CachedFunction {
+                key,
+                deps,
+                function: function.clone(),
+            },
+        );
+        Ok(function)
+    }
+
+    /// Fingerprint what a top-level name currently resolves to
+    fn dep_fingerprint(&self, name: Symbol) -> u64 {
+        let resolved = (
+            self.consts.get(&name),
+            self.mutable_statics.contains(&name),
+            self.constructors.get(&name),
+            self.functions.get(&name),
+            self.types.get(&name),
+        );
+        fingerprint(&format!("{:?}", resolved))
+    }
+
+    /// Look up a tuple-struct or enum-variant constructor, noting the
+    /// lookup as a dependency of the function being checked
+    fn constructor(&mut self, name: Symbol) -> Option<Constructor> {
+        self.deps.push(name);
+        self.constructors.get(&name).cloned()
+    }
+
+    /// Check the associated constants of every impl, registering their types.
+    fn check_assoc_consts(&mut self, program: &Program) -> Result<Vec<TypedItem>, NovaError> {
+        let mut items = Vec::new();
+        for item in &program.items {
+            let Item::Impl(imp) = item else {
+                continue;
+            };
+            let self_name = self.impl_self_name(imp);
+            self.self_type = Some(TypeInfo::Named(self_name.clone()));
+            self.assoc_types = self.impl_assoc_types(imp)?;
+            for item in &imp.items {
+                let ImplItem::Const(c) = item else {
+                    continue;
+                };
+                let ty = self.resolve_type(&c.ty)?;
+                let mut value = self.check_expr(&c.value)?;
+                self.coerce(&ty, &value.ty, c.value.span)?;
+                self.check_int_literals()?;
+                self.finish_expr(&mut value);
+                if let Some(folded) = const_eval(&c.value) {
+                    value.kind = TypedExprKind::Literal(folded.literal());
+                }
+                let name = Symbol::intern(&format!("{}::{}", self_name, c.name.name));
+                self.consts.insert(name, ty.clone());
+                items.push(TypedItem::Const(TypedConst { name, ty, value }));
+            }
+            self.self_type = None;
+            self.assoc_types.clear();
         }
+        Ok(items)
     }
 
-    /// Check a complete program
-    fn check_program(&mut self, program: &Program) -> Result<TypedProgram, NovaError> {
+    /// Check static items, registering their types.
+    ///
+    /// The initializer must be a constant: an integer or float expression
+    /// that [`const_eval`] can evaluate, or a bool literal.
+    fn check_statics(&mut self, program: &Program) -> Result<Vec<TypedItem>, NovaError> {
         let mut items = Vec::new();
+        for item in &program.items {
+            let Item::Static(s) = item else {
+                continue;
+            };
+            let ty = self.resolve_type(&s.ty)?;
+            let value = self.check_expr(&s.value)?;
+            self.coerce(&ty, &value.ty, s.value.span)?;
+            self.check_int_literals()?;
+            let value = match (&ty, const_eval(&s.value), &s.value.kind) {
+                (TypeInfo::Int(_), Some(value @ ConstValue::Int(_)), _)
+                | (TypeInfo::Float, Some(value @ ConstValue::Float(_)), _) => Some(value.literal()),
+                (_, _, ExprKind::Literal(lit @ Literal::Bool(_))) => Some(lit.clone()),
+                _ => None,
+            };
+            let Some(value) = value else {
+                return Err(NovaError::NonConstStatic { span: s.value.span });
+            };
+            self.consts.insert(s.name.name, ty.clone());
+            if s.mutable {
+                self.mutable_statics.insert(s.name.name);
+            }
+            items.push(TypedItem::Static(TypedStatic {
+                name: s.name.name,
+                mutable: s.mutable,
+                ty,
+                value,
+            }));
+        }
+        Ok(items)
+    }
+
+    /// Record the signature of every top-level function before any body is
+    /// checked, so a call can come before the function it calls, and
+    /// functions can call each other in any order.
+    ///
+    /// Struct and enum names are registered in the same pass, so a type can
+    /// be used before it is declared.
+    ///
+    /// A generic function's type parameters are recorded too, and stay
+    /// `Named` in its signature until a use instantiates them.
+    ///
+    /// TODO: Register methods
+    fn register_signatures(&mut self, program: &Program) -> Result<(), NovaError> {
+        for (name, ty) in intrinsics() {
+            self.functions.insert(Symbol::intern(name), ty);
+        }
+        // An alias can name another declared after it, so resolve them all
+        // once per alias; a cycle is left pointing at a name
+        let aliases: Vec<&TypeAlias> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::TypeAlias(a) => Some(a),
+                _ => None,
+            })
+            .collect();
+        for _ in 0..aliases.len() {
+            for alias in &aliases {
+                let ty = self.resolve_type(&alias.ty)?;
+                self.types.insert(alias.name.name, ty);
+            }
+        }
+        for item in &program.items {
+            let name = match item {
+                Item::Struct(s) => s.name.name,
+                Item::Enum(e) => e.name.name,
+                _ => continue,
+            };
+            self.types.insert(name, TypeInfo::Named(name.to_string()));
+        }
+        for item in &program.items {
+            let Item::Function(f) = item else {
+                continue;
+            };
+            if intrinsics().iter().any(|(name, _)| f.name.name == *name) {
+                return Err(NovaError::Custom {
+                    message: format!("`{}` is a built-in function", f.name.name),
+                    span: f.name.span,
+                });
+            }
+            if !f.generics.is_empty() {
+                let names = f.generics.iter().map(|g| g.name.name.to_string());
+                self.generics.insert(f.name.name, names.collect());
+            }
+            let mut params = Vec::new();
+            for param in &f.params {
+                params.push(self.resolve_type(&param.ty)?);
+            }
+            let ret = match &f.return_type {
+                Some(ty) => self.resolve_type(ty)?,
+                None => TypeInfo::Unit,
+            };
+            self.functions
+                .insert(f.name.name, TypeInfo::Function(params, Box::new(ret)));
+        }
+        Ok(())
+    }
+
+    /// Register the constructors of tuple structs and enum variants.
+    ///
+    /// Variants are registered both as `Enum::Variant` and as the bare
+    /// `Variant`, so `Some(x)` resolves without a `use` (there are no
+    /// imports yet). A bare name two enums share is ambiguous, so those
+    /// variants are only reachable qualified.
+    fn register_constructors(&mut self, program: &Program) -> Result<(), NovaError> {
+        let shared = shared_variant_names(program.items.iter().filter_map(|item| match item {
+            Item::Enum(e) => Some(e.variants.iter().map(|v| v.name.name)),
+            _ => None,
+        }));
+        for item in &program.items {
+            match item {
+                Item::Struct(s) if s.is_tuple => {
+                    let mut fields = Vec::new();
+                    for field in &s.fields {
+                        fields.push(self.resolve_type(&field.ty)?);
+                    }
+                    let ty = self.types[&s.name.name].clone();
+                    self.constructors
+                        .insert(s.name.name, Constructor { ty, fields });
+                }
+                Item::Enum(e) => {
+                    let ty = self.types[&e.name.name].clone();
+                    for variant in &e.variants {
+                        let fields = match &variant.fields {
+                            VariantFields::Unit => Vec::new(),
+                            VariantFields::Tuple(types) => {
+                                let mut fields = Vec::new();
+                                for t in types {
+                                    fields.push(self.resolve_type(t)?);
+                                }
+                                fields
+                            }
+                            // Struct variants are matched with field patterns
+                            VariantFields::Struct(_) => continue,
+                        };
+                        let ctor = Constructor {
+                            ty: ty.clone(),
+                            fields,
+                        };
+                        let qualified = format!("{}::{}", e.name.name, variant.name.name);
+                        self.constructors
+                            .insert(Symbol::intern(&qualified), ctor.clone());
+                        if !shared.contains(&variant.name.name) {
+                            self.constructors.insert(variant.name.name, ctor);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reject structs and enums that contain themselves by value.
+    ///
+    /// Such a type would have infinite size. Indirection through a
+    /// reference (or any type that isn't a local struct/enum, like a future
+    /// `Box<S>`) breaks the cycle.
+    fn check_recursive_types(&mut self, program: &Program) -> Result<(), NovaError> {
+        // Types stored inline in each struct/enum, by name
+        let mut contents: HashMap<String, (Vec<TypeInfo>, Span)> = HashMap::new();
+        for item in &program.items {
+            match item {
+                Item::Struct(s) => {
+                    let mut tys = Vec::new();
+                    for field in &s.fields {
+                        tys.push(self.resolve_type(&field.ty)?);
+                    }
+                    contents.insert(s.name.name.to_string(), (tys, s.name.span));
+                }
+                Item::Enum(e) => {
+                    let mut tys = Vec::new();
+                    for variant in &e.variants {
+                        match &variant.fields {
+                            VariantFields::Unit => {}
+                            VariantFields::Tuple(types) => {
+                                for t in types {
+                                    tys.push(self.resolve_type(t)?);
+                                }
+                            }
+                            VariantFields::Struct(fields) => {
+                                for field in fields {
+                                    tys.push(self.resolve_type(&field.ty)?);
+                                }
+                            }
+                        }
+                    }
+                    contents.insert(e.name.name.to_string(), (tys, e.name.span));
+                }
+                _ => {}
+            }
+        }
+
+        for item in &program.items {
+            let name = match item {
+                Item::Struct(s) => &s.name,
+                Item::Enum(e) => &e.name,
+                _ => continue,
+            };
+            let name = name.name.to_string();
+            let mut visited = Vec::new();
+            if contains_by_value(&contents, &contents[&name].0, &name, &mut visited) {
+                return Err(NovaError::InfinitelyRecursiveType {
+                    span: contents[&name].1,
+                    name,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a struct definition, resolving its field types
+    fn check_struct(&mut self, s: &StructDef) -> Result<TypedStruct, NovaError> {
+        Ok(TypedStruct {
+            name: s.name.name,
+            fields: self.check_fields(&s.fields)?,
+            repr: check_repr(&s.attrs)?,
+        })
+    }
+
+    /// Check an enum definition, resolving its payload types and discriminants
+    fn check_enum(&mut self, e: &EnumDef) -> Result<TypedEnum, NovaError> {
+        let repr = check_repr(&e.attrs)?;
+        let mut variants: Vec<TypedVariant> = Vec::new();
+        let mut next_discriminant = 0;
+        for variant in &e.variants {
+            let discriminant = match &variant.discriminant {
+                Some(expr) => {
+                    let value = const_discriminant(expr)?;
+                    if value < 0 {
+                        return Err(NovaError::NegativeDiscriminant {
+                            value,
+                            span: expr.span,
+                        });
+                    }
+                    value
+                }
+                None => next_discriminant,
+            };
+            if let Some(first) = variants.iter().find(|v| v.discriminant == discriminant) {
+                return Err(NovaError::DuplicateDiscriminant {
+                    value: discriminant,
+                    first: first.name.to_string(),
+                    span: variant.span,
+                });
+            }
+            // Overflow leaves the next implicit discriminant a duplicate
+            next_discriminant = discriminant.saturating_add(1);
+
+            let fields = match &variant.fields {
+                VariantFields::Unit => Vec::new(),
+                VariantFields::Tuple(types) => {
+                    let mut fields = Vec::new();
+                    for (i, t) in types.iter().enumerate() {
+                        fields.push((Symbol::intern(&i.to_string()), self.resolve_type(t)?));
+                    }
+                    fields
+                }
+                VariantFields::Struct(fields) => self.check_fields(fields)?,
+            };
+            variants.push(TypedVariant {
+                name: variant.name.name,
+                discriminant,
+                fields,
+            });
+        }
+        Ok(TypedEnum {
+            name: e.name.name,
+            variants,
+            repr,
+        })
+    }
+
+    /// Resolve the types of named fields
+    fn check_fields(&mut self, fields: &[Field]) -> Result<Vec<(Symbol, TypeInfo)>, NovaError> {
+        let mut typed = Vec::new();
+        for field in fields {
+            typed.push((field.name.name, self.resolve_type(&field.ty)?));
+        }
+        Ok(typed)
+    }
+
+    /// Check that every `impl Trait for Type` provides the trait's required
+    /// methods, with the signatures the trait declares.
+    ///
+    /// `Self` in the trait means the impl's type. Traits not defined in the
+    /// program (like `Display`) aren't checked.
+    fn check_trait_impls(&mut self, program: &Program) -> Result<(), NovaError> {
+        let traits: HashMap<Symbol, &TraitDef> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Trait(t) => Some((t.name.name, t)),
+                _ => None,
+            })
+            .collect();
 
         for item in &program.items {
-            // TODO: Handle other items (struct, enum, etc.)
-            if let Item::Function(f) = item {
-                items.push(TypedItem::Function(self.check_function(f)?));
+            let Item::Impl(imp) = item else {
+                continue;
+            };
+            let Some(Type {
+                kind: TypeKind::Path(path),
+                span: trait_span,
+            }) = &imp.trait_
+            else {
+                continue;
+            };
+            let Some(trait_def) = traits.get(&path_symbol(path)) else {
+                continue;
+            };
+
+            // The trait's type parameters stand for the impl's arguments,
+            // `T` for `i64` in `impl From<i64> for W`
+            let mut args = Vec::new();
+            if let Some(segment) = path.segments.last() {
+                for arg in &segment.generics {
+                    args.push(self.resolve_type(arg)?);
+                }
+            }
+            let trait_args: HashMap<String, TypeInfo> = trait_def
+                .generics
+                .iter()
+                .map(|param| param.name.name.to_string())
+                .zip(args)
+                .collect();
+
+            self.self_type = Some(TypeInfo::Named(self.impl_self_name(imp)));
+            self.assoc_types = self.impl_assoc_types(imp)?;
+            for trait_item in &trait_def.items {
+                let TraitItem::Function(expected) = trait_item else {
+                    continue;
+                };
+                let provided = imp.items.iter().find_map(|item| match item {
+                    ImplItem::Function(f) if f.name.name == expected.name.name => Some(f),
+                    _ => None,
+                });
+                let Some(provided) = provided else {
+                    if expected.default_body.is_none() {
+                        return Err(NovaError::MissingTraitMethod {
+                            trait_name: trait_def.name.name.to_string(),
+                            method: expected.name.name.to_string(),
+                            span: *trait_span,
+                        });
+                    }
+                    continue;
+                };
+                // A method's own type parameters match by position, so
+                // each is renamed after its index
+                let positional = |generics: &[GenericParam]| -> HashMap<String, TypeInfo> {
+                    generics
+                        .iter()
+                        .enumerate()
+                        .map(|(i, param)| {
+                            let name = param.name.name.to_string();
+                            (name, TypeInfo::Named(format!("<generic {}>", i)))
+                        })
+                        .collect()
+                };
+                let rename = |ty: &TypeInfo, names: &HashMap<String, TypeInfo>| {
+                    map_type(ty, &|t| match t {
+                        TypeInfo::Named(name) => names.get(name).cloned(),
+                        _ => None,
+                    })
+                };
+                // Unless the method has a parameter of the same name
+                let mut trait_args = trait_args.clone();
+                for param in &expected.generics {
+                    trait_args.remove(param.name.name.as_str());
+                }
+                let expected_sig = self.signature(&expected.params, &expected.return_type)?;
+                let expected_sig = rename(&expected_sig, &trait_args);
+                let found_sig = self.signature(&provided.params, &provided.return_type)?;
+                let arity_matches = expected.generics.len() == provided.generics.len();
+                if !arity_matches
+                    || rename(&expected_sig, &positional(&expected.generics))
+                        != rename(&found_sig, &positional(&provided.generics))
+                {
+                    return Err(NovaError::TraitMethodSignatureMismatch {
+                        method: expected.name.name.to_string(),
+                        expected: format!("{:?}", expected_sig),
+                        found: format!("{:?}", found_sig),
+                        span: provided.name.span,
+                    });
+                }
             }
+            self.self_type = None;
+            self.assoc_types.clear();
+        }
+        Ok(())
+    }
+
+    /// The function type of a signature
+    fn signature(
+        &mut self,
+        params: &[Param],
+        return_type: &Option<Type>,
+    ) -> Result<TypeInfo, NovaError> {
+        let mut resolved = Vec::new();
+        for param in params {
+            resolved.push(self.resolve_type(&param.ty)?);
+        }
+        let ret = match return_type {
+            Some(ty) => self.resolve_type(ty)?,
+            None => TypeInfo::Unit,
+        };
+        Ok(TypeInfo::Function(resolved, Box::new(ret)))
+    }
+
+    /// Get the name of an impl block's self type (e.g. `Point`)
+    fn impl_self_name(&self, imp: &ImplBlock) -> String {
+        match &imp.self_type.kind {
+            TypeKind::Path(path) => path_symbol(path).to_string(),
+            _ => "?".to_string(),
         }
+    }
 
-        Ok(TypedProgram { items })
+    /// The associated types an impl defines, `type Item = i64;`, resolved
+    fn impl_assoc_types(&mut self, imp: &ImplBlock) -> Result<Vec<(Symbol, TypeInfo)>, NovaError> {
+        let mut types = Vec::new();
+        for item in &imp.items {
+            if let ImplItem::Type(t) = item {
+                types.push((t.name.name, self.resolve_type(&t.ty)?));
+            }
+        }
+        Ok(types)
     }
 
     /// Check a function
     fn check_function(&mut self, f: &Function) -> Result<TypedFunction, NovaError> {
         // Parse parameter types
+        // Signatures are written out in full, so callers can be checked
+        // without looking at the body
+        let signature = f.params.iter().map(|p| &p.ty).chain(&f.return_type);
+        if let Some(span) = signature.filter_map(find_infer).next() {
+            return Err(NovaError::InferInSignature { span });
+        }
+
+        let scope = self.env.len();
         let mut params = Vec::new();
-        for param in &f.params {
+        // A parameter that destructures is passed whole, then taken apart
+        // by a `let` at the top of the body
+        let mut unpack = Vec::new();
+        for (i, param) in f.params.iter().enumerate() {
             let ty = self.resolve_type(&param.ty)?;
-            let name = self.pattern_name(&param.pattern);
-            self.env.push((name.clone(), ty.clone()));
+            let name = match &param.pattern.kind {
+                PatternKind::Ident(ident, _) => ident.name,
+                PatternKind::Wildcard => Symbol::intern("_"),
+                _ => {
+                    // Not a name the source can spell
+                    let name = Symbol::intern(&format!("<param {}>", i));
+                    unpack.push(TypedStmt::Let {
+                        pattern: param.pattern.clone(),
+                        ty: ty.clone(),
+                        value: Some(TypedExpr {
+                            kind: TypedExprKind::Variable(name),
+                            ty: ty.clone(),
+                        }),
+                    });
+                    name
+                }
+            };
+            self.bind_pattern(&param.pattern, &ty)?;
             params.push((name, ty));
         }
 
@@ -158,36 +1101,170 @@ impl TypeChecker {
         self.return_type = Some(return_type.clone());
 
         // Check body
-        let body = self.check_block(&f.body)?;
+        let mut body = self.check_block(&f.body)?;
+        body.stmts.splice(0..0, unpack);
 
-        // Verify return type
-        // TODO: More sophisticated type unification
+        // Verify return type, where a body that diverges (`!`) fits any
+        let tail = match f.body.stmts.last() {
+            Some(Stmt::Expr(e)) if !e.has_semi => e.expr.span,
+            _ => f.body.span,
+        };
+        self.coerce(&return_type, &body.ty, tail)?;
+        self.check_int_literals()?;
 
         // Clean up environment
-        for _ in &f.params {
-            self.env.pop();
-        }
+        self.env.truncate(scope);
         self.return_type = None;
 
+        self.finish_block(&mut body);
         Ok(TypedFunction {
-            name: f.name.name.clone(),
+            name: f.name.name,
             params,
             return_type,
             body,
+            doc: Attribute::doc(&f.attrs),
         })
     }
 
+    /// Check that each integer literal seen since the last call fits the
+    /// type it was solved to
+    fn check_int_literals(&mut self) -> Result<(), NovaError> {
+        for (n, ty, span) in std::mem::take(&mut self.int_literals) {
+            let TypeInfo::Int(width) = self.solved(&ty) else {
+                continue;
+            };
+            let (min, max) = width.range();
+            if n < min || n > max {
+                return Err(NovaError::IntLiteralOutOfRange {
+                    value: n,
+                    ty: self.shown(&ty),
+                    span,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the type variables in a checked block with their solutions.
+    ///
+    /// A variable nothing constrained, like that of a `let x;` that is
+    /// never assigned, becomes `Unknown`.
+    fn finish_block(&self, block: &mut TypedBlock) {
+        block.ty = self.solved(&block.ty);
+        for stmt in &mut block.stmts {
+            match stmt {
+                TypedStmt::Let { ty, value, .. } => {
+                    *ty = self.solved(ty);
+                    if let Some(value) = value {
+                        self.finish_expr(value);
+                    }
+                }
+                TypedStmt::Expr(expr) => self.finish_expr(expr),
+            }
+        }
+    }
+
+    /// Replace the type variables in a checked expression with their
+    /// solutions, see `finish_block`
+    fn finish_expr(&self, expr: &mut TypedExpr) {
+        expr.ty = self.solved(&expr.ty);
+        match &mut expr.kind {
+            TypedExprKind::Literal(_) | TypedExprKind::Variable(_) | TypedExprKind::Continue => {}
+            TypedExprKind::Binary(left, _, right) | TypedExprKind::Index(left, right) => {
+                self.finish_expr(left);
+                self.finish_expr(right);
+            }
+            TypedExprKind::Unary(_, inner)
+            | TypedExprKind::Try(inner)
+            | TypedExprKind::Ref(_, inner)
+            | TypedExprKind::Cast(inner) => self.finish_expr(inner),
+            TypedExprKind::Call(func, args) => {
+                self.finish_expr(func);
+                for arg in args {
+                    self.finish_expr(arg);
+                }
+            }
+            TypedExprKind::Tuple(elems) | TypedExprKind::Array(elems) => {
+                for elem in elems {
+                    self.finish_expr(elem);
+                }
+            }
+            TypedExprKind::If(cond, then_block, else_expr) => {
+                self.finish_expr(cond);
+                self.finish_block(then_block);
+                if let Some(e) = else_expr {
+                    self.finish_expr(e);
+                }
+            }
+            TypedExprKind::Match(scrutinee, arms) => {
+                self.finish_expr(scrutinee);
+                for arm in arms {
+                    if let Some(guard) = &mut arm.guard {
+                        self.finish_expr(guard);
+                    }
+                    self.finish_expr(&mut arm.body);
+                }
+            }
+            TypedExprKind::Block(block) | TypedExprKind::Loop(block) => self.finish_block(block),
+            TypedExprKind::While(cond, body) | TypedExprKind::For(_, cond, body) => {
+                self.finish_expr(cond);
+                self.finish_block(body);
+            }
+            TypedExprKind::Break(value) | TypedExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.finish_expr(value);
+                }
+            }
+            TypedExprKind::Range(start, end, _) => {
+                for bound in [start, end].into_iter().flatten() {
+                    self.finish_expr(bound);
+                }
+            }
+            TypedExprKind::Closure(params, body) => {
+                for (_, ty) in params {
+                    *ty = self.solved(ty);
+                }
+                self.finish_expr(body);
+            }
+        }
+    }
+
     /// Check a block
+    ///
+    /// A `let` binding is only visible to the statements after it: unlike
+    /// items, locals aren't hoisted, so a use before the `let` is undefined.
+    ///
+    /// A block without a final expression diverges, with type `!`, if one
+    /// of its statements does, like the `return 1;` in `{ return 1; }`.
     fn check_block(&mut self, block: &Block) -> Result<TypedBlock, NovaError> {
         let mut stmts = Vec::new();
         let mut last_ty = TypeInfo::Unit;
+        let mut diverges = false;
 
+        // Names bound in the block go out of scope with it
+        let scope = self.env.len();
         for stmt in &block.stmts {
-            let (typed_stmt, ty) = self.check_stmt(stmt)?;
+            let checked = self.check_stmt(stmt);
+            if checked.is_err() {
+                self.env.truncate(scope);
+            }
+            let (typed_stmt, ty) = checked?;
+            let value = match &typed_stmt {
+                TypedStmt::Let { value, .. } => value.as_ref(),
+                TypedStmt::Expr(expr) => Some(expr),
+            };
+            if value.is_some_and(|v| self.apply(&v.ty) == TypeInfo::Never) {
+                diverges = true;
+            }
             last_ty = ty;
             stmts.push(typed_stmt);
         }
+        self.env.truncate(scope);
 
+        let has_tail = matches!(block.stmts.last(), Some(Stmt::Expr(e)) if !e.has_semi);
+        if diverges && !has_tail {
+            last_ty = TypeInfo::Never;
+        }
         Ok(TypedBlock { stmts, ty: last_ty })
     }
 
@@ -195,25 +1272,40 @@ impl TypeChecker {
     fn check_stmt(&mut self, stmt: &Stmt) -> Result<(TypedStmt, TypeInfo), NovaError> {
         match stmt {
             Stmt::Let(let_stmt) => {
-                let name = self.pattern_name(&let_stmt.pattern);
-
                 let (value, inferred_ty) = if let Some(ref expr) = let_stmt.value {
                     let typed_expr = self.check_expr(expr)?;
                     let ty = typed_expr.ty.clone();
                     (Some(typed_expr), ty)
+                } else if let_stmt.ty.is_none() {
+                    // `let x;` takes the type of what is assigned to it
+                    (None, self.fresh_var())
                 } else {
                     (None, TypeInfo::Unknown)
                 };
 
                 let ty = if let Some(ref explicit_ty) = let_stmt.ty {
-                    self.resolve_type(explicit_ty)?
+                    let ty = self.resolve_type(explicit_ty)?;
+                    if let Some(ref expr) = let_stmt.value {
+                        self.coerce(&ty, &inferred_ty, expr.span)?;
+                    }
+                    fill_inferred(ty, &inferred_ty)
                 } else {
                     inferred_ty
                 };
 
-                self.env.push((name.clone(), ty.clone()));
+                let scope = self.env.len();
+                self.bind_pattern(&let_stmt.pattern, &ty)?;
+                // `let x;` may be assigned its value later, once
+                if let_stmt.value.is_none() {
+                    for (_, _, assignable) in &mut self.env[scope..] {
+                        if *assignable == Assignable::No {
+                            *assignable = Assignable::Once;
+                        }
+                    }
+                }
 
-                Ok((TypedStmt::Let { name, ty, value }, TypeInfo::Unit))
+                let pattern = let_stmt.pattern.clone();
+                Ok((TypedStmt::Let { pattern, ty, value }, TypeInfo::Unit))
             }
             Stmt::Expr(expr_stmt) => {
                 let typed_expr = self.check_expr(&expr_stmt.expr)?;
@@ -241,37 +1333,95 @@ impl TypeChecker {
     fn check_expr(&mut self, expr: &Expr) -> Result<TypedExpr, NovaError> {
         match &expr.kind {
             ExprKind::Literal(lit) => {
-                let ty = match lit {
-                    Literal::Int(_) => TypeInfo::Int,
-                    Literal::Float(_) => TypeInfo::Float,
-                    Literal::String(_) => TypeInfo::String,
-                    Literal::Bool(_) => TypeInfo::Bool,
-                    Literal::Char(_) => TypeInfo::Char,
-                };
+                let ty = self.literal_type(lit);
+                if let Literal::Int(n) = lit {
+                    self.int_literals.push((*n, ty.clone(), expr.span));
+                }
                 Ok(TypedExpr {
                     kind: TypedExprKind::Literal(lit.clone()),
                     ty,
                 })
             }
+            ExprKind::FormatString(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        FormatPart::Literal(s) => text.push_str(s),
+                        // TODO: Lower interpolations once strings exist at
+                        // runtime, rather than silently dropping them
+                        FormatPart::Expr(e) => {
+                            self.check_expr(e)?;
+                            return Err(NovaError::FormatInterpolation { span: e.span });
+                        }
+                    }
+                }
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Literal(Literal::String(text)),
+                    ty: TypeInfo::String,
+                })
+            }
             ExprKind::Path(path) => {
-                let name = &path.segments[0].ident.name;
-                let ty = self
-                    .env
-                    .iter()
-                    .rev()
-                    .find(|(n, _)| n == name)
-                    .map(|(_, t)| t.clone())
-                    .unwrap_or(TypeInfo::Unknown);
+                let name = path.segments[0].ident.name;
+                let local = if path.segments.len() == 1 {
+                    self.env
+                        .iter()
+                        .rev()
+                        .find(|(n, _, _)| *n == name)
+                        .map(|(_, t, _)| t.clone())
+                } else {
+                    None
+                };
+                let in_scope = local.is_some();
+                let symbol = match in_scope {
+                    true => name,
+                    false => self.item_symbol(path),
+                };
+                if !in_scope {
+                    self.deps.push(symbol);
+                }
+                let ty = match local.or_else(|| self.consts.get(&symbol).cloned()) {
+                    Some(ty) => ty,
+                    None => match self.functions.get(&symbol).cloned() {
+                        Some(ty) => self.instantiate(symbol, &ty, path)?,
+                        None => self
+                            .constructors
+                            .get(&symbol)
+                            .map(|c| {
+                                if c.fields.is_empty() {
+                                    c.ty.clone()
+                                } else {
+                                    TypeInfo::Function(c.fields.clone(), Box::new(c.ty.clone()))
+                                }
+                            })
+                            .unwrap_or(TypeInfo::Unknown),
+                    },
+                };
+                // A longer path may name something in a module, so only a
+                // single name has to be defined
+                // TODO: Look up names brought in by `use`
+                let known = self.consts.contains_key(&symbol)
+                    || self.functions.contains_key(&symbol)
+                    || self.constructors.contains_key(&symbol);
+                if path.segments.len() == 1 && !(known || in_scope) {
+                    return Err(NovaError::UndefinedVariable {
+                        name: name.to_string(),
+                        span: path.span,
+                    });
+                }
                 Ok(TypedExpr {
-                    kind: TypedExprKind::Variable(name.clone()),
+                    kind: TypedExprKind::Variable(symbol),
                     ty,
                 })
             }
             ExprKind::Binary(left, op, right) => {
+                if *op == BinOp::Assign {
+                    self.check_assignable(left)?;
+                }
                 let left_typed = self.check_expr(left)?;
                 let right_typed = self.check_expr(right)?;
 
-                let ty = self.binary_result_type(&left_typed.ty, *op, &right_typed.ty)?;
+                let ty =
+                    self.binary_result_type(&left_typed.ty, *op, &right_typed.ty, right.span)?;
 
                 Ok(TypedExpr {
                     kind: TypedExprKind::Binary(Box::new(left_typed), *op, Box::new(right_typed)),
@@ -280,6 +1430,14 @@ impl TypeChecker {
             }
             ExprKind::Unary(op, inner) => {
                 let inner_typed = self.check_expr(inner)?;
+                // `-128` is range checked as one literal, so that it fits
+                // an `i8`
+                if let (UnaryOp::Neg, ExprKind::Literal(Literal::Int(_))) = (op, &inner.kind) {
+                    if let Some((n, _, span)) = self.int_literals.last_mut() {
+                        *n = -*n;
+                        *span = expr.span;
+                    }
+                }
                 let ty = inner_typed.ty.clone();
                 Ok(TypedExpr {
                     kind: TypedExprKind::Unary(*op, Box::new(inner_typed)),
@@ -287,14 +1445,46 @@ impl TypeChecker {
                 })
             }
             ExprKind::Call(func, args) => {
-                let func_typed = self.check_expr(func)?;
+                let mut func_typed = self.check_expr(func)?;
+                func_typed.ty = self.apply(&func_typed.ty);
+                if self.is_integer(&func_typed.ty) || !is_callable(&func_typed.ty) {
+                    return Err(NovaError::NotCallable {
+                        ty: self.shown(&func_typed.ty),
+                        span: func.span,
+                    });
+                }
                 let mut args_typed = Vec::new();
                 for arg in args {
                     args_typed.push(self.check_expr(arg)?);
                 }
+                let params = match &func_typed.ty {
+                    TypeInfo::Function(params, _) => Some(params),
+                    TypeInfo::Reference(_, inner) => match &**inner {
+                        TypeInfo::Function(params, _) => Some(params),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(params) = params {
+                    if params.len() != args.len() {
+                        return Err(NovaError::WrongArgCount {
+                            expected: params.len(),
+                            found: args.len(),
+                            span: expr.span,
+                        });
+                    }
+                    for ((param, arg), typed) in params.iter().zip(args).zip(&args_typed) {
+                        self.coerce(param, &typed.ty, arg.span)?;
+                    }
+                }
 
+                // The arguments may have solved the return type's variables
                 let ty = match &func_typed.ty {
-                    TypeInfo::Function(_, ret) => (**ret).clone(),
+                    TypeInfo::Function(_, ret) => self.apply(ret),
+                    TypeInfo::Reference(_, inner) => match &**inner {
+                        TypeInfo::Function(_, ret) => self.apply(ret),
+                        _ => TypeInfo::Unknown,
+                    },
                     _ => TypeInfo::Unknown,
                 };
 
@@ -305,64 +1495,429 @@ impl TypeChecker {
             }
             ExprKind::If(cond, then_block, else_expr) => {
                 let cond_typed = self.check_expr(cond)?;
+                self.unify(&TypeInfo::Bool, &cond_typed.ty, cond.span)?;
+                // Either branch may initialize a deferred binding
+                let pending = self.uninitialized();
                 let then_typed = self.check_block(then_block)?;
+                let initialized = self.initialized_by_branch(&pending);
                 let else_typed = if let Some(e) = else_expr {
                     Some(Box::new(self.check_expr(e)?))
                 } else {
                     None
                 };
+                self.mark_initialized(&initialized);
 
-                let ty = then_typed.ty.clone();
+                // Without an `else` the value is `()` either way. Otherwise
+                // the branches agree, unless one of them diverges
+                let ty = match (&else_typed, else_expr) {
+                    (Some(typed), Some(e)) => self.join(&then_typed.ty, &typed.ty, e.span)?,
+                    _ => {
+                        self.unify(&TypeInfo::Unit, &then_typed.ty, then_block.span)?;
+                        TypeInfo::Unit
+                    }
+                };
 
                 Ok(TypedExpr {
                     kind: TypedExprKind::If(Box::new(cond_typed), then_typed, else_typed),
                     ty,
                 })
             }
-            ExprKind::Block(block) => {
-                let typed_block = self.check_block(block)?;
-                let ty = typed_block.ty.clone();
+            ExprKind::Match(scrutinee, arms) => {
+                let scrutinee_typed = self.check_expr(scrutinee)?;
+                let mut typed_arms = Vec::new();
+                let mut ty = TypeInfo::Never;
+                // Each arm may initialize a deferred binding
+                let pending = self.uninitialized();
+                let mut initialized = Vec::new();
+
+                for arm in arms {
+                    let scope = self.env.len();
+                    self.check_pattern(&arm.pattern, &scrutinee_typed.ty)?;
+                    let guard = match &arm.guard {
+                        Some(g) => Some(self.check_expr(g)?),
+                        None => None,
+                    };
+                    let body = self.check_expr(&arm.body)?;
+                    self.env.truncate(scope);
+                    initialized.extend(self.initialized_by_branch(&pending));
+
+                    // The first arm that doesn't diverge decides the type
+                    if self.apply(&ty) == TypeInfo::Never {
+                        ty = body.ty.clone();
+                    }
+                    typed_arms.push(TypedMatchArm {
+                        pattern: arm.pattern.clone(),
+                        guard,
+                        body,
+                    });
+                }
+
+                self.mark_initialized(&initialized);
+                check_range_overlap(arms)?;
                 Ok(TypedExpr {
-                    kind: TypedExprKind::Block(typed_block),
+                    kind: TypedExprKind::Match(Box::new(scrutinee_typed), typed_arms),
                     ty,
                 })
             }
-            ExprKind::Return(value) => {
-                let typed_value = if let Some(v) = value {
-                    Some(Box::new(self.check_expr(v)?))
-                } else {
-                    None
+            ExprKind::While(cond, body) => {
+                let cond_typed = self.check_expr(cond)?;
+                let (body_typed, _) = self.check_loop_body(body, false)?;
+                Ok(TypedExpr {
+                    kind: TypedExprKind::While(Box::new(cond_typed), body_typed),
+                    ty: TypeInfo::Unit,
+                })
+            }
+            ExprKind::For(pattern, iter, body) => {
+                // TODO: Destructure tuples and structs once IR can
+                if !matches!(pattern.kind, PatternKind::Ident(..) | PatternKind::Wildcard) {
+                    return Err(NovaError::ForPatternNotName { span: pattern.span });
+                }
+                let iter_typed = self.check_expr(iter)?;
+                let scope = self.env.len();
+                // TODO: Bind the item type of other iterators once traits are resolved
+                let item_ty = match &iter_typed.ty {
+                    TypeInfo::Range(bound) => (**bound).clone(),
+                    ty => element_type(ty).unwrap_or(TypeInfo::Unknown),
                 };
+                let name = self.pattern_name(pattern);
+                self.env
+                    .push((name, item_ty, pattern_is_mut(pattern).into()));
+                let body_typed = self.check_loop_body(body, false);
+                self.env.truncate(scope);
+                let (body_typed, _) = body_typed?;
                 Ok(TypedExpr {
-                    kind: TypedExprKind::Return(typed_value),
-                    ty: TypeInfo::Never,
+                    kind: TypedExprKind::For(name, Box::new(iter_typed), body_typed),
+                    ty: TypeInfo::Unit,
                 })
             }
-            // TODO: Implement remaining expression types
-            _ => Ok(TypedExpr {
-                kind: TypedExprKind::Literal(Literal::Bool(true)),
-                ty: TypeInfo::Unknown,
-            }),
-        }
-    }
+            ExprKind::Loop(body) => {
+                let (body_typed, break_ty) = self.check_loop_body(body, true)?;
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Loop(body_typed),
+                    // A loop without `break` never finishes
+                    ty: break_ty.unwrap_or(TypeInfo::Never),
+                })
+            }
+            ExprKind::Break(value) => {
+                let typed_value = match value {
+                    Some(v) => Some(Box::new(self.check_expr(v)?)),
+                    None => None,
+                };
+                let ty = typed_value
+                    .as_ref()
+                    .map_or(TypeInfo::Unit, |v| v.ty.clone());
+                if let Some(context) = self.loops.last_mut() {
+                    if typed_value.is_some() && !context.break_with_value {
+                        return Err(NovaError::BreakValueInWhile { span: expr.span });
+                    }
+                    // The first `break` decides the type of its loop
+                    context.break_ty.get_or_insert(ty);
+                }
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Break(typed_value),
+                    ty: TypeInfo::Never,
+                })
+            }
+            ExprKind::Continue => Ok(TypedExpr {
+                kind: TypedExprKind::Continue,
+                ty: TypeInfo::Never,
+            }),
+            // TODO: Only allow calls to `unsafe fn`s in unsafe blocks
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => {
+                let typed_block = self.check_block(block)?;
+                let ty = typed_block.ty.clone();
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Block(typed_block),
+                    ty,
+                })
+            }
+            ExprKind::Return(value) => {
+                // A bare `return` returns `()`
+                let return_type = self.return_type.clone().unwrap_or(TypeInfo::Unknown);
+                let typed_value = match value {
+                    Some(v) => {
+                        let typed = self.check_expr(v)?;
+                        self.coerce(&return_type, &typed.ty, v.span)?;
+                        Some(Box::new(typed))
+                    }
+                    None => {
+                        self.coerce(&return_type, &TypeInfo::Unit, expr.span)?;
+                        None
+                    }
+                };
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Return(typed_value),
+                    ty: TypeInfo::Never,
+                })
+            }
+            ExprKind::Try(inner) => {
+                let inner_typed = self.check_expr(inner)?;
+                let return_type = self.return_type.clone().unwrap_or(TypeInfo::Unknown);
+                if !is_fallible(&return_type) {
+                    // Point at the `?` itself, which ends the expression
+                    let end = expr.span.end();
+                    return Err(NovaError::TryInNonResultFn {
+                        return_type: format!("{:?}", return_type),
+                        span: Span::new(end.saturating_sub(1), end),
+                    });
+                }
+                // TODO: Unwrap the payload once named types carry arguments
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Try(Box::new(inner_typed)),
+                    ty: TypeInfo::Unknown,
+                })
+            }
+            ExprKind::Cast(inner, target) => {
+                let inner_typed = self.check_expr(inner)?;
+                let ty = self.resolve_type(target)?;
+                let from = self.apply(&inner_typed.ty);
+                let numeric = |t: &TypeInfo| self.is_integer(t) || *t == TypeInfo::Float;
+                let castable = match (&from, &ty) {
+                    (TypeInfo::Unknown, _) => true,
+                    (TypeInfo::Variable(var), _) if !self.int_vars.contains(var) => true,
+                    (TypeInfo::Bool | TypeInfo::Char, TypeInfo::Int(_)) => true,
+                    (from, to) => numeric(from) && numeric(to),
+                };
+                if !castable {
+                    return Err(NovaError::InvalidCast {
+                        from: self.shown(&from),
+                        to: format!("{:?}", ty),
+                        span: expr.span,
+                    });
+                }
+                // A constant cast is done now, truncating as it would at runtime
+                if let Some(value) = const_eval(expr) {
+                    return Ok(TypedExpr {
+                        kind: TypedExprKind::Literal(value.literal()),
+                        ty,
+                    });
+                }
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Cast(Box::new(inner_typed)),
+                    ty,
+                })
+            }
+            ExprKind::Ref(mutable, inner) => {
+                if *mutable {
+                    self.check_assignable(inner).map_err(|err| match err {
+                        NovaError::AssignToImmutable { name, span } => {
+                            NovaError::BorrowImmutableAsMut { name, span }
+                        }
+                        err => err,
+                    })?;
+                }
+                let inner_typed = self.check_expr(inner)?;
+                let ty = TypeInfo::Reference(*mutable, Box::new(inner_typed.ty.clone()));
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Ref(*mutable, Box::new(inner_typed)),
+                    ty,
+                })
+            }
+            ExprKind::Range(start, end, inclusive) => {
+                let start = match start {
+                    Some(e) => Some(self.check_expr(e)?),
+                    None => None,
+                };
+                let end = match end {
+                    Some(e) => Some(self.check_expr(e)?),
+                    None => None,
+                };
+                let bound = match (&start, &end) {
+                    (Some(s), Some(e)) => {
+                        self.coerce(&s.ty, &e.ty, expr.span)?;
+                        fill_inferred(s.ty.clone(), &e.ty)
+                    }
+                    (Some(bound), None) | (None, Some(bound)) => bound.ty.clone(),
+                    (None, None) => TypeInfo::Unknown,
+                };
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Range(start.map(Box::new), end.map(Box::new), *inclusive),
+                    ty: TypeInfo::Range(Box::new(bound)),
+                })
+            }
+            ExprKind::Index(base, index) => {
+                let base_typed = self.check_expr(base)?;
+                let index_typed = self.check_expr(index)?;
+                // TODO: Index other types (like `Vec`) once traits are resolved
+                let ty = match (element_type(&base_typed.ty), &index_typed.ty) {
+                    (Some(elem), TypeInfo::Range(bound)) => {
+                        let int = self.fresh_int_var();
+                        self.coerce(&int, bound, index.span)?;
+                        TypeInfo::Slice(Box::new(elem))
+                    }
+                    (Some(elem), index_ty) => {
+                        let int = self.fresh_int_var();
+                        self.coerce(&int, index_ty, index.span)?;
+                        elem
+                    }
+                    (None, _) => TypeInfo::Unknown,
+                };
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Index(Box::new(base_typed), Box::new(index_typed)),
+                    ty,
+                })
+            }
+            ExprKind::Closure(params, ret, body) => self.check_closure(params, ret, body),
+            ExprKind::Tuple(elems) => {
+                let elems = elems
+                    .iter()
+                    .map(|e| self.check_expr(e))
+                    .collect::<Result<Vec<_>, _>>()?;
+                // `()` is the unit value
+                let ty = match elems.is_empty() {
+                    true => TypeInfo::Unit,
+                    false => TypeInfo::Tuple(elems.iter().map(|e| e.ty.clone()).collect()),
+                };
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Tuple(elems),
+                    ty,
+                })
+            }
+            ExprKind::Array(elems) => {
+                let elem_ty = self.fresh_var();
+                let mut typed = Vec::new();
+                for elem in elems {
+                    let elem_typed = self.check_expr(elem)?;
+                    self.coerce(&elem_ty, &elem_typed.ty, elem.span)?;
+                    typed.push(elem_typed);
+                }
+                Ok(TypedExpr {
+                    kind: TypedExprKind::Array(typed),
+                    ty: TypeInfo::Array(Box::new(elem_ty), elems.len()),
+                })
+            }
+            ExprKind::StructLit(..) => Err(unsupported("Struct literals", expr.span)),
+            ExprKind::Await(_) => Err(unsupported("`.await` expressions", expr.span)),
+            ExprKind::MacroCall { .. } => Err(unsupported("Macro calls", expr.span)),
+            // TODO: Type fields and dereferences. Until then they're unknown,
+            // which lets them be checked as assignment targets.
+            ExprKind::Field(..) | ExprKind::Deref(_) => Ok(TypedExpr {
+                kind: TypedExprKind::Literal(Literal::Bool(true)),
+                ty: TypeInfo::Unknown,
+            }),
+        }
+    }
+
+    /// Check a closure, giving it a function type.
+    ///
+    /// Parameters and a return type that aren't written out start as fresh
+    /// type variables, solved by the body and by calls to the closure. The
+    /// body can't `break` out of loops around the closure.
+    ///
+    /// TODO: Check `return` and `?` against the inferred return type
+    fn check_closure(
+        &mut self,
+        params: &[Param],
+        ret: &Option<Type>,
+        body: &Expr,
+    ) -> Result<TypedExpr, NovaError> {
+        let scope = self.env.len();
+        let mut typed_params = Vec::new();
+        for param in params {
+            let ty = match param.ty.kind {
+                TypeKind::Infer => self.fresh_var(),
+                _ => self.resolve_type(&param.ty)?,
+            };
+            let name = self.pattern_name(&param.pattern);
+            self.env
+                .push((name, ty.clone(), pattern_is_mut(&param.pattern).into()));
+            typed_params.push((name, ty));
+        }
+        let (ret_ty, return_context) = match ret {
+            Some(ty) => {
+                let ty = self.resolve_type(ty)?;
+                (ty.clone(), ty)
+            }
+            None => (self.fresh_var(), TypeInfo::Unknown),
+        };
+
+        let outer_return = self.return_type.replace(return_context);
+        let outer_loops = std::mem::take(&mut self.loops);
+        let body_typed = self.check_expr(body);
+        self.return_type = outer_return;
+        self.loops = outer_loops;
+        self.env.truncate(scope);
+        let body_typed = body_typed?;
+        self.unify(&ret_ty, &body_typed.ty, body.span)?;
+
+        let param_types = typed_params.iter().map(|(_, ty)| ty.clone()).collect();
+        Ok(TypedExpr {
+            kind: TypedExprKind::Closure(typed_params, Box::new(body_typed)),
+            ty: TypeInfo::Function(param_types, Box::new(ret_ty)),
+        })
+    }
+
+    /// Check the body of a loop, which must be unit.
+    ///
+    /// Returns the body and the type of its first `break`, if any. The loop
+    /// stays on `self.loops` while the body is checked, so each `break` is
+    /// attributed to its innermost loop. Only a `loop` (`break_with_value`)
+    /// may be left with `break value`.
+    fn check_loop_body(
+        &mut self,
+        body: &Block,
+        break_with_value: bool,
+    ) -> Result<(TypedBlock, Option<TypeInfo>), NovaError> {
+        self.loops.push(LoopContext {
+            break_with_value,
+            break_ty: None,
+            scope: self.env.len(),
+        });
+        let typed = self.check_block(body);
+        let break_ty = self.loops.pop().and_then(|context| context.break_ty);
+        let typed = typed?;
+        match typed.ty {
+            TypeInfo::Unit | TypeInfo::Never | TypeInfo::Unknown => Ok((typed, break_ty)),
+            ref ty => Err(NovaError::TypeMismatch {
+                expected: format!("{:?}", TypeInfo::Unit),
+                found: format!("{:?}", ty),
+                span: body.span,
+            }),
+        }
+    }
 
-    /// Resolve a type annotation to a TypeInfo
-    fn resolve_type(&self, ty: &Type) -> Result<TypeInfo, NovaError> {
+    /// Resolve a type annotation to a TypeInfo, noting the declared type
+    /// names it looks up as dependencies of the function being checked
+    fn resolve_type(&mut self, ty: &Type) -> Result<TypeInfo, NovaError> {
         match &ty.kind {
+            // An associated type of the impl being checked. In a trait,
+            // where there's no impl, it stays a name of its own
+            TypeKind::Path(path)
+                if path.segments.len() == 2 && path.segments[0].ident.name == "Self" =>
+            {
+                let name = path.segments[1].ident.name;
+                Ok(self
+                    .assoc_types
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, ty)| ty.clone())
+                    .unwrap_or_else(|| TypeInfo::Named(format!("Self::{}", name))))
+            }
             TypeKind::Path(path) => {
-                let name = &path.segments[0].ident.name;
+                let name = path.segments[0].ident.name;
+                if let Some(width) = IntWidth::from_name(name.as_str()) {
+                    return Ok(TypeInfo::Int(width));
+                }
                 match name.as_str() {
-                    "i8" | "i16" | "i32" | "i64" | "i128" => Ok(TypeInfo::Int),
-                    "u8" | "u16" | "u32" | "u64" | "u128" => Ok(TypeInfo::Int),
                     "f32" | "f64" => Ok(TypeInfo::Float),
                     "bool" => Ok(TypeInfo::Bool),
                     "String" | "str" => Ok(TypeInfo::String),
                     "char" => Ok(TypeInfo::Char),
                     "()" => Ok(TypeInfo::Unit),
                     "!" => Ok(TypeInfo::Never),
-                    _ => Ok(TypeInfo::Named(name.clone())),
+                    // TODO: Report unknown type names once type parameters
+                    // and imported types are in scope here
+                    _ => {
+                        self.deps.push(name);
+                        Ok(self
+                            .types
+                            .get(&name)
+                            .cloned()
+                            .unwrap_or_else(|| TypeInfo::Named(name.to_string())))
+                    }
                 }
             }
+            TypeKind::Tuple(types) if types.is_empty() => Ok(TypeInfo::Unit),
             TypeKind::Tuple(types) => {
                 let mut resolved = Vec::new();
                 for t in types {
@@ -374,27 +1929,82 @@ impl TypeChecker {
                 *mutable,
                 Box::new(self.resolve_type(inner)?),
             )),
+            TypeKind::Fn(params, ret) => {
+                let mut resolved = Vec::new();
+                for p in params {
+                    resolved.push(self.resolve_type(p)?);
+                }
+                let ret = match ret {
+                    Some(r) => self.resolve_type(r)?,
+                    None => TypeInfo::Unit,
+                };
+                Ok(TypeInfo::Function(resolved, Box::new(ret)))
+            }
+            TypeKind::Array(elem, len) => {
+                let len = const_discriminant(len)
+                    .ok()
+                    .and_then(|n| usize::try_from(n).ok())
+                    .ok_or_else(|| NovaError::Custom {
+                        message: "Array length must be a constant integer".to_string(),
+                        span: len.span,
+                    })?;
+                Ok(TypeInfo::Array(Box::new(self.resolve_type(elem)?), len))
+            }
+            TypeKind::Slice(elem) => Ok(TypeInfo::Slice(Box::new(self.resolve_type(elem)?))),
+            TypeKind::ImplTrait(bounds) => Ok(TypeInfo::ImplTrait(self.bound_names(bounds))),
+            TypeKind::DynTrait(bounds) => Ok(TypeInfo::DynTrait(self.bound_names(bounds))),
             TypeKind::Never => Ok(TypeInfo::Never),
             TypeKind::Infer => Ok(TypeInfo::Unknown),
-            _ => Ok(TypeInfo::Unknown),
+            TypeKind::SelfType => self
+                .self_type
+                .clone()
+                .ok_or(NovaError::SelfOutsideImpl { span: ty.span }),
         }
     }
 
+    /// Get the names of trait bounds (e.g. `Display`, `std::fmt::Debug`)
+    fn bound_names(&self, bounds: &[Type]) -> Vec<String> {
+        bounds
+            .iter()
+            .map(|b| match &b.kind {
+                TypeKind::Path(path) => path
+                    .segments
+                    .iter()
+                    .map(|s| s.ident.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join("::"),
+                _ => "?".to_string(),
+            })
+            .collect()
+    }
+
     /// Get the result type of a binary operation
+    ///
+    /// `span` is the right operand's, where a mismatch is reported.
     fn binary_result_type(
-        &self,
+        &mut self,
         left: &TypeInfo,
         op: BinOp,
-        _right: &TypeInfo,
+        right: &TypeInfo,
+        span: Span,
     ) -> Result<TypeInfo, NovaError> {
         // Simplified type rules
         match op {
             BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => {
-                // Arithmetic: both operands should be numbers
-                Ok(left.clone())
+                // Arithmetic: both operands should be the same number type.
+                // Either side can be a block-like expression (`1 + if c { 2 }
+                // else { 3 }`), so take the type from whichever operand is known.
+                self.unify(left, right, span)?;
+                let left = self.apply(left);
+                if left == TypeInfo::Unknown {
+                    Ok(self.apply(right))
+                } else {
+                    Ok(left)
+                }
             }
             BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
-                // Comparison: result is bool
+                // Comparison: operands of one type, result is bool
+                self.unify(left, right, span)?;
                 Ok(TypeInfo::Bool)
             }
             BinOp::And | BinOp::Or => {
@@ -403,33 +2013,2738 @@ impl TypeChecker {
             }
             BinOp::Assign => {
                 // Assignment: result is unit
+                self.coerce(left, right, span)?;
                 Ok(TypeInfo::Unit)
             }
             _ => Ok(left.clone()),
         }
     }
 
+    /// The top-level name `path` refers to: relative to the module of the
+    /// function being checked, or an enclosing one, if it names something
+    /// there, and otherwise as written
+    fn item_symbol(&self, path: &Path) -> Symbol {
+        let symbol = path_symbol(path);
+        let mut module = self.module.as_str();
+        while !module.is_empty() {
+            let qualified = Symbol::intern(&format!("{}{}", module, symbol));
+            if self.consts.contains_key(&qualified)
+                || self.functions.contains_key(&qualified)
+                || self.constructors.contains_key(&qualified)
+            {
+                return qualified;
+            }
+            // `a::b::` to `a::`
+            module = match module[..module.len() - 2].rfind("::") {
+                Some(end) => &module[..end + 2],
+                None => "",
+            };
+        }
+        symbol
+    }
+
+    /// A new, unsolved type variable
+    fn fresh_var(&mut self) -> TypeInfo {
+        self.next_var += 1;
+        TypeInfo::Variable(self.next_var - 1)
+    }
+
+    /// A new type variable that only an integer type can solve
+    fn fresh_int_var(&mut self) -> TypeInfo {
+        self.int_vars.insert(self.next_var);
+        self.fresh_var()
+    }
+
+    /// Whether `ty` is an integer type, or the yet unsolved type of an
+    /// integer literal
+    fn is_integer(&self, ty: &TypeInfo) -> bool {
+        match self.apply(ty) {
+            TypeInfo::Int(_) => true,
+            TypeInfo::Variable(var) => self.int_vars.contains(&var),
+            _ => false,
+        }
+    }
+
+    /// The type of a literal. An integer literal's type is inferred from
+    /// its uses, see `fresh_int_var`.
+    fn literal_type(&mut self, lit: &Literal) -> TypeInfo {
+        match lit {
+            Literal::Int(_) => self.fresh_int_var(),
+            Literal::Float(_) => TypeInfo::Float,
+            Literal::String(_) => TypeInfo::String,
+            Literal::Bool(_) => TypeInfo::Bool,
+            Literal::Char(_) => TypeInfo::Char,
+            // `&[u8; N]`
+            Literal::Bytes(bytes) => TypeInfo::Reference(
+                false,
+                Box::new(TypeInfo::Array(
+                    Box::new(TypeInfo::Int(IntWidth::U8)),
+                    bytes.len(),
+                )),
+            ),
+        }
+    }
+
+    /// Give a generic function's type parameters fresh type variables, so
+    /// that each use of it is inferred on its own. Type arguments written
+    /// on the `path`, as in `g::<bool>`, are unified with their variables.
+    fn instantiate(
+        &mut self,
+        function: Symbol,
+        ty: &TypeInfo,
+        path: &Path,
+    ) -> Result<TypeInfo, NovaError> {
+        let names = self.generics.get(&function).cloned().unwrap_or_default();
+        let type_args = &path.segments[path.segments.len() - 1].generics;
+        if !type_args.is_empty() && type_args.len() != names.len() {
+            return Err(NovaError::Custom {
+                message: format!(
+                    "`{}` takes {} type arguments, but {} were given",
+                    function,
+                    names.len(),
+                    type_args.len()
+                ),
+                span: path.span,
+            });
+        }
+        let mut vars = HashMap::new();
+        for (i, name) in names.into_iter().enumerate() {
+            let var = self.fresh_var();
+            if let Some(arg) = type_args.get(i) {
+                let arg_ty = self.resolve_type(arg)?;
+                self.unify(&var, &arg_ty, arg.span)?;
+            }
+            vars.insert(name, var);
+        }
+        Ok(map_type(ty, &|t| match t {
+            TypeInfo::Named(name) => vars.get(name).cloned(),
+            _ => None,
+        }))
+    }
+
+    /// The canonical form of a type with what is solved so far
+    fn apply(&self, ty: &TypeInfo) -> TypeInfo {
+        ty.resolve_fully(&self.substitution)
+    }
+
+    /// `apply`, with the variables still unsolved made `Unknown`, except
+    /// that an integer literal nothing constrained gets the default integer
+    /// type
+    fn solved(&self, ty: &TypeInfo) -> TypeInfo {
+        let int = TypeInfo::Int(self.options.default_int.width());
+        map_type(&self.apply(ty), &|t| match t {
+            TypeInfo::Variable(var) if self.int_vars.contains(var) => Some(int.clone()),
+            TypeInfo::Variable(_) => Some(TypeInfo::Unknown),
+            _ => None,
+        })
+    }
+
+    /// How a type is written in a diagnostic, with an unsolved integer
+    /// literal type shown as the default integer type
+    fn shown(&self, ty: &TypeInfo) -> String {
+        let int = TypeInfo::Int(self.options.default_int.width());
+        let ty = map_type(&self.apply(ty), &|t| match t {
+            TypeInfo::Variable(var) if self.int_vars.contains(var) => Some(int.clone()),
+            _ => None,
+        });
+        format!("{:?}", ty)
+    }
+
+    /// Check that a value of type `found` may be used where `expected` is.
+    ///
+    /// The types are unified, except that `&mut T` coerces to `&T` (but not
+    /// the other way around) at the top level.
+    fn coerce(
+        &mut self,
+        expected: &TypeInfo,
+        found: &TypeInfo,
+        span: Span,
+    ) -> Result<(), NovaError> {
+        let expected = self.apply(expected);
+        let found = self.apply(found);
+        match (&expected, &found) {
+            (TypeInfo::Reference(false, e), TypeInfo::Reference(true, f)) => self
+                .unify(e, f, span)
+                .map_err(|err| self.mismatch_of(err, &expected, &found)),
+            (TypeInfo::Reference(true, _), TypeInfo::Reference(false, _)) => {
+                Err(NovaError::MutabilityMismatch {
+                    expected: self.shown(&expected),
+                    found: self.shown(&found),
+                    span,
+                })
+            }
+            _ => self.unify(&expected, &found, span),
+        }
+    }
+
+    /// The type of a value that comes from one of two branches.
+    ///
+    /// `!` is the bottom type: a branch that diverges never produces a
+    /// value, so the other branch's type is the result (and `!` if both
+    /// diverge). Otherwise the branches' types are unified.
+    fn join(
+        &mut self,
+        first: &TypeInfo,
+        second: &TypeInfo,
+        span: Span,
+    ) -> Result<TypeInfo, NovaError> {
+        match (self.apply(first), self.apply(second)) {
+            (TypeInfo::Never, _) => Ok(second.clone()),
+            (_, TypeInfo::Never) => Ok(first.clone()),
+            _ => {
+                self.unify(first, second, span)?;
+                Ok(first.clone())
+            }
+        }
+    }
+
+    /// Make two types equal, solving type variables on the way.
+    ///
+    /// Anything not yet fully known, such as an `Unknown` or a trait bound,
+    /// unifies with any type, and so does a diverging `found`. The type of
+    /// an integer literal only unifies with integer types. A mismatch
+    /// anywhere inside is reported for the whole types. A variable can't be
+    /// solved with a type containing it (`let x; x = &x;`): no type is
+    /// equal to a reference to itself.
+    fn unify(
+        &mut self,
+        expected: &TypeInfo,
+        found: &TypeInfo,
+        span: Span,
+    ) -> Result<(), NovaError> {
+        self.unify_parts(expected, found, span)
+            .map_err(|err| self.mismatch_of(err, expected, found))
+    }
+
+    /// `unify`, reporting a mismatch for just the parts that differ
+    fn unify_parts(
+        &mut self,
+        expected: &TypeInfo,
+        found: &TypeInfo,
+        span: Span,
+    ) -> Result<(), NovaError> {
+        use TypeInfo::*;
+        let expected = self.apply(expected);
+        let found = self.apply(found);
+        match (&expected, &found) {
+            (Variable(a), Variable(b)) if a == b => Ok(()),
+            (Unknown | ImplTrait(_) | DynTrait(_), _)
+            | (_, Unknown | ImplTrait(_) | DynTrait(_) | Never) => Ok(()),
+            (Variable(var), ty) | (ty, Variable(var)) => {
+                if contains_var(ty, *var) {
+                    return Err(NovaError::InfiniteType {
+                        ty: self.shown(ty),
+                        span,
+                    });
+                }
+                if self.int_vars.contains(var) {
+                    match ty {
+                        Int(_) => {}
+                        Variable(other) => {
+                            self.int_vars.insert(*other);
+                        }
+                        _ => {
+                            return Err(NovaError::TypeMismatch {
+                                expected: self.shown(&expected),
+                                found: self.shown(&found),
+                                span,
+                            })
+                        }
+                    }
+                }
+                self.substitution.insert(*var, ty.clone());
+                Ok(())
+            }
+            (Reference(m1, a), Reference(m2, b)) if m1 == m2 => self.unify_parts(a, b, span),
+            (Array(a, n), Array(b, m)) if n == m => self.unify_parts(a, b, span),
+            (Slice(a), Slice(b)) | (Range(a), Range(b)) => self.unify_parts(a, b, span),
+            (Tuple(a), Tuple(b)) if a.len() == b.len() => {
+                for (a, b) in a.iter().zip(b) {
+                    self.unify_parts(a, b, span)?;
+                }
+                Ok(())
+            }
+            (Function(p1, r1), Function(p2, r2)) if p1.len() == p2.len() => {
+                for (a, b) in p1.iter().zip(p2) {
+                    self.unify_parts(a, b, span)?;
+                }
+                self.unify_parts(r1, r2, span)
+            }
+            _ if expected == found => Ok(()),
+            _ => Err(NovaError::TypeMismatch {
+                expected: self.shown(&expected),
+                found: self.shown(&found),
+                span,
+            }),
+        }
+    }
+
+    /// Widen a mismatch found inside two types to the whole types
+    fn mismatch_of(&self, err: NovaError, expected: &TypeInfo, found: &TypeInfo) -> NovaError {
+        match err {
+            NovaError::TypeMismatch { span, .. } => NovaError::TypeMismatch {
+                expected: self.shown(expected),
+                found: self.shown(found),
+                span,
+            },
+            err => err,
+        }
+    }
+
+    /// Bind the names in an irrefutable pattern, a `let`'s or a
+    /// parameter's, each with its part of `ty`.
+    ///
+    /// A tuple pattern makes `ty` a tuple of as many elements. Patterns
+    /// that could fail to match bind as they would in a `match` arm.
+    fn bind_pattern(&mut self, pattern: &Pattern, ty: &TypeInfo) -> Result<(), NovaError> {
+        match &pattern.kind {
+            PatternKind::Ident(ident, mutable) => {
+                self.env.push((ident.name, ty.clone(), (*mutable).into()));
+                Ok(())
+            }
+            PatternKind::Wildcard => Ok(()),
+            PatternKind::Tuple(elems)
+                if !elems.iter().any(|e| matches!(e.kind, PatternKind::Rest(_))) =>
+            {
+                let elem_tys: Vec<_> = elems.iter().map(|_| self.fresh_var()).collect();
+                self.coerce(&TypeInfo::Tuple(elem_tys.clone()), ty, pattern.span)
+                    .map_err(|err| match err {
+                        // The elements are unsolved, so only the shape differs
+                        NovaError::TypeMismatch { span, .. } => NovaError::TypeMismatch {
+                            expected: format!("a tuple of {} elements", elems.len()),
+                            found: self.shown(ty),
+                            span,
+                        },
+                        err => err,
+                    })?;
+                for (elem, elem_ty) in elems.iter().zip(&elem_tys) {
+                    self.bind_pattern(elem, elem_ty)?;
+                }
+                Ok(())
+            }
+            _ => self.check_pattern(pattern, ty),
+        }
+    }
+
+    /// Check a pattern against the type it matches, binding its names.
+    fn check_pattern(&mut self, pattern: &Pattern, expected: &TypeInfo) -> Result<(), NovaError> {
+        match &pattern.kind {
+            PatternKind::Ident(ident, mutable) => match self.constructor(ident.name) {
+                // A bare unit variant like `None` matches instead of binding
+                Some(ctor) => self.check_constructor_pattern(
+                    &ctor,
+                    ident.name.as_str(),
+                    &[],
+                    pattern,
+                    expected,
+                ),
+                None => {
+                    self.env
+                        .push((ident.name, expected.clone(), (*mutable).into()));
+                    Ok(())
+                }
+            },
+            PatternKind::Path(path) | PatternKind::TupleStruct(path, _) => {
+                let fields = match &pattern.kind {
+                    PatternKind::TupleStruct(_, fields) => fields.as_slice(),
+                    _ => &[],
+                };
+                let name = path_symbol(path);
+                match self.constructor(name) {
+                    Some(ctor) => self.check_constructor_pattern(
+                        &ctor,
+                        name.as_str(),
+                        fields,
+                        pattern,
+                        expected,
+                    ),
+                    None => {
+                        // Unknown constructors (e.g. library types that aren't
+                        // declared yet) still bind their sub-patterns
+                        for field in fields {
+                            self.check_pattern(field, &TypeInfo::Unknown)?;
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            PatternKind::Struct(_, fields) => {
+                // TODO: Check field names and types once struct fields are recorded
+                for field in fields {
+                    match &field.pattern {
+                        Some(sub) => self.check_pattern(sub, &TypeInfo::Unknown)?,
+                        None => self
+                            .env
+                            .push((field.name.name, TypeInfo::Unknown, Assignable::No)),
+                    }
+                }
+                Ok(())
+            }
+            PatternKind::Tuple(elems) => {
+                for (i, elem) in elems.iter().enumerate() {
+                    let elem_ty = match expected {
+                        TypeInfo::Tuple(tys) if tys.len() == elems.len() => tys[i].clone(),
+                        _ => TypeInfo::Unknown,
+                    };
+                    self.check_pattern(elem, &elem_ty)?;
+                }
+                Ok(())
+            }
+            PatternKind::Slice(elems) => {
+                let elem_ty = match peel_refs(expected) {
+                    TypeInfo::Array(elem, _) | TypeInfo::Slice(elem) => (**elem).clone(),
+                    _ => TypeInfo::Unknown,
+                };
+                for elem in elems {
+                    match &elem.kind {
+                        // `tail @ ..` binds the elements the others don't match
+                        PatternKind::Rest(Some(name)) => self.env.push((
+                            name.name,
+                            TypeInfo::Slice(Box::new(elem_ty.clone())),
+                            Assignable::No,
+                        )),
+                        _ => self.check_pattern(elem, &elem_ty)?,
+                    }
+                }
+                Ok(())
+            }
+            PatternKind::Or(alternatives) => {
+                for alt in alternatives {
+                    self.check_pattern(alt, expected)?;
+                }
+                Ok(())
+            }
+            // `"on"` matches a `&str` scrutinee as well as a `String`
+            PatternKind::Literal(lit) => {
+                let ty = self.literal_type(lit);
+                self.coerce(peel_refs(expected), &ty, pattern.span)
+            }
+            PatternKind::Range(lo, hi, _) => {
+                for bound in [lo, hi].into_iter().flatten() {
+                    self.check_pattern(bound, expected)?;
+                }
+                match int_range(pattern) {
+                    Some((lo, hi)) if lo > hi => Err(NovaError::Custom {
+                        message: "Range pattern is empty: its lower bound exceeds its upper bound"
+                            .to_string(),
+                        span: pattern.span,
+                    }),
+                    _ => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check a constructor pattern: the constructor must build the matched
+    /// type, and each sub-pattern binds to the matching payload field.
+    fn check_constructor_pattern(
+        &mut self,
+        ctor: &Constructor,
+        name: &str,
+        fields: &[Pattern],
+        pattern: &Pattern,
+        expected: &TypeInfo,
+    ) -> Result<(), NovaError> {
+        let expected = &self.apply(expected);
+        if matches!(expected, TypeInfo::Named(_)) && *expected != ctor.ty {
+            return Err(NovaError::TypeMismatch {
+                expected: format!("{:?}", expected),
+                found: format!("{:?}", ctor.ty),
+                span: pattern.span,
+            });
+        }
+        if fields.len() != ctor.fields.len() {
+            return Err(NovaError::WrongPatternArity {
+                constructor: name.to_string(),
+                expected: ctor.fields.len(),
+                found: fields.len(),
+                span: pattern.span,
+            });
+        }
+        for (field, ty) in fields.iter().zip(&ctor.fields) {
+            self.check_pattern(field, ty)?;
+        }
+        Ok(())
+    }
+
+    /// Check that `target` names a place that may be assigned: a `mut`
+    /// binding or `static mut`, or a place reached through `&mut`.
+    fn check_assignable(&mut self, target: &Expr) -> Result<(), NovaError> {
+        let immutable = |name: String| NovaError::AssignToImmutable {
+            name,
+            span: target.span,
+        };
+        match &target.kind {
+            ExprKind::Path(path) => {
+                let name = path_symbol(path);
+                let local = match path.segments.len() {
+                    1 => self.env.iter().rposition(|(n, _, _)| *n == name),
+                    _ => None,
+                };
+                let assignable = match local {
+                    Some(i) => match self.env[i].2 {
+                        Assignable::Yes => true,
+                        Assignable::No => false,
+                        // The deferred initialization, unless a loop could repeat it
+                        Assignable::Once => {
+                            let in_loop = self.loops.last().is_some_and(|l| i < l.scope);
+                            self.env[i].2 = Assignable::No;
+                            !in_loop
+                        }
+                    },
+                    // Constants never change; unknown names are reported elsewhere
+                    None => {
+                        !self.consts.contains_key(&name) || self.mutable_statics.contains(&name)
+                    }
+                };
+                if assignable {
+                    Ok(())
+                } else {
+                    Err(immutable(name.to_string()))
+                }
+            }
+            ExprKind::Deref(inner) => match self.check_expr(inner)?.ty {
+                TypeInfo::Reference(false, _) => Err(immutable(format!("*{}", place_name(inner)))),
+                _ => Ok(()),
+            },
+            ExprKind::Field(base, _) | ExprKind::Index(base, _) => {
+                match self.check_expr(base)?.ty {
+                    TypeInfo::Reference(true, _) => Ok(()),
+                    TypeInfo::Reference(false, _) => Err(immutable(place_name(base))),
+                    _ => self.check_assignable(base),
+                }
+            }
+            // TODO: Reject assigning to temporaries like `f() = 1`
+            _ => Ok(()),
+        }
+    }
+
+    /// The `env` indices of the deferred bindings not initialized yet
+    fn uninitialized(&self) -> Vec<usize> {
+        let env = self.env.iter().enumerate();
+        env.filter(|(_, (_, _, assignable))| *assignable == Assignable::Once)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The bindings among `pending` that the branch just checked
+    /// initialized. They become uninitialized again, for the branches
+    /// that are its alternatives; `mark_initialized` ends that.
+    fn initialized_by_branch(&mut self, pending: &[usize]) -> Vec<usize> {
+        let initialized: Vec<usize> = pending
+            .iter()
+            .copied()
+            .filter(|&i| self.env[i].2 == Assignable::No)
+            .collect();
+        for &i in &initialized {
+            self.env[i].2 = Assignable::Once;
+        }
+        initialized
+    }
+
+    /// Make the deferred bindings at `indices` initialized
+    fn mark_initialized(&mut self, indices: &[usize]) {
+        for &i in indices {
+            self.env[i].2 = Assignable::No;
+        }
+    }
+
     /// Get the name from a pattern
-    fn pattern_name(&self, pattern: &Pattern) -> String {
+    fn pattern_name(&self, pattern: &Pattern) -> Symbol {
         match &pattern.kind {
-            PatternKind::Ident(ident, _) => ident.name.clone(),
-            _ => "_".to_string(),
+            PatternKind::Ident(ident, _) => ident.name,
+            _ => Symbol::intern("_"),
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::lex;
-    use crate::parser::parse;
+/// A loop whose body is being checked
+#[derive(Debug)]
+struct LoopContext {
+    /// Whether `break` may carry a value (only in `loop`)
+    break_with_value: bool,
+    /// The type of the first `break`
+    break_ty: Option<TypeInfo>,
+    /// The length of `env` outside the loop. A deferred binding from
+    /// there can't be initialized in the loop, which may run twice.
+    scope: usize,
+}
 
-    #[test]
-    fn test_typecheck_simple() {
-        let source = "fn main() { let x: i32 = 42; }";
-        let tokens = lex(source).unwrap();
-        let ast = parse(source, tokens).unwrap();
-        let typed = check(&ast).unwrap();
-        assert_eq!(typed.items.len(), 1);
+/// Whether a binding in `env` may be assigned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assignable {
+    No,
+    Yes,
+    /// Declared without a value (`let x;`), and not initialized yet
+    Once,
+}
+
+impl From<bool> for Assignable {
+    fn from(mutable: bool) -> Self {
+        if mutable {
+            Assignable::Yes
+        } else {
+            Assignable::No
+        }
+    }
+}
+
+/// A tuple-struct or enum-variant constructor
+#[derive(Debug, Clone)]
+struct Constructor {
+    /// The type the constructor builds
+    ty: TypeInfo,
+    /// Positional payload types (empty for unit constructors)
+    fields: Vec<TypeInfo>,
+}
+
+/// Whether any of `tys` stores the type `target` inline, directly or
+/// through the fields of other local types
+fn contains_by_value(
+    contents: &HashMap<String, (Vec<TypeInfo>, Span)>,
+    tys: &[TypeInfo],
+    target: &str,
+    visited: &mut Vec<String>,
+) -> bool {
+    tys.iter().any(|ty| match ty {
+        TypeInfo::Named(name) if name == target => true,
+        TypeInfo::Named(name) => {
+            if visited.contains(name) {
+                return false;
+            }
+            visited.push(name.clone());
+            match contents.get(name) {
+                Some((inner, _)) => contains_by_value(contents, inner, target, visited),
+                None => false,
+            }
+        }
+        TypeInfo::Tuple(elems) => contains_by_value(contents, elems, target, visited),
+        TypeInfo::Array(elem, _) => contains_by_value(
+            contents,
+            std::slice::from_ref(elem.as_ref()),
+            target,
+            visited,
+        ),
+        // References, functions and trait objects don't store the value inline
+        _ => false,
+    })
+}
+
+/// Whether `?` may propagate out of a function returning `ty`
+fn is_fallible(ty: &TypeInfo) -> bool {
+    match ty {
+        TypeInfo::Named(name) => name == "Result" || name == "Option",
+        TypeInfo::Unknown => true,
+        _ => false,
+    }
+}
+
+/// The error for an expression kind the checker can't type yet
+fn unsupported(what: &'static str, span: Span) -> NovaError {
+    NovaError::UnsupportedExpression { what, span }
+}
+
+/// The built-in functions, which lower to WASM instructions instead of calls
+///
+/// `memory_size()` is the size of linear memory in pages, and
+/// `memory_grow(pages)` adds pages, giving the old size or -1 on failure.
+/// Both count pages in an `i32`, the type WASM's instructions take.
+fn intrinsics() -> [(&'static str, TypeInfo); 2] {
+    let int = TypeInfo::Int(IntWidth::I32);
+    [
+        (
+            "memory_size",
+            TypeInfo::Function(vec![], Box::new(int.clone())),
+        ),
+        (
+            "memory_grow",
+            TypeInfo::Function(vec![int.clone()], Box::new(int)),
+        ),
+    ]
+}
+
+/// `ty` with any references removed
+fn peel_refs(mut ty: &TypeInfo) -> &TypeInfo {
+    while let TypeInfo::Reference(_, inner) = ty {
+        ty = inner;
+    }
+    ty
+}
+
+/// Reject two unguarded arms whose integer ranges share a value
+fn check_range_overlap(arms: &[MatchArm]) -> Result<(), NovaError> {
+    let ranges: Vec<_> = arms
+        .iter()
+        .filter(|arm| arm.guard.is_none())
+        .filter_map(|arm| Some((int_range(&arm.pattern)?, arm.pattern.span)))
+        .collect();
+    for (i, &((lo, hi), second)) in ranges.iter().enumerate() {
+        if let Some(&(_, first)) = ranges[..i]
+            .iter()
+            .find(|((other_lo, other_hi), _)| lo <= *other_hi && *other_lo <= hi)
+        {
+            return Err(NovaError::OverlappingPatterns { first, second });
+        }
+    }
+    Ok(())
+}
+
+/// The values an integer range pattern matches, as inclusive bounds
+fn int_range(pattern: &Pattern) -> Option<(i128, i128)> {
+    let PatternKind::Range(lo, hi, inclusive) = &pattern.kind else {
+        return None;
+    };
+    let bound = |p: &Option<Box<Pattern>>| match p.as_deref().map(|p| &p.kind) {
+        Some(PatternKind::Literal(Literal::Int(n))) => Some(Some(*n)),
+        None => Some(None),
+        _ => None,
+    };
+    let lo = bound(lo)?.unwrap_or(i128::MIN);
+    let hi = match bound(hi)? {
+        Some(hi) if *inclusive => hi,
+        Some(hi) => hi.checked_sub(1)?,
+        None => i128::MAX,
+    };
+    Some((lo, hi))
+}
+
+/// The element type of an array or slice, or a reference to one
+fn element_type(ty: &TypeInfo) -> Option<TypeInfo> {
+    match ty {
+        TypeInfo::Array(elem, _) | TypeInfo::Slice(elem) => Some((**elem).clone()),
+        TypeInfo::Reference(_, inner) => element_type(inner),
+        _ => None,
+    }
+}
+
+/// Whether a pattern binds its name with `mut`
+fn pattern_is_mut(pattern: &Pattern) -> bool {
+    matches!(pattern.kind, PatternKind::Ident(_, true))
+}
+
+/// A short description of an assigned place, for errors (`p`, `p.x`, `*r`)
+fn place_name(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Path(path) => path_symbol(path).to_string(),
+        ExprKind::Field(base, field) => format!("{}.{}", place_name(base), field.name),
+        ExprKind::Index(base, _) => format!("{}[..]", place_name(base)),
+        ExprKind::Deref(inner) => format!("*{}", place_name(inner)),
+        _ => "expression".to_string(),
+    }
+}
+
+/// The layout an item's `#[repr]` attributes ask for. `packed` wins over
+/// `C`, which it implies the field order of.
+fn check_repr(attrs: &[Attribute]) -> Result<Repr, NovaError> {
+    let mut repr = Repr::Default;
+    for attr in attrs {
+        let AttributeKind::Repr(hints) = &attr.kind else {
+            continue;
+        };
+        for hint in hints {
+            repr = match hint.name.as_str() {
+                "C" if repr == Repr::Default => Repr::C,
+                "C" => repr,
+                "packed" => Repr::Packed,
+                name => {
+                    return Err(NovaError::UnknownRepr {
+                        name: name.to_string(),
+                        span: hint.span,
+                    })
+                }
+            };
+        }
+    }
+    Ok(repr)
+}
+
+/// Evaluate an enum discriminant or array length, a constant integer
+fn const_discriminant(expr: &Expr) -> Result<i64, NovaError> {
+    match const_eval(expr) {
+        Some(ConstValue::Int(n)) => {
+            i64::try_from(n).map_err(|_| NovaError::NonConstDiscriminant { span: expr.span })
+        }
+        _ => Err(NovaError::NonConstDiscriminant { span: expr.span }),
+    }
+}
+
+/// A number known at compile time
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConstValue {
+    Int(i128),
+    Float(f64),
+}
+
+impl ConstValue {
+    fn literal(self) -> Literal {
+        match self {
+            ConstValue::Int(n) => Literal::Int(n),
+            ConstValue::Float(n) => Literal::Float(n),
+        }
+    }
+}
+
+/// Evaluate a constant expression: number literals, integer arithmetic
+/// and `as`.
+///
+/// Overflow, division by zero and casts that can't be done at compile time
+/// aren't constants, so give `None` like any other expression.
+fn const_eval(expr: &Expr) -> Option<ConstValue> {
+    match &expr.kind {
+        ExprKind::Literal(Literal::Int(n)) => Some(ConstValue::Int(*n)),
+        ExprKind::Literal(Literal::Float(n)) => Some(ConstValue::Float(*n)),
+        ExprKind::Unary(UnaryOp::Neg, inner) => match const_eval(inner)? {
+            ConstValue::Int(n) => n.checked_neg().map(ConstValue::Int),
+            ConstValue::Float(n) => Some(ConstValue::Float(-n)),
+        },
+        ExprKind::Unary(UnaryOp::BitNot, inner) => match const_eval(inner)? {
+            ConstValue::Int(n) => Some(ConstValue::Int(!n)),
+            ConstValue::Float(_) => None,
+        },
+        ExprKind::Binary(left, op, right) => match (const_eval(left)?, const_eval(right)?) {
+            (ConstValue::Int(l), ConstValue::Int(r)) => match op {
+                BinOp::Add => l.checked_add(r),
+                BinOp::Sub => l.checked_sub(r),
+                BinOp::Mul => l.checked_mul(r),
+                BinOp::Div => l.checked_div(r),
+                BinOp::Rem => l.checked_rem(r),
+                BinOp::BitAnd => Some(l & r),
+                BinOp::BitOr => Some(l | r),
+                BinOp::BitXor => Some(l ^ r),
+                BinOp::Shl => u32::try_from(r).ok().and_then(|r| l.checked_shl(r)),
+                BinOp::Shr => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+                _ => None,
+            }
+            .map(ConstValue::Int),
+            // TODO: Float arithmetic, once it's decided how to round
+            _ => None,
+        },
+        ExprKind::Cast(inner, ty) => {
+            let TypeKind::Path(path) = &ty.kind else {
+                return None;
+            };
+            let [segment] = &path.segments[..] else {
+                return None;
+            };
+            const_cast(const_eval(inner)?, segment.ident.name.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// Cast a constant to the primitive type `to` as `as` would at runtime:
+/// integers wrap to the target's width, floats saturate at its bounds.
+///
+/// `u128` targets only take values an `i128` can hold.
+fn const_cast(value: ConstValue, to: &str) -> Option<ConstValue> {
+    let (min, max) = match to {
+        "i8" => (i8::MIN as i128, i8::MAX as i128),
+        "i16" => (i16::MIN as i128, i16::MAX as i128),
+        "i32" => (i32::MIN as i128, i32::MAX as i128),
+        "i64" | "isize" => (i64::MIN as i128, i64::MAX as i128),
+        "i128" => (i128::MIN, i128::MAX),
+        "u8" => (0, u8::MAX as i128),
+        "u16" => (0, u16::MAX as i128),
+        "u32" => (0, u32::MAX as i128),
+        "u64" | "usize" => (0, u64::MAX as i128),
+        "u128" => (0, i128::MAX),
+        "f32" => {
+            return Some(ConstValue::Float(match value {
+                ConstValue::Int(n) => n as f32 as f64,
+                ConstValue::Float(n) => n as f32 as f64,
+            }))
+        }
+        "f64" => {
+            return Some(ConstValue::Float(match value {
+                ConstValue::Int(n) => n as f64,
+                ConstValue::Float(n) => n,
+            }))
+        }
+        _ => return None,
+    };
+    let n = match value {
+        // Keep the low bits, sign-extending for signed targets
+        ConstValue::Int(n) => match to {
+            "i8" => n as i8 as i128,
+            "i16" => n as i16 as i128,
+            "i32" => n as i32 as i128,
+            "i64" | "isize" => n as i64 as i128,
+            "u8" => n as u8 as i128,
+            "u16" => n as u16 as i128,
+            "u32" => n as u32 as i128,
+            "u64" | "usize" => n as u64 as i128,
+            "u128" if n < 0 => return None,
+            _ => n,
+        },
+        ConstValue::Float(n) if n.is_nan() => 0,
+        ConstValue::Float(n) => n.trunc().clamp(min as f64, max as f64) as i128,
+    };
+    Some(ConstValue::Int(n))
+}
+
+/// The variant names that more than one enum uses, given each enum's
+pub(crate) fn shared_variant_names<I>(enums: impl Iterator<Item = I>) -> HashSet<Symbol>
+where
+    I: IntoIterator<Item = Symbol>,
+{
+    let mut seen = HashSet::new();
+    let mut shared = HashSet::new();
+    for names in enums {
+        // A name repeated within one enum is a different error
+        let names: HashSet<Symbol> = names.into_iter().collect();
+        for name in names {
+            if !seen.insert(name) {
+                shared.insert(name);
+            }
+        }
+    }
+    shared
+}
+
+/// Hash a value with the standard library's hasher
+fn fingerprint(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A fingerprint of a function's AST that doesn't change when it moves
+///
+/// The AST has no `Hash`, so this hashes the `Debug` output of a copy with
+/// every span made relative to the start of the function and its attributes.
+fn function_fingerprint(f: &Function) -> u64 {
+    let base = start_with_attrs(&f.attrs, f.span);
+    fingerprint(&format!("{:?}", rebased(f, base)))
+}
+
+/// Fill the `_` placeholders of an annotation from the value's type
+///
+/// `let r: &_ = &1.5;` gives `r` the type `&f64`.
+fn fill_inferred(annotated: TypeInfo, inferred: &TypeInfo) -> TypeInfo {
+    use TypeInfo::*;
+    match (annotated, inferred) {
+        (Unknown, _) => inferred.clone(),
+        (Reference(m, a), Reference(_, b)) => Reference(m, Box::new(fill_inferred(*a, b))),
+        (Array(a, n), Array(b, _)) => Array(Box::new(fill_inferred(*a, b)), n),
+        (Tuple(a), Tuple(b)) if a.len() == b.len() => Tuple(
+            a.into_iter()
+                .zip(b)
+                .map(|(a, b)| fill_inferred(a, b))
+                .collect(),
+        ),
+        (annotated, _) => annotated,
+    }
+}
+
+/// Find a `_` placeholder anywhere in a type annotation
+fn find_infer(ty: &Type) -> Option<Span> {
+    match &ty.kind {
+        TypeKind::Infer => Some(ty.span),
+        TypeKind::Path(path) => path
+            .segments
+            .iter()
+            .flat_map(|s| &s.generics)
+            .find_map(find_infer),
+        TypeKind::Tuple(types) | TypeKind::ImplTrait(types) | TypeKind::DynTrait(types) => {
+            types.iter().find_map(find_infer)
+        }
+        TypeKind::Array(inner, _) | TypeKind::Slice(inner) | TypeKind::Reference(_, inner) => {
+            find_infer(inner)
+        }
+        TypeKind::Fn(params, ret) => params.iter().chain(ret.as_deref()).find_map(find_infer),
+        TypeKind::Never | TypeKind::SelfType => None,
+    }
+}
+
+/// Rebuild a type, replacing each part that `replace` gives a new type for
+fn map_type(ty: &TypeInfo, replace: &impl Fn(&TypeInfo) -> Option<TypeInfo>) -> TypeInfo {
+    use TypeInfo::*;
+    if let Some(replaced) = replace(ty) {
+        return replaced;
+    }
+    let map = |t: &TypeInfo| Box::new(map_type(t, replace));
+    match ty {
+        Array(elem, len) => Array(map(elem), *len),
+        Slice(elem) => Slice(map(elem)),
+        Range(bound) => Range(map(bound)),
+        Tuple(elems) => Tuple(elems.iter().map(|t| map_type(t, replace)).collect()),
+        Function(params, ret) => Function(
+            params.iter().map(|t| map_type(t, replace)).collect(),
+            map(ret),
+        ),
+        Reference(mutable, inner) => Reference(*mutable, map(inner)),
+        _ => ty.clone(),
+    }
+}
+
+/// Whether type variable `var` appears in `ty`
+fn contains_var(ty: &TypeInfo, var: usize) -> bool {
+    use TypeInfo::*;
+    match ty {
+        Variable(v) => *v == var,
+        Array(inner, _) | Slice(inner) | Range(inner) | Reference(_, inner) => {
+            contains_var(inner, var)
+        }
+        Tuple(elems) => elems.iter().any(|t| contains_var(t, var)),
+        Function(params, ret) => {
+            params.iter().any(|t| contains_var(t, var)) || contains_var(ret, var)
+        }
+        _ => false,
+    }
+}
+
+/// Whether a value of this type could be called.
+///
+/// Unknown types pass, since function items don't have a type yet, and so
+/// do trait objects, which may be closures.
+fn is_callable(ty: &TypeInfo) -> bool {
+    match ty {
+        TypeInfo::Function(..)
+        | TypeInfo::ImplTrait(_)
+        | TypeInfo::DynTrait(_)
+        | TypeInfo::Never
+        | TypeInfo::Variable(_)
+        | TypeInfo::Unknown => true,
+        TypeInfo::Reference(_, inner) => is_callable(inner),
+        _ => false,
+    }
+}
+
+/// Intern a path's segments joined with `::` (e.g. `Color::Red`)
+pub(crate) fn path_symbol(path: &Path) -> Symbol {
+    if path.segments.len() == 1 {
+        return path.segments[0].ident.name;
+    }
+    let joined = path
+        .segments
+        .iter()
+        .map(|s| s.ident.name.as_str())
+        .collect::<Vec<_>>()
+        .join("::");
+    Symbol::intern(&joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_typecheck_simple() {
+        let source = "fn main() { let x: i32 = 42; }";
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check(&ast).unwrap();
+        assert_eq!(typed.items.len(), 1);
+    }
+
+    fn first_param_type(source: &str) -> TypeInfo {
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check(&ast).unwrap();
+        match &typed.items[0] {
+            TypedItem::Function(f) => f.params[0].1.clone(),
+            _ => panic!("Expected function"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_fn_type() {
+        let ty = first_param_type("fn apply(f: fn(i32) -> bool) { }");
+        assert_eq!(
+            ty,
+            TypeInfo::Function(vec![TypeInfo::Int(IntWidth::I32)], Box::new(TypeInfo::Bool))
+        );
+    }
+
+    #[test]
+    fn test_resolve_fn_type_without_return() {
+        let ty = first_param_type("fn apply(f: fn(i32, f64)) { }");
+        assert_eq!(
+            ty,
+            TypeInfo::Function(
+                vec![TypeInfo::Int(IntWidth::I32), TypeInfo::Float],
+                Box::new(TypeInfo::Unit)
+            )
+        );
+    }
+
+    #[test]
+    fn test_resolve_impl_trait_type() {
+        let ty = first_param_type("fn show(x: impl Display + Debug) { }");
+        assert_eq!(
+            ty,
+            TypeInfo::ImplTrait(vec!["Display".to_string(), "Debug".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_resolve_dyn_trait_type() {
+        let ty = first_param_type("fn show(x: &dyn fmt::Display) { }");
+        assert_eq!(
+            ty,
+            TypeInfo::Reference(
+                false,
+                Box::new(TypeInfo::DynTrait(vec!["fmt::Display".to_string()]))
+            )
+        );
+    }
+
+    fn let_types(source: &str) -> Vec<TypeInfo> {
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check(&ast).unwrap();
+        match &typed.items[0] {
+            TypedItem::Function(f) => f
+                .body
+                .stmts
+                .iter()
+                .filter_map(|s| match s {
+                    TypedStmt::Let { ty, .. } => Some(ty.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => panic!("Expected function"),
+        }
+    }
+
+    #[test]
+    fn test_unsafe_block_has_its_value_type() {
+        let tys = let_types("fn f() { let x = unsafe { 1.5 }; }");
+        assert_eq!(tys, vec![TypeInfo::Float]);
+    }
+
+    #[test]
+    fn test_if_operand_type_participates() {
+        let tys = let_types("fn f(c: bool) { let y = 1 + if c { 2 } else { 3 }; }");
+        assert_eq!(tys, vec![TypeInfo::Int(IntWidth::I64)]);
+    }
+
+    #[test]
+    fn test_block_like_lhs_type_participates() {
+        let tys = let_types("fn f(n: i32) { let y = match n { _ => 1.5 } * 2.0; }");
+        assert_eq!(tys, vec![TypeInfo::Float]);
+    }
+
+    #[test]
+    fn test_unify_infers_deferred_let_from_assignment() {
+        let tys = let_types("fn f() { let x; x = 1.5; let y = x + 2.0; }");
+        assert_eq!(tys, vec![TypeInfo::Float, TypeInfo::Float]);
+        // Never assigned, so nothing is known
+        assert_eq!(let_types("fn f() { let x; }"), vec![TypeInfo::Unknown]);
+    }
+
+    #[test]
+    fn test_generic_call_infers_type_arguments() {
+        let source = "fn f() { let a = id(true); let b = id(&1); } fn id<T>(x: T) -> T { x }";
+        let tys = let_types(source);
+        let int_ref = TypeInfo::Reference(false, Box::new(TypeInfo::Int(IntWidth::I64)));
+        assert_eq!(tys, vec![TypeInfo::Bool, int_ref]);
+        assert!(matches!(
+            check_source("fn f() { let a: bool = id(1); } fn id<T>(x: T) -> T { x }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        // Both arguments are the same `T`
+        assert!(matches!(
+            check_source("fn f() { pick(1, true); } fn pick<T>(a: T, b: T) -> T { a }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_turbofish_type_arguments_are_checked() {
+        let tys = let_types("fn f() { let a = id::<bool>(true); } fn id<T>(x: T) -> T { x }");
+        assert_eq!(tys, vec![TypeInfo::Bool]);
+        assert!(matches!(
+            check_source("fn f() -> i64 { g::<bool>(1) } fn g<T>(x: T) -> T { x }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check_source("fn f() { g::<i64, bool>(1); } fn g<T>(x: T) -> T { x }"),
+            Err(NovaError::Custom { .. })
+        ));
+    }
+
+    #[test]
+    fn test_closure_types_are_inferred() {
+        use TypeInfo::*;
+        let int_fn = Function(vec![Int(IntWidth::I64)], Box::new(Int(IntWidth::I64)));
+        assert_eq!(
+            let_types("fn f() { let f = |x| x + 1; }"),
+            vec![int_fn.clone()]
+        );
+        // A call solves what the body leaves open
+        let tys = let_types("fn f() { let id = |x| x; let y = id(true); }");
+        assert_eq!(tys, vec![Function(vec![Bool], Box::new(Bool)), Bool]);
+        let source = "fn f() { let f = |x| x + 1; f(true); }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_annotated_closure() {
+        use TypeInfo::*;
+        let tys = let_types("fn f() { let eq = |a: i64, b: i64| -> bool { a == b }; }");
+        let int = Int(IntWidth::I64);
+        assert_eq!(tys, vec![Function(vec![int.clone(), int], Box::new(Bool))]);
+        let source = "fn f() { let g = |a: bool| -> i64 { a }; }";
+        match check_source(source) {
+            Err(NovaError::TypeMismatch { span, .. }) => {
+                assert_eq!(&source[span.start() as usize..span.end() as usize], "{ a }");
+            }
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tuple_types() {
+        use TypeInfo::*;
+        let tys = let_types("fn f(a: i32) { let t = (a, 1.5, true); let u = (); }");
+        let t = Tuple(vec![Int(IntWidth::I32), Float, Bool]);
+        assert_eq!(tys, vec![t, Unit]);
+        assert!(check_source("fn swap(a: i32, b: i32) -> (i32, i32) { (b, a) }").is_ok());
+        assert!(check_source("fn f() -> () { () }").is_ok());
+        assert!(matches!(
+            check_source("fn f() { let t: (i64, bool) = (1, 2); }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_integer_literals_take_the_width_they_are_used_at() {
+        use IntWidth::*;
+        let tys = let_types("fn f() { let x: i32 = 1; let y = x + 2; let z = 3; }");
+        assert_eq!(
+            tys,
+            vec![TypeInfo::Int(I32), TypeInfo::Int(I32), TypeInfo::Int(I64)]
+        );
+        let tys = let_types("fn f(b: u8) { let n = 1; let m = b * n; }");
+        assert_eq!(tys, vec![TypeInfo::Int(U8), TypeInfo::Int(U8)]);
+        // Only an integer type solves an integer literal's type
+        assert!(matches!(
+            check_source("fn f() { let b: bool = 1; }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        // Widths don't mix
+        match check_source("fn f(a: i32, b: i64) { a + b; }") {
+            Err(NovaError::TypeMismatch {
+                expected, found, ..
+            }) => assert_eq!(
+                (expected.as_str(), found.as_str()),
+                ("Int(I32)", "Int(I64)")
+            ),
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+        // Unconstrained literals take the default integer type
+        let source = "fn f() { let z = 3; }";
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let options = CheckOptions {
+            default_int: DefaultInt::I32,
+        };
+        let program = check_with_options(&ast, &options).unwrap();
+        let TypedItem::Function(f) = &program.items[0] else {
+            panic!("Expected a function");
+        };
+        assert!(matches!(
+            &f.body.stmts[0],
+            TypedStmt::Let {
+                ty: TypeInfo::Int(I32),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_unify_rejects_mismatched_operands() {
+        let source = "fn f() { let x = 1; let y = x + 2.0; }";
+        match check_source(source) {
+            Err(NovaError::TypeMismatch {
+                expected,
+                found,
+                span,
+            }) => {
+                assert_eq!((expected.as_str(), found.as_str()), ("Int(I64)", "Float"));
+                assert_eq!(&source[span.start() as usize..span.end() as usize], "2.0");
+            }
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+        // A mismatch inside a type is reported for the whole type
+        match check_source("fn f(r: &bool) { let x: &i64 = r; }") {
+            Err(NovaError::TypeMismatch {
+                expected, found, ..
+            }) => assert_eq!(
+                (expected.as_str(), found.as_str()),
+                ("Reference(false, Int(I64))", "Reference(false, Bool)")
+            ),
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_if_branches_have_matching_types() {
+        let program = check_source("fn f(c: bool) -> i64 { if c { 1 } else { 2 } }").unwrap();
+        let TypedItem::Function(f) = &program.items[0] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(f.body.ty, TypeInfo::Int(IntWidth::I64));
+        // A diverging branch takes the other branch's type
+        assert!(check_source("fn f(c: bool) -> i64 { if c { return 0 } else { 2 } }").is_ok());
+
+        let source = "fn f(c: bool) { let x = if c { 1 } else { true }; }";
+        match check_source(source) {
+            Err(NovaError::TypeMismatch { span, .. }) => {
+                assert_eq!(
+                    &source[span.start() as usize..span.end() as usize],
+                    "{ true }"
+                );
+            }
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+        // Without an `else` the `then` branch must be `()`
+        assert!(matches!(
+            check_source("fn f(c: bool) { if c { 1 }; }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_if_with_one_diverging_branch_has_the_others_type() {
+        // `return;` in a block without a final expression diverges too
+        for source in [
+            "fn f(c: bool) { let x = if c { return; } else { 5 }; }",
+            "fn f(c: bool) { let x = if c { 5 } else { return; }; }",
+            "fn f(c: bool) { let x = if c { return } else if !c { 5 } else { loop {} }; }",
+        ] {
+            assert_eq!(
+                let_types(source),
+                [TypeInfo::Int(IntWidth::I64)],
+                "{source}"
+            );
+        }
+        assert!(matches!(
+            check_source("fn f(c: bool) { let x = if c { return; } else { 5 }; let b: bool = x; }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_if_with_both_branches_diverging_is_never() {
+        let source = "fn f(c: bool) -> i64 { let x = if c { return 1; } else { return 2; }; x }";
+        assert_eq!(let_types(source), [TypeInfo::Never]);
+        let source = "fn f(c: bool) -> i64 { if c { return 1; } else { return 2; } }";
+        let program = check_source(source).unwrap();
+        let TypedItem::Function(f) = &program.items[0] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(f.body.ty, TypeInfo::Never);
+        // A final expression after the `return` is still the block's value
+        let source = "fn f() -> i64 { let x = { return 1; 2 }; x }";
+        assert_eq!(let_types(source), [TypeInfo::Int(IntWidth::I64)]);
+    }
+
+    #[test]
+    fn test_if_condition_must_be_bool() {
+        let source = "fn f() { if 1 { } }";
+        match check_source(source) {
+            Err(NovaError::TypeMismatch {
+                expected,
+                found,
+                span,
+            }) => {
+                assert_eq!((expected.as_str(), found.as_str()), ("Bool", "Int(I64)"));
+                assert_eq!(&source[span.start() as usize..span.end() as usize], "1");
+            }
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_fully_canonicalizes_unified_variables() {
+        let mut checker = TypeChecker::new(CheckOptions::default());
+        let (a, b, c) = (
+            checker.fresh_var(),
+            checker.fresh_var(),
+            checker.fresh_var(),
+        );
+        let span = Span::new(0, 0);
+        let by_ref = |ty: &TypeInfo| TypeInfo::Reference(false, Box::new(ty.clone()));
+        let canonical = |t: &TypeChecker, ty: &TypeInfo| ty.resolve_fully(&t.substitution);
+
+        assert_ne!(canonical(&checker, &a), canonical(&checker, &b));
+        checker.unify(&a, &b, span).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(canonical(&checker, &a), canonical(&checker, &b));
+        assert_eq!(
+            canonical(&checker, &by_ref(&a)),
+            canonical(&checker, &by_ref(&b))
+        );
+        // `c` was never unified with either
+        assert_ne!(canonical(&checker, &a), canonical(&checker, &c));
+
+        // Solving one solves the other
+        checker
+            .unify(&b, &TypeInfo::Int(IntWidth::I64), span)
+            .unwrap();
+        assert_eq!(
+            canonical(&checker, &by_ref(&a)),
+            by_ref(&TypeInfo::Int(IntWidth::I64))
+        );
+    }
+
+    #[test]
+    fn test_unify_occurs_check() {
+        assert!(matches!(
+            check_source("fn f() { let x; x = &x; }"),
+            Err(NovaError::InfiniteType { .. })
+        ));
+    }
+
+    const OPTION: &str = "enum Option { Some(i64), None }";
+
+    #[test]
+    fn test_match_tuple_variant_binds_payload() {
+        let source = format!(
+            "fn f(o: Option) {{ let y = match o {{ Some(x) => x, None => 0 }}; }} {}",
+            OPTION
+        );
+        assert_eq!(let_types(&source), vec![TypeInfo::Int(IntWidth::I64)]);
+    }
+
+    #[test]
+    fn test_match_wrong_arity() {
+        let source = format!(
+            "fn f(o: Option) {{ let y = match o {{ Some(x, y) => x, None => 0 }}; }} {}",
+            OPTION
+        );
+        let tokens = lex(&source).unwrap();
+        let ast = parse(&source, tokens).unwrap();
+        match check(&ast) {
+            Err(NovaError::WrongPatternArity {
+                expected, found, ..
+            }) => {
+                assert_eq!(expected, 1);
+                assert_eq!(found, 2);
+            }
+            other => panic!("Expected WrongPatternArity, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_match_qualified_unit_variant() {
+        let source = "fn f(c: Color) { let y = match c { Color::Red => 1.0, _ => 0.0 }; } \
+                      enum Color { Red, Green }";
+        assert_eq!(let_types(source), vec![TypeInfo::Float]);
+    }
+
+    #[test]
+    fn test_match_tuple_struct_fields() {
+        let source = "fn f(p: Point) { let y = match p { Point(_, b) => b }; } \
+                      struct Point(i64, bool);";
+        assert_eq!(let_types(source), vec![TypeInfo::Bool]);
+    }
+
+    #[test]
+    fn test_match_constructor_of_other_type() {
+        let source = "fn f(c: Color) { let y = match c { Some(x) => x, _ => 0 }; } \
+                      enum Color { Red } enum Option { Some(i64) }";
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        assert!(matches!(check(&ast), Err(NovaError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_constructor_call_type() {
+        let source = format!("fn f() {{ let o = Some(1); let n = None; }} {}", OPTION);
+        let named = TypeInfo::Named("Option".to_string());
+        assert_eq!(let_types(&source), vec![named.clone(), named]);
+    }
+
+    #[test]
+    fn test_struct_and_impl_are_retained() {
+        let source = "struct Point { x: i64, y: f64 } \
+                      impl Point { fn origin_x() -> i64 { 0 } fn scale(k: f64) -> f64 { k } }";
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check(&ast).unwrap();
+        assert_eq!(typed.items.len(), 3);
+
+        match &typed.items[0] {
+            TypedItem::Struct(s) => {
+                assert_eq!(s.name, "Point");
+                assert_eq!(
+                    s.fields,
+                    vec![
+                        (Symbol::intern("x"), TypeInfo::Int(IntWidth::I64)),
+                        (Symbol::intern("y"), TypeInfo::Float)
+                    ]
+                );
+            }
+            other => panic!("Expected struct, got {:?}", other),
+        }
+        let methods: Vec<&str> = typed.items[1..]
+            .iter()
+            .map(|item| match item {
+                TypedItem::Function(f) => f.name.as_str(),
+                other => panic!("Expected method, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(methods, vec!["Point::origin_x", "Point::scale"]);
+    }
+
+    #[test]
+    fn test_enum_is_retained() {
+        let source = "enum Shape { Empty, Circle(f64), Rect { w: i64, h: i64 } }";
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check(&ast).unwrap();
+        match &typed.items[0] {
+            TypedItem::Enum(e) => {
+                assert_eq!(e.name, "Shape");
+                let arities: Vec<usize> = e.variants.iter().map(|v| v.fields.len()).collect();
+                assert_eq!(arities, vec![0, 1, 2]);
+                assert_eq!(
+                    e.variants[1].fields[0],
+                    (Symbol::intern("0"), TypeInfo::Float)
+                );
+            }
+            other => panic!("Expected enum, got {:?}", other),
+        }
+    }
+
+    fn discriminants(source: &str) -> Result<Vec<i64>, NovaError> {
+        match &check_source(source)?.items[0] {
+            TypedItem::Enum(e) => Ok(e.variants.iter().map(|v| v.discriminant).collect()),
+            other => panic!("Expected enum, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_enum_discriminants() {
+        let values = discriminants("enum E { A, B = 4, C, D = 1 << 4, E = (2 + 3) * 2 }").unwrap();
+        assert_eq!(values, [0, 4, 5, 16, 10]);
+    }
+
+    #[test]
+    fn test_duplicate_discriminant() {
+        // `C` is implicitly 2, like `A`
+        let result = discriminants("enum E { A = 2, B = 1, C }");
+        match result {
+            Err(NovaError::DuplicateDiscriminant { value, first, .. }) => {
+                assert_eq!((value, first.as_str()), (2, "A"));
+            }
+            other => panic!("Expected duplicate discriminant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_discriminants() {
+        assert!(matches!(
+            discriminants("enum E { A = -1 }"),
+            Err(NovaError::NegativeDiscriminant { value: -1, .. })
+        ));
+        for source in [
+            "enum E { A = x }",
+            "enum E { A = 1 / 0 }",
+            "enum E { A = true }",
+        ] {
+            assert!(
+                matches!(
+                    discriminants(source),
+                    Err(NovaError::NonConstDiscriminant { .. })
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_shared_variant_names_must_be_qualified() {
+        let enums = "enum A { X(i64) } enum B { X(bool), Y }";
+        let matching = |arm: &str| format!("{enums} fn f(a: A) {{ match a {{ {arm} }} }}");
+        // A bare `X` is neither enum's variant, so it's an unknown constructor
+        check_source(&matching("X(n) => { let m: i64 = n; }")).unwrap();
+        assert!(matches!(
+            check_source(&matching("A::X(n) => { let m: bool = n; }")),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        // `Y` belongs to `B` alone
+        assert!(matches!(
+            check_source(&matching("Y => {}")),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_type_aliases_resolve_to_their_target() {
+        check_source("type Km = i64; fn f() { let d: Km = 5; }").unwrap();
+        check_source(
+            "type Route = (Km, Flag); type Km = i64; type Flag = bool;
+            fn f(r: Route) { let t: (i64, bool) = r; }",
+        )
+        .unwrap();
+        assert!(matches!(
+            check_source("type Km = i64; fn f() { let d: Km = true; }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    fn check_source(source: &str) -> Result<TypedProgram, NovaError> {
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        check(&ast)
+    }
+
+    /// Check `source` against `cache`, returning how many functions it checked
+    fn recheck(source: &str, cache: &mut CheckCache) -> usize {
+        let tokens = lex(source).unwrap();
+        let ast = parse(source, tokens).unwrap();
+        let typed = check_incremental(&ast, &CheckOptions::default(), cache).unwrap();
+        let fresh = check(&ast).unwrap();
+        assert_eq!(format!("{:?}", typed.items), format!("{:?}", fresh.items));
+        cache.checked()
+    }
+
+    #[test]
+    fn test_incremental_rechecks_only_edited_function() {
+        let mut cache = CheckCache::default();
+        let before = "fn f() { 1; } fn g(x: i64) { x; } struct P; impl P { fn m(self) { self; } }";
+        assert_eq!(recheck(before, &mut cache), 3);
+        assert_eq!(recheck(before, &mut cache), 0);
+        // The edit moves `g` and `P::m`, which are still reused
+        let after = "fn f() { let y = 1.5; y; } fn g(x: i64) { x; } struct P; impl P { fn m(self) { self; } }";
+        assert_eq!(recheck(after, &mut cache), 1);
+        assert_eq!(recheck(before, &mut cache), 1);
+        // A program that doesn't check leaves the cache usable
+        let tokens = lex("fn f() { let x: bool = 1; }").unwrap();
+        let broken = parse("fn f() { let x: bool = 1; }", tokens).unwrap();
+        assert!(check_incremental(&broken, &CheckOptions::default(), &mut cache).is_err());
+        assert_eq!(recheck(before, &mut cache), 0);
+    }
+
+    #[test]
+    fn test_incremental_rechecks_when_dependency_changes() {
+        let mut cache = CheckCache::default();
+        let uses_variant = |payload: &str| {
+            format!("enum E {{ A({payload}) }} fn f(e: E) {{ match e {{ A(x) => x, }}; }} fn g() {{ 1; }}")
+        };
+        assert_eq!(recheck(&uses_variant("i64"), &mut cache), 2);
+        assert_eq!(recheck(&uses_variant("bool"), &mut cache), 1);
+        // A name that didn't resolve before is a dependency too
+        let before = "struct S; fn f() { S::K; }";
+        let after = "struct S; impl S { const K: i64 = 1; } fn f() { S::K; }";
+        assert_eq!(recheck(before, &mut cache), 1);
+        assert_eq!(recheck(after, &mut cache), 1);
+        // So is the signature of a function it calls
+        let calls = |ret: &str| format!("fn f() {{ g(); }} fn g() -> {ret} {{ loop {{}} }}");
+        assert_eq!(recheck(&calls("i64"), &mut cache), 2);
+        assert_eq!(recheck(&calls("bool"), &mut cache), 2);
+    }
+
+    #[test]
+    fn test_incremental_rechecks_when_named_type_changes() {
+        let mut cache = CheckCache::default();
+        let check_with = |source: &str, cache: &mut CheckCache| {
+            let ast = parse(source, lex(source).unwrap()).unwrap();
+            check_incremental(&ast, &CheckOptions::default(), cache).map(|_| cache.checked())
+        };
+        let aliased = |target: &str| format!("type T = {target}; fn f() {{ let t: T = 1; }}");
+        assert_eq!(check_with(&aliased("i64"), &mut cache).ok(), Some(1));
+        assert!(check_with(&aliased("bool"), &mut cache).is_err());
+        // The failed check left the first result cached
+        assert_eq!(check_with(&aliased("i64"), &mut cache).ok(), Some(0));
+        // A type declared in place of an unknown name
+        let source = "fn f(s: S) { let n: i64 = s; }";
+        assert!(check_with(source, &mut cache).is_err());
+        let source = "type S = i64; fn f(s: S) { let n: i64 = s; }";
+        assert_eq!(check_with(source, &mut cache).ok(), Some(1));
+    }
+
+    #[test]
+    fn test_inline_module_items_are_checked() {
+        let source = "mod m { fn g() { let x: i64 = true; } } fn f() {}";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        let source = "mod m { fn g() -> i64 { h() } fn h() -> i64 { 1 } } fn f() -> i64 { m::g() }";
+        let typed = check_source(source).unwrap();
+        let names: Vec<_> = typed
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TypedItem::Function(f) => Some(f.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["m::g", "m::h", "f"]);
+    }
+
+    #[test]
+    fn test_try_in_result_fn() {
+        let source = "fn f(r: Result<i32, String>) -> Result<i32, String> { let x = r?; r }";
+        assert!(check_source(source).is_ok());
+        let source = "fn f(o: Option<i32>) -> Option<i32> { o?; o }";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_try_in_non_result_fn() {
+        let source = "fn f(r: Result<i32, String>) -> i32 { let x = r?; x }";
+        match check_source(source) {
+            Err(NovaError::TryInNonResultFn { span, .. }) => {
+                let q = source.find('?').unwrap() as u32;
+                assert_eq!((span.start(), span.end()), (q, q + 1));
+            }
+            other => panic!("Expected TryInNonResultFn, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_assoc_const_is_registered() {
+        let source =
+            "struct P {} fn f() -> i64 { P::N } impl P { const N: i64 = 5; type Item = i64; }";
+        let typed = check_source(source).unwrap();
+        match &typed.items[0] {
+            TypedItem::Const(c) => {
+                assert_eq!(c.name, "P::N");
+                assert_eq!(c.ty, TypeInfo::Int(IntWidth::I64));
+            }
+            other => panic!("Expected const, got {:?}", other),
+        }
+        let f = typed
+            .items
+            .iter()
+            .find_map(|item| match item {
+                TypedItem::Function(f) if f.name == "f" => Some(f),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(f.body.ty, TypeInfo::Int(IntWidth::I64));
+    }
+
+    #[test]
+    fn test_assoc_const_type_mismatch() {
+        let source = "struct P {} impl P { const N: i64 = true; }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_self_type_resolves_to_impl_type() {
+        let source = "struct P {} impl P { fn clone(&self) -> Self { loop {} } }";
+        let typed = check_source(source).unwrap();
+        let clone = typed
+            .items
+            .iter()
+            .find_map(|item| match item {
+                TypedItem::Function(f) if f.name == "P::clone" => Some(f),
+                _ => None,
+            })
+            .unwrap();
+        let p = TypeInfo::Named("P".to_string());
+        assert_eq!(clone.return_type, p);
+        assert_eq!(
+            clone.params[0].1,
+            TypeInfo::Reference(false, Box::new(p.clone()))
+        );
+    }
+
+    #[test]
+    fn test_self_outside_impl() {
+        for source in ["fn f() -> Self { loop {} }", "fn f(x: &Self) {}"] {
+            assert!(matches!(
+                check_source(source),
+                Err(NovaError::SelfOutsideImpl { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_self_assoc_types_resolve_to_the_impls() {
+        let source =
+            "struct C(i64); impl C { type Item = bool; fn get(&self) -> Self::Item { true } }";
+        let typed = check_source(source).unwrap();
+        let get = typed
+            .items
+            .iter()
+            .find_map(|item| match item {
+                TypedItem::Function(f) if f.name == "C::get" => Some(f),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(get.return_type, TypeInfo::Bool);
+
+        // A trait method's `Self::Item` is the impl's
+        let iter = "trait Iter { type Item; fn next(&mut self) -> Self::Item; } struct C(i64);";
+        for method in [
+            "fn next(&mut self) -> i64 { 1 }",
+            "fn next(&mut self) -> Self::Item { 1 }",
+        ] {
+            let source = format!("{iter} impl Iter for C {{ type Item = i64; {method} }}");
+            assert!(check_source(&source).is_ok(), "{method}");
+        }
+        let source = format!(
+            "{iter} impl Iter for C {{ type Item = bool; fn next(&mut self) -> i64 {{ 1 }} }}"
+        );
+        assert!(matches!(
+            check_source(&source),
+            Err(NovaError::TraitMethodSignatureMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_byte_string_is_byte_array_ref() {
+        let types = stmt_types(r#"fn f() { br"a\b"; }"#);
+        let bytes = TypeInfo::Array(Box::new(TypeInfo::Int(IntWidth::U8)), 3);
+        assert_eq!(types, [TypeInfo::Reference(false, Box::new(bytes))]);
+    }
+
+    #[test]
+    fn test_call_non_callable() {
+        for source in [
+            "fn f() { let x = 5; x() }",
+            "fn f(s: String) { s(1) }",
+            "enum E { A } fn f() { A() }",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::NotCallable { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let source = "fn main() { return y; }";
+        match check_source(source) {
+            Err(err @ NovaError::UndefinedVariable { .. }) => {
+                assert_eq!(err.code(), "E0201");
+                assert_eq!(err.span(), Span::new(19, 20));
+            }
+            other => panic!("Expected an undefined variable, got {:?}", other),
+        }
+        // Out of scope once its block ends
+        assert!(matches!(
+            check_source("fn main() { { let y = 1; } y; }"),
+            Err(NovaError::UndefinedVariable { .. })
+        ));
+        // Functions, constructors and longer paths aren't variables
+        let source = "enum E { A } fn g() {} fn main() { g; A; m::y; }";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_local_is_not_visible_before_its_let() {
+        let source = "fn f() -> i64 { let x = y + 1; let y = 2; x }";
+        match check_source(source) {
+            Err(NovaError::UndefinedVariable { name, span }) => {
+                assert_eq!(name, "y");
+                assert_eq!(&source[span.start() as usize..span.end() as usize], "y");
+            }
+            other => panic!("Expected an undefined variable, got {:?}", other),
+        }
+        // Nor to its own initializer, or a closure written before it
+        for source in [
+            "fn f() { let x = x; }",
+            "fn f() { let g = || y; let y = 1; }",
+        ] {
+            assert!(
+                matches!(
+                    check_source(source),
+                    Err(NovaError::UndefinedVariable { .. })
+                ),
+                "{source}"
+            );
+        }
+        // A parameter of the same name is what the initializer sees
+        assert!(check_source("fn f(x: i64) -> i64 { let x = x + 1; x }").is_ok());
+        // Items can still be used before they're declared
+        assert!(check_source("fn f() -> i64 { g() } fn g() -> i64 { 1 }").is_ok());
+    }
+
+    #[test]
+    fn test_unknown_repr() {
+        let source = "#[repr(transparent)] struct S { x: i64 }";
+        match check_source(source) {
+            Err(err @ NovaError::UnknownRepr { .. }) => {
+                assert_eq!(
+                    err.message(),
+                    "Unknown repr `transparent`: expected `C` or `packed`"
+                );
+                assert_eq!(
+                    &source[err.span().start() as usize..err.span().end() as usize],
+                    "transparent"
+                );
+            }
+            other => panic!("Expected UnknownRepr, got {:?}", other),
+        }
+        assert!(matches!(
+            check_source("#[repr(u8)] enum E { A, B }"),
+            Err(NovaError::UnknownRepr { .. })
+        ));
+        assert!(check_source("#[repr(C, packed)] struct S { x: i64 }").is_ok());
+    }
+
+    #[test]
+    fn test_call_arity_mismatch() {
+        // `f` is defined after the call
+        let source = "fn main() { f(1, 2, 3); } fn f(b: bool) {}";
+        match check_source(source) {
+            Err(err @ NovaError::WrongArgCount { .. }) => {
+                assert_eq!(err.message(), "Function takes 1 argument, but 3 were given");
+                assert_eq!(
+                    &source[err.span().start() as usize..err.span().end() as usize],
+                    "f(1, 2, 3)"
+                );
+            }
+            other => panic!("Expected an argument count error, got {:?}", other),
+        }
+        for source in [
+            "fn main() { f(); } fn f(a: i64, b: i64) {}",
+            "fn f(g: &fn(i64)) { g(1, 2); }",
+            &format!("fn f() {{ Some(1, 2); }} {OPTION}"),
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::WrongArgCount { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_call_argument_type_mismatch() {
+        let source = "fn main() { f(true, 1); } fn f(a: bool, b: bool) {}";
+        match check_source(source) {
+            Err(NovaError::TypeMismatch {
+                expected,
+                found,
+                span,
+            }) => {
+                assert_eq!((expected.as_str(), found.as_str()), ("Bool", "Int(I64)"));
+                assert_eq!(span, Span::new(20, 21));
+            }
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_call_function_value() {
+        let types =
+            stmt_types("fn f(g: fn(i64) -> bool, h: &fn() -> i64) { g(1); h(); m::other(); }");
+        assert_eq!(
+            types,
+            [
+                TypeInfo::Bool,
+                TypeInfo::Int(IntWidth::I64),
+                TypeInfo::Unknown
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mut_ref_coerces_to_shared() {
+        let source = "fn g(r: &i64) {} fn f() { let mut x = 1; g(&mut x); let r: &i64 = &mut x; }";
+        assert!(check_source(source).is_ok());
+        // Same mutability is fine too
+        let source = "fn g(r: &mut i64) {} fn f(h: fn(&mut i64)) { let mut x = 1; h(&mut x); }";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_shared_ref_does_not_coerce_to_mut() {
+        for source in [
+            "fn f(h: fn(&mut i64)) { let x = 1; h(&x); }",
+            "fn f() { let x = 1; let r: &mut i64 = &x; }",
+        ] {
+            assert!(
+                matches!(
+                    check_source(source),
+                    Err(NovaError::MutabilityMismatch { .. })
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_coercion_is_top_level_only() {
+        // `&&mut T` is not `&&T`, and the referenced types must still match
+        for source in [
+            "fn f(h: fn(& &i64)) { let mut x = 1; h(& &mut x); }",
+            "fn f(h: fn(&bool)) { let mut x = 1; h(&mut x); }",
+            "fn f() { let x: bool = 1; }",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::TypeMismatch { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_infer_placeholder_in_let() {
+        let source = "fn f(xs: Vec<i64>) { let x: _ = 1; let v: Vec<_> = xs; let r: &_ = &1.5; }";
+        assert_eq!(
+            let_types(source),
+            [
+                TypeInfo::Int(IntWidth::I64),
+                TypeInfo::Named("Vec".to_string()),
+                TypeInfo::Reference(false, Box::new(TypeInfo::Float)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infer_placeholder_rejected_in_signature() {
+        for source in [
+            "fn f() -> _ { 1 }",
+            "fn f(x: _) {}",
+            "fn f(v: Vec<_>) {}",
+            "fn f(g: fn() -> &_) {}",
+        ] {
+            assert!(
+                matches!(
+                    check_source(source),
+                    Err(NovaError::InferInSignature { .. })
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    /// Types of the expression statements in the body of the first function
+    fn stmt_types(source: &str) -> Vec<TypeInfo> {
+        let typed = check_source(source).unwrap();
+        match &typed.items[0] {
+            TypedItem::Function(f) => f
+                .body
+                .stmts
+                .iter()
+                .filter_map(|s| match s {
+                    TypedStmt::Expr(e) => Some(e.ty.clone()),
+                    TypedStmt::Let { .. } => None,
+                })
+                .collect(),
+            _ => panic!("Expected function"),
+        }
+    }
+
+    #[test]
+    fn test_while_and_for_are_unit() {
+        let source = "fn f(n: i64) { while n > 0 { n; } for i in n { } }";
+        assert_eq!(stmt_types(source), vec![TypeInfo::Unit, TypeInfo::Unit]);
+    }
+
+    #[test]
+    fn test_loop_is_never_without_break() {
+        assert_eq!(stmt_types("fn f() { loop {} }"), vec![TypeInfo::Never]);
+    }
+
+    #[test]
+    fn test_body_and_returns_must_have_the_return_type() {
+        for source in [
+            "fn f() -> bool { 1 }",
+            "fn f() -> i64 { true }",
+            "fn f() { 1 }",
+            "fn f(c: bool) -> i64 { if c { return true; } 2 }",
+            "fn f() -> i64 { return; }",
+            "fn f() { return 1; }",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::TypeMismatch { .. })),
+                "{source}"
+            );
+        }
+        // `!` fits any return type
+        check_source(
+            "fn a() -> bool { return true; }
+            fn b() -> i64 { loop {} }
+            fn c() -> bool { if true { return false; } true }
+            fn d() { return; }",
+        )
+        .unwrap();
+        let program = check_source("fn f(c: bool) -> i64 { if c { return 1; } 40 + 2 }").unwrap();
+        let TypedItem::Function(f) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(f.body.ty, TypeInfo::Int(IntWidth::I64));
+    }
+
+    #[test]
+    fn test_loop_takes_break_value_type() {
+        let source = "fn f() -> i64 { loop { break 1; } }";
+        assert_eq!(stmt_types(source), vec![TypeInfo::Int(IntWidth::I64)]);
+        // A `break` in an inner `while` belongs to the `while`
+        let source = "fn f() { loop { while true { break; } } }";
+        assert_eq!(stmt_types(source), vec![TypeInfo::Never]);
+    }
+
+    #[test]
+    fn test_break_value_only_in_loop() {
+        for source in [
+            "fn f(c: bool) { while c { break 1; } }",
+            "fn f(n: i64) { for i in n { break true; } }",
+            // The innermost loop decides, even inside a `loop`
+            "fn f() { loop { while true { break 1; } } }",
+        ] {
+            assert!(
+                matches!(
+                    check_source(source),
+                    Err(NovaError::BreakValueInWhile { .. })
+                ),
+                "{source}"
+            );
+        }
+        // A bare `break` is fine in every loop
+        assert!(check_source("fn f(c: bool) { while c { break; } }").is_ok());
+    }
+
+    #[test]
+    fn test_loop_body_must_be_unit() {
+        assert!(matches!(
+            check_source("fn f() { while true { 1 } }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_expressions_are_rejected() {
+        for source in [
+            "struct P { x: i64 } fn f() -> i64 { let p = P { x: 1 }; 0 }",
+            "fn f() -> bool { Q { y: true } }",
+            "fn f() { println!(\"hi\"); }",
+        ] {
+            assert!(
+                matches!(
+                    check_source(source),
+                    Err(NovaError::UnsupportedExpression { .. })
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_string_checks_interpolations() {
+        let source = r#"fn f() { let s: String = f"{{a}} = 1"; }"#;
+        assert!(check_source(source).is_ok());
+        // Interpolations are checked, then rejected rather than dropped
+        let source = r#"fn f(a: i64) { let s: String = f"a = {a + 1}"; }"#;
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::FormatInterpolation { .. })
+        ));
+        let source = r#"fn f(r: Result<i64, String>) { let s = f"{r?}"; }"#;
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TryInNonResultFn { .. })
+        ));
+    }
+
+    #[test]
+    fn test_try_in_unit_fn() {
+        let source = "fn f(r: Result<i32, String>) { r?; }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TryInNonResultFn { .. })
+        ));
+    }
+
+    #[test]
+    fn test_directly_recursive_struct() {
+        let source = "struct S { value: i64, next: S }";
+        match check_source(source) {
+            Err(NovaError::InfinitelyRecursiveType { name, span }) => {
+                assert_eq!(name, "S");
+                assert_eq!((span.start(), span.end()), (7, 8));
+            }
+            other => panic!("Expected InfinitelyRecursiveType, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reference_breaks_recursion() {
+        assert!(check_source("struct S { value: i64, next: &S }").is_ok());
+        assert!(check_source("struct Node { children: Box<Node> }").is_ok());
+    }
+
+    #[test]
+    fn test_mutually_recursive_types() {
+        let source = "struct A { b: (i64, B) } enum B { Leaf, Branch(A) }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::InfinitelyRecursiveType { .. })
+        ));
+        let source = "struct A { b: B } struct B { a: &A }";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_memory_intrinsics() {
+        assert!(check_source("fn f() -> i32 { memory_grow(memory_size()) }").is_ok());
+        assert!(matches!(
+            check_source("fn f() { let b: bool = memory_size(); }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check_source("fn memory_size() -> i32 { 0 }"),
+            Err(NovaError::Custom { .. })
+        ));
+    }
+
+    #[test]
+    fn test_forward_references() {
+        assert!(check_source("fn a() { b(); } fn b() {}").is_ok());
+        let source = "fn same(p: P) -> P { p } struct P { x: i64 }";
+        assert!(check_source(source).is_ok());
+        // Aliases resolve before their definitions too
+        assert!(check_source("fn id(n: N) -> i64 { n } type N = i64;").is_ok());
+        // The signature is known before the body that uses it
+        let source = "fn a() { let x: bool = b(); } fn b() -> i64 { 1 }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        let source = "fn a() { b(true); } fn b(x: i64) {}";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mutually_recursive_functions() {
+        let program = check_source(
+            "fn is_even(n: i64) -> bool { if n == 0 { true } else { is_odd(n - 1) } }
+            fn is_odd(n: i64) -> bool { if n == 0 { false } else { is_even(n - 1) } }
+            fn main() -> bool { is_odd(7) }",
+        )
+        .unwrap();
+        let main = program
+            .items
+            .iter()
+            .find_map(|item| match item {
+                TypedItem::Function(f) if f.name == "main" => Some(f),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(main.body.ty, TypeInfo::Bool);
+        // Types are known before their declarations too
+        let source = "fn a(p: P) -> Shape { b(p) } fn b(p: P) -> Shape { Shape::Dot(p) }
+            enum Shape { Dot(P) } struct P(i64);";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_static_initializers() {
+        let program = check_source(
+            "static mut COUNTER: i32 = 2 * 3; static SCALE: f64 = -0.5; static ON: bool = true;
+            fn f() -> i32 { COUNTER }",
+        )
+        .unwrap();
+        let values: Vec<_> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TypedItem::Static(s) => Some(format!("{:?}", s.value)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values, ["Int(6)", "Float(-0.5)", "Bool(true)"]);
+
+        for source in [
+            "fn one() -> i64 { 1 } static X: i64 = one();",
+            "static X: f64 = 1.0 + 2.0;",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::NonConstStatic { .. })),
+                "{source}"
+            );
+        }
+        for source in [
+            "static X: bool = 1;",
+            "static N: i64 = 1; fn f() { let b: bool = N; }",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::TypeMismatch { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_array_literal_type() {
+        check_source("fn f() { let a: [i64; 3] = [1, 2, 3]; }").unwrap();
+        for source in [
+            "fn f() { let a = [1, true]; }",
+            "fn f() { let a: [bool; 3] = [1, 2, 3]; }",
+            "fn f() { let a: [i64; 2] = [1, 2, 3]; }",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::TypeMismatch { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_64_bit_extremes_are_constants() {
+        let program = check_source(
+            "static MAX: u64 = 18446744073709551615;
+            static MIN: i64 = -9223372036854775808;
+            static HEX: u64 = 0xFFFF_FFFF_FFFF_FFFF;",
+        )
+        .unwrap();
+        let statics: Vec<_> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TypedItem::Static(s) => Some(s.value.clone()),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(
+            statics[..],
+            [
+                Literal::Int(max),
+                Literal::Int(min),
+                Literal::Int(hex)
+            ] if max == u64::MAX as i128 && min == i64::MIN as i128 && hex == max
+        ));
+    }
+
+    #[test]
+    fn test_int_literals_must_fit_their_type() {
+        for source in [
+            "fn f() { let x: i64 = 18446744073709551615; }",
+            "fn f() { let x: i64 = -9223372036854775809; }",
+            "fn f() { let x: i32 = 2147483648; }",
+            "fn f() -> i32 { let x: i32 = 0; x + 3000000000 }",
+            "fn f() { let x: u8 = 256; }",
+            "fn f() { let x: u8 = -1; }",
+            "static X: u8 = 300;",
+        ] {
+            assert!(
+                matches!(
+                    check_source(source),
+                    Err(NovaError::IntLiteralOutOfRange { .. })
+                ),
+                "{source}"
+            );
+        }
+        // The bounds themselves fit, and an unconstrained literal is
+        // checked against the default integer type
+        check_source(
+            "fn f() {
+                let a: i8 = -128;
+                let b: u8 = 255;
+                let c: i32 = -2147483648;
+                let d: u64 = 18446744073709551615;
+                let e = 9223372036854775807;
+            }",
+        )
+        .unwrap();
+        assert!(check_source("fn f() { let e = 9223372036854775808; }").is_err());
+    }
+
+    #[test]
+    fn test_const_casts() {
+        let program = check_source(
+            "static WIDE: i64 = 200 as u8 as i64;
+            static TRUNCATED: u8 = 300 as u8;
+            static SIGNED: i8 = 200 as i8;
+            static SATURATED: u8 = 300.7 as u8;
+            static NEGATIVE: i32 = -1.5 as i32;
+            static FLOAT: f64 = 3 as f64;
+            static NARROW: f64 = 0.1 as f32;
+            static WRAPPED: u64 = -1 as u64;
+            struct P {} impl P { const X: i64 = 300 as i64; }
+            fn buffer(b: [u8; 4 as usize]) -> [u8; 4] { b }",
+        )
+        .unwrap();
+        let statics: Vec<_> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TypedItem::Static(s) => Some(format!("{:?}", s.value)),
+                _ => None,
+            })
+            .collect();
+        let narrow = format!("Float({:?})", 0.1f32 as f64);
+        assert_eq!(
+            statics,
+            [
+                "Int(200)",
+                "Int(44)",
+                "Int(-56)",
+                "Int(255)",
+                "Int(-1)",
+                "Float(3.0)",
+                &narrow,
+                "Int(18446744073709551615)"
+            ]
+        );
+        assert!(program.items.iter().any(|item| matches!(
+            item,
+            TypedItem::Const(c) if matches!(c.value.kind, TypedExprKind::Literal(Literal::Int(300)))
+        )));
+        assert_eq!(discriminants("enum E { A = 258 as u8 }").unwrap(), [2]);
+
+        // Not constants: a bool source and a negative `u128`
+        for source in [
+            "static X: i64 = true as i64;",
+            "static X: u128 = -1 as u128;",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::NonConstStatic { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_casts() {
+        let source = "fn f(x: i64) -> bool { x as bool }";
+        let Err(NovaError::InvalidCast { from, to, .. }) = check_source(source) else {
+            panic!("Expected an invalid cast");
+        };
+        assert_eq!((from.as_str(), to.as_str()), ("Int(I64)", "Bool"));
+        let source = "struct S {} fn f() { 1 as S; }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::InvalidCast { .. })
+        ));
+        assert!(check_source("fn f(b: bool) -> f64 { let n: i64 = b as i64; n as f64 }").is_ok());
+    }
+
+    #[test]
+    fn test_range_patterns() {
+        let source = r#"fn f(t: i64) -> i64 { match t { -40..=0 => 1, 1..=30 => 2, 31..40 => 3, 40.. => 4, _ => 5 } }"#;
+        assert!(check_source(source).is_ok());
+        // A guard means the later arm can still be reached
+        let source = "fn f(t: i64) { match t { 0..=10 if t > 5 => 1, 5..=20 => 2, _ => 3 }; }";
+        assert!(check_source(source).is_ok());
+        // Every pattern has the scrutinee's type
+        let source = "fn f(t: bool) { match t { 0..=1 => 1, _ => 2 }; }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        let source = r#"fn f(t: i64) { match t { "hot" => 1, _ => 2 }; }"#;
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+        let source = "fn f(t: i64) { match t { 10..=-10 => 1, _ => 2 }; }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::Custom { .. })
+        ));
+    }
+
+    #[test]
+    fn test_slice_pattern_elements_have_the_element_type() {
+        let source = "fn f(s: [i64; 3]) -> i64 { match s { [a, rest @ ..] => a, _ => 0 } }";
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            check_source("fn f(s: [i64; 3]) { match s { [true, ..] => 1, _ => 2 }; }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_or_pattern_alternatives_share_a_type() {
+        assert!(check_source("fn f(t: i64) { match t { 1 | 2 => 1, _ => 2 }; }").is_ok());
+        assert!(matches!(
+            check_source("fn f(t: i64) { match t { 1 | true => 1, _ => 2 }; }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_overlapping_range_patterns() {
+        let span_text =
+            |source: &'static str, span: Span| &source[span.start() as usize..span.end() as usize];
+        let source = "fn f(t: i64) { match t { -40..=0 => 1, 1..=30 => 2, 25..50 => 3, _ => 4 }; }";
+        match check_source(source) {
+            Err(NovaError::OverlappingPatterns { first, second }) => {
+                assert_eq!(span_text(source, first), "1..=30");
+                assert_eq!(span_text(source, second), "25..50");
+            }
+            other => panic!("Expected OverlappingPatterns, got {:?}", other),
+        }
+        // An exclusive end doesn't overlap the range starting there
+        let source = "fn f(t: i64) { match t { ..0 => 1, 0.. => 2 }; }";
+        assert!(check_source(source).is_ok());
+        let source = "fn f(t: i64) { match t { ..=0 => 1, 0.. => 2 }; }";
+        assert!(matches!(
+            check_source(source),
+            Err(NovaError::OverlappingPatterns { .. })
+        ));
+    }
+
+    #[test]
+    fn test_assign_to_immutable_binding() {
+        for (source, place) in [
+            ("fn f() { let x = 1; x = 2; }", "x"),
+            ("fn f(x: i64) { x = 2; }", "x"),
+            ("fn f(r: &i64) { *r = 2; }", "*r"),
+            ("struct P { x: i64 } fn f(p: &P) { p.x = 2; }", "p"),
+            ("struct P { x: i64 } fn f(p: P) { p.x = 2; }", "p"),
+            ("static N: i64 = 1; fn f() { N = 2; }", "N"),
+            ("fn f() { let x = 1; { let mut x = 2; } x = 3; }", "x"),
+            // A deferred initialization happens once
+            ("fn f() { let x; x = 1; x = 2; }", "x"),
+            ("fn f(c: bool) { let x; if c { x = 1; } x = 2; }", "x"),
+            ("fn f() { let x; while true { x = 1; } }", "x"),
+            ("fn f() { let x = 1; x += 2; }", "x"),
+        ] {
+            match check_source(source) {
+                Err(NovaError::AssignToImmutable { name, .. }) => assert_eq!(name, place),
+                other => panic!("Expected AssignToImmutable for {source}, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_mut_borrow_of_immutable_place() {
+        for (source, place) in [
+            ("fn f() { let x = 1; let r = &mut x; *r = 2; }", "x"),
+            ("fn f(r: &[i64; 2]) { let e = &mut r[0]; }", "r"),
+        ] {
+            match check_source(source) {
+                Err(NovaError::BorrowImmutableAsMut { name, .. }) => assert_eq!(name, place),
+                other => panic!(
+                    "Expected BorrowImmutableAsMut for {source}, got {:?}",
+                    other
+                ),
+            }
+        }
+        assert!(check_source("fn f() { let mut x = 1; let r = &mut x; *r = 2; }").is_ok());
+    }
+
+    #[test]
+    fn test_assign_to_mutable_binding() {
+        for source in [
+            "fn f() { let mut x = 1; x = 2; }",
+            "fn f(mut x: i64) { x = 2; }",
+            "fn f(r: &mut i64) { *r = 2; }",
+            "struct P { x: i64 } fn f(p: &mut P) { p.x = 2; }",
+            "struct P { x: i64 } fn f(mut p: P) { p.x = 2; }",
+            "static mut N: i64 = 1; fn f() { N = 2; }",
+            "fn f() { let x; x = 1; }",
+            "fn f(c: bool) { let x; if c { x = 1; } else { x = 2; } }",
+            "fn f(n: i64) { let x; match n { 0 => x = 1, _ => x = 2 } }",
+            "fn f() { let mut x; x = 1; x = 2; }",
+            "fn f() { let x = 1; let mut x = x; x = 2; }",
+            "fn f() { let mut x = 1; x <<= 2; x -= 1; }",
+        ] {
+            assert!(check_source(source).is_ok(), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_range_types() {
+        let types = let_types("fn f(n: i64) { let r = 0..n; let a = ..=n; let all = ..; }");
+        assert_eq!(
+            types,
+            [
+                TypeInfo::Range(Box::new(TypeInfo::Int(IntWidth::I64))),
+                TypeInfo::Range(Box::new(TypeInfo::Int(IntWidth::I64))),
+                TypeInfo::Range(Box::new(TypeInfo::Unknown)),
+            ]
+        );
+        assert!(matches!(
+            check_source("fn f() { let r = 0..true; }"),
+            Err(NovaError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_for_over_range_binds_element() {
+        assert!(check_source("fn f(n: i64) { for i in 0..n { let x: i64 = i; } }").is_ok());
+        assert!(check_source("fn f(xs: &[f64]) { for x in xs { let y: f64 = x; } }").is_ok());
+        for source in [
+            "fn f(n: i64) { for i in 0..n { let b: bool = i; } }",
+            "fn f(xs: &[f64]) { for x in xs { let b: bool = x; } }",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::TypeMismatch { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_for_pattern_must_be_a_name() {
+        assert!(check_source("fn f(n: i64) { for _ in 0..n { } }").is_ok());
+        let result = check_source("fn f(n: i64) { for (a, b) in 0..n { } }");
+        assert!(matches!(result, Err(NovaError::ForPatternNotName { .. })));
+    }
+
+    #[test]
+    fn test_let_tuple_pattern_binds_each_element() {
+        assert!(check_source(
+            "fn f() { let (x, y): (i32, bool) = (1, true); let a: i32 = x; let b: bool = y; }"
+        )
+        .is_ok());
+        // Nested, and with the types inferred from the value
+        assert!(check_source(
+            "fn f() { let ((x, _), mut y) = ((1.5, 2), true); let a: f64 = x; y = false; }"
+        )
+        .is_ok());
+        let result =
+            check_source("fn f() { let (x, y): (i32, bool) = (1, true); let b: bool = x; }");
+        assert!(matches!(result, Err(NovaError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_let_tuple_pattern_must_match_the_shape() {
+        for (source, found) in [
+            ("fn f() { let (x, y) = 5; }", "Int(I64)"),
+            (
+                "fn f() { let (x, y) = (1, 2, 3); }",
+                "Tuple([Int(I64), Int(I64), Int(I64)])",
+            ),
+        ] {
+            match check_source(source) {
+                Err(NovaError::TypeMismatch {
+                    expected,
+                    found: actual,
+                    ..
+                }) => {
+                    assert_eq!(expected, "a tuple of 2 elements");
+                    assert_eq!(actual, found);
+                }
+                other => panic!("Expected TypeMismatch for {source}, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_tuple_parameter_binds_each_element() {
+        let source = "fn f((x, y): (i64, bool)) -> i64 { let b: bool = y; x }";
+        let program = check_source(source).unwrap();
+        let TypedItem::Function(f) = &program.items[0] else {
+            panic!("Expected a function");
+        };
+        // Passed whole, then unpacked at the top of the body
+        assert_eq!(f.params.len(), 1);
+        assert!(matches!(
+            &f.body.stmts[0],
+            TypedStmt::Let {
+                pattern: Pattern {
+                    kind: PatternKind::Tuple(_),
+                    ..
+                },
+                ..
+            }
+        ));
+        let result = check_source("fn f((x, y): (i64, bool)) { let b: bool = x; }");
+        assert!(matches!(result, Err(NovaError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_index_with_range_is_a_slice() {
+        let types =
+            let_types("fn f(arr: [i64; 4]) { let s = arr[1..3]; let x = arr[0]; let y = s[0]; }");
+        assert_eq!(
+            types,
+            [
+                TypeInfo::Slice(Box::new(TypeInfo::Int(IntWidth::I64))),
+                TypeInfo::Int(IntWidth::I64),
+                TypeInfo::Int(IntWidth::I64)
+            ]
+        );
+        for source in [
+            "fn f(arr: [i64; 4]) { let x = arr[true]; }",
+            "fn f(arr: [i64; 4]) { let s = arr[0.5..2.5]; }",
+        ] {
+            assert!(
+                matches!(check_source(source), Err(NovaError::TypeMismatch { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    const SHAPE: &str = "trait Shape { fn area(&self) -> f64; fn name(&self) -> i64 { 0 } }
+        struct Square { side: f64 }";
+
+    #[test]
+    fn test_complete_trait_impl() {
+        let source = format!(
+            "{SHAPE} impl Shape for Square {{ fn area(&self) -> f64 {{ 1.0 }} }}
+            impl Shape for i64 {{
+                fn area(&self) -> f64 {{ 2.0 }}
+                fn name(&self) -> i64 {{ 1 }}
+            }}"
+        );
+        assert!(check_source(&source).is_ok());
+    }
+
+    #[test]
+    fn test_missing_trait_method() {
+        let source = format!("{SHAPE} impl Shape for Square {{ fn name(&self) -> i64 {{ 1 }} }}");
+        match check_source(&source) {
+            Err(NovaError::MissingTraitMethod {
+                trait_name, method, ..
+            }) => {
+                assert_eq!((trait_name.as_str(), method.as_str()), ("Shape", "area"));
+            }
+            other => panic!("Expected MissingTraitMethod, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_trait_method_signature_mismatch() {
+        for method in [
+            "fn area(&self) -> i64 { 1 }",
+            "fn area(&self, x: f64) -> f64 { x }",
+        ] {
+            let source = format!("{SHAPE} impl Shape for Square {{ {method} }}");
+            assert!(
+                matches!(
+                    check_source(&source),
+                    Err(NovaError::TraitMethodSignatureMismatch { ref method, .. }) if method == "area"
+                ),
+                "{method}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_trait_impl_substitutes_trait_args_and_method_generics() {
+        let from = "trait From<T> { fn from(t: T) -> Self; } struct W(i64);";
+        assert!(check_source(&format!(
+            "{from} impl From<i64> for W {{ fn from(t: i64) -> W {{ W(t) }} }}"
+        ))
+        .is_ok());
+        assert!(check_source(
+            "trait Id { fn f<T>(x: T) -> T; } struct S; impl Id for S { fn f<U>(x: U) -> U { x } }"
+        )
+        .is_ok());
+
+        for source in [
+            format!("{from} impl From<i64> for W {{ fn from(t: bool) -> W {{ W(1) }} }}"),
+            "trait Id { fn f<T>(x: T) -> T; } struct S; impl Id for S { fn f<U, V>(x: U) -> U { x } }"
+                .to_string(),
+            "trait Id { fn f<T, U>(x: T, y: U) -> T; } struct S; impl Id for S { fn f<T, U>(x: U, y: T) -> T { y } }"
+                .to_string(),
+        ] {
+            assert!(
+                matches!(
+                    check_source(&source),
+                    Err(NovaError::TraitMethodSignatureMismatch { .. })
+                ),
+                "{source}"
+            );
+        }
     }
 }
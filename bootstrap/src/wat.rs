@@ -0,0 +1,621 @@
+//! WAT (WebAssembly text format) output
+//!
+//! [`to_wat`] prints the binary `codegen` produced as WAT, for reading
+//! what the compiler emitted. Functions keep the names they have in the
+//! IR, and each is preceded by its `///` doc comment as `;;` lines.
+//!
+//! The text is written from the binary rather than from the IR, so it
+//! shows exactly the instructions an engine runs, in the flat (unfolded)
+//! form: one instruction per line, indented by block nesting.
+
+use std::fmt::Write;
+
+use crate::ir::Module;
+use crate::leb128;
+
+/// The WAT text of `wasm`, the output of [`crate::codegen::generate`] for
+/// `module`
+///
+/// # Panics
+///
+/// If `wasm` isn't a module `codegen` could have produced.
+pub fn to_wat(wasm: &[u8], module: &Module) -> String {
+    write_module(wasm, module).expect("`to_wat` reads the output of `codegen::generate`")
+}
+
+/// The sections of a module, by id
+#[derive(Default)]
+struct Sections<'a> {
+    types: Option<&'a [u8]>,
+    functions: Option<&'a [u8]>,
+    memory: Option<&'a [u8]>,
+    globals: Option<&'a [u8]>,
+    exports: Option<&'a [u8]>,
+    code: Option<&'a [u8]>,
+    data: Option<&'a [u8]>,
+}
+
+fn write_module(wasm: &[u8], module: &Module) -> Option<String> {
+    let mut reader = Reader::new(wasm);
+    if reader.take(8)? != b"\0asm\x01\0\0\0" {
+        return None;
+    }
+    let mut sections = Sections::default();
+    while !reader.is_at_end() {
+        let id = reader.byte()?;
+        let len = reader.u32()? as usize;
+        let contents = Some(reader.take(len)?);
+        match id {
+            1 => sections.types = contents,
+            3 => sections.functions = contents,
+            5 => sections.memory = contents,
+            6 => sections.globals = contents,
+            7 => sections.exports = contents,
+            10 => sections.code = contents,
+            11 => sections.data = contents,
+            _ => {}
+        }
+    }
+
+    // Functions are numbered in the order they're declared, as in `module`
+    let names: Vec<String> = module
+        .functions
+        .iter()
+        .map(|f| format!("${}", identifier(&f.name)))
+        .collect();
+    let mut out = String::from("(module\n");
+
+    let mut types = Vec::new();
+    if let Some(bytes) = sections.types {
+        let mut reader = Reader::new(bytes);
+        for i in 0..reader.u32()? {
+            if reader.byte()? != 0x60 {
+                return None;
+            }
+            let params = reader.value_types()?;
+            let results = reader.value_types()?;
+            let signature = signature(&params, &results);
+            writeln!(out, "  (type (;{};) (func{}))", i, signature).ok()?;
+            types.push(signature);
+        }
+    }
+
+    if let (Some(functions), Some(code)) = (sections.functions, sections.code) {
+        let mut functions = Reader::new(functions);
+        let mut code = Reader::new(code);
+        let count = functions.u32()?;
+        if code.u32()? != count {
+            return None;
+        }
+        for i in 0..count as usize {
+            let ty = functions.u32()?;
+            if let Some(doc) = module.functions.get(i).and_then(|f| f.doc.as_ref()) {
+                for line in doc.lines() {
+                    match line {
+                        "" => out.push_str("  ;;\n"),
+                        line => writeln!(out, "  ;; {}", line).ok()?,
+                    }
+                }
+            }
+            let name = names.get(i)?;
+            let signature = types.get(ty as usize)?;
+            writeln!(out, "  (func {} (type {}){}", name, ty, signature).ok()?;
+            let len = code.u32()? as usize;
+            let mut body = Reader::new(code.take(len)?);
+            let mut locals = Vec::new();
+            for _ in 0..body.u32()? {
+                let count = body.u32()?;
+                let ty = value_type(body.byte()?)?;
+                locals.extend(std::iter::repeat_n(ty, count as usize));
+            }
+            if !locals.is_empty() {
+                writeln!(out, "    (local {})", locals.join(" ")).ok()?;
+            }
+            write_body(&mut out, &mut body, &names)?;
+            out.push_str("  )\n");
+        }
+    }
+
+    if let Some(bytes) = sections.memory {
+        let mut reader = Reader::new(bytes);
+        for i in 0..reader.u32()? {
+            let limits = match reader.byte()? {
+                0x00 => reader.u32()?.to_string(),
+                0x01 => format!("{} {}", reader.u32()?, reader.u32()?),
+                _ => return None,
+            };
+            writeln!(out, "  (memory (;{};) {})", i, limits).ok()?;
+        }
+    }
+
+    if let Some(bytes) = sections.globals {
+        let mut reader = Reader::new(bytes);
+        for i in 0..reader.u32()? {
+            let ty = value_type(reader.byte()?)?;
+            let ty = match reader.byte()? {
+                0x00 => ty.to_string(),
+                0x01 => format!("(mut {})", ty),
+                _ => return None,
+            };
+            let init = reader.const_expr()?;
+            writeln!(out, "  (global (;{};) {} ({}))", i, ty, init).ok()?;
+        }
+    }
+
+    if let Some(bytes) = sections.exports {
+        let mut reader = Reader::new(bytes);
+        for _ in 0..reader.u32()? {
+            let name = reader.name()?;
+            if reader.byte()? != 0x00 {
+                return None;
+            }
+            let function = names.get(reader.u32()? as usize)?;
+            let name = string(name.as_bytes());
+            writeln!(out, "  (export {} (func {}))", name, function).ok()?;
+        }
+    }
+
+    if let Some(bytes) = sections.data {
+        let mut reader = Reader::new(bytes);
+        for _ in 0..reader.u32()? {
+            if reader.u32()? != 0 {
+                return None;
+            }
+            let offset = reader.const_expr()?;
+            let len = reader.u32()? as usize;
+            let bytes = reader.take(len)?;
+            writeln!(out, "  (data ({}) {})", offset, string(bytes)).ok()?;
+        }
+    }
+
+    out.push_str(")\n");
+    Some(out)
+}
+
+/// Write the instructions of a function body, up to the `end` closing it
+fn write_body(out: &mut String, body: &mut Reader, names: &[String]) -> Option<()> {
+    let mut depth = 0usize;
+    loop {
+        let op = body.byte()?;
+        let text = match op {
+            0x00 => "unreachable".to_string(),
+            0x01 => "nop".to_string(),
+            0x02..=0x04 => {
+                let name = ["block", "loop", "if"][(op - 0x02) as usize];
+                let result = match body.byte()? {
+                    0x40 => String::new(),
+                    ty => format!(" (result {})", value_type(ty)?),
+                };
+                indent(out, depth);
+                writeln!(out, "{}{}", name, result).ok()?;
+                depth += 1;
+                continue;
+            }
+            0x05 => {
+                indent(out, depth.checked_sub(1)?);
+                out.push_str("else\n");
+                continue;
+            }
+            0x0B if depth == 0 => return Some(()),
+            0x0B => {
+                depth -= 1;
+                "end".to_string()
+            }
+            0x0C => format!("br {}", body.u32()?),
+            0x0D => format!("br_if {}", body.u32()?),
+            0x0F => "return".to_string(),
+            0x10 => format!("call {}", names.get(body.u32()? as usize)?),
+            0x1A => "drop".to_string(),
+            0x1B => "select".to_string(),
+            0x20..=0x24 => {
+                let name = [
+                    "local.get",
+                    "local.set",
+                    "local.tee",
+                    "global.get",
+                    "global.set",
+                ][(op - 0x20) as usize];
+                format!("{} {}", name, body.u32()?)
+            }
+            0x28..=0x3E => {
+                let align = 1u64 << body.u32()?;
+                let offset = body.u32()?;
+                let name = MEMORY_OPS[(op - 0x28) as usize];
+                match offset {
+                    0 => format!("{} align={}", name, align),
+                    _ => format!("{} offset={} align={}", name, offset, align),
+                }
+            }
+            0x3F | 0x40 => {
+                body.byte()?; // memory 0
+                ["memory.size", "memory.grow"][(op - 0x3F) as usize].to_string()
+            }
+            0x41..=0x44 => body.constant(op)?,
+            0x45..=0xBF => NUMERIC_OPS[(op - 0x45) as usize].to_string(),
+            0xFC => TRUNC_SAT_OPS.get(body.u32()? as usize)?.to_string(),
+            _ => return None,
+        };
+        indent(out, depth);
+        out.push_str(&text);
+        out.push('\n');
+    }
+}
+
+fn indent(out: &mut String, depth: usize) {
+    out.push_str(&"  ".repeat(depth + 2));
+}
+
+/// ` (param ...) (result ...)`, leaving out an empty list
+fn signature(params: &[&str], results: &[&str]) -> String {
+    let mut text = String::new();
+    if !params.is_empty() {
+        text += &format!(" (param {})", params.join(" "));
+    }
+    if !results.is_empty() {
+        text += &format!(" (result {})", results.join(" "));
+    }
+    text
+}
+
+/// The name of a value type
+fn value_type(byte: u8) -> Option<&'static str> {
+    match byte {
+        0x7F => Some("i32"),
+        0x7E => Some("i64"),
+        0x7D => Some("f32"),
+        0x7C => Some("f64"),
+        _ => None,
+    }
+}
+
+/// `name` with the characters a WAT identifier can't hold replaced
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c,
+            '!' | '#' | '$' | '%' | '&' | '\'' | '*' | '+' | '-' | '.' | '/' => c,
+            ':' | '<' | '=' | '>' | '?' | '@' | '\\' | '^' | '_' | '`' | '|' | '~' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// A WAT string literal holding `bytes`
+fn string(bytes: &[u8]) -> String {
+    let mut text = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => {
+                text.push('\\');
+                text.push(b as char);
+            }
+            0x20..=0x7E => text.push(b as char),
+            _ => text += &format!("\\{:02x}", b),
+        }
+    }
+    text.push('"');
+    text
+}
+
+/// Reads a module's bytes in order
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let (value, len) = leb128::decode_u32(&self.bytes[self.pos..]).ok()?;
+        self.pos += len;
+        Some(value)
+    }
+
+    fn name(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.take(len)?).ok()
+    }
+
+    fn value_types(&mut self) -> Option<Vec<&'static str>> {
+        (0..self.u32()?).map(|_| value_type(self.byte()?)).collect()
+    }
+
+    /// The operand of the `*.const` instruction `op`, with its name
+    fn constant(&mut self, op: u8) -> Option<String> {
+        Some(match op {
+            0x41 => {
+                let (value, len) = leb128::decode_i32(&self.bytes[self.pos..]).ok()?;
+                self.pos += len;
+                format!("i32.const {}", value)
+            }
+            0x42 => {
+                let (value, len) = leb128::decode_i64(&self.bytes[self.pos..]).ok()?;
+                self.pos += len;
+                format!("i64.const {}", value)
+            }
+            0x43 => {
+                let value = f32::from_le_bytes(self.take(4)?.try_into().ok()?);
+                format!("f32.const {}", float(value as f64))
+            }
+            0x44 => {
+                let value = f64::from_le_bytes(self.take(8)?.try_into().ok()?);
+                format!("f64.const {}", float(value))
+            }
+            _ => return None,
+        })
+    }
+
+    /// A constant expression (a global's value or a data segment's
+    /// offset): one `*.const` and an `end`
+    fn const_expr(&mut self) -> Option<String> {
+        let op = self.byte()?;
+        let constant = self.constant(op)?;
+        match self.byte()? {
+            0x0B => Some(constant),
+            _ => None,
+        }
+    }
+}
+
+/// How WAT spells a float
+fn float(value: f64) -> String {
+    match value {
+        v if v.is_nan() => "nan".to_string(),
+        f64::INFINITY => "inf".to_string(),
+        f64::NEG_INFINITY => "-inf".to_string(),
+        v => format!("{:?}", v),
+    }
+}
+
+/// Loads and stores, from `i32.load` (0x28) to `i64.store32` (0x3E)
+const MEMORY_OPS: [&str; 23] = [
+    "i32.load",
+    "i64.load",
+    "f32.load",
+    "f64.load",
+    "i32.load8_s",
+    "i32.load8_u",
+    "i32.load16_s",
+    "i32.load16_u",
+    "i64.load8_s",
+    "i64.load8_u",
+    "i64.load16_s",
+    "i64.load16_u",
+    "i64.load32_s",
+    "i64.load32_u",
+    "i32.store",
+    "i64.store",
+    "f32.store",
+    "f64.store",
+    "i32.store8",
+    "i32.store16",
+    "i64.store8",
+    "i64.store16",
+    "i64.store32",
+];
+
+/// The saturating float-to-int conversions, `0xFC` followed by 0 to 7
+const TRUNC_SAT_OPS: [&str; 8] = [
+    "i32.trunc_sat_f32_s",
+    "i32.trunc_sat_f32_u",
+    "i32.trunc_sat_f64_s",
+    "i32.trunc_sat_f64_u",
+    "i64.trunc_sat_f32_s",
+    "i64.trunc_sat_f32_u",
+    "i64.trunc_sat_f64_s",
+    "i64.trunc_sat_f64_u",
+];
+
+/// The numeric instructions, which take no immediates, from `i32.eqz`
+/// (0x45) to `f64.reinterpret_i64` (0xBF)
+const NUMERIC_OPS: [&str; 123] = [
+    "i32.eqz",
+    "i32.eq",
+    "i32.ne",
+    "i32.lt_s",
+    "i32.lt_u",
+    "i32.gt_s",
+    "i32.gt_u",
+    "i32.le_s",
+    "i32.le_u",
+    "i32.ge_s",
+    "i32.ge_u",
+    "i64.eqz",
+    "i64.eq",
+    "i64.ne",
+    "i64.lt_s",
+    "i64.lt_u",
+    "i64.gt_s",
+    "i64.gt_u",
+    "i64.le_s",
+    "i64.le_u",
+    "i64.ge_s",
+    "i64.ge_u",
+    "f32.eq",
+    "f32.ne",
+    "f32.lt",
+    "f32.gt",
+    "f32.le",
+    "f32.ge",
+    "f64.eq",
+    "f64.ne",
+    "f64.lt",
+    "f64.gt",
+    "f64.le",
+    "f64.ge",
+    "i32.clz",
+    "i32.ctz",
+    "i32.popcnt",
+    "i32.add",
+    "i32.sub",
+    "i32.mul",
+    "i32.div_s",
+    "i32.div_u",
+    "i32.rem_s",
+    "i32.rem_u",
+    "i32.and",
+    "i32.or",
+    "i32.xor",
+    "i32.shl",
+    "i32.shr_s",
+    "i32.shr_u",
+    "i32.rotl",
+    "i32.rotr",
+    "i64.clz",
+    "i64.ctz",
+    "i64.popcnt",
+    "i64.add",
+    "i64.sub",
+    "i64.mul",
+    "i64.div_s",
+    "i64.div_u",
+    "i64.rem_s",
+    "i64.rem_u",
+    "i64.and",
+    "i64.or",
+    "i64.xor",
+    "i64.shl",
+    "i64.shr_s",
+    "i64.shr_u",
+    "i64.rotl",
+    "i64.rotr",
+    "f32.abs",
+    "f32.neg",
+    "f32.ceil",
+    "f32.floor",
+    "f32.trunc",
+    "f32.nearest",
+    "f32.sqrt",
+    "f32.add",
+    "f32.sub",
+    "f32.mul",
+    "f32.div",
+    "f32.min",
+    "f32.max",
+    "f32.copysign",
+    "f64.abs",
+    "f64.neg",
+    "f64.ceil",
+    "f64.floor",
+    "f64.trunc",
+    "f64.nearest",
+    "f64.sqrt",
+    "f64.add",
+    "f64.sub",
+    "f64.mul",
+    "f64.div",
+    "f64.min",
+    "f64.max",
+    "f64.copysign",
+    "i32.wrap_i64",
+    "i32.trunc_f32_s",
+    "i32.trunc_f32_u",
+    "i32.trunc_f64_s",
+    "i32.trunc_f64_u",
+    "i64.extend_i32_s",
+    "i64.extend_i32_u",
+    "i64.trunc_f32_s",
+    "i64.trunc_f32_u",
+    "i64.trunc_f64_s",
+    "i64.trunc_f64_u",
+    "f32.convert_i32_s",
+    "f32.convert_i32_u",
+    "f32.convert_i64_s",
+    "f32.convert_i64_u",
+    "f32.demote_f64",
+    "f64.convert_i32_s",
+    "f64.convert_i32_u",
+    "f64.convert_i64_s",
+    "f64.convert_i64_u",
+    "f64.promote_f32",
+    "i32.reinterpret_f32",
+    "i64.reinterpret_f64",
+    "f32.reinterpret_i32",
+    "f64.reinterpret_i64",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wat(source: &str) -> String {
+        let tokens = crate::lexer::lex(source).unwrap();
+        let ast = crate::parser::parse(source, tokens).unwrap();
+        let typed = crate::types::check(&ast).unwrap();
+        let module = crate::ir::lower(&typed);
+        let wasm = crate::codegen::generate(&module).unwrap();
+        to_wat(&wasm, &module)
+    }
+
+    #[test]
+    fn test_function_doc_becomes_wat_comment() {
+        let source = "/// Adds one.
+            ///
+            /// Never overflows in practice.
+            fn inc(n: i64) -> i64 { n + 1 }
+            fn bare() {}";
+        let text = wat(source);
+        let doc = "  ;; Adds one.\n  ;;\n  ;; Never overflows in practice.\n";
+        let func = "  (func $inc (type 0) (param i64) (result i64)\n";
+        assert!(text.contains(&format!("{}{}", doc, func)), "{}", text);
+        // A function without a doc has no comment before it
+        assert!(text.contains("  )\n  (func $bare"), "{}", text);
+    }
+
+    #[test]
+    fn test_wat_of_a_module() {
+        let source = "static mut N: i64 = 7;
+            fn f(c: bool) -> i64 { if c { N = 1; } N }";
+        let text = wat(source);
+        let expected = [
+            "(module",
+            "  (type (;0;) (func (param i32) (result i64)))",
+            "  (func $f (type 0) (param i32) (result i64)",
+            "    (local i64)",
+            "    local.get 0",
+            "    if",
+            "      i64.const 1",
+            "      local.set 1",
+            "      local.get 1",
+            "      global.set 0",
+            "    end",
+            "    i64.const 0",
+            "    local.set 1",
+            "    global.get 0",
+            "    local.set 1",
+            "    local.get 1",
+            "  )",
+            "  (memory (;0;) 1)",
+            "  (global (;0;) (mut i64) (i64.const 7))",
+            "  (export \"f\" (func $f))",
+            ")",
+        ];
+        assert_eq!(text.lines().collect::<Vec<_>>(), expected, "{}", text);
+    }
+
+    #[test]
+    fn test_wat_strings_and_names_are_escaped() {
+        assert_eq!(string(b"a\"b\\c\n"), "\"a\\\"b\\\\c\\0a\"");
+        assert_eq!(identifier("Point::new<i64, bool>"), "Point::new<i64__bool>");
+        assert_eq!(identifier("a b"), "a_b");
+    }
+}
@@ -0,0 +1,388 @@
+//! End-to-end tests: compile Nova source and run the WASM it becomes
+
+mod wasm;
+
+use wasm::{run_wasm, run_wasm_memory, run_wasm_results, Value};
+
+fn compile(source: &str) -> Vec<u8> {
+    nova::compile_str(source).unwrap_or_else(|errors| panic!("{:?}", errors))
+}
+
+#[test]
+fn test_add() {
+    let wasm = compile("fn add(a: i64, b: i64) -> i64 { a + b }");
+    let sum = run_wasm(&wasm, "add", &[Value::I64(40), Value::I64(2)]);
+    assert_eq!(sum, Value::I64(42));
+}
+
+#[test]
+fn test_recursive_fib() {
+    let wasm = compile("fn fib(n: i64) -> i64 { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }");
+    let fibs: Vec<_> = (0..12)
+        .map(|n| run_wasm(&wasm, "fib", &[Value::I64(n)]))
+        .collect();
+    let expected = [0, 1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89].map(Value::I64);
+    assert_eq!(fibs, expected);
+}
+
+#[test]
+fn test_iterative_fib() {
+    let source = "fn fib(n: i64) -> i64 {
+        let mut a = 0;
+        let mut b = 1;
+        let mut i = 0;
+        while i < n {
+            let next = a + b;
+            a = b;
+            b = next;
+            i = i + 1;
+        }
+        a
+    }";
+    let wasm = compile(source);
+    assert_eq!(
+        run_wasm(&wasm, "fib", &[Value::I64(50)]),
+        Value::I64(12586269025)
+    );
+}
+
+#[test]
+fn test_counting_loop() {
+    let wasm = compile("fn count() -> i64 { let mut i = 0; while i < 10 { i = i + 1; } i }");
+    assert_eq!(run_wasm(&wasm, "count", &[]), Value::I64(10));
+}
+
+#[test]
+fn test_break_and_continue() {
+    let source = "fn odd_until(stop: i64) -> i64 {
+        let mut i = 0;
+        let mut odd = 0;
+        while true {
+            i = i + 1;
+            if i > stop { break; }
+            if i % 2 == 0 { continue; }
+            odd = odd + 1;
+        }
+        odd
+    }
+    fn first_over(limit: i64) -> i64 {
+        let mut i = 1;
+        loop { i = i * 3; if i > limit { break i; } }
+    }";
+    let wasm = compile(source);
+    assert_eq!(
+        run_wasm(&wasm, "odd_until", &[Value::I64(9)]),
+        Value::I64(5)
+    );
+    assert_eq!(
+        run_wasm(&wasm, "first_over", &[Value::I64(100)]),
+        Value::I64(243)
+    );
+}
+
+#[test]
+fn test_nested_loops() {
+    let source = "fn pairs(n: i64) -> i64 {
+        let mut i = 0;
+        let mut count = 0;
+        while i < n {
+            let mut j = 0;
+            while j < i { j = j + 1; count = count + 1; }
+            i = i + 1;
+        }
+        count
+    }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "pairs", &[Value::I64(5)]), Value::I64(10));
+}
+
+#[test]
+fn test_range_for() {
+    let source = "fn sum() -> i64 {
+        let mut total = 0;
+        for i in 1..=5 { total = total + i; }
+        total
+    }
+    fn odd_sum(n: i64) -> i64 {
+        let mut total = 0;
+        for i in 0..n {
+            if i % 2 == 0 { continue; }
+            if i > 7 { break; }
+            total = total + i;
+        }
+        total
+    }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "sum", &[]), Value::I64(15));
+    // `continue` still steps the counter: 1 + 3 + 5 + 7
+    assert_eq!(
+        run_wasm(&wasm, "odd_sum", &[Value::I64(100)]),
+        Value::I64(16)
+    );
+    assert_eq!(run_wasm(&wasm, "odd_sum", &[Value::I64(4)]), Value::I64(4));
+}
+
+#[test]
+fn test_if_else_takes_either_branch() {
+    let wasm = compile("fn pick(c: bool) -> i64 { if c { 10 } else { 20 } }");
+    assert_eq!(run_wasm(&wasm, "pick", &[Value::I32(1)]), Value::I64(10));
+    assert_eq!(run_wasm(&wasm, "pick", &[Value::I32(0)]), Value::I64(20));
+}
+
+#[test]
+fn test_early_return() {
+    let source = "fn clamp(a: i64) -> i64 { if a > 5 { return 7; } a }
+        fn both(c: bool) -> i64 { if c { return 1; } else { return 2; } }
+        fn from_loop() -> i64 {
+            let mut i = 0;
+            while i < 10 { i = i + 1; if i == 3 { return 100; } }
+            i
+        }
+        fn as_value(c: bool) -> i64 { let x = if c { return 7; } else { 5 }; x + 1 }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "clamp", &[Value::I64(9)]), Value::I64(7));
+    assert_eq!(run_wasm(&wasm, "clamp", &[Value::I64(2)]), Value::I64(2));
+    assert_eq!(run_wasm(&wasm, "both", &[Value::I32(1)]), Value::I64(1));
+    assert_eq!(run_wasm(&wasm, "both", &[Value::I32(0)]), Value::I64(2));
+    assert_eq!(run_wasm(&wasm, "from_loop", &[]), Value::I64(100));
+    assert_eq!(run_wasm(&wasm, "as_value", &[Value::I32(1)]), Value::I64(7));
+    assert_eq!(run_wasm(&wasm, "as_value", &[Value::I32(0)]), Value::I64(6));
+}
+
+#[test]
+fn test_else_if_chain() {
+    let source = "fn sign(x: i64) -> i64 { if x < 0 { -1 } else if x == 0 { 0 } else { 1 } }";
+    let wasm = compile(source);
+    for (x, sign) in [(-7, -1), (0, 0), (3, 1)] {
+        assert_eq!(run_wasm(&wasm, "sign", &[Value::I64(x)]), Value::I64(sign));
+    }
+}
+
+#[test]
+fn test_match_on_enum() {
+    let source = "enum Shape { Square(i64), Rect(i64, i64), Empty }
+        fn area(s: Shape) -> i64 {
+            match s { Shape::Square(x) => x * x, Shape::Rect(w, h) => w * h, Shape::Empty => 0, }
+        }
+        fn rect() -> i64 { area(Shape::Rect(3, 4)) }
+        fn empty() -> i64 { area(Shape::Empty) }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "rect", &[]), Value::I64(12));
+    assert_eq!(run_wasm(&wasm, "empty", &[]), Value::I64(0));
+}
+
+#[test]
+fn test_tuple_is_returned_as_multiple_values() {
+    let wasm = compile("fn swap(a: i64, b: i64) -> (i64, i64) { (b, a) }");
+    let results = run_wasm_results(&wasm, "swap", &[Value::I64(1), Value::I64(2)]);
+    assert_eq!(results, Ok(vec![Value::I64(2), Value::I64(1)]));
+}
+
+#[test]
+fn test_tuple_patterns_destructure() {
+    let source = "fn swap(a: i64, b: i64) -> (i64, i64) { (b, a) }
+        fn diff() -> i64 { let (x, y): (i64, i64) = swap(3, 10); x - y }
+        fn pick((n, take): (i64, bool)) -> i64 { if take { n } else { 0 } }
+        fn nested() -> i64 { let ((a, b), c) = ((1, 2), 3); a * 100 + b * 10 + c }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "diff", &[]), Value::I64(7));
+    assert_eq!(
+        run_wasm(&wasm, "pick", &[Value::I64(5), Value::I32(1)]),
+        Value::I64(5)
+    );
+    assert_eq!(
+        run_wasm(&wasm, "pick", &[Value::I64(5), Value::I32(0)]),
+        Value::I64(0)
+    );
+    assert_eq!(run_wasm(&wasm, "nested", &[]), Value::I64(123));
+}
+
+#[test]
+fn test_static_mut_reads_back_its_write() {
+    let source = "static mut COUNTER: i64 = 0;
+        fn bump() -> i64 { COUNTER = COUNTER + 1; COUNTER }";
+    let wasm = compile(source);
+    // Each run is a fresh instance, starting from the initializer
+    assert_eq!(run_wasm(&wasm, "bump", &[]), Value::I64(1));
+    assert_eq!(run_wasm(&wasm, "bump", &[]), Value::I64(1));
+}
+
+#[test]
+fn test_division_by_zero_traps() {
+    let wasm = compile("fn div(a: i64, b: i64) -> i64 { a / b }");
+    assert_eq!(
+        run_wasm(&wasm, "div", &[Value::I64(7), Value::I64(2)]),
+        Value::I64(3)
+    );
+    let trap = run_wasm_results(&wasm, "div", &[Value::I64(7), Value::I64(0)]);
+    assert_eq!(trap, Err("integer divide by zero".to_string()));
+}
+
+#[test]
+fn test_range_index_takes_a_slice() {
+    let source = "fn mid() -> i64 {
+        let arr = [10, 20, 30, 40];
+        let s = arr[1..3];
+        s[0] * 100 + s[1]
+    }
+    fn pieces() -> i64 {
+        let arr = [10, 20, 30, 40];
+        match arr[1..3] {
+            [a, b] => a + b,
+            _ => 0,
+        }
+    }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "mid", &[]), Value::I64(2030));
+    // The slice has two elements, so only `[a, b]` matches it
+    assert_eq!(run_wasm(&wasm, "pieces", &[]), Value::I64(50));
+}
+
+#[test]
+fn test_casts_convert_at_runtime() {
+    let source = "fn to_float(x: i64) -> f64 { x as f64 }
+    fn to_int(x: f64) -> i64 { x as i64 }
+    fn wrap(x: i64) -> i32 { x as i32 }
+    fn widen(x: u32) -> i64 { x as i64 }
+    fn byte(x: i64) -> i64 { (x as u8) as i64 }
+    fn signed_byte(x: i64) -> i64 { (x as i8) as i64 }
+    fn saturate(x: f64) -> u8 { x as u8 }";
+    let wasm = compile(source);
+    let run = |name: &str, arg: Value| run_wasm(&wasm, name, &[arg]);
+    assert_eq!(run("to_float", Value::I64(-7)), Value::F64(-7.0));
+    assert_eq!(run("to_int", Value::F64(-3.9)), Value::I64(-3));
+    assert_eq!(run("to_int", Value::F64(1e300)), Value::I64(i64::MAX));
+    assert_eq!(run("wrap", Value::I64(0x1_0000_0005)), Value::I32(5));
+    assert_eq!(run("widen", Value::I32(-1)), Value::I64(u32::MAX as i64));
+    assert_eq!(run("byte", Value::I64(300)), Value::I64(44));
+    assert_eq!(run("signed_byte", Value::I64(200)), Value::I64(-56));
+    assert_eq!(run("saturate", Value::F64(300.5)), Value::I32(255));
+    assert_eq!(run("saturate", Value::F64(-1.0)), Value::I32(0));
+}
+
+#[test]
+fn test_match_tests_array_and_tuple_elements() {
+    let source = "fn empty() -> i64 { match [1, 2, 3] { [] => 0, _ => 1 } }
+    fn first(n: i64) -> i64 { match (n, 2) { (5, _) => 10, _ => 20 } }
+    fn ends(n: i64) -> i64 {
+        match [n, 2, 3] { [1, ..] => 100, [first, .., 3] => first, _ => 7 }
+    }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "empty", &[]), Value::I64(1));
+    assert_eq!(run_wasm(&wasm, "first", &[Value::I64(5)]), Value::I64(10));
+    assert_eq!(run_wasm(&wasm, "first", &[Value::I64(1)]), Value::I64(20));
+    assert_eq!(run_wasm(&wasm, "ends", &[Value::I64(1)]), Value::I64(100));
+    assert_eq!(run_wasm(&wasm, "ends", &[Value::I64(8)]), Value::I64(8));
+}
+
+#[test]
+fn test_match_tests_variant_fields_and_guards() {
+    let source = "enum Opt { No, Yes(i64) }
+    fn pick(n: i64) -> i64 {
+        match Opt::Yes(n) { Opt::Yes(5) => 1, Opt::Yes(x) if x > 10 => 2, Opt::Yes(x) => x, Opt::No => 0 }
+    }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "pick", &[Value::I64(5)]), Value::I64(1));
+    assert_eq!(run_wasm(&wasm, "pick", &[Value::I64(11)]), Value::I64(2));
+    assert_eq!(run_wasm(&wasm, "pick", &[Value::I64(7)]), Value::I64(7));
+}
+
+#[test]
+fn test_unsigned_operands_use_unsigned_ops() {
+    let big = Value::I32(0xFFFF_FFFE_u32 as i32);
+    let wasm = compile("fn half(n: u32) -> u32 { n / 2 }");
+    assert_eq!(run_wasm(&wasm, "half", &[big]), Value::I32(0x7FFF_FFFF));
+    let wasm = compile("fn rem(n: u32) -> u32 { n % 10 }");
+    assert_eq!(run_wasm(&wasm, "rem", &[big]), Value::I32(4));
+
+    let wasm = compile("fn above(n: u32) -> bool { n > 1 }");
+    assert_eq!(run_wasm(&wasm, "above", &[big]), Value::I32(1));
+    let wasm = compile("fn below(n: u32) -> bool { n < 1 }");
+    assert_eq!(run_wasm(&wasm, "below", &[big]), Value::I32(0));
+    let wasm = compile("fn at_least(n: u32) -> bool { n >= 1 }");
+    assert_eq!(run_wasm(&wasm, "at_least", &[big]), Value::I32(1));
+    let wasm = compile("fn at_most(n: u32) -> bool { n <= 1 }");
+    assert_eq!(run_wasm(&wasm, "at_most", &[big]), Value::I32(0));
+
+    let wasm = compile("fn shift(n: u64) -> u64 { n >> 1 }");
+    assert_eq!(
+        run_wasm(&wasm, "shift", &[Value::I64(-2)]),
+        Value::I64(0x7FFF_FFFF_FFFF_FFFF)
+    );
+    // Signed operands keep the signed ops
+    let wasm = compile("fn shift(n: i64) -> i64 { n >> 1 }");
+    assert_eq!(run_wasm(&wasm, "shift", &[Value::I64(-2)]), Value::I64(-1));
+}
+
+#[test]
+fn test_unsigned_range_patterns_compare_unsigned() {
+    let wasm =
+        compile("fn large(n: u32) -> bool { match n { 5..=4294967295 => true, _ => false } }");
+    assert_eq!(run_wasm(&wasm, "large", &[Value::I32(10)]), Value::I32(1));
+    assert_eq!(run_wasm(&wasm, "large", &[Value::I32(4)]), Value::I32(0));
+}
+
+#[test]
+fn test_i32_function_under_i64_default() {
+    let wasm = compile("fn double(n: i32) -> i32 { n * 2 }");
+    assert_eq!(run_wasm(&wasm, "double", &[Value::I32(21)]), Value::I32(42));
+
+    let source = "static BASE: i32 = 5;
+    fn count(n: i32) -> i32 {
+        let mut total = BASE;
+        for i in 0..n {
+            total = total + i;
+        }
+        total
+    }";
+    let wasm = compile(source);
+    assert_eq!(run_wasm(&wasm, "count", &[Value::I32(4)]), Value::I32(11));
+}
+
+#[test]
+fn test_memory_size_is_i32() {
+    let wasm = compile("fn pages() -> i32 { memory_grow(2); memory_size() }");
+    assert_eq!(run_wasm(&wasm, "pages", &[]), Value::I32(3));
+}
+
+#[test]
+fn test_nested_tuples_pass_through_memory() {
+    let source = "fn nest(a: i64) -> ((i64, i64), i64) { ((a, a + 1), a + 2) }
+    fn digits(t: ((i64, i64), i64)) -> i64 { let ((x, y), z) = t; x * 100 + y * 10 + z }
+    fn both(a: i64, b: i64) -> i64 {
+        let first = nest(a);
+        let second = nest(b);
+        digits(first) * 1000 + digits(second)
+    }";
+    let wasm = compile(source);
+    // Each call's tuple gets its own memory, so `second` leaves `first` alone
+    let result = run_wasm(&wasm, "both", &[Value::I64(1), Value::I64(5)]);
+    assert_eq!(result, Value::I64(123_567));
+}
+
+#[test]
+fn test_float_negation() {
+    let wasm = compile("fn neg(x: f64) -> f64 { -x }");
+    assert_eq!(run_wasm(&wasm, "neg", &[Value::F64(2.5)]), Value::F64(-2.5));
+    // `neg` flips the sign of zero, where `0.0 - x` wouldn't
+    let Value::F64(zero) = run_wasm(&wasm, "neg", &[Value::F64(0.0)]) else {
+        panic!("expected an f64");
+    };
+    assert!(zero == 0.0 && zero.is_sign_negative());
+}
+
+#[test]
+fn test_strings_survive_allocations() {
+    let source = r#"enum Shape { Circle(i64), Rect(i64, i64) }
+    fn f() -> str {
+        let s = Shape::Rect(7, 9);
+        let xs = [1, 2, 3];
+        "hello"
+    }"#;
+    let wasm = compile(source);
+    let (results, memory) = run_wasm_memory(&wasm, "f", &[]).unwrap();
+    let [Value::I32(addr), Value::I32(len)] = results[..] else {
+        panic!("expected an address and a length, got {:?}", results);
+    };
+    assert_eq!(&memory[addr as usize..(addr + len) as usize], b"hello");
+}
@@ -0,0 +1,771 @@
+//! A tiny WebAssembly interpreter for the end-to-end tests
+//!
+//! Just enough of the spec to run what `codegen` emits: the type, function,
+//! memory, global, export, code and data sections, and the instructions for
+//! constants, integer and float arithmetic, comparisons, locals, globals,
+//! memory, calls, structured control flow and `return`. Anything else is an
+//! error naming the opcode, so a test fails clearly when codegen starts
+//! emitting something new.
+//!
+//! It doesn't validate the module: a binary an engine would reject may
+//! still run here. Engines are the judge of validity, this is the judge of
+//! what the code computes.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A WASM value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+/// Run the exported function `func` with `args`, returning its one result.
+///
+/// Panics if the module can't be decoded, the call traps, or the function
+/// doesn't return exactly one value.
+pub fn run_wasm(bytes: &[u8], func: &str, args: &[Value]) -> Value {
+    match run_wasm_results(bytes, func, args) {
+        Ok(results) if results.len() == 1 => results[0],
+        Ok(results) => panic!("`{}` returned {:?}, not one value", func, results),
+        Err(trap) => panic!("`{}` trapped: {}", func, trap),
+    }
+}
+
+/// Run the exported function `func` with `args`, returning all its results
+/// (none, one, or several for a multi-value return) or why it trapped.
+pub fn run_wasm_results(bytes: &[u8], func: &str, args: &[Value]) -> Result<Vec<Value>, String> {
+    let mut instance = Instance::new(bytes)?;
+    let index = *instance
+        .exports
+        .get(func)
+        .ok_or_else(|| format!("no exported function `{}`", func))?;
+    instance.call(index, args.to_vec(), 0)
+}
+
+/// Like [`run_wasm_results`], also returning the memory as the call left it
+pub fn run_wasm_memory(
+    bytes: &[u8],
+    func: &str,
+    args: &[Value],
+) -> Result<(Vec<Value>, Vec<u8>), String> {
+    let mut instance = Instance::new(bytes)?;
+    let index = *instance
+        .exports
+        .get(func)
+        .ok_or_else(|| format!("no exported function `{}`", func))?;
+    let results = instance.call(index, args.to_vec(), 0)?;
+    Ok((results, instance.memory))
+}
+
+/// Calls nested deeper than this trap instead of overflowing the stack
+const MAX_CALL_DEPTH: usize = 1_000;
+
+const PAGE_SIZE: usize = 65_536;
+
+/// A value type, by its encoding
+type ValType = u8;
+
+struct FuncType {
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+}
+
+struct Func {
+    type_index: u32,
+    /// Declared locals, after the parameters
+    locals: Vec<ValType>,
+    /// The instructions, ending with the function's `end`
+    code: Rc<[u8]>,
+    /// The `else` (if any) and `end` of each `block`, `loop` and `if`, by
+    /// the position of its opcode
+    ends: Rc<HashMap<usize, (Option<usize>, usize)>>,
+}
+
+struct Instance {
+    types: Vec<FuncType>,
+    funcs: Vec<Func>,
+    globals: Vec<Value>,
+    memory: Vec<u8>,
+    exports: HashMap<String, u32>,
+}
+
+/// A cursor over bytes, reading the binary format's encodings
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| format!("unexpected end of input at {}", self.pos))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + len;
+        let bytes = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or_else(|| format!("{} bytes past the end at {}", len, self.pos))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut result: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            result |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return u32::try_from(result).map_err(|_| "u32 out of range".to_string());
+            }
+            shift += 7;
+            if shift > 28 {
+                return Err("u32 too long".to_string());
+            }
+        }
+    }
+
+    /// A signed LEB128 integer of up to `bits` bits
+    fn signed(&mut self, bits: u32) -> Result<i64, String> {
+        let mut result: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            result |= i64::from(byte & 0x7F) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1 << shift;
+                }
+                return Ok(result);
+            }
+            if shift >= bits + 7 {
+                return Err(format!("s{} too long", bits));
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "name isn't UTF-8".to_string())
+    }
+
+    /// A constant expression (one `*.const` or `global.get`, then `end`)
+    fn const_expr(&mut self, globals: &[Value]) -> Result<Value, String> {
+        let value = match self.byte()? {
+            0x41 => Value::I32(self.signed(32)? as i32),
+            0x42 => Value::I64(self.signed(64)?),
+            0x43 => Value::F32(f32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+            0x44 => Value::F64(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            0x23 => *globals
+                .get(self.u32()? as usize)
+                .ok_or("global.get of an unknown global")?,
+            op => return Err(format!("unsupported constant expression 0x{:02X}", op)),
+        };
+        match self.byte()? {
+            0x0B => Ok(value),
+            _ => Err("constant expression without end".to_string()),
+        }
+    }
+}
+
+/// The zero value of a value type
+fn zero(ty: ValType) -> Result<Value, String> {
+    match ty {
+        0x7F => Ok(Value::I32(0)),
+        0x7E => Ok(Value::I64(0)),
+        0x7D => Ok(Value::F32(0.0)),
+        0x7C => Ok(Value::F64(0.0)),
+        _ => Err(format!("unknown value type 0x{:02X}", ty)),
+    }
+}
+
+/// Skip the immediates of the instruction `op`, whose opcode was just read
+fn skip_immediates(reader: &mut Reader, op: u8) -> Result<(), String> {
+    match op {
+        0x02..=0x04 => {
+            reader.signed(33)?; // block type
+        }
+        0x0C | 0x0D | 0x10 | 0x20..=0x24 => {
+            reader.u32()?;
+        }
+        0x0E => {
+            for _ in 0..=reader.u32()? {
+                reader.u32()?;
+            }
+        }
+        0x11 | 0x28..=0x3E => {
+            reader.u32()?;
+            reader.u32()?;
+        }
+        0x3F | 0x40 => {
+            reader.byte()?;
+        }
+        0xFC => {
+            reader.u32()?; // the instruction after the prefix
+        }
+        0x41 => {
+            reader.signed(32)?;
+        }
+        0x42 => {
+            reader.signed(64)?;
+        }
+        0x43 => {
+            reader.take(4)?;
+        }
+        0x44 => {
+            reader.take(8)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Find the `else` and `end` of every block in `code`
+fn match_ends(code: &[u8]) -> Result<HashMap<usize, (Option<usize>, usize)>, String> {
+    let mut ends = HashMap::new();
+    let mut open: Vec<(usize, Option<usize>)> = Vec::new();
+    let mut reader = Reader::new(code);
+    while !reader.at_end() {
+        let pos = reader.pos;
+        let op = reader.byte()?;
+        match op {
+            0x02..=0x04 => open.push((pos, None)),
+            0x05 => match open.last_mut() {
+                Some((_, else_pos)) => *else_pos = Some(pos),
+                None => return Err("else outside a block".to_string()),
+            },
+            0x0B => {
+                // The function's own `end` closes no block
+                if let Some((start, else_pos)) = open.pop() {
+                    ends.insert(start, (else_pos, pos));
+                }
+            }
+            _ => {}
+        }
+        skip_immediates(&mut reader, op)?;
+    }
+    Ok(ends)
+}
+
+/// What a `br` to a label does
+#[derive(Debug, Clone, Copy)]
+struct Label {
+    /// Where execution continues: the start of a `loop`'s body, or just
+    /// after a `block`'s or `if`'s `end`
+    target: usize,
+    /// Values a branch carries: a loop's parameters (none here) or the
+    /// block's results
+    arity: usize,
+    /// Operand stack height when the block was entered
+    height: usize,
+    is_loop: bool,
+}
+
+impl Instance {
+    fn new(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(8)? != b"\0asm\x01\0\0\0" {
+            return Err("not a WASM version 1 module".to_string());
+        }
+        let mut instance = Instance {
+            types: Vec::new(),
+            funcs: Vec::new(),
+            globals: Vec::new(),
+            memory: Vec::new(),
+            exports: HashMap::new(),
+        };
+        let mut func_types = Vec::new();
+        while !reader.at_end() {
+            let id = reader.byte()?;
+            let len = reader.u32()? as usize;
+            let mut section = Reader::new(reader.take(len)?);
+            match id {
+                1 => {
+                    for _ in 0..section.u32()? {
+                        if section.byte()? != 0x60 {
+                            return Err("expected a function type".to_string());
+                        }
+                        let count = section.u32()? as usize;
+                        let params = section.take(count)?.to_vec();
+                        let count = section.u32()? as usize;
+                        let results = section.take(count)?.to_vec();
+                        instance.types.push(FuncType { params, results });
+                    }
+                }
+                3 => {
+                    for _ in 0..section.u32()? {
+                        func_types.push(section.u32()?);
+                    }
+                }
+                5 if section.u32()? > 0 => {
+                    let flags = section.byte()?;
+                    let min = section.u32()? as usize;
+                    if flags & 1 != 0 {
+                        section.u32()?; // maximum
+                    }
+                    instance.memory = vec![0; min * PAGE_SIZE];
+                }
+                6 => {
+                    for _ in 0..section.u32()? {
+                        section.take(2)?; // type and mutability
+                        let value = section.const_expr(&instance.globals)?;
+                        instance.globals.push(value);
+                    }
+                }
+                7 => {
+                    for _ in 0..section.u32()? {
+                        let name = section.name()?;
+                        let kind = section.byte()?;
+                        let index = section.u32()?;
+                        if kind == 0x00 {
+                            instance.exports.insert(name, index);
+                        }
+                    }
+                }
+                10 => {
+                    let count = section.u32()? as usize;
+                    if count != func_types.len() {
+                        return Err("function and code sections disagree".to_string());
+                    }
+                    for &type_index in &func_types {
+                        let size = section.u32()? as usize;
+                        let mut body = Reader::new(section.take(size)?);
+                        let mut locals = Vec::new();
+                        for _ in 0..body.u32()? {
+                            let count = body.u32()? as usize;
+                            let ty = body.byte()?;
+                            locals.extend(std::iter::repeat_n(ty, count));
+                        }
+                        let code: Rc<[u8]> = body.bytes[body.pos..].into();
+                        let ends = Rc::new(match_ends(&code)?);
+                        instance.funcs.push(Func {
+                            type_index,
+                            locals,
+                            code,
+                            ends,
+                        });
+                    }
+                }
+                11 => {
+                    for _ in 0..section.u32()? {
+                        if section.u32()? != 0 {
+                            return Err("only active segments in memory 0 are supported".into());
+                        }
+                        let offset = match section.const_expr(&instance.globals)? {
+                            Value::I32(offset) => offset as u32 as usize,
+                            _ => return Err("data offset isn't an i32".to_string()),
+                        };
+                        let len = section.u32()? as usize;
+                        let data = section.take(len)?;
+                        instance
+                            .memory
+                            .get_mut(offset..offset + len)
+                            .ok_or("data segment out of bounds")?
+                            .copy_from_slice(data);
+                    }
+                }
+                // Custom sections, and ones codegen doesn't emit
+                _ => {}
+            }
+        }
+        Ok(instance)
+    }
+
+    /// Call function `index` with `args`, `depth` calls deep
+    fn call(&mut self, index: u32, args: Vec<Value>, depth: usize) -> Result<Vec<Value>, String> {
+        if depth > MAX_CALL_DEPTH {
+            return Err("call stack exhausted".to_string());
+        }
+        let func = self
+            .funcs
+            .get(index as usize)
+            .ok_or_else(|| format!("call to unknown function {}", index))?;
+        let ty = &self.types[func.type_index as usize];
+        if args.len() != ty.params.len() {
+            return Err(format!(
+                "function {} takes {} arguments, got {}",
+                index,
+                ty.params.len(),
+                args.len()
+            ));
+        }
+        let result_count = ty.results.len();
+        let mut locals = args;
+        for &local in &func.locals {
+            locals.push(zero(local)?);
+        }
+        // Hold the code apart from the instance, which the call can change
+        let code = Rc::clone(&func.code);
+        let ends = Rc::clone(&func.ends);
+        self.execute(&code, &ends, &mut locals, result_count, depth)
+    }
+
+    fn execute(
+        &mut self,
+        code: &[u8],
+        ends: &HashMap<usize, (Option<usize>, usize)>,
+        locals: &mut [Value],
+        result_count: usize,
+        depth: usize,
+    ) -> Result<Vec<Value>, String> {
+        let mut stack: Vec<Value> = Vec::new();
+        let mut labels: Vec<Label> = Vec::new();
+        let mut reader = Reader::new(code);
+
+        macro_rules! pop {
+            () => {
+                stack.pop().ok_or("operand stack underflow")?
+            };
+            ($variant:ident) => {
+                match stack.pop() {
+                    Some(Value::$variant(v)) => v,
+                    other => {
+                        return Err(format!(
+                            "expected {} on the stack, found {:?}",
+                            stringify!($variant),
+                            other
+                        ))
+                    }
+                }
+            };
+        }
+        macro_rules! unary {
+            ($variant:ident, $result:ident, |$a:ident| $e:expr) => {{
+                let $a = pop!($variant);
+                stack.push(Value::$result($e));
+            }};
+        }
+        macro_rules! binary {
+            ($variant:ident, $result:ident, |$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop!($variant);
+                let $a = pop!($variant);
+                stack.push(Value::$result($e));
+            }};
+        }
+        macro_rules! load {
+            ($size:expr, |$bytes:ident| $e:expr) => {{
+                reader.u32()?; // alignment
+                let offset = reader.u32()? as usize;
+                let addr = pop!(I32) as u32 as usize + offset;
+                let $bytes: [u8; $size] = self
+                    .memory
+                    .get(addr..addr + $size)
+                    .ok_or_else(|| format!("load out of bounds at {}", addr))?
+                    .try_into()
+                    .unwrap();
+                stack.push($e);
+            }};
+        }
+        macro_rules! store {
+            ($variant:ident, |$v:ident| $bytes:expr) => {{
+                reader.u32()?; // alignment
+                let offset = reader.u32()? as usize;
+                let $v = pop!($variant);
+                let addr = pop!(I32) as u32 as usize + offset;
+                let bytes = $bytes;
+                self.memory
+                    .get_mut(addr..addr + bytes.len())
+                    .ok_or_else(|| format!("store out of bounds at {}", addr))?
+                    .copy_from_slice(&bytes);
+            }};
+        }
+        let trap_div_zero = || "integer divide by zero".to_string();
+
+        loop {
+            let pos = reader.pos;
+            let op = reader.byte()?;
+            match op {
+                0x00 => return Err("unreachable".to_string()),
+                0x01 => {}
+                0x02..=0x04 => {
+                    let block_type = reader.signed(33)?;
+                    let arity = match block_type {
+                        -64 => 0, // 0x40, empty
+                        t if t < 0 => 1,
+                        t => self.types[t as usize].results.len(),
+                    };
+                    let (else_pos, end) = *ends.get(&pos).ok_or("block without end")?;
+                    let mut label = Label {
+                        target: end + 1,
+                        arity,
+                        height: stack.len(),
+                        is_loop: op == 0x03,
+                    };
+                    if op == 0x03 {
+                        label.target = reader.pos;
+                        label.arity = 0;
+                    }
+                    if op == 0x04 {
+                        label.height -= 1;
+                        if pop!(I32) == 0 {
+                            match else_pos {
+                                Some(else_pos) => reader.pos = else_pos + 1,
+                                None => {
+                                    reader.pos = end + 1;
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    labels.push(label);
+                }
+                0x05 => {
+                    // The end of the `then` arm: skip the `else` arm
+                    let label = labels.pop().ok_or("else outside a block")?;
+                    reader.pos = label.target;
+                }
+                0x0B => {
+                    if labels.pop().is_none() {
+                        break;
+                    }
+                }
+                0x0C | 0x0D => {
+                    let depth_out = reader.u32()? as usize;
+                    if op == 0x0D && pop!(I32) == 0 {
+                        continue;
+                    }
+                    if depth_out == labels.len() {
+                        // A branch to the function's own label returns
+                        break;
+                    }
+                    let index = labels
+                        .len()
+                        .checked_sub(depth_out + 1)
+                        .ok_or("branch to an unknown label")?;
+                    let label = labels[index];
+                    let carried = stack.split_off(stack.len() - label.arity);
+                    stack.truncate(label.height);
+                    stack.extend(carried);
+                    // A loop's label stays: the branch goes round again
+                    labels.truncate(if label.is_loop { index + 1 } else { index });
+                    reader.pos = label.target;
+                }
+                0x0F => break,
+                0x10 => {
+                    let callee = reader.u32()?;
+                    let ty = self
+                        .funcs
+                        .get(callee as usize)
+                        .map(|f| &self.types[f.type_index as usize])
+                        .ok_or_else(|| format!("call to unknown function {}", callee))?;
+                    let count = ty.params.len();
+                    let args = stack.split_off(stack.len() - count);
+                    let results = self.call(callee, args, depth + 1)?;
+                    stack.extend(results);
+                }
+                0x1A => {
+                    pop!();
+                }
+                0x1B => {
+                    let cond = pop!(I32);
+                    let b = pop!();
+                    let a = pop!();
+                    stack.push(if cond != 0 { a } else { b });
+                }
+                0x20 => {
+                    let index = reader.u32()? as usize;
+                    stack.push(*locals.get(index).ok_or("unknown local")?);
+                }
+                0x21 | 0x22 => {
+                    let index = reader.u32()? as usize;
+                    let value = pop!();
+                    *locals.get_mut(index).ok_or("unknown local")? = value;
+                    if op == 0x22 {
+                        stack.push(value);
+                    }
+                }
+                0x23 => {
+                    let index = reader.u32()? as usize;
+                    stack.push(*self.globals.get(index).ok_or("unknown global")?);
+                }
+                0x24 => {
+                    let index = reader.u32()? as usize;
+                    let value = pop!();
+                    *self.globals.get_mut(index).ok_or("unknown global")? = value;
+                }
+                0x28 => load!(4, |b| Value::I32(i32::from_le_bytes(b))),
+                0x29 => load!(8, |b| Value::I64(i64::from_le_bytes(b))),
+                0x2A => load!(4, |b| Value::F32(f32::from_le_bytes(b))),
+                0x2B => load!(8, |b| Value::F64(f64::from_le_bytes(b))),
+                0x2C => load!(1, |b| Value::I32(b[0] as i8 as i32)),
+                0x2D => load!(1, |b| Value::I32(b[0] as i32)),
+                0x2E => load!(2, |b| Value::I32(i16::from_le_bytes(b) as i32)),
+                0x2F => load!(2, |b| Value::I32(u16::from_le_bytes(b) as i32)),
+                0x36 => store!(I32, |v| v.to_le_bytes()),
+                0x37 => store!(I64, |v| v.to_le_bytes()),
+                0x38 => store!(F32, |v| v.to_le_bytes()),
+                0x39 => store!(F64, |v| v.to_le_bytes()),
+                0x3A => store!(I32, |v| [v as u8]),
+                0x3B => store!(I32, |v| (v as u16).to_le_bytes()),
+                0x3F => {
+                    reader.byte()?;
+                    stack.push(Value::I32((self.memory.len() / PAGE_SIZE) as i32));
+                }
+                0x40 => {
+                    reader.byte()?;
+                    let pages = pop!(I32) as u32 as usize;
+                    let old = self.memory.len() / PAGE_SIZE;
+                    self.memory.resize((old + pages) * PAGE_SIZE, 0);
+                    stack.push(Value::I32(old as i32));
+                }
+                0x41 => stack.push(Value::I32(reader.signed(32)? as i32)),
+                0x42 => stack.push(Value::I64(reader.signed(64)?)),
+                0x43 => {
+                    let bytes = reader.take(4)?.try_into().unwrap();
+                    stack.push(Value::F32(f32::from_le_bytes(bytes)));
+                }
+                0x44 => {
+                    let bytes = reader.take(8)?.try_into().unwrap();
+                    stack.push(Value::F64(f64::from_le_bytes(bytes)));
+                }
+
+                // i32 comparisons
+                0x45 => unary!(I32, I32, |a| (a == 0) as i32),
+                0x46 => binary!(I32, I32, |a, b| (a == b) as i32),
+                0x47 => binary!(I32, I32, |a, b| (a != b) as i32),
+                0x48 => binary!(I32, I32, |a, b| (a < b) as i32),
+                0x49 => binary!(I32, I32, |a, b| ((a as u32) < (b as u32)) as i32),
+                0x4A => binary!(I32, I32, |a, b| (a > b) as i32),
+                0x4B => binary!(I32, I32, |a, b| ((a as u32) > (b as u32)) as i32),
+                0x4C => binary!(I32, I32, |a, b| (a <= b) as i32),
+                0x4D => binary!(I32, I32, |a, b| ((a as u32) <= (b as u32)) as i32),
+                0x4E => binary!(I32, I32, |a, b| (a >= b) as i32),
+                0x4F => binary!(I32, I32, |a, b| ((a as u32) >= (b as u32)) as i32),
+
+                // i64 comparisons
+                0x50 => unary!(I64, I32, |a| (a == 0) as i32),
+                0x51 => binary!(I64, I32, |a, b| (a == b) as i32),
+                0x52 => binary!(I64, I32, |a, b| (a != b) as i32),
+                0x53 => binary!(I64, I32, |a, b| (a < b) as i32),
+                0x54 => binary!(I64, I32, |a, b| ((a as u64) < (b as u64)) as i32),
+                0x55 => binary!(I64, I32, |a, b| (a > b) as i32),
+                0x56 => binary!(I64, I32, |a, b| ((a as u64) > (b as u64)) as i32),
+                0x57 => binary!(I64, I32, |a, b| (a <= b) as i32),
+                0x58 => binary!(I64, I32, |a, b| ((a as u64) <= (b as u64)) as i32),
+                0x59 => binary!(I64, I32, |a, b| (a >= b) as i32),
+                0x5A => binary!(I64, I32, |a, b| ((a as u64) >= (b as u64)) as i32),
+
+                // f64 comparisons
+                0x61 => binary!(F64, I32, |a, b| (a == b) as i32),
+                0x62 => binary!(F64, I32, |a, b| (a != b) as i32),
+                0x63 => binary!(F64, I32, |a, b| (a < b) as i32),
+                0x64 => binary!(F64, I32, |a, b| (a > b) as i32),
+                0x65 => binary!(F64, I32, |a, b| (a <= b) as i32),
+                0x66 => binary!(F64, I32, |a, b| (a >= b) as i32),
+
+                // i32 arithmetic
+                0x6A => binary!(I32, I32, |a, b| a.wrapping_add(b)),
+                0x6B => binary!(I32, I32, |a, b| a.wrapping_sub(b)),
+                0x6C => binary!(I32, I32, |a, b| a.wrapping_mul(b)),
+                0x6D => binary!(I32, I32, |a, b| {
+                    if b == 0 {
+                        return Err(trap_div_zero());
+                    }
+                    a.checked_div(b).ok_or("integer overflow")?
+                }),
+                0x6E => binary!(I32, I32, |a, b| {
+                    (a as u32).checked_div(b as u32).ok_or_else(trap_div_zero)? as i32
+                }),
+                0x6F => binary!(I32, I32, |a, b| {
+                    if b == 0 {
+                        return Err(trap_div_zero());
+                    }
+                    a.wrapping_rem(b)
+                }),
+                0x70 => binary!(I32, I32, |a, b| {
+                    (a as u32).checked_rem(b as u32).ok_or_else(trap_div_zero)? as i32
+                }),
+                0x71 => binary!(I32, I32, |a, b| a & b),
+                0x72 => binary!(I32, I32, |a, b| a | b),
+                0x73 => binary!(I32, I32, |a, b| a ^ b),
+                0x74 => binary!(I32, I32, |a, b| a.wrapping_shl(b as u32)),
+                0x75 => binary!(I32, I32, |a, b| a.wrapping_shr(b as u32)),
+                0x76 => binary!(I32, I32, |a, b| (a as u32).wrapping_shr(b as u32) as i32),
+
+                // i64 arithmetic
+                0x7C => binary!(I64, I64, |a, b| a.wrapping_add(b)),
+                0x7D => binary!(I64, I64, |a, b| a.wrapping_sub(b)),
+                0x7E => binary!(I64, I64, |a, b| a.wrapping_mul(b)),
+                0x7F => binary!(I64, I64, |a, b| {
+                    if b == 0 {
+                        return Err(trap_div_zero());
+                    }
+                    a.checked_div(b).ok_or("integer overflow")?
+                }),
+                0x80 => binary!(I64, I64, |a, b| {
+                    (a as u64).checked_div(b as u64).ok_or_else(trap_div_zero)? as i64
+                }),
+                0x81 => binary!(I64, I64, |a, b| {
+                    if b == 0 {
+                        return Err(trap_div_zero());
+                    }
+                    a.wrapping_rem(b)
+                }),
+                0x82 => binary!(I64, I64, |a, b| {
+                    (a as u64).checked_rem(b as u64).ok_or_else(trap_div_zero)? as i64
+                }),
+                0x83 => binary!(I64, I64, |a, b| a & b),
+                0x84 => binary!(I64, I64, |a, b| a | b),
+                0x85 => binary!(I64, I64, |a, b| a ^ b),
+                0x86 => binary!(I64, I64, |a, b| a.wrapping_shl(b as u32)),
+                0x87 => binary!(I64, I64, |a, b| a.wrapping_shr(b as u32)),
+                0x88 => binary!(I64, I64, |a, b| (a as u64).wrapping_shr(b as u32) as i64),
+
+                // f64 arithmetic
+                0x99 => unary!(F64, F64, |a| a.abs()),
+                0x9A => unary!(F64, F64, |a| -a),
+                0x9F => unary!(F64, F64, |a| a.sqrt()),
+                0xA0 => binary!(F64, F64, |a, b| a + b),
+                0xA1 => binary!(F64, F64, |a, b| a - b),
+                0xA2 => binary!(F64, F64, |a, b| a * b),
+                0xA3 => binary!(F64, F64, |a, b| a / b),
+
+                // Conversions
+                0xA7 => unary!(I64, I32, |a| a as i32),
+                0xAC => unary!(I32, I64, |a| a as i64),
+                0xAD => unary!(I32, I64, |a| a as u32 as i64),
+                0xB7 => unary!(I32, F64, |a| a as f64),
+                0xB8 => unary!(I32, F64, |a| a as u32 as f64),
+                0xB9 => unary!(I64, F64, |a| a as f64),
+                0xBA => unary!(I64, F64, |a| a as u64 as f64),
+
+                // Saturating conversions, as Rust's `as` converts
+                0xFC => match reader.u32()? {
+                    2 => unary!(F64, I32, |a| a as i32),
+                    3 => unary!(F64, I32, |a| a as u32 as i32),
+                    6 => unary!(F64, I64, |a| a as i64),
+                    7 => unary!(F64, I64, |a| a as u64 as i64),
+                    sub => return Err(format!("unsupported opcode 0xFC {} at {}", sub, pos)),
+                },
+
+                _ => return Err(format!("unsupported opcode 0x{:02X} at {}", op, pos)),
+            }
+        }
+
+        // Returning (or falling off the end) leaves the results on top
+        if stack.len() < result_count {
+            return Err("function returned too few values".to_string());
+        }
+        Ok(stack.split_off(stack.len() - result_count))
+    }
+}